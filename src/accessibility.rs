@@ -0,0 +1,1505 @@
+//! Widgets through the AT-SPI2 accessibility tree.
+//!
+//! Toolkits publish their widgets on the AT-SPI bus, a D-Bus bus of its own
+//! whose address the session bus hands out. `accessibility_tree` finds the
+//! application owning a window by PID, picks its top-level element for that
+//! window (by title), and walks it with dbus-send, reading each element's
+//! role, name, states and screen extents. That takes a few dbus-send runs per
+//! element, so the walk is bounded by depth and element count and by default
+//! leaves out elements that aren't showing.
+//!
+//! `click_element` looks up an element by name and role the same way and
+//! clicks the middle of its extents like click_at (region rules included), or
+//! invokes its default AT-SPI action, which needs no position at all.
+//!
+//! `find_elements` searches the same way for every element with a role and
+//! a name matching a regex, e.g. all buttons of a dialog.
+//!
+//! `list_element_actions` reads the actions an element offers through the
+//! Action interface (press, toggle, expand or contract...) and
+//! `invoke_element_action` runs one by name, which operates controls that
+//! are scrolled away or covered.
+//!
+//! `snapshot_ui` flattens the same walk into the window's interactive
+//! elements only (buttons, fields, boxes, items), each with its label, rect,
+//! center and current value, as a compact text-only picture of the screen.
+//!
+//! `focus_element` gives an element keyboard focus with Component.GrabFocus
+//! and, for toolkits that ignore that, presses Tab until it has focus.
+//!
+//! `get_focused_text` finds the element with keyboard focus and reads its
+//! Text interface (contents and caret) and Value interface (sliders, spin
+//! buttons), to check what typing actually put in a field.
+//!
+//! `list_menus` walks the window's menu bar, hidden menus included, into
+//! paths like "File > Export > PDF", and `activate_menu` invokes the item at
+//! such a path through its AT-SPI action without opening the menus on screen.
+//!
+//! `set_element_text` replaces an editable widget's contents through the
+//! EditableText interface in one call, with no keystrokes (so no keyboard
+//! layout or autocompletion in the way), and reads them back to check.
+//!
+//! Apps publish a tree only while accessibility is on: GNOME turns it on with
+//! `gsettings set org.gnome.desktop.interface toolkit-accessibility true`,
+//! Chromium needs `--force-renderer-accessibility` and Qt
+//! `QT_LINUX_ACCESSIBILITY_ALWAYS_ON=1`.
+
+use crate::control::sleep_or_cancel;
+use crate::{output, process, XdotoolServer};
+use regex_automata::{meta::Regex, util::syntax};
+use rmcp::{
+    handler::server::wrapper::Parameters,
+    model::*,
+    service::{RequestContext, RoleServer},
+    ErrorData as McpError,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::process::Command;
+use std::time::Duration;
+
+/// How long a toolkit gets to move focus before the element's state is checked
+const FOCUS_SETTLE: Duration = Duration::from_millis(50);
+
+const REGISTRY: &str = "org.a11y.atspi.Registry";
+const ROOT_PATH: &str = "/org/a11y/atspi/accessible/root";
+
+/// AT-SPI state names, by bit number in GetState's two words
+const STATES: &[&str] = &[
+    "invalid", "active", "armed", "busy", "checked", "collapsed", "defunct", "editable", "enabled",
+    "expandable", "expanded", "focusable", "focused", "has-tooltip", "horizontal", "iconified",
+    "modal", "multi-line", "multiselectable", "opaque", "pressed", "resizable", "selectable",
+    "selected", "sensitive", "showing", "single-line", "stale", "transient", "vertical", "visible",
+    "manages-descendants", "indeterminate", "required", "truncated", "animated", "invalid-entry",
+    "supports-autocompletion", "selectable-text", "is-default", "visited", "checkable", "has-popup",
+    "read-only",
+];
+
+/// States worth showing in the text summary; the rest only go in the structured result
+const NOTABLE_STATES: &[&str] = &[
+    "busy", "checked", "collapsed", "editable", "expanded", "focused", "indeterminate",
+    "invalid-entry", "modal", "pressed", "read-only", "required", "selected",
+];
+
+/// One value of a dbus-send reply
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Str(String),
+    Path(String),
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+}
+
+/// The values of a `dbus-send --print-reply` reply in order, nesting flattened
+fn tokens(reply: &str) -> Vec<Token> {
+    // The first line is "method return time=... sender=..."; values follow one per line
+    reply.lines().skip(1).filter_map(|line| {
+        let line = line.trim().trim_start_matches("variant").trim_start();
+        if let Some(text) = line.strip_prefix("string \"") {
+            return Some(Token::Str(text.strip_suffix('"').unwrap_or(text).to_string()));
+        }
+        if let Some(path) = line.strip_prefix("object path \"") {
+            return Some(Token::Path(path.trim_end_matches('"').to_string()));
+        }
+        let (kind, value) = line.split_once(' ')?;
+        match kind {
+            "byte" | "int16" | "uint16" | "int32" | "uint32" | "int64" | "uint64" => value.trim().parse().ok().map(Token::Int),
+            "double" => value.trim().parse().ok().map(Token::Float),
+            "boolean" => value.trim().parse().ok().map(Token::Bool),
+            _ => None,
+        }
+    }).collect()
+}
+
+/// The AT-SPI bus
+pub(crate) struct Bus {
+    address: String,
+}
+
+impl Bus {
+    /// Ask the session bus where the AT-SPI bus is
+    pub(crate) fn open() -> Result<Self, McpError> {
+        let output = process::output(Command::new("dbus-send")
+            .args(["--session", "--print-reply", "--reply-timeout=1000", "--dest=org.a11y.Bus"])
+            .args(["/org/a11y/bus", "org.a11y.Bus.GetAddress"]))
+            .map_err(|e| process::error("dbus-send", e))?;
+        let address = match tokens(&String::from_utf8_lossy(&output.stdout)).first() {
+            Some(Token::Str(address)) if output.status.success() => address.clone(),
+            _ => return Err(McpError::internal_error(
+                format!("Can't reach the AT-SPI bus (is at-spi2-core running?): {}", String::from_utf8_lossy(&output.stderr).trim()),
+                None
+            )),
+        };
+        Ok(Self { address })
+    }
+
+    /// Call a method, returning the reply's values, or None if the call failed
+    fn call(&self, dest: &str, path: &str, method: &str, args: &[&str]) -> Option<Vec<Token>> {
+        let output = process::output(Command::new("dbus-send")
+            .arg(format!("--bus={}", self.address))
+            .args(["--print-reply", "--reply-timeout=1000"])
+            .arg(format!("--dest={}", dest))
+            .args([path, method])
+            .args(args))
+            .ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        Some(tokens(&String::from_utf8_lossy(&output.stdout)))
+    }
+
+    /// An element's children, as (bus name, object path)
+    fn children(&self, node: &Node) -> Vec<Node> {
+        let tokens = self.call(&node.0, &node.1, "org.a11y.atspi.Accessible.GetChildren", &[]).unwrap_or_default();
+        tokens.chunks(2).filter_map(|pair| match pair {
+            [Token::Str(bus), Token::Path(path)] => Some((bus.clone(), path.clone())),
+            _ => None,
+        }).collect()
+    }
+
+    fn role(&self, node: &Node) -> String {
+        match self.call(&node.0, &node.1, "org.a11y.atspi.Accessible.GetRoleName", &[]).unwrap_or_default().first() {
+            Some(Token::Str(role)) => role.clone(),
+            _ => "unknown".to_string(),
+        }
+    }
+
+    /// A D-Bus property of one of the element's interfaces
+    fn property(&self, node: &Node, interface: &str, property: &str) -> Option<Token> {
+        let args = [format!("string:{}", interface), format!("string:{}", property)];
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        self.call(&node.0, &node.1, "org.freedesktop.DBus.Properties.Get", &args)?.into_iter().next()
+    }
+
+    fn name(&self, node: &Node) -> String {
+        match self.property(node, "org.a11y.atspi.Accessible", "Name") {
+            Some(Token::Str(name)) => name,
+            _ => String::new(),
+        }
+    }
+
+    fn states(&self, node: &Node) -> Vec<String> {
+        let tokens = self.call(&node.0, &node.1, "org.a11y.atspi.Accessible.GetState", &[]).unwrap_or_default();
+        let words: Vec<i64> = tokens.iter().filter_map(|token| match token {
+            Token::Int(word) => Some(*word),
+            _ => None,
+        }).collect();
+        STATES.iter().enumerate()
+            .filter(|(bit, _)| words.get(bit / 32).is_some_and(|word| word & (1 << (bit % 32)) != 0))
+            .map(|(_, state)| state.to_string())
+            .collect()
+    }
+
+    /// Screen extents, for elements with the Component interface
+    fn extents(&self, node: &Node) -> Option<Extents> {
+        // Coordinate type 0 is relative to the screen
+        let tokens = self.call(&node.0, &node.1, "org.a11y.atspi.Component.GetExtents", &["uint32:0"])?;
+        match tokens.as_slice() {
+            [Token::Int(x), Token::Int(y), Token::Int(width), Token::Int(height)] => Some(Extents {
+                x: *x as i32,
+                y: *y as i32,
+                width: *width as i32,
+                height: *height as i32,
+            }),
+            _ => None,
+        }
+    }
+
+    /// All of an element's text, for elements with the Text interface
+    fn text(&self, node: &Node) -> Option<String> {
+        match self.call(&node.0, &node.1, "org.a11y.atspi.Text.GetText", &["int32:0", "int32:-1"])?.first() {
+            Some(Token::Str(text)) => Some(text.clone()),
+            _ => None,
+        }
+    }
+
+    /// Invoke the element's default action, returning whether it has one
+    fn do_action(&self, node: &Node) -> bool {
+        self.invoke(node, 0)
+    }
+
+    /// Invoke the element's action at `index`, returning whether the call went through
+    fn invoke(&self, node: &Node, index: usize) -> bool {
+        let arg = format!("int32:{}", index);
+        self.call(&node.0, &node.1, "org.a11y.atspi.Action.DoAction", &[&arg]).is_some()
+    }
+
+    /// The element's actions, empty without the Action interface
+    fn actions(&self, node: &Node) -> Vec<ElementAction> {
+        let tokens = self.call(&node.0, &node.1, "org.a11y.atspi.Action.GetActions", &[]).unwrap_or_default();
+        // (name, localized description, key binding) per action
+        tokens.chunks(3).enumerate().filter_map(|(index, action)| match action {
+            [Token::Str(name), Token::Str(description), Token::Str(key_binding)] => Some(ElementAction {
+                index,
+                name: name.clone(),
+                description: description.clone(),
+                key_binding: key_binding.clone(),
+            }),
+            _ => None,
+        }).collect()
+    }
+
+    /// Replace an editable element's whole text, returning whether it took the call
+    fn set_text(&self, node: &Node, text: &str) -> bool {
+        let arg = format!("string:{}", text);
+        let reply = self.call(&node.0, &node.1, "org.a11y.atspi.EditableText.SetTextContents", &[&arg]);
+        reply.is_some_and(|reply| reply.first() != Some(&Token::Bool(false)))
+    }
+
+    /// Ask the toolkit to give the element keyboard focus, returning whether it says it did
+    fn grab_focus(&self, node: &Node) -> bool {
+        let reply = self.call(&node.0, &node.1, "org.a11y.atspi.Component.GrabFocus", &[]);
+        reply.is_some_and(|reply| reply.first() == Some(&Token::Bool(true)))
+    }
+
+    /// PID of the process that owns the element's bus connection
+    fn pid(&self, node: &Node) -> Option<i64> {
+        let arg = format!("string:{}", node.0);
+        match self.call("org.freedesktop.DBus", "/org/freedesktop/DBus", "org.freedesktop.DBus.GetConnectionUnixProcessID", &[&arg])?.first() {
+            Some(Token::Int(pid)) => Some(*pid),
+            _ => None,
+        }
+    }
+
+    fn has_state(&self, node: &Node, state: &str) -> bool {
+        self.states(node).iter().any(|found| found == state)
+    }
+
+    /// An element without its children
+    fn leaf(&self, node: &Node, states: Vec<String>) -> Element {
+        Element {
+            id: format!("{}{}", node.0, node.1),
+            role: self.role(node),
+            name: self.name(node),
+            states,
+            extents: self.extents(node),
+            children: vec![],
+        }
+    }
+}
+
+/// An element as (application's bus name, object path)
+pub(crate) type Node = (String, String);
+
+#[derive(Debug, Clone, Copy, Serialize, JsonSchema)]
+pub struct Extents {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct Element {
+    /// The application's bus name followed by the element's object path
+    pub id: String,
+    /// AT-SPI role name, e.g. "push button", "text", "menu item"
+    pub role: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub name: String,
+    pub states: Vec<String>,
+    /// Where it is on the screen, if it has a position
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extents: Option<Extents>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<Element>,
+}
+
+impl Element {
+    /// Role and name, e.g. `push button "OK"`
+    fn label(&self) -> String {
+        if self.name.is_empty() {
+            self.role.clone()
+        } else {
+            format!("{} \"{}\"", self.role, self.name)
+        }
+    }
+
+    /// Indented summary lines, one per element
+    fn describe(&self, depth: usize, lines: &mut Vec<String>) {
+        let mut line = format!("{}- {}", "  ".repeat(depth), self.role);
+        if !self.name.is_empty() {
+            line.push_str(&format!(" \"{}\"", self.name));
+        }
+        if let Some(extents) = self.extents {
+            line.push_str(&format!(" ({},{} {}x{})", extents.x, extents.y, extents.width, extents.height));
+        }
+        let notable: Vec<&str> = self.states.iter().map(String::as_str).filter(|state| NOTABLE_STATES.contains(state)).collect();
+        if !notable.is_empty() {
+            line.push_str(&format!(" [{}]", notable.join(", ")));
+        }
+        lines.push(line);
+        for child in &self.children {
+            child.describe(depth + 1, lines);
+        }
+    }
+}
+
+/// Roles snapshot_ui treats as something to operate, besides anything editable
+const INTERACTIVE_ROLES: &[&str] = &[
+    "push button", "toggle button", "check box", "radio button", "combo box", "text", "entry",
+    "password text", "spin button", "slider", "link", "page tab", "list item", "tree item",
+    "menu", "menu item", "check menu item", "radio menu item", "scroll bar",
+];
+
+/// Roles whose value is whether they're checked
+const CHECKABLE_ROLES: &[&str] = &["check box", "radio button", "toggle button", "check menu item", "radio menu item"];
+
+/// Roles whose value comes from the Value interface
+const RANGE_ROLES: &[&str] = &["slider", "spin button", "scroll bar", "progress bar"];
+
+/// Texts presented as a snapshot value are cut to this many characters
+const SNAPSHOT_TEXT_LENGTH: usize = 200;
+
+/// How far a walk may go
+struct Walk<'a> {
+    bus: &'a Bus,
+    max_depth: usize,
+    max_elements: usize,
+    showing_only: bool,
+    count: usize,
+    truncated: bool,
+}
+
+impl Walk<'_> {
+    /// Read `node`, whose `states` the caller has, and its subtree
+    fn element(&mut self, node: &Node, states: Vec<String>, depth: usize) -> Element {
+        self.count += 1;
+        let mut element = self.bus.leaf(node, states);
+        let children = self.bus.children(node);
+        if !children.is_empty() && depth >= self.max_depth {
+            self.truncated = true;
+            return element;
+        }
+        for child in children {
+            if self.count >= self.max_elements {
+                self.truncated = true;
+                break;
+            }
+            let states = self.bus.states(&child);
+            if self.showing_only && !states.iter().any(|state| state == "showing") {
+                continue;
+            }
+            element.children.push(self.element(&child, states, depth + 1));
+        }
+        element
+    }
+}
+
+/// The first showing element under `root` with the role and name, breadth first. A name
+/// equal to `name` (ignoring case) beats one only containing it.
+fn find(bus: &Bus, root: &Node, name: Option<&str>, role: Option<&str>, max_elements: usize) -> (Option<Node>, usize) {
+    let name = name.map(str::to_lowercase);
+    let mut queue = std::collections::VecDeque::from([root.clone()]);
+    let (mut read, mut partial) = (0, None);
+    while let Some(node) = queue.pop_front() {
+        if read >= max_elements {
+            break;
+        }
+        read += 1;
+        let role_matches = role.is_none_or(|role| bus.role(&node).eq_ignore_ascii_case(role));
+        if role_matches {
+            let found = bus.name(&node).to_lowercase();
+            match &name {
+                None => return (Some(node), read),
+                Some(name) if found == *name => return (Some(node), read),
+                Some(name) if partial.is_none() && !name.is_empty() && found.contains(name.as_str()) => partial = Some(node.clone()),
+                _ => {}
+            }
+        }
+        queue.extend(bus.children(&node).into_iter()
+            .filter(|child| bus.states(child).iter().any(|state| state == "showing")));
+    }
+    (partial, read)
+}
+
+/// Every element under `root` (itself included) with the role and a name matching the pattern,
+/// breadth first, with how many elements were read and whether a limit cut the search short
+fn find_all(
+    bus: &Bus,
+    root: &Node,
+    role: Option<&str>,
+    pattern: Option<&Regex>,
+    showing_only: bool,
+    (max_elements, max_results): (usize, usize),
+) -> (Vec<Element>, usize, bool) {
+    let mut queue = std::collections::VecDeque::from([(root.clone(), bus.states(root))]);
+    let (mut found, mut read) = (vec![], 0);
+    while let Some((node, states)) = queue.pop_front() {
+        if read >= max_elements || found.len() >= max_results {
+            return (found, read, true);
+        }
+        read += 1;
+        let node_role = bus.role(&node);
+        if role.is_none_or(|role| node_role.eq_ignore_ascii_case(role)) {
+            let name = bus.name(&node);
+            if pattern.is_none_or(|pattern| pattern.is_match(&name)) {
+                found.push(Element {
+                    id: format!("{}{}", node.0, node.1),
+                    role: node_role,
+                    name,
+                    extents: bus.extents(&node),
+                    states,
+                    children: vec![],
+                });
+            }
+        }
+        for child in bus.children(&node) {
+            let states = bus.states(&child);
+            if !showing_only || states.iter().any(|state| state == "showing") {
+                queue.push_back((child, states));
+            }
+        }
+    }
+    (found, read, false)
+}
+
+/// The element under `root` with keyboard focus, with its states, breadth first through showing elements
+fn focused(bus: &Bus, root: &Node, max_elements: usize) -> (Option<(Node, Vec<String>)>, usize) {
+    let mut queue = std::collections::VecDeque::from([(root.clone(), bus.states(root))]);
+    let mut read = 0;
+    while let Some((node, states)) = queue.pop_front() {
+        if read >= max_elements {
+            break;
+        }
+        read += 1;
+        if states.iter().any(|state| state == "focused") {
+            return (Some((node, states)), read);
+        }
+        queue.extend(bus.children(&node).into_iter()
+            .map(|child| {
+                let states = bus.states(&child);
+                (child, states)
+            })
+            .filter(|(_, states)| states.iter().any(|state| state == "showing")));
+    }
+    (None, read)
+}
+
+/// Separator between the levels of a menu path
+const MENU_SEPARATOR: &str = " > ";
+
+/// Roles of menu entries that can hold or be activated
+const MENU_ROLES: &[&str] = &["menu", "menu item", "check menu item", "radio menu item"];
+
+/// Whether a menu label matches a level of a path: ignoring case and a trailing ellipsis
+fn same_label(label: &str, wanted: &str) -> bool {
+    let trim = |text: &str| text.trim().trim_end_matches("...").trim_end_matches('…').trim().to_lowercase();
+    trim(label) == trim(wanted)
+}
+
+/// The entries of a menu (or the menu bar) that have a label, with their labels
+fn menu_entries(bus: &Bus, menu: &Node) -> Vec<(Node, String, String)> {
+    bus.children(menu).into_iter().filter_map(|child| {
+        let role = bus.role(&child);
+        if !MENU_ROLES.contains(&role.as_str()) {
+            return None;
+        }
+        let name = bus.name(&child);
+        (!name.trim().is_empty()).then_some((child, role, name))
+    }).collect()
+}
+
+/// Every entry under `menu`, depth first, with its path
+fn menu_paths(bus: &Bus, menu: &Node, prefix: &str, max_elements: usize, items: &mut Vec<MenuItem>) -> bool {
+    for (node, role, name) in menu_entries(bus, menu) {
+        if items.len() >= max_elements {
+            return false;
+        }
+        let path = match prefix {
+            "" => name,
+            prefix => format!("{}{}{}", prefix, MENU_SEPARATOR, name),
+        };
+        let states = bus.states(&node);
+        let has = |state: &str| states.iter().any(|found| found == state);
+        let submenu = role == "menu";
+        items.push(MenuItem {
+            path: path.clone(),
+            role,
+            enabled: has("enabled") || has("sensitive"),
+            checked: has("checked"),
+            submenu,
+        });
+        if submenu && !menu_paths(bus, &node, &path, max_elements, items) {
+            return false;
+        }
+    }
+    true
+}
+
+/// An element from its ID as accessibility_tree shows it: bus name, then object path
+fn parse_id(id: &str) -> Option<Node> {
+    let slash = id.find('/')?;
+    let (bus, path) = id.split_at(slash);
+    (!bus.is_empty()).then(|| (bus.to_string(), path.to_string()))
+}
+
+/// The interactive elements of `element`'s tree, in tree order
+fn interactive(bus: &Bus, element: Element, found: &mut Vec<UiElement>) {
+    let role = element.role.as_str();
+    if INTERACTIVE_ROLES.contains(&role) || element.states.iter().any(|state| state == "editable") {
+        let node = parse_id(&element.id).unwrap_or_default();
+        let has = |state: &str| element.states.iter().any(|found| found == state);
+        let value = if CHECKABLE_ROLES.contains(&role) {
+            Some(if has("checked") { "checked" } else { "unchecked" }.to_string())
+        } else if RANGE_ROLES.contains(&role) {
+            match bus.property(&node, "org.a11y.atspi.Value", "CurrentValue") {
+                Some(Token::Float(value)) => Some(value.to_string()),
+                Some(Token::Int(value)) => Some(value.to_string()),
+                _ => None,
+            }
+        } else if has("editable") || role == "combo box" {
+            bus.text(&node).map(|text| match text.char_indices().nth(SNAPSHOT_TEXT_LENGTH) {
+                Some((end, _)) => format!("{}…", &text[..end]),
+                None => text,
+            })
+        } else {
+            None
+        };
+        let mut states: Vec<String> = element.states.iter()
+            .filter(|state| NOTABLE_STATES.contains(&state.as_str()) && *state != "checked" && *state != "editable")
+            .cloned()
+            .collect();
+        if !has("enabled") && !has("sensitive") {
+            states.push("disabled".to_string());
+        }
+        let rect = element.extents.filter(|extents| extents.width > 0 && extents.height > 0);
+        found.push(UiElement {
+            id: element.id.clone(),
+            role: element.role.clone(),
+            label: element.name.clone(),
+            rect,
+            center: rect.map(|rect| (rect.x + rect.width / 2, rect.y + rect.height / 2).into()),
+            value,
+            states,
+        });
+    }
+    for child in element.children {
+        interactive(bus, child, found);
+    }
+}
+
+impl XdotoolServer {
+    /// A showing element of the window with the name (and role), and the middle of its extents
+    pub(crate) fn element_center(window_id: &str, name: &str, role: Option<&str>) -> Result<(Element, (i32, i32)), McpError> {
+        let bus = Bus::open()?;
+        let frame = Self::window_element(&bus, window_id)?;
+        let (found, read) = find(&bus, &frame, Some(name), role, default_search_elements());
+        let Some(node) = found else {
+            return Err(McpError::internal_error(
+                format!("No showing element named \"{}\" ({} searched)", name, read),
+                None
+            ));
+        };
+        let element = bus.leaf(&node, bus.states(&node));
+        match element.extents.filter(|extents| extents.width > 0 && extents.height > 0) {
+            Some(extents) => {
+                let center = (extents.x + extents.width / 2, extents.y + extents.height / 2);
+                Ok((element, center))
+            }
+            None => Err(McpError::internal_error(format!("{} has no position on screen", element.label()), None)),
+        }
+    }
+
+    /// The element a call names: by `element` ID, by name and role in the window, or else the focused one
+    fn target_element(bus: &Bus, target: ElementTarget) -> Result<Node, McpError> {
+        let (name, role, max_elements) = (target.name.as_deref(), target.role.as_deref(), target.max_elements.max(1));
+        if let Some(id) = target.element.as_deref() {
+            return parse_id(id).ok_or_else(|| McpError::invalid_params(
+                format!("{} isn't an element ID like accessibility_tree returns, e.g. \":1.42/org/a11y/atspi/accessible/7\"", id),
+                None
+            ));
+        }
+        let window_id = match target.window_id {
+            Some(id) => id,
+            None => Self::run_xdotool(&["getactivewindow"])?.trim().to_string(),
+        };
+        let frame = Self::window_element(bus, &window_id)?;
+        if name.is_none() && role.is_none() {
+            let (found, read) = focused(bus, &frame, max_elements);
+            return found.map(|(node, _)| node).ok_or_else(|| McpError::internal_error(
+                format!("Nothing in window {} has keyboard focus ({} elements searched)", window_id, read),
+                None
+            ));
+        }
+        let (found, read) = find(bus, &frame, name, role, max_elements);
+        found.ok_or_else(|| McpError::internal_error(
+            format!(
+                "No showing {}{} in window {} ({} elements searched)",
+                role.unwrap_or("element"), name.map(|name| format!(" \"{}\"", name)).unwrap_or_default(), window_id, read
+            ),
+            None
+        ))
+    }
+
+    /// Refuse to act on an element unless a window of its application is on the allowlist
+    fn check_element(&self, bus: &Bus, node: &Node) -> Result<(), McpError> {
+        if self.config.allowed_window_classes.is_empty() {
+            return Ok(());
+        }
+        let windows = match bus.pid(node).and_then(|pid| u32::try_from(pid).ok()) {
+            Some(pid) => Self::pid_windows(pid, false)?,
+            None => vec![],
+        };
+        let mut blocked = McpError::internal_error(
+            format!("Input blocked: the application owning {} has no window to check against allowed_window_classes", node.0),
+            None
+        );
+        for window_id in &windows {
+            match self.check_window(window_id) {
+                Ok(()) => return Ok(()),
+                Err(e) => blocked = e,
+            }
+        }
+        Err(blocked)
+    }
+
+    /// The window's menu bar
+    fn menu_bar(bus: &Bus, window_id: &str) -> Result<Node, McpError> {
+        let frame = Self::window_element(bus, window_id)?;
+        let (found, _) = find(bus, &frame, None, Some("menu bar"), default_search_elements());
+        found.ok_or_else(|| McpError::internal_error(
+            format!("Window {} has no accessible menu bar", window_id),
+            None
+        ))
+    }
+
+    /// The top-level AT-SPI element of an X window: its application's, matched by PID, with the window's title
+    pub(crate) fn window_element(bus: &Bus, window_id: &str) -> Result<Node, McpError> {
+        let pid: i64 = Self::run_xdotool(&["getwindowpid", window_id])?.trim().parse()
+            .map_err(|_| McpError::internal_error(format!("Window {} has no _NET_WM_PID", window_id), None))?;
+        let title = Self::window_name(window_id).unwrap_or_default();
+        let registry = (REGISTRY.to_string(), ROOT_PATH.to_string());
+        let app = bus.children(&registry).into_iter().find(|app| bus.pid(app) == Some(pid));
+        let Some(app) = app else {
+            return Err(McpError::internal_error(
+                format!(
+                    "No accessible application has PID {} (window {}). Turn on accessibility for it, e.g. gsettings set org.gnome.desktop.interface toolkit-accessibility true",
+                    pid, window_id
+                ),
+                None
+            ));
+        };
+        let frames = bus.children(&app);
+        let frame = frames.iter().find(|frame| bus.name(frame) == title)
+            .or_else(|| frames.iter().find(|frame| bus.states(frame).iter().any(|state| state == "active")))
+            .or(frames.first());
+        frame.cloned().ok_or_else(|| McpError::internal_error(
+            format!("The application with PID {} has no accessible windows", pid),
+            None
+        ))
+    }
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct AccessibilityTreeParams {
+    #[schemars(description = "Window ID. Default: the active window")]
+    pub window_id: Option<String>,
+    #[schemars(description = "Levels below the window to read. Default: 12")]
+    #[serde(default = "default_max_depth")]
+    pub max_depth: usize,
+    #[schemars(description = "Stop after this many elements. Default: 300")]
+    #[serde(default = "default_max_elements")]
+    pub max_elements: usize,
+    #[schemars(description = "Include elements that aren't showing (other tabs, closed menus). Default: false")]
+    #[serde(default)]
+    pub include_hidden: bool,
+}
+
+fn default_max_depth() -> usize { 12 }
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ClickElementParams {
+    #[schemars(description = "Accessible name, e.g. the button label. An exact match (ignoring case) wins over one containing it")]
+    pub name: Option<String>,
+    #[schemars(description = "AT-SPI role name as accessibility_tree shows it, e.g. \"push button\", \"check box\", \"menu item\"")]
+    pub role: Option<String>,
+    #[schemars(description = "Window ID. Default: the active window")]
+    pub window_id: Option<String>,
+    #[schemars(description = "Invoke the element's default action (press, toggle, activate) through AT-SPI instead of clicking, which also works when it's covered or scrolled away. Default: false")]
+    #[serde(default)]
+    pub use_action: bool,
+    #[schemars(description = "Button to click: 1 (left), 2 (middle), 3 (right). Default: 1")]
+    #[serde(default = "crate::default_button")]
+    pub button: u8,
+    #[schemars(description = "Stop searching after this many elements. Default: 1000")]
+    #[serde(default = "default_search_elements")]
+    pub max_elements: usize,
+    #[schemars(description = "Only report what would be clicked. Default: the server's dry_run setting")]
+    pub dry_run: Option<bool>,
+}
+
+fn default_search_elements() -> usize { 1000 }
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct GetFocusedTextParams {
+    #[schemars(description = "Window ID. Default: the active window")]
+    pub window_id: Option<String>,
+    #[schemars(description = "Stop searching after this many elements. Default: 1000")]
+    #[serde(default = "default_search_elements")]
+    pub max_elements: usize,
+}
+
+fn default_max_elements() -> usize { 300 }
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct AccessibilityTree {
+    pub window: output::Window,
+    pub root: Element,
+    /// How many elements were read
+    pub elements: usize,
+    /// Whether max_depth or max_elements cut the tree short
+    pub truncated: bool,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ClickedElement {
+    pub element: Element,
+    /// "click", or "action" for the element's default action
+    pub method: String,
+    /// Where the click landed, for clicks
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub position: Option<output::Point>,
+    /// What would have been done, in dry runs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dry_run: Option<String>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct SnapshotUiParams {
+    #[schemars(description = "Window ID. Default: the active window")]
+    pub window_id: Option<String>,
+    #[schemars(description = "Stop after reading this many elements. Default: 1000")]
+    #[serde(default = "default_search_elements")]
+    pub max_elements: usize,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct UiElement {
+    /// Element ID, for the other accessibility tools
+    pub id: String,
+    pub role: String,
+    /// Accessible name, usually the visible label
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub label: String,
+    /// Where it is on the screen; missing for elements without a position
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rect: Option<Extents>,
+    /// The middle of rect, where click_at would hit it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub center: Option<output::Point>,
+    /// Text of fields, number of sliders and spin buttons, "checked"/"unchecked" of check boxes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+    /// The states worth knowing: focused, disabled, expanded, selected...
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub states: Vec<String>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct UiSnapshot {
+    pub window: output::Window,
+    /// Interactive elements in tree order
+    pub elements: Vec<UiElement>,
+    /// How many elements were read
+    pub searched: usize,
+    /// Whether max_elements cut the walk short
+    pub truncated: bool,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct FindElementsParams {
+    #[schemars(description = "AT-SPI role name, e.g. \"push button\", \"text\", \"check box\". Default: any")]
+    pub role: Option<String>,
+    #[schemars(description = "Regex the accessible name must match, ignoring case, e.g. '^(ok|cancel)$'. Default: any name")]
+    pub name_pattern: Option<String>,
+    #[schemars(description = "Window ID. Default: the active window")]
+    pub window_id: Option<String>,
+    #[schemars(description = "Include elements that aren't showing (other tabs, closed menus). Default: false")]
+    #[serde(default)]
+    pub include_hidden: bool,
+    #[schemars(description = "Stop after reading this many elements. Default: 1000")]
+    #[serde(default = "default_search_elements")]
+    pub max_elements: usize,
+    #[schemars(description = "Stop after this many matches. Default: 100")]
+    #[serde(default = "default_max_results")]
+    pub max_results: usize,
+}
+
+fn default_max_results() -> usize { 100 }
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct FoundElements {
+    pub window: output::Window,
+    /// Matches in breadth-first order, without their children
+    pub elements: Vec<Element>,
+    /// How many elements were read
+    pub searched: usize,
+    /// Whether max_elements or max_results cut the search short
+    pub truncated: bool,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct FocusElementParams {
+    #[serde(flatten)]
+    pub target: ElementTarget,
+    #[schemars(description = "Tab presses to try when the toolkit ignores the focus request. 0 never presses Tab. Default: 30")]
+    #[serde(default = "default_max_tabs")]
+    pub max_tabs: u32,
+    #[schemars(description = "Only report which element would get focus. Default: the server's dry_run setting")]
+    pub dry_run: Option<bool>,
+}
+
+fn default_max_tabs() -> u32 { 30 }
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct FocusedElement {
+    pub element: Element,
+    /// "grab_focus" if AT-SPI focused it, "tab" if Tab presses got there, "already" if it had focus
+    pub method: String,
+    /// Tab presses it took
+    pub tabs: u32,
+    /// What would have been done, in dry runs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dry_run: Option<String>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct InvokeElementActionParams {
+    #[serde(flatten)]
+    pub target: ElementTarget,
+    #[schemars(description = "Action name as list_element_actions shows it, e.g. \"click\", \"toggle\", \"expand or contract\", ignoring case. Default: the element's first (default) action")]
+    pub action: Option<String>,
+    #[schemars(description = "Only report which action would be invoked. Default: the server's dry_run setting")]
+    pub dry_run: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct ElementAction {
+    pub index: usize,
+    /// Toolkit name, e.g. "click", "press", "toggle", "expand or contract"
+    pub name: String,
+    /// Localized description, often empty
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub description: String,
+    /// Keyboard shortcut for it, if the toolkit gives one
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub key_binding: String,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ElementActions {
+    pub element: Element,
+    pub actions: Vec<ElementAction>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct InvokedAction {
+    pub element: Element,
+    pub action: ElementAction,
+    /// What would have been done, in dry runs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dry_run: Option<String>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ListMenusParams {
+    #[schemars(description = "Window ID. Default: the active window")]
+    pub window_id: Option<String>,
+    #[schemars(description = "Stop after this many menu entries. Default: 500")]
+    #[serde(default = "default_menu_items")]
+    pub max_items: usize,
+}
+
+fn default_menu_items() -> usize { 500 }
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ActivateMenuParams {
+    #[schemars(description = "Menu path as list_menus shows it, levels separated by '>', e.g. \"File > Export > PDF\". Matched ignoring case and trailing ellipses")]
+    pub path: String,
+    #[schemars(description = "Window ID. Default: the active window")]
+    pub window_id: Option<String>,
+    #[schemars(description = "Only report which item would be activated. Default: the server's dry_run setting")]
+    pub dry_run: Option<bool>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct MenuItem {
+    /// Labels from the menu bar down, joined with " > "
+    pub path: String,
+    /// "menu" for submenus, otherwise "menu item", "check menu item" or "radio menu item"
+    pub role: String,
+    pub enabled: bool,
+    pub checked: bool,
+    /// Whether it opens a submenu rather than doing something
+    pub submenu: bool,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct Menus {
+    pub window: output::Window,
+    pub items: Vec<MenuItem>,
+    /// Whether max_items cut the list short
+    pub truncated: bool,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ActivatedMenu {
+    /// The path with each level's label as the app has it
+    pub path: String,
+    pub element: Element,
+    /// What would have been done, in dry runs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dry_run: Option<String>,
+}
+
+/// Which element a call works on
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ElementTarget {
+    #[schemars(description = "Element ID from accessibility_tree or find_elements. Default: found by name and role, or the focused widget")]
+    pub element: Option<String>,
+    #[schemars(description = "Accessible name, when not giving an element ID. An exact match (ignoring case) wins over one containing it")]
+    pub name: Option<String>,
+    #[schemars(description = "AT-SPI role, e.g. \"push button\", \"text\", \"toggle button\", when not giving an element ID")]
+    pub role: Option<String>,
+    #[schemars(description = "Window to search by name, role or focus. Default: the active window")]
+    pub window_id: Option<String>,
+    #[schemars(description = "Stop searching after this many elements. Default: 1000")]
+    #[serde(default = "default_search_elements")]
+    pub max_elements: usize,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct SetElementTextParams {
+    #[schemars(description = "Text the widget should contain, replacing what's there. Empty clears it")]
+    pub text: String,
+    #[serde(flatten)]
+    pub target: ElementTarget,
+    #[schemars(description = "The text is a password or other secret: it is never echoed back or logged. Default: false")]
+    #[serde(default)]
+    pub secret: bool,
+    #[schemars(description = "Only report which widget would be set. Default: the server's dry_run setting")]
+    pub dry_run: Option<bool>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ElementTextSet {
+    pub element: Element,
+    pub characters: usize,
+    /// The text set, unless it was secret
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    /// Whether the widget's text read back the same afterwards (password fields read back masked)
+    pub verified: bool,
+    /// What would have been done, in dry runs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dry_run: Option<String>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct FocusedText {
+    pub element: Element,
+    /// The widget's whole text, for text widgets
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    /// Characters in the text
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub character_count: Option<i64>,
+    /// Character offset of the caret in the text
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub caret_offset: Option<i64>,
+    /// Current value, for sliders, spin buttons and progress bars
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<f64>,
+}
+
+#[rmcp::tool_router(router = accessibility_router, vis = "pub(crate)")]
+impl XdotoolServer {
+    #[rmcp::tool(description = "Get a window's UI elements from the AT-SPI accessibility tree: role, name, states and screen extents of each widget, so you can target real buttons and fields instead of guessing pixels. Needs dbus-send and the app's accessibility support turned on", annotations(read_only_hint = true), output_schema = output::schema::<AccessibilityTree>())]
+    pub async fn accessibility_tree(
+        &self,
+        Parameters(params): Parameters<AccessibilityTreeParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let window_id = match params.window_id {
+            Some(id) => id,
+            None => Self::run_xdotool(&["getactivewindow"])?.trim().to_string(),
+        };
+        let bus = Bus::open()?;
+        let frame = Self::window_element(&bus, &window_id)?;
+        let mut walk = Walk {
+            bus: &bus,
+            max_depth: params.max_depth,
+            max_elements: params.max_elements.max(1),
+            showing_only: !params.include_hidden,
+            count: 0,
+            truncated: false,
+        };
+        let root = walk.element(&frame, bus.states(&frame), 0);
+        let mut lines = vec![];
+        root.describe(0, &mut lines);
+        if walk.truncated {
+            lines.push(format!("(cut short after {} elements; raise max_depth or max_elements for more)", walk.count));
+        }
+        let window = output::Window { id: window_id, name: Some(root.name.clone()).filter(|name| !name.is_empty()) };
+        Ok(output::structured(
+            lines.join("\n"),
+            &AccessibilityTree { window, root, elements: walk.count, truncated: walk.truncated }
+        ))
+    }
+
+    #[rmcp::tool(description = "Click a UI element found by its accessible name and/or role through AT-SPI, at the middle of its screen extents, or invoke its default action. More robust than coordinates across themes and resolutions; see accessibility_tree for names and roles", annotations(read_only_hint = false, destructive_hint = true), output_schema = output::schema::<ClickedElement>())]
+    pub async fn click_element(
+        &self,
+        Parameters(params): Parameters<ClickElementParams>,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        if params.name.is_none() && params.role.is_none() {
+            return Err(McpError::invalid_params("Give a name, a role or both", None));
+        }
+        let window_id = match params.window_id {
+            Some(id) => id,
+            None => Self::run_xdotool(&["getactivewindow"])?.trim().to_string(),
+        };
+        let bus = Bus::open()?;
+        let frame = Self::window_element(&bus, &window_id)?;
+        let (found, read) = find(&bus, &frame, params.name.as_deref(), params.role.as_deref(), params.max_elements.max(1));
+        let Some(node) = found else {
+            let wanted = match (&params.role, &params.name) {
+                (Some(role), Some(name)) => format!("{} \"{}\"", role, name),
+                (Some(role), None) => role.clone(),
+                (None, name) => format!("\"{}\"", name.as_deref().unwrap_or_default()),
+            };
+            return Err(McpError::internal_error(
+                format!("No showing {} in window {} ({} elements searched)", wanted, window_id, read),
+                None
+            ));
+        };
+        let element = bus.leaf(&node, bus.states(&node));
+        let label = element.label();
+        let dry_run = params.dry_run.unwrap_or(self.config.dry_run);
+
+        if params.use_action {
+            let center = element.extents
+                .filter(|extents| extents.width > 0 && extents.height > 0)
+                .map(|extents| (extents.x + extents.width / 2, extents.y + extents.height / 2));
+            let mut clicked = ClickedElement { element, method: "action".to_string(), position: None, dry_run: None };
+            if dry_run {
+                let report = format!("invoke the default action of {}", label);
+                clicked.dry_run = Some(report.clone());
+                return Ok(output::structured(format!("Dry run, would {}", report), &clicked));
+            }
+            self.check_window(&window_id)?;
+            // The action lands where the element is, so region rules still apply
+            if let Some(center) = center {
+                self.check_policy_at("click_element", center, &context).await?;
+            }
+            if !bus.do_action(&node) {
+                return Err(McpError::internal_error(
+                    format!("{} has no default action; click it instead", label),
+                    None
+                ));
+            }
+            return Ok(output::structured(format!("Invoked the default action of {}", label), &clicked));
+        }
+
+        let Some(extents) = element.extents.filter(|extents| extents.width > 0 && extents.height > 0) else {
+            return Err(McpError::internal_error(
+                format!("{} has no position on screen; try use_action", label),
+                None
+            ));
+        };
+        let (x, y) = (extents.x + extents.width / 2, extents.y + extents.height / 2);
+        if !dry_run {
+            self.check_policy_at("click_element", (x, y), &context).await?;
+        }
+        let (px, py) = self.bound_point(x, y, params.dry_run)?;
+        let (x_arg, y_arg, button) = (px.to_string(), py.to_string(), params.button.to_string());
+        let mut clicked = ClickedElement { element, method: "click".to_string(), position: Some((px, py).into()), dry_run: None };
+        clicked.dry_run = self.run_input(&["mousemove", &x_arg, &y_arg, "click", &button], params.dry_run)?;
+        if let Some(command) = &clicked.dry_run {
+            return Ok(output::structured(Self::dry_run_report(command), &clicked));
+        }
+        self.history.lock().unwrap().record(format!("click {}", Self::button_name(params.button)), px, py);
+        Ok(output::structured(
+            format!("Clicked {} {} at ({}, {})", Self::button_name(params.button), label, px, py),
+            &clicked
+        ))
+    }
+
+    #[rmcp::tool(description = "Read the text and caret position (or value, for sliders and spin buttons) of the widget with keyboard focus through AT-SPI, e.g. to check what type_text actually put in a field", annotations(read_only_hint = true), output_schema = output::schema::<FocusedText>())]
+    pub async fn get_focused_text(
+        &self,
+        Parameters(params): Parameters<GetFocusedTextParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let window_id = match params.window_id {
+            Some(id) => id,
+            None => Self::run_xdotool(&["getactivewindow"])?.trim().to_string(),
+        };
+        let bus = Bus::open()?;
+        let frame = Self::window_element(&bus, &window_id)?;
+        let (found, read) = focused(&bus, &frame, params.max_elements.max(1));
+        let Some((node, states)) = found else {
+            return Err(McpError::internal_error(
+                format!("Nothing in window {} has keyboard focus ({} elements searched)", window_id, read),
+                None
+            ));
+        };
+        let element = bus.leaf(&node, states);
+        let label = element.label();
+        let int = |property| match bus.property(&node, "org.a11y.atspi.Text", property) {
+            Some(Token::Int(value)) => Some(value),
+            _ => None,
+        };
+        let text = bus.text(&node);
+        let (character_count, caret_offset) = match text {
+            Some(_) => (int("CharacterCount"), int("CaretOffset")),
+            None => (None, None),
+        };
+        let value = match bus.property(&node, "org.a11y.atspi.Value", "CurrentValue") {
+            Some(Token::Float(value)) => Some(value),
+            Some(Token::Int(value)) => Some(value as f64),
+            _ => None,
+        };
+        let summary = match (&text, value) {
+            (Some(text), _) => match caret_offset {
+                Some(caret) => format!("The focused {} contains \"{}\" (caret at {})", label, text, caret),
+                None => format!("The focused {} contains \"{}\"", label, text),
+            },
+            (None, Some(value)) => format!("The focused {} is at {}", label, value),
+            (None, None) => return Err(McpError::internal_error(
+                format!("The focused {} has no text or value", label),
+                None
+            )),
+        };
+        Ok(output::structured(summary, &FocusedText { element, text, character_count, caret_offset, value }))
+    }
+
+    #[rmcp::tool(description = "Describe a window's interactive UI elements (buttons, fields, check boxes, tabs, items) as JSON from AT-SPI: label, role, clickable rect and center, current value and notable states. A text-only picture of the screen for clients that can't use screenshots", annotations(read_only_hint = true), output_schema = output::schema::<UiSnapshot>())]
+    pub async fn snapshot_ui(
+        &self,
+        Parameters(params): Parameters<SnapshotUiParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let window_id = match params.window_id {
+            Some(id) => id,
+            None => Self::run_xdotool(&["getactivewindow"])?.trim().to_string(),
+        };
+        let bus = Bus::open()?;
+        let frame = Self::window_element(&bus, &window_id)?;
+        let mut walk = Walk {
+            bus: &bus,
+            max_depth: usize::MAX,
+            max_elements: params.max_elements.max(1),
+            showing_only: true,
+            count: 0,
+            truncated: false,
+        };
+        let root = walk.element(&frame, bus.states(&frame), 0);
+        let title = Some(root.name.clone()).filter(|name| !name.is_empty());
+        let mut elements = vec![];
+        interactive(&bus, root, &mut elements);
+        let mut lines: Vec<String> = elements.iter().map(|element| {
+            let mut line = element.role.clone();
+            if !element.label.is_empty() {
+                line.push_str(&format!(" \"{}\"", element.label));
+            }
+            if let (Some(rect), Some(center)) = (element.rect, &element.center) {
+                line.push_str(&format!(" at ({},{}) {}x{}", center.x, center.y, rect.width, rect.height));
+            }
+            if let Some(value) = &element.value {
+                line.push_str(&format!(" = \"{}\"", value));
+            }
+            if !element.states.is_empty() {
+                line.push_str(&format!(" [{}]", element.states.join(", ")));
+            }
+            line
+        }).collect();
+        if elements.is_empty() {
+            lines.push(format!("Window {} has no interactive elements showing ({} read)", window_id, walk.count));
+        }
+        if walk.truncated {
+            lines.push(format!("(stopped after {} elements; raise max_elements for more)", walk.count));
+        }
+        let window = output::Window { id: window_id, name: title };
+        Ok(output::structured(
+            lines.join("\n"),
+            &UiSnapshot { window, elements, searched: walk.count, truncated: walk.truncated }
+        ))
+    }
+
+    #[rmcp::tool(description = "Find every UI element in a window with a given AT-SPI role and/or a name matching a regex, with screen extents and states (enabled, checked, expanded, focused...), e.g. all buttons or text fields of a dialog", annotations(read_only_hint = true), output_schema = output::schema::<FoundElements>())]
+    pub async fn find_elements(
+        &self,
+        Parameters(params): Parameters<FindElementsParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let pattern = params.name_pattern.as_deref()
+            .map(|pattern| Regex::builder()
+                .syntax(syntax::Config::new().case_insensitive(true))
+                .build(pattern)
+                .map_err(|e| McpError::internal_error(format!("Invalid name_pattern '{}': {}", pattern, e), None)))
+            .transpose()?;
+        let window_id = match params.window_id {
+            Some(id) => id,
+            None => Self::run_xdotool(&["getactivewindow"])?.trim().to_string(),
+        };
+        let bus = Bus::open()?;
+        let frame = Self::window_element(&bus, &window_id)?;
+        let limits = (params.max_elements.max(1), params.max_results.max(1));
+        let (elements, searched, truncated) = find_all(&bus, &frame, params.role.as_deref(), pattern.as_ref(), !params.include_hidden, limits);
+        let mut lines = vec![];
+        for element in &elements {
+            element.describe(0, &mut lines);
+        }
+        if elements.is_empty() {
+            lines.push(format!("No matching elements in window {} ({} searched)", window_id, searched));
+        }
+        if truncated {
+            lines.push(format!("(stopped after {} elements; raise max_elements or max_results for more)", searched));
+        }
+        let window = output::Window { id: window_id.clone(), name: Self::window_name(&window_id).ok() };
+        Ok(output::structured(lines.join("\n"), &FoundElements { window, elements, searched, truncated }))
+    }
+
+    #[rmcp::tool(description = "List a window's menu entries from its AT-SPI menu bar as paths like \"File > Export > PDF\", closed menus included, with whether each is enabled or checked. Use with activate_menu", annotations(read_only_hint = true), output_schema = output::schema::<Menus>())]
+    pub async fn list_menus(
+        &self,
+        Parameters(params): Parameters<ListMenusParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let window_id = match params.window_id {
+            Some(id) => id,
+            None => Self::run_xdotool(&["getactivewindow"])?.trim().to_string(),
+        };
+        let bus = Bus::open()?;
+        let menu_bar = Self::menu_bar(&bus, &window_id)?;
+        let mut items = vec![];
+        let truncated = !menu_paths(&bus, &menu_bar, "", params.max_items.max(1), &mut items);
+        let mut lines: Vec<String> = items.iter().map(|item| {
+            let mut line = item.path.clone();
+            if item.submenu {
+                line.push_str(" >");
+            }
+            if !item.enabled {
+                line.push_str(" [disabled]");
+            }
+            if item.checked {
+                line.push_str(" [checked]");
+            }
+            line
+        }).collect();
+        if lines.is_empty() {
+            lines.push(format!("The menu bar of window {} is empty", window_id));
+        }
+        if truncated {
+            lines.push(format!("(cut short after {} entries; raise max_items for more)", items.len()));
+        }
+        let window = output::Window { id: window_id.clone(), name: Self::window_name(&window_id).ok() };
+        Ok(output::structured(lines.join("\n"), &Menus { window, items, truncated }))
+    }
+
+    #[rmcp::tool(description = "Activate a menu item by its path, e.g. \"File > Export > PDF\", through its AT-SPI action, without opening the menus on screen. See list_menus for the paths", annotations(read_only_hint = false, destructive_hint = true), output_schema = output::schema::<ActivatedMenu>())]
+    pub async fn activate_menu(
+        &self,
+        Parameters(params): Parameters<ActivateMenuParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let levels: Vec<&str> = params.path.split('>').map(str::trim).collect();
+        if levels.iter().any(|level| level.is_empty()) {
+            return Err(McpError::invalid_params("path needs a label at every level, e.g. \"File > Save\"", None));
+        }
+        let window_id = match params.window_id {
+            Some(id) => id,
+            None => Self::run_xdotool(&["getactivewindow"])?.trim().to_string(),
+        };
+        self.check_window(&window_id)?;
+        let bus = Bus::open()?;
+        let mut node = Self::menu_bar(&bus, &window_id)?;
+        let mut path = vec![];
+        for level in &levels {
+            let entries = menu_entries(&bus, &node);
+            let Some((entry, _, name)) = entries.iter().find(|(_, _, name)| same_label(name, level)) else {
+                let labels: Vec<&str> = entries.iter().map(|(_, _, name)| name.as_str()).collect();
+                let place = if path.is_empty() {
+                    "the menu bar".to_string()
+                } else {
+                    format!("\"{}\"", path.join(MENU_SEPARATOR))
+                };
+                return Err(McpError::internal_error(
+                    format!("No \"{}\" in {}; it has: {}", level, place, labels.join(", ")),
+                    None
+                ));
+            };
+            node = entry.clone();
+            path.push(name.clone());
+        }
+        let path = path.join(MENU_SEPARATOR);
+        let element = bus.leaf(&node, bus.states(&node));
+        if !element.states.iter().any(|state| state == "enabled" || state == "sensitive") {
+            return Err(McpError::internal_error(format!("\"{}\" is disabled", path), None));
+        }
+        let mut activated = ActivatedMenu { path, element, dry_run: None };
+        if params.dry_run.unwrap_or(self.config.dry_run) {
+            let report = format!("activate \"{}\"", activated.path);
+            activated.dry_run = Some(report.clone());
+            return Ok(output::structured(format!("Dry run, would {}", report), &activated));
+        }
+        if !bus.do_action(&node) {
+            return Err(McpError::internal_error(
+                format!("\"{}\" has no action; open the menus with click_element instead", activated.path),
+                None
+            ));
+        }
+        Ok(output::structured(format!("Activated \"{}\"", activated.path), &activated))
+    }
+
+    #[rmcp::tool(description = "Set an editable widget's whole text through AT-SPI EditableText, replacing (or with empty text, clearing) what's there, instantly and without keystrokes or keyboard layout issues. Targets an element ID, a name and role, or the focused widget", annotations(read_only_hint = false, destructive_hint = true), output_schema = output::schema::<ElementTextSet>())]
+    pub async fn set_element_text(
+        &self,
+        Parameters(params): Parameters<SetElementTextParams>,
+    ) -> Result<CallToolResult, McpError> {
+        self.check_text_length(&params.text)?;
+        let bus = Bus::open()?;
+        let node = Self::target_element(&bus, params.target)?;
+        self.check_element(&bus, &node)?;
+        let element = bus.leaf(&node, bus.states(&node));
+        let label = element.label();
+        if !element.states.iter().any(|state| state == "editable") {
+            return Err(McpError::internal_error(format!("{} isn't editable", label), None));
+        }
+        let mut set = ElementTextSet {
+            element,
+            characters: params.text.chars().count(),
+            text: (!params.secret).then(|| params.text.clone()),
+            verified: false,
+            dry_run: None,
+        };
+        if params.dry_run.unwrap_or(self.config.dry_run) {
+            let report = if params.secret {
+                format!("set the text of {} to {} characters (secret)", label, set.characters)
+            } else {
+                format!("set the text of {} to \"{}\"", label, params.text)
+            };
+            set.dry_run = Some(report.clone());
+            return Ok(output::structured(format!("Dry run, would {}", report), &set));
+        }
+        if !bus.set_text(&node, &params.text) {
+            return Err(McpError::internal_error(
+                format!("{} doesn't support setting its text; use click_element and type_text instead", label),
+                None
+            ));
+        }
+        set.verified = bus.text(&node).as_deref() == Some(params.text.as_str());
+        let note = if set.verified { "" } else { " (it reads back differently)" };
+        let text = if params.secret {
+            format!("Set the text of {} to {} characters (secret){}", label, set.characters, note)
+        } else {
+            format!("Set the text of {} to \"{}\"{}", label, params.text, note)
+        };
+        Ok(output::structured(text, &set))
+    }
+
+    #[rmcp::tool(description = "List the AT-SPI actions a UI element offers (press, toggle, expand or contract...), for invoke_element_action. Targets an element ID, a name and role, or the focused widget", annotations(read_only_hint = true), output_schema = output::schema::<ElementActions>())]
+    pub async fn list_element_actions(
+        &self,
+        Parameters(target): Parameters<ElementTarget>,
+    ) -> Result<CallToolResult, McpError> {
+        let bus = Bus::open()?;
+        let node = Self::target_element(&bus, target)?;
+        let element = bus.leaf(&node, bus.states(&node));
+        let actions = bus.actions(&node);
+        let text = if actions.is_empty() {
+            format!("{} has no actions", element.label())
+        } else {
+            let names: Vec<&str> = actions.iter().map(|action| action.name.as_str()).collect();
+            format!("{} can: {}", element.label(), names.join(", "))
+        };
+        Ok(output::structured(text, &ElementActions { element, actions }))
+    }
+
+    #[rmcp::tool(description = "Invoke an AT-SPI action of a UI element by name (press, toggle, expand or contract...), or its default action, operating controls even when they're off-screen or covered by other windows. See list_element_actions for the names", annotations(read_only_hint = false, destructive_hint = true), output_schema = output::schema::<InvokedAction>())]
+    pub async fn invoke_element_action(
+        &self,
+        Parameters(params): Parameters<InvokeElementActionParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let bus = Bus::open()?;
+        let node = Self::target_element(&bus, params.target)?;
+        self.check_element(&bus, &node)?;
+        let element = bus.leaf(&node, bus.states(&node));
+        let label = element.label();
+        let actions = bus.actions(&node);
+        let action = match &params.action {
+            Some(wanted) => actions.iter().find(|action| action.name.eq_ignore_ascii_case(wanted)),
+            None => actions.first(),
+        };
+        let Some(action) = action.cloned() else {
+            let names: Vec<&str> = actions.iter().map(|action| action.name.as_str()).collect();
+            let message = match &params.action {
+                Some(wanted) if !names.is_empty() => format!("{} has no \"{}\" action; it has: {}", label, wanted, names.join(", ")),
+                _ => format!("{} has no actions", label),
+            };
+            return Err(McpError::internal_error(message, None));
+        };
+        let mut invoked = InvokedAction { element, action, dry_run: None };
+        if params.dry_run.unwrap_or(self.config.dry_run) {
+            let report = format!("invoke \"{}\" on {}", invoked.action.name, label);
+            invoked.dry_run = Some(report.clone());
+            return Ok(output::structured(format!("Dry run, would {}", report), &invoked));
+        }
+        if !bus.invoke(&node, invoked.action.index) {
+            return Err(McpError::internal_error(
+                format!("{} refused \"{}\"", label, invoked.action.name),
+                None
+            ));
+        }
+        Ok(output::structured(format!("Invoked \"{}\" on {}", invoked.action.name, label), &invoked))
+    }
+
+    #[rmcp::tool(description = "Give a UI element keyboard focus, so typing goes to it: asks the toolkit through AT-SPI, and if that doesn't work presses Tab until it's focused. Targets an element ID or a name and role", annotations(read_only_hint = false, destructive_hint = false), output_schema = output::schema::<FocusedElement>())]
+    pub async fn focus_element(
+        &self,
+        Parameters(params): Parameters<FocusElementParams>,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let target = params.target;
+        if target.element.is_none() && target.name.is_none() && target.role.is_none() {
+            return Err(McpError::invalid_params("Give an element ID, or a name, a role or both", None));
+        }
+        let bus = Bus::open()?;
+        let node = Self::target_element(&bus, target)?;
+        let element = bus.leaf(&node, bus.states(&node));
+        let label = element.label();
+        if element.states.iter().any(|state| state == "focused") {
+            let focused = FocusedElement { element, method: "already".to_string(), tabs: 0, dry_run: None };
+            return Ok(output::structured(format!("{} already has focus", label), &focused));
+        }
+        if !element.states.iter().any(|state| state == "focusable") {
+            return Err(McpError::internal_error(format!("{} can't take keyboard focus", label), None));
+        }
+        let mut focused = FocusedElement { element, method: "grab_focus".to_string(), tabs: 0, dry_run: None };
+        if params.dry_run.unwrap_or(self.config.dry_run) {
+            let report = format!("focus {}, pressing Tab up to {} times if asking doesn't work", label, params.max_tabs);
+            focused.dry_run = Some(report.clone());
+            return Ok(output::structured(format!("Dry run, would {}", report), &focused));
+        }
+
+        if bus.grab_focus(&node) {
+            sleep_or_cancel(FOCUS_SETTLE, &context.ct).await?;
+        }
+        if bus.has_state(&node, "focused") {
+            focused.element.states = bus.states(&node);
+            return Ok(output::structured(format!("Focused {}", label), &focused));
+        }
+        focused.method = "tab".to_string();
+        while focused.tabs < params.max_tabs {
+            self.run_input(&["key", "Tab"], Some(false))?;
+            focused.tabs += 1;
+            sleep_or_cancel(FOCUS_SETTLE, &context.ct).await?;
+            if bus.has_state(&node, "focused") {
+                focused.element.states = bus.states(&node);
+                return Ok(output::structured(format!("Focused {} after {} Tab press(es)", label, focused.tabs), &focused));
+            }
+        }
+        Err(McpError::internal_error(
+            format!("{} didn't take focus when asked or after {} Tab press(es)", label, focused.tabs),
+            None
+        ))
+    }
+}
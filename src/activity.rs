@@ -0,0 +1,168 @@
+//! Noticing the user at the keyboard, so the agent doesn't fight them for the cursor.
+//!
+//! Idle time comes from `xprintidle` (the XScreenSaver extension), else
+//! GNOME's `org.gnome.Mutter.IdleMonitor` D-Bus service, which also works on
+//! Wayland. Input the server sends resets the idle time as well, so the user
+//! counts as active only for input noticed after the server's own last
+//! input. With `[user_activity] when_active` set, input tools and batch
+//! steps are refused, or deferred until the user has been idle for
+//! `idle_ms`, while they're active. If no source answers, the user is
+//! assumed to be away.
+
+use crate::control::sleep_or_cancel;
+use crate::{display, output, process, XdotoolServer};
+use rmcp::{model::*, ErrorData as McpError};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::process::Command;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
+
+/// How long after the server's own input the idle time may still be from it
+const OWN_INPUT_SLACK: Duration = Duration::from_millis(100);
+
+/// How often deferred input checks whether the user has gone idle
+const DEFER_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// What input tools do while the user is active
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WhenActive {
+    /// Send input anyway
+    #[default]
+    Ignore,
+    /// Refuse the call with an error
+    Refuse,
+    /// Wait until the user is idle, up to max_defer_secs
+    Defer,
+}
+
+/// The `[user_activity]` config table
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UserActivity {
+    pub when_active: WhenActive,
+    /// Milliseconds without user input before the user counts as idle
+    pub idle_ms: u64,
+    /// Seconds deferred input waits for the user to go idle before it's refused
+    pub max_defer_secs: u64,
+}
+
+impl Default for UserActivity {
+    fn default() -> Self {
+        Self { when_active: WhenActive::Ignore, idle_ms: 2000, max_defer_secs: 30 }
+    }
+}
+
+/// When the server last sent input
+static OWN_INPUT: Mutex<Option<Instant>> = Mutex::new(None);
+
+/// Note that the server just sent input
+pub(crate) fn note_input() {
+    *OWN_INPUT.lock().unwrap() = Some(Instant::now());
+}
+
+/// xprintidle's idle time, in milliseconds
+fn xprintidle() -> Option<Duration> {
+    let output = process::output(&mut display::command("xprintidle")).ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8_lossy(&output.stdout).trim().parse().ok().map(Duration::from_millis)
+}
+
+/// `GetIdletime` of Mutter's core idle monitor
+fn mutter_idle() -> Option<Duration> {
+    let output = process::output(Command::new("dbus-send")
+        .args(["--session", "--print-reply", "--reply-timeout=500", "--dest=org.gnome.Mutter.IdleMonitor"])
+        .args(["/org/gnome/Mutter/IdleMonitor/Core", "org.gnome.Mutter.IdleMonitor.GetIdletime"]))
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    // Reply looks like: method return ... \n   uint64 1234
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    stdout.split_whitespace().skip_while(|word| *word != "uint64").nth(1)?.parse().ok().map(Duration::from_millis)
+}
+
+/// Time since the last input on the display, and which source told
+pub fn idle_time() -> Option<(Duration, &'static str)> {
+    xprintidle().map(|idle| (idle, "xprintidle"))
+        .or_else(|| mutter_idle().map(|idle| (idle, "mutter")))
+}
+
+/// Whether input `idle` ago, within `threshold`, was the user's rather than the server's
+fn active(idle: Duration, threshold: Duration) -> bool {
+    if idle >= threshold {
+        return false;
+    }
+    match *OWN_INPUT.lock().unwrap() {
+        Some(own) => idle + OWN_INPUT_SLACK < own.elapsed(),
+        None => true,
+    }
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct IdleTime {
+    /// Milliseconds since the last keyboard or mouse input, the server's own included
+    pub idle_ms: u64,
+    /// Whether the user sent input within the configured idle_ms, after the server's own last input
+    pub user_active: bool,
+    /// Where the idle time came from: "xprintidle" or "mutter"
+    pub source: String,
+}
+
+impl XdotoolServer {
+    /// Refuse or defer input while the user is active, as configured
+    pub(crate) async fn check_user_idle(&self, cancel: &CancellationToken) -> Result<(), McpError> {
+        let settings = &self.config.user_activity;
+        if settings.when_active == WhenActive::Ignore {
+            return Ok(());
+        }
+        let threshold = Duration::from_millis(settings.idle_ms);
+        let started = Instant::now();
+        let deadline = Duration::from_secs(settings.max_defer_secs);
+        loop {
+            let Some((idle, _)) = idle_time() else {
+                return Ok(());
+            };
+            if !active(idle, threshold) {
+                return Ok(());
+            }
+            if settings.when_active == WhenActive::Refuse {
+                return Err(McpError::internal_error(
+                    format!("The user is using the mouse or keyboard (input {}ms ago), so input was refused. Try again once they stop.", idle.as_millis()),
+                    None
+                ));
+            }
+            if started.elapsed() >= deadline {
+                return Err(McpError::internal_error(
+                    format!("The user kept using the mouse or keyboard for {}s, so input was refused", settings.max_defer_secs),
+                    None
+                ));
+            }
+            sleep_or_cancel(DEFER_POLL_INTERVAL, cancel).await?;
+        }
+    }
+}
+
+#[rmcp::tool_router(router = activity_router, vis = "pub(crate)")]
+impl XdotoolServer {
+    #[rmcp::tool(description = "Get how long the keyboard and mouse have been idle, and whether the user (not this server) is using them, to avoid fighting them for the cursor. Needs xprintidle, or GNOME", annotations(read_only_hint = true), output_schema = output::schema::<IdleTime>())]
+    pub async fn get_idle_time(&self) -> Result<CallToolResult, McpError> {
+        let Some((idle, source)) = idle_time() else {
+            return Err(McpError::internal_error(
+                "Can't tell the idle time: install xprintidle (or run GNOME for its idle monitor)",
+                None
+            ));
+        };
+        let user_active = active(idle, Duration::from_millis(self.config.user_activity.idle_ms));
+        let text = if user_active {
+            format!("Idle for {}ms: the user is using the mouse or keyboard", idle.as_millis())
+        } else {
+            format!("Idle for {}ms", idle.as_millis())
+        };
+        Ok(output::structured(text, &IdleTime { idle_ms: idle.as_millis() as u64, user_active, source: source.to_string() }))
+    }
+}
@@ -0,0 +1,215 @@
+//! Restricting input to windows whose WM_CLASS is on an allowlist.
+//!
+//! With `allowed_window_classes` set, every click is checked against the
+//! window under the cursor and every key press or typed text against the
+//! active window, right before it is sent. An xdotool invocation is split
+//! into its chained commands so a `mousemove ... click` checks the window at
+//! the destination, not where the cursor started. AT-SPI calls that act on
+//! an element never reach xdotool, so they check a window of the element's
+//! application with `check_window` first.
+
+use crate::backend;
+use crate::display;
+use crate::process;
+use crate::script::is_command_name;
+use crate::windowcache;
+use crate::XdotoolServer;
+use rmcp::ErrorData as McpError;
+use tokio_util::sync::CancellationToken;
+
+/// What an xdotool command sends input to
+enum Target<'a> {
+    Pointer,
+    Focus,
+    /// An explicit `--window <id>` option
+    Window(&'a str),
+}
+
+/// The `--window` option of a command, looking only at its leading options
+fn window_option<'a>(command: &[&'a str]) -> Option<&'a str> {
+    let mut i = 1;
+    while let Some(arg) = command.get(i).filter(|arg| arg.starts_with("--")) {
+        match *arg {
+            "--window" => return command.get(i + 1).copied(),
+            "--clearmodifiers" | "--sync" => i += 1,
+            _ => i += 2,
+        }
+    }
+    None
+}
+
+fn target<'a>(command: &[&'a str]) -> Option<Target<'a>> {
+    let pointer = matches!(command[0], "click" | "mousedown" | "mouseup");
+    let keyboard = matches!(command[0], "key" | "keydown" | "keyup" | "type");
+    if !pointer && !keyboard {
+        return None;
+    }
+    match window_option(command) {
+        Some(window) => Some(Target::Window(window)),
+        None if pointer => Some(Target::Pointer),
+        None => Some(Target::Focus),
+    }
+}
+
+/// Where a `type` command starting at `args[start]` ends. Its text runs to the end of the
+/// arguments unless `--terminator` or `--args` ends it early, and chaining resumes after that.
+fn type_end(args: &[&str], start: usize) -> usize {
+    let command = &args[start..];
+    let (options, text) = backend::parse(command, &["--delay", "--window", "--terminator", "--file", "--args"]);
+    let text_start = args.len() - text.len();
+    let option = |name: &str| options.iter().find_map(|(option, value)| match option.strip_prefix(name) {
+        Some("") => Some(*value),
+        Some(joined) => joined.strip_prefix('='),
+        None => None,
+    });
+    if let Some(terminator) = option("--terminator") {
+        if let Some(i) = text.iter().position(|word| *word == terminator) {
+            return text_start + i + 1;
+        }
+    } else if let Some(count) = option("--args").and_then(|count| count.parse::<usize>().ok()) {
+        return text_start.saturating_add(count).min(args.len());
+    }
+    args.len()
+}
+
+/// Split chained xdotool arguments into one slice per command. A `type` command's text is
+/// never read as a command name.
+pub(crate) fn split_commands<'a>(args: &'a [&'a str]) -> Vec<&'a [&'a str]> {
+    let mut commands = vec![];
+    let mut start = 0;
+    let mut i = 1;
+    while i < args.len() {
+        if args[start] == "type" {
+            let end = type_end(args, start);
+            if end >= args.len() {
+                break;
+            }
+            commands.push(&args[start..end]);
+            start = end;
+            i = end + 1;
+            continue;
+        }
+        if is_command_name(args[i]) {
+            commands.push(&args[start..i]);
+            start = i;
+        }
+        i += 1;
+    }
+    if start < args.len() {
+        commands.push(&args[start..]);
+    }
+    commands
+}
+
+/// A window's instance and class names, empty if it has none
+pub(crate) fn window_classes(window_id: &str) -> Result<Vec<String>, McpError> {
+    windowcache::classes(window_id, || backend::windows().window_classes(window_id))
+}
+
+/// Instance and class names from a window's WM_CLASS, empty if it has none
+pub(crate) fn x11_window_classes(window_id: &str) -> Result<Vec<String>, McpError> {
+    let output = process::output(display::command("xprop")
+        .args(["-id", window_id, "WM_CLASS"]))
+        .map_err(|e| process::error("xprop", e))?;
+
+    // Output looks like: WM_CLASS(STRING) = "Navigator", "firefox"
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let values = stdout.split_once('=').map(|(_, values)| values).unwrap_or("");
+    Ok(values.split(',')
+        .map(|value| value.trim().trim_matches('"').to_string())
+        .filter(|value| !value.is_empty())
+        .collect())
+}
+
+impl XdotoolServer {
+    /// Refuse input if the window it would go to isn't on the allowlist
+    fn check_target(&self, target: Target) -> Result<(), McpError> {
+        let window_id = match target {
+            Target::Pointer => Self::window_under_cursor()?,
+            Target::Focus => Self::run_xdotool(&["getactivewindow"])?.trim().to_string(),
+            Target::Window(id) => id.to_string(),
+        };
+        let classes = window_classes(&window_id)?;
+        let allowed = classes.iter().any(|class| {
+            self.config.allowed_window_classes.iter().any(|allowed| allowed.eq_ignore_ascii_case(class))
+        });
+        if allowed {
+            return Ok(());
+        }
+
+        let what = match target {
+            Target::Pointer => "window under the cursor",
+            Target::Focus => "active window",
+            Target::Window(_) => "target window",
+        };
+        let class = if classes.is_empty() { "none".to_string() } else { classes.join(", ") };
+        Err(McpError::internal_error(
+            format!("Input blocked: the {} ({}, WM_CLASS: {}) is not in allowed_window_classes", what, window_id, class),
+            None
+        ))
+    }
+
+    /// Refuse input to `window_id` if the allowlist is set and it isn't on it, for input that doesn't go through xdotool
+    #[cfg(feature = "accessibility")]
+    pub(crate) fn check_window(&self, window_id: &str) -> Result<(), McpError> {
+        if self.config.allowed_window_classes.is_empty() {
+            return Ok(());
+        }
+        self.check_target(Target::Window(window_id))
+    }
+
+    /// Run xdotool input arguments, checking each click and key press against the window allowlist
+    pub(crate) fn run_checked(&self, args: &[&str]) -> Result<String, McpError> {
+        if self.config.allowed_window_classes.is_empty() {
+            return Self::run_xdotool(args);
+        }
+        let mut stdout = String::new();
+        for command in split_commands(args) {
+            if let Some(target) = target(command) {
+                self.check_target(target)?;
+            }
+            stdout.push_str(&Self::run_xdotool(command)?);
+        }
+        Ok(stdout)
+    }
+
+    /// `run_checked` for commands that may run long, killing xdotool if `cancel` fires
+    pub(crate) async fn run_checked_until(&self, args: &[&str], cancel: &CancellationToken) -> Result<String, McpError> {
+        if self.config.allowed_window_classes.is_empty() {
+            return Self::run_xdotool_until(args, cancel).await;
+        }
+        let mut stdout = String::new();
+        for command in split_commands(args) {
+            if let Some(target) = target(command) {
+                self.check_target(target)?;
+            }
+            stdout.push_str(&Self::run_xdotool_until(command, cancel).await?);
+        }
+        Ok(stdout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commands_split_at_command_names() {
+        assert_eq!(split_commands(&["mousemove", "10", "20", "click", "1"]), [&["mousemove", "10", "20"][..], &["click", "1"]]);
+        assert_eq!(split_commands(&["type", "--delay", "12", "click", "exec"]), [&["type", "--delay", "12", "click", "exec"][..]]);
+        assert!(split_commands(&[]).is_empty());
+    }
+
+    #[test]
+    fn type_text_ends_at_its_terminator() {
+        let args = ["type", "--terminator", "END", "x", "END", "exec", "sh"];
+        assert_eq!(split_commands(&args), [&["type", "--terminator", "END", "x", "END"][..], &["exec", "sh"]]);
+        let args = ["type", "--terminator=END", "x", "END", "key", "a"];
+        assert_eq!(split_commands(&args), [&["type", "--terminator=END", "x", "END"][..], &["key", "a"]]);
+        let args = ["type", "--args", "1", "x", "windowkill"];
+        assert_eq!(split_commands(&args), [&["type", "--args", "1", "x"][..], &["windowkill"]]);
+        // No terminator in the text: all of it is text
+        let args = ["type", "--terminator", "END", "x", "exec"];
+        assert_eq!(split_commands(&args), [&args[..]]);
+    }
+}
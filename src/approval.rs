@@ -0,0 +1,93 @@
+//! Human approval, asked through MCP elicitation.
+//!
+//! Calls matched by a `require_approval` rule (see the policy module) and
+//! `rearm` send the client an elicitation request describing what is about to
+//! happen; anything but an accept refuses it. Dry runs don't touch the
+//! desktop, so they skip the prompt.
+
+use crate::{control, XdotoolServer};
+use rmcp::{
+    model::*,
+    service::{RequestContext, RoleServer},
+    ErrorData as McpError,
+};
+use std::collections::BTreeMap;
+
+impl XdotoolServer {
+    /// Whether a call would only report what it does. Only tools that can dry-run (and plugin
+    /// tools, which all can) count; the flag on any other tool doesn't skip a check.
+    pub(crate) fn is_dry_run(&self, request: &CallToolRequestParam) -> bool {
+        let name = request.name.as_ref();
+        if !control::DRY_RUN_TOOLS.contains(&name) && !self.config.plugins.iter().any(|plugin| plugin.name == name) {
+            return false;
+        }
+        request.arguments.as_ref()
+            .and_then(|args| args.get("dry_run"))
+            .and_then(|value| value.as_bool())
+            .unwrap_or(self.config.dry_run)
+    }
+
+    /// Put a yes/no question to the user via elicitation; anything but accept is an error
+    pub(crate) async fn ask_user(
+        what: &str,
+        message: String,
+        context: &RequestContext<RoleServer>,
+    ) -> Result<(), McpError> {
+        if !context.peer.supports_elicitation() {
+            return Err(McpError::internal_error(
+                format!("'{}' requires approval, but the client does not support elicitation", what),
+                None
+            ));
+        }
+
+        let result = context.peer.create_elicitation(CreateElicitationRequestParam {
+            message,
+            requested_schema: ElicitationSchema::new(BTreeMap::new()),
+        }).await.map_err(|e| McpError::internal_error(
+            format!("Approval request for '{}' failed: {}", what, e),
+            None
+        ))?;
+
+        match result.action {
+            ElicitationAction::Accept => Ok(()),
+            ElicitationAction::Decline | ElicitationAction::Cancel => Err(McpError::internal_error(
+                format!("'{}' was not approved", what),
+                None
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call(name: &str, arguments: serde_json::Value) -> CallToolRequestParam {
+        CallToolRequestParam { name: name.to_string().into(), arguments: arguments.as_object().cloned() }
+    }
+
+    #[test]
+    fn only_tools_that_dry_run_are_dry_runs() {
+        let server = XdotoolServer::with_config(crate::config::Config::default());
+        assert!(server.is_dry_run(&call("click", serde_json::json!({"dry_run": true}))));
+        assert!(!server.is_dry_run(&call("click", serde_json::json!({}))));
+        for tool in ["launch_app", "open_uri", "set_clipboard", "copy_selection", "run_in_display"] {
+            assert!(!server.is_dry_run(&call(tool, serde_json::json!({"dry_run": true}))), "{}", tool);
+        }
+
+        let server = XdotoolServer::with_config(crate::config::Config { dry_run: true, ..Default::default() });
+        assert!(server.is_dry_run(&call("type_text", serde_json::json!({"text": "a"}))));
+        assert!(!server.is_dry_run(&call("launch_app", serde_json::json!({"command": "gedit"}))));
+    }
+
+    #[test]
+    fn dry_run_tools_are_the_ones_taking_dry_run() {
+        let server = XdotoolServer::with_config(crate::config::Config::default());
+        for tool in server.tool_router.list_all() {
+            let takes = tool.input_schema.get("properties")
+                .and_then(|properties| properties.get("dry_run"))
+                .is_some();
+            assert_eq!(takes, control::DRY_RUN_TOOLS.contains(&tool.name.as_ref()), "{}", tool.name);
+        }
+    }
+}
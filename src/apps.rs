@@ -0,0 +1,547 @@
+//! Running applications rather than windows.
+//!
+//! `list_apps` groups the visible top-level windows by the app that owns
+//! them: the WM_CLASS class name together with `_NET_WM_PID`, so two running
+//! copies of one program are two apps, and one program's main window and
+//! dialogs are one. Each app comes with its windows, the virtual desktop of
+//! each, and what /proc says about the process (its name and resident
+//! memory), where the process runs on this machine.
+//!
+//! `focus_or_launch` is "go to Firefox": it activates a window whose WM_CLASS
+//! names the app, or if there's none, starts the app from its .desktop entry
+//! (found by file name, Name= or StartupWMClass= in the XDG applications
+//! directories) and activates the window it opens. The entry's Exec line is
+//! run without a shell, and only if its program is in `[launch] programs`.
+//!
+//! `is_window_responding` tells a frozen app from a slow one before clicking
+//! into it. It pings the window with `_NET_WM_PING` (the check window
+//! managers use to offer "Force quit") where the window supports it and the
+//! `ping` feature is built, and otherwise only sees whether the process is
+//! stopped or gone.
+
+use crate::control::sleep_or_cancel;
+use crate::{allowlist, display, launch, output, XdotoolServer};
+use rmcp::{
+    handler::server::wrapper::Parameters,
+    model::*,
+    service::{RequestContext, RoleServer},
+    ErrorData as McpError,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct AppWindow {
+    pub id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Virtual desktop it's on, numbered from 0; -1 for windows on every desktop
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub desktop: Option<i64>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct App {
+    /// WM_CLASS class name, e.g. "firefox"; absent for windows without WM_CLASS
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub class: Option<String>,
+    /// WM_CLASS instance name, e.g. "Navigator", of its first window
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instance: Option<String>,
+    /// From the windows' _NET_WM_PID; absent if they don't set it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pid: Option<u32>,
+    /// The process's name, from /proc
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub process: Option<String>,
+    /// The process's resident memory in KiB, from /proc
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory_kb: Option<u64>,
+    /// Whether it owns the active window
+    pub active: bool,
+    /// In ID order
+    pub windows: Vec<AppWindow>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct Apps {
+    /// By class name, then PID
+    pub apps: Vec<App>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct FocusOrLaunchParams {
+    #[schemars(description = "The app, as its WM_CLASS, .desktop file name or menu name, e.g. \"firefox\" or \"Text Editor\"")]
+    pub app: String,
+    #[schemars(description = "Seconds to wait for the window of an app it had to start. Default: 30")]
+    #[serde(default = "default_launch_timeout")]
+    pub timeout_seconds: u64,
+    #[schemars(description = "Only report what would be activated or started. Default: the server's dry_run setting")]
+    pub dry_run: Option<bool>,
+}
+
+fn default_launch_timeout() -> u64 { 30 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum AppAction {
+    /// A window of it was already open and got activated
+    Activated,
+    /// It was started from its .desktop entry and its new window activated
+    Launched,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct FocusedOrLaunched {
+    pub action: AppAction,
+    /// The window activated; absent in dry runs that would launch
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub window: Option<output::Window>,
+    /// The .desktop file used, when the app was (or would be) started
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub entry: Option<String>,
+    /// The command started, from the entry's Exec line
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub command: Vec<String>,
+    /// PID of the started command
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pid: Option<u32>,
+    /// How long the window took to appear, in milliseconds
+    pub waited_ms: u64,
+    /// What would have been done, in dry runs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dry_run: Option<String>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct IsWindowRespondingParams {
+    #[schemars(description = "Window ID (from search_window or get_active_window)")]
+    pub window_id: String,
+    #[schemars(description = "Milliseconds to wait for the window's reply before calling it unresponsive. Default: 2000")]
+    #[serde(default = "default_ping_timeout")]
+    pub timeout_ms: u64,
+}
+
+fn default_ping_timeout() -> u64 { 2000 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ResponseCheck {
+    /// The window was sent _NET_WM_PING and its reply awaited
+    Ping,
+    /// Only the owning process's state in /proc was read
+    ProcessState,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct WindowResponding {
+    pub window_id: String,
+    /// Absent when neither check could tell
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub responding: Option<bool>,
+    /// How it was told
+    pub check: ResponseCheck,
+    /// How long the ping's reply took, in milliseconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub round_trip_ms: Option<u64>,
+    /// From the window's _NET_WM_PID
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pid: Option<u32>,
+    /// Why it isn't responding, or why it couldn't be pinged
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// A process's name and resident memory in KiB, where /proc has them
+fn process_info(pid: u32) -> (Option<String>, Option<u64>) {
+    let name = std::fs::read_to_string(format!("/proc/{}/comm", pid)).ok()
+        .map(|name| name.trim().to_string());
+    // A line like "VmRSS:	  123456 kB"; kernel threads and zombies have none
+    let memory = std::fs::read_to_string(format!("/proc/{}/status", pid)).ok().and_then(|status| {
+        let line = status.lines().find_map(|line| line.strip_prefix("VmRSS:"))?;
+        line.split_whitespace().next()?.parse().ok()
+    });
+    (name, memory)
+}
+
+/// An application's .desktop entry, the parts focus_or_launch uses
+#[derive(Debug)]
+struct DesktopEntry {
+    path: PathBuf,
+    exec: String,
+    wm_class: Option<String>,
+}
+
+/// Directories holding .desktop entries, most important first, as the XDG base directory spec orders them
+fn application_dirs() -> Vec<PathBuf> {
+    let data_home = std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .unwrap_or_else(|| {
+            let home = std::env::var_os("HOME").map(PathBuf::from).unwrap_or_default();
+            home.join(".local/share")
+        });
+    let data_dirs = std::env::var("XDG_DATA_DIRS").ok()
+        .filter(|dirs| !dirs.is_empty())
+        .unwrap_or_else(|| "/usr/local/share:/usr/share".to_string());
+    std::iter::once(data_home)
+        .chain(data_dirs.split(':').map(PathBuf::from).filter(|path| path.is_absolute()))
+        .map(|dir| dir.join("applications"))
+        .collect()
+}
+
+/// Undo a desktop entry value's escapes: \s, \n, \t, \r and \\
+fn unescape(value: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('s') => unescaped.push(' '),
+            Some('n') => unescaped.push('\n'),
+            Some('t') => unescaped.push('\t'),
+            Some('r') => unescaped.push('\r'),
+            Some('\\') => unescaped.push('\\'),
+            // Others (\" and \; among them) are the Exec quoting's or a list's to undo
+            Some(other) => {
+                unescaped.push('\\');
+                unescaped.push(other);
+            }
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+/// The [Desktop Entry] group of a .desktop file, if it's an application that can be started
+fn parse_entry(path: PathBuf, text: &str) -> Option<(DesktopEntry, Vec<String>)> {
+    let (mut in_group, mut kind, mut exec, mut wm_class, mut hidden) = (false, None, None, None, false);
+    // Everything it answers to, lowercase
+    let mut names = vec![];
+    for line in text.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_group = line == "[Desktop Entry]";
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=').filter(|_| in_group) else {
+            continue;
+        };
+        let value = unescape(value.trim());
+        match key.trim() {
+            "Type" => kind = Some(value),
+            "Exec" => exec = Some(value),
+            "StartupWMClass" => wm_class = Some(value),
+            "Hidden" => hidden = value == "true",
+            // Name= itself, not a translation like Name[de]=
+            "Name" => names.push(value.to_lowercase()),
+            _ => {}
+        }
+    }
+    if hidden || kind.as_deref() != Some("Application") {
+        return None;
+    }
+    let stem = path.file_stem()?.to_str()?.to_lowercase();
+    // org.gnome.gedit answers to gedit too
+    if let Some((_, last)) = stem.rsplit_once('.') {
+        names.push(last.to_string());
+    }
+    names.push(stem);
+    names.extend(wm_class.iter().map(|class| class.to_lowercase()));
+    Some((DesktopEntry { path, exec: exec?, wm_class }, names))
+}
+
+/// The first .desktop entry answering to `app`; entries in earlier directories hide ones of the same name in later ones
+fn find_entry(app: &str) -> Option<DesktopEntry> {
+    let app = app.to_lowercase();
+    let mut seen = HashSet::new();
+    for dir in application_dirs() {
+        let mut files: Vec<PathBuf> = std::fs::read_dir(&dir).into_iter().flatten().flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|extension| extension == "desktop"))
+            .collect();
+        files.sort();
+        for path in files {
+            if !seen.insert(path.file_name().map(|name| name.to_os_string())) {
+                continue;
+            }
+            let Ok(text) = std::fs::read_to_string(&path) else {
+                continue;
+            };
+            if let Some((entry, names)) = parse_entry(path, &text) {
+                if names.contains(&app) {
+                    return Some(entry);
+                }
+            }
+        }
+    }
+    None
+}
+
+/// An Exec line as argv: split on spaces outside double quotes, with the field codes
+/// for files, URLs, the icon and the entry (%f, %U, %i, %k...) dropped, as nothing is being opened
+fn exec_argv(exec: &str) -> Result<Vec<String>, String> {
+    let (mut argv, mut arg, mut in_arg, mut quoted) = (vec![], String::new(), false, false);
+    let mut chars = exec.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                in_arg = true;
+            }
+            '\\' if quoted => match chars.next() {
+                Some(escaped) => arg.push(escaped),
+                None => return Err("ends in a backslash".to_string()),
+            },
+            ' ' | '\t' if !quoted => {
+                if in_arg {
+                    argv.push(std::mem::take(&mut arg));
+                    in_arg = false;
+                }
+            }
+            '%' => {
+                match chars.next() {
+                    Some('%') => arg.push('%'),
+                    Some(_) => {}
+                    None => return Err("ends in a lone %".to_string()),
+                }
+                in_arg = true;
+            }
+            c => {
+                arg.push(c);
+                in_arg = true;
+            }
+        }
+    }
+    if quoted {
+        return Err("has an unclosed quote".to_string());
+    }
+    if in_arg {
+        argv.push(arg);
+    }
+    // An argument that was only a field code expands to nothing
+    argv.retain(|arg| !arg.is_empty());
+    if argv.is_empty() {
+        return Err("names no program".to_string());
+    }
+    Ok(argv)
+}
+
+/// Send the window _NET_WM_PING: the reply's round trip, None without one in `timeout`,
+/// or why it can't be pinged
+#[cfg(feature = "ping")]
+async fn ping(window_id: &str, timeout: Duration) -> Result<Option<Duration>, String> {
+    let window = window_id.parse().map_err(|_| format!("'{}' isn't a window ID", window_id))?;
+    tokio::task::spawn_blocking(move || crate::ping::ping(window, timeout)).await
+        .unwrap_or_else(|e| Err(e.to_string()))
+}
+
+#[cfg(not(feature = "ping"))]
+async fn ping(_window_id: &str, _timeout: Duration) -> Result<Option<Duration>, String> {
+    Err("this build leaves out the 'ping' feature".to_string())
+}
+
+/// Whether a window's WM_CLASS instance or class name is one of `classes` (lowercase)
+fn class_matches(window_id: &str, classes: &[String]) -> bool {
+    allowlist::window_classes(window_id).unwrap_or_default().iter()
+        .any(|class| classes.contains(&class.to_lowercase()))
+}
+
+#[rmcp::tool_router(router = apps_router, vis = "pub(crate)")]
+impl XdotoolServer {
+    #[rmcp::tool(description = "List the running GUI apps instead of windows: visible windows grouped by WM_CLASS and PID, with each app's windows (title, desktop), process name, memory use and whether it's active", annotations(read_only_hint = true), output_schema = output::schema::<Apps>())]
+    pub async fn list_apps(&self) -> Result<CallToolResult, McpError> {
+        let mut window_ids = Self::visible_windows()?;
+        window_ids.sort_by_key(|id| id.parse::<u64>().unwrap_or(u64::MAX));
+        let active = Self::run_xdotool(&["getactivewindow"]).ok().map(|id| id.trim().to_string());
+
+        let mut apps: BTreeMap<(Option<String>, Option<u32>), App> = BTreeMap::new();
+        for id in window_ids {
+            let mut classes = allowlist::window_classes(&id).unwrap_or_default().into_iter();
+            let instance = classes.next();
+            let class = classes.next().or_else(|| instance.clone());
+            let pid = Self::run_xdotool(&["getwindowpid", &id]).ok().and_then(|pid| pid.trim().parse().ok());
+            let desktop = Self::run_xdotool(&["get_desktop_for_window", &id]).ok().and_then(|desktop| desktop.trim().parse().ok());
+            let app = apps.entry((class.clone(), pid)).or_insert_with(|| {
+                let (process, memory_kb) = pid.map(process_info).unwrap_or_default();
+                App { class, instance, pid, process, memory_kb, active: false, windows: vec![] }
+            });
+            app.active |= active.as_deref() == Some(id.as_str());
+            let name = Self::window_name(&id).ok();
+            app.windows.push(AppWindow { id, name, desktop });
+        }
+        let apps: Vec<App> = apps.into_values().collect();
+
+        let text = if apps.is_empty() {
+            "No apps have visible windows".to_string()
+        } else {
+            let lines: Vec<String> = apps.iter().map(|app| {
+                let pid = app.pid.map(|pid| format!("pid {}", pid)).unwrap_or_else(|| "no pid".to_string());
+                let memory = app.memory_kb.map(|kb| format!(", {} MiB", kb / 1024)).unwrap_or_default();
+                let active = if app.active { ", active" } else { "" };
+                let titles: Vec<String> = app.windows.iter()
+                    .map(|window| format!("  {} \"{}\"", window.id, window.name.as_deref().unwrap_or_default()))
+                    .collect();
+                format!(
+                    "{} ({}{}{}), {} window(s):\n{}",
+                    app.class.as_deref().unwrap_or("(no class)"), pid, memory, active, app.windows.len(), titles.join("\n")
+                )
+            }).collect();
+            format!("{} app(s):\n{}", apps.len(), lines.join("\n"))
+        };
+        Ok(output::structured(text, &Apps { apps }))
+    }
+
+    #[rmcp::tool(description = "Go to an app: activate a window of it (matched by WM_CLASS) if one is open, else start it from its .desktop entry and activate its window once it appears. The standard \"switch to Firefox\" step; the entry's program must be in the server's launch programs list", annotations(read_only_hint = false, destructive_hint = false, open_world_hint = true), output_schema = output::schema::<FocusedOrLaunched>())]
+    pub async fn focus_or_launch(
+        &self,
+        Parameters(params): Parameters<FocusOrLaunchParams>,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let app = params.app.trim();
+        if app.is_empty() {
+            return Err(McpError::invalid_params("app can't be empty", None));
+        }
+        let entry = find_entry(app);
+        let mut classes = vec![app.to_lowercase()];
+        classes.extend(entry.as_ref().and_then(|entry| entry.wm_class.as_ref()).map(|class| class.to_lowercase()));
+        let mut result = FocusedOrLaunched {
+            action: AppAction::Activated, window: None, entry: None, command: vec![], pid: None, waited_ms: 0, dry_run: None,
+        };
+
+        let existing = Self::visible_windows()?;
+        let active = Self::run_xdotool(&["getactivewindow"]).ok().map(|id| id.trim().to_string());
+        let matching: Vec<&String> = existing.iter().filter(|id| class_matches(id, &classes)).collect();
+        // Already there if it's the active window, else its first
+        let open = matching.iter().find(|id| active.as_deref() == Some(id.as_str())).or(matching.first());
+        if let Some(&id) = open {
+            result.dry_run = self.run_input(&["windowactivate", "--sync", id], params.dry_run)?;
+            let name = Self::window_name(id).ok();
+            let text = format!("window {} \"{}\" of {}", id, name.as_deref().unwrap_or_default(), app);
+            result.window = Some(output::Window { id: id.clone(), name });
+            if let Some(command) = &result.dry_run {
+                return Ok(output::structured(Self::dry_run_report(command), &result));
+            }
+            return Ok(output::structured(format!("Activated {}", text), &result));
+        }
+
+        let Some(entry) = entry else {
+            return Err(McpError::internal_error(
+                format!("No window of {} is open and no .desktop entry answers to that name", app),
+                None
+            ));
+        };
+        let argv = exec_argv(&entry.exec).map_err(|reason| McpError::internal_error(
+            format!("The Exec line of {} {}", entry.path.display(), reason),
+            None
+        ))?;
+        self.check_launchable(&argv[0])?;
+        result.action = AppAction::Launched;
+        result.entry = Some(entry.path.to_string_lossy().into_owned());
+        result.command = argv;
+        if params.dry_run.unwrap_or(self.config.dry_run) {
+            let report = format!("start {} and activate its window", result.command.join(" "));
+            result.dry_run = Some(report.clone());
+            return Ok(output::structured(format!("Dry run, would {}", report), &result));
+        }
+
+        let started = Instant::now();
+        // Not killed with the call: the app is meant to outlive it
+        let child = display::async_command(&result.command[0])
+            .args(&result.command[1..])
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| McpError::internal_error(format!("Failed to run {}: {}", result.command[0], e), None))?;
+        let pid = child.id().unwrap_or_default();
+        result.pid = Some(pid);
+        tracing::info!("Launched {} (pid {}) from {}", result.command[0], pid, entry.path.display());
+        let mut checked: HashSet<String> = existing.into_iter().collect();
+        let timeout = Duration::from_secs(params.timeout_seconds);
+        loop {
+            sleep_or_cancel(crate::wait::POLL_INTERVAL, &context.ct).await?;
+            let new: Vec<String> = Self::visible_windows()?.into_iter().filter(|id| !checked.contains(id)).collect();
+            if !new.is_empty() {
+                let tree = launch::process_tree(pid);
+                for id in new {
+                    let owner: Option<u32> = Self::run_xdotool(&["getwindowpid", &id]).ok().and_then(|owner| owner.trim().parse().ok());
+                    // Apps that hand off to a copy already running (or to a launcher like flatpak) open it from another PID
+                    if owner.is_some_and(|owner| tree.contains(&owner)) || class_matches(&id, &classes) {
+                        self.run_input(&["windowactivate", "--sync", &id], Some(false))?;
+                        result.waited_ms = started.elapsed().as_millis() as u64;
+                        let name = Self::window_name(&id).ok();
+                        let text = format!(
+                            "Started {} (pid {}) and activated its window {} \"{}\" after {}ms",
+                            app, pid, id, name.as_deref().unwrap_or_default(), result.waited_ms
+                        );
+                        result.window = Some(output::Window { id, name });
+                        return Ok(output::structured(text, &result));
+                    }
+                    if owner.is_some() {
+                        checked.insert(id);
+                    }
+                }
+            }
+            if started.elapsed() >= timeout {
+                return Err(McpError::internal_error(
+                    format!("{} (pid {}) opened no window within {}s", app, pid, params.timeout_seconds),
+                    None
+                ));
+            }
+        }
+    }
+
+    #[rmcp::tool(description = "Check whether a window's app is responding, by sending it _NET_WM_PING and waiting for the reply (or, for windows that don't support that, checking its process isn't stopped). Use before clicking into an app that might be frozen, to wait, skip it or kill it instead", annotations(read_only_hint = true), output_schema = output::schema::<WindowResponding>())]
+    pub async fn is_window_responding(
+        &self,
+        Parameters(params): Parameters<IsWindowRespondingParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let pid: Option<u32> = Self::run_xdotool(&["getwindowpid", &params.window_id]).ok().and_then(|pid| pid.trim().parse().ok());
+        let mut result = WindowResponding {
+            window_id: params.window_id.clone(),
+            responding: None,
+            check: ResponseCheck::ProcessState,
+            round_trip_ms: None,
+            pid,
+            reason: None,
+        };
+        // A stopped or dead process can't answer, however long a ping waits
+        match pid.map(|pid| (pid, launch::state(pid))) {
+            Some((pid, Some(state))) if state == "T" || state == "t" => {
+                result.responding = Some(false);
+                result.reason = Some(format!("its process {} is stopped; signal_window_process with CONT resumes it", pid));
+            }
+            Some((pid, Some(state))) if state == "Z" || state == "X" => {
+                result.responding = Some(false);
+                result.reason = Some(format!("its process {} has exited", pid));
+            }
+            _ => match ping(&params.window_id, Duration::from_millis(params.timeout_ms)).await {
+                Ok(Some(round_trip)) => {
+                    result.check = ResponseCheck::Ping;
+                    result.responding = Some(true);
+                    result.round_trip_ms = Some(round_trip.as_millis() as u64);
+                }
+                Ok(None) => {
+                    result.check = ResponseCheck::Ping;
+                    result.responding = Some(false);
+                    result.reason = Some(format!("no reply to _NET_WM_PING within {}ms", params.timeout_ms));
+                }
+                Err(reason) => result.reason = Some(format!("can't ping it: {}", reason)),
+            },
+        }
+
+        let text = match (result.responding, &result.reason) {
+            (Some(true), _) => format!("Window {} is responding ({}ms)", params.window_id, result.round_trip_ms.unwrap_or_default()),
+            (Some(false), Some(reason)) => format!("Window {} isn't responding: {}", params.window_id, reason),
+            (_, reason) => format!("Can't tell whether window {} is responding: {}", params.window_id, reason.as_deref().unwrap_or_default()),
+        };
+        Ok(output::structured(text, &result))
+    }
+}
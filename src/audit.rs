@@ -0,0 +1,157 @@
+//! Append-only audit log of every tool call.
+//!
+//! With `audit_log` set in the config, each call appends one JSON line with
+//! the time, tool name, arguments, outcome and the window under the cursor
+//! once the call finished. `get_audit_log` and the `audit://recent` resource
+//! read back the latest entries. Typed text is redacted per the
+//! `[redaction]` settings before it is written.
+
+use crate::output;
+use crate::XdotoolServer;
+use rmcp::{handler::server::wrapper::Parameters, model::*, ErrorData as McpError};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const RECENT_URI: &str = "audit://recent";
+
+/// How much of a result's text is kept in an entry
+const RESULT_LIMIT: usize = 500;
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct GetAuditLogParams {
+    #[schemars(description = "How many of the most recent entries to return. Default: 20")]
+    #[serde(default = "default_count")]
+    pub count: usize,
+}
+
+fn default_count() -> usize { 20 }
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct AuditEntries {
+    /// Oldest first, each with timestamp, tool, client, arguments, ok, result and window
+    pub entries: Vec<serde_json::Value>,
+}
+
+#[derive(Debug, Serialize)]
+struct WindowInfo {
+    id: String,
+    name: String,
+}
+
+#[derive(Debug, Serialize)]
+struct AuditEntry<'a> {
+    /// Unix time in seconds
+    timestamp: f64,
+    tool: &'a str,
+    /// Client or transport session that made the call
+    client: String,
+    arguments: &'a Option<JsonObject>,
+    ok: bool,
+    result: String,
+    window: Option<WindowInfo>,
+}
+
+/// Text of a call's outcome, with images and long output elided
+fn summarize(result: &Result<CallToolResult, McpError>) -> String {
+    let text = match result {
+        Ok(result) => result.content.iter()
+            .map(|content| match content.as_text() {
+                Some(text) => text.text.clone(),
+                None => "[non-text content]".to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join("\n"),
+        Err(e) => e.message.to_string(),
+    };
+    match text.char_indices().nth(RESULT_LIMIT) {
+        Some((end, _)) => format!("{}...", &text[..end]),
+        None => text,
+    }
+}
+
+/// The last `count` lines of the log, oldest first
+pub fn read_recent(path: &Path, count: usize) -> Result<Vec<String>, McpError> {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(McpError::internal_error(
+            format!("Failed to read audit log {}: {}", path.display(), e),
+            None
+        )),
+    };
+    let lines: Vec<&str> = text.lines().filter(|line| !line.is_empty()).collect();
+    Ok(lines[lines.len().saturating_sub(count)..].iter().map(|line| line.to_string()).collect())
+}
+
+impl XdotoolServer {
+    /// Append an entry for a finished call, if the audit log is enabled. Failures are only logged.
+    pub(crate) fn audit(&self, tool: &str, arguments: &Option<JsonObject>, result: &Result<CallToolResult, McpError>) {
+        let Some(path) = &self.config.audit_log else {
+            return;
+        };
+        let window = Self::window_under_cursor().ok().map(|id| {
+            let name = Self::window_name(&id).unwrap_or_default();
+            WindowInfo { id, name }
+        });
+        let (arguments, hidden) = self.redactor.arguments(tool, arguments);
+        let mut summary = summarize(result);
+        for text in hidden.iter().filter(|text| !text.is_empty()) {
+            summary = summary.replace(text.as_str(), &self.redactor.show(text, true));
+        }
+        let entry = AuditEntry {
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs_f64(),
+            tool,
+            client: self.client_name(),
+            arguments: &arguments,
+            ok: matches!(result, Ok(result) if result.is_error != Some(true)),
+            result: summary,
+            window,
+        };
+
+        let written = serde_json::to_string(&entry).map_err(std::io::Error::other).and_then(|line| {
+            let mut file = std::fs::OpenOptions::new().create(true).append(true).open(path)?;
+            writeln!(file, "{}", line)
+        });
+        if let Err(e) = written {
+            tracing::warn!("Failed to write audit log {}: {}", path.display(), e);
+        }
+    }
+
+    /// Audit resource, listed only when the log is enabled
+    pub(crate) fn audit_resources(&self) -> Vec<Resource> {
+        if self.config.audit_log.is_none() {
+            return vec![];
+        }
+        let mut resource = RawResource::new(RECENT_URI, "audit-recent.jsonl");
+        resource.description = Some("The 50 most recent audit log entries, as JSON lines".to_string());
+        resource.mime_type = Some("application/jsonl".to_string());
+        vec![resource.no_annotation()]
+    }
+}
+
+#[rmcp::tool_router(router = audit_router, vis = "pub(crate)")]
+impl XdotoolServer {
+    #[rmcp::tool(description = "Read the most recent audit log entries (JSON lines with timestamp, tool, arguments, outcome and the window under the cursor)", annotations(read_only_hint = true), output_schema = output::schema::<AuditEntries>())]
+    pub async fn get_audit_log(
+        &self,
+        Parameters(params): Parameters<GetAuditLogParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let Some(path) = &self.config.audit_log else {
+            return Err(McpError::internal_error(
+                "Audit log is disabled. Set audit_log in the config file to enable it.",
+                None
+            ));
+        };
+        let lines = read_recent(path, params.count)?;
+        let entries = AuditEntries {
+            entries: lines.iter().filter_map(|line| serde_json::from_str(line).ok()).collect(),
+        };
+        if lines.is_empty() {
+            return Ok(output::structured("Audit log is empty", &entries));
+        }
+        Ok(output::structured(lines.join("\n"), &entries))
+    }
+}
@@ -0,0 +1,53 @@
+//! Bearer-token authentication for the network transports.
+//!
+//! A network transport won't start unless `auth_token` (or
+//! `$RMCP_XDOTOOL_AUTH_TOKEN`) or a `[[clients]]` token is configured. The
+//! auth token gives a connection every tool; a client token gives it that
+//! client's scope. HTTP and SSE requests carry the token in an
+//! `Authorization: Bearer` header, and TCP connections send
+//! `Bearer <token>` as their first line. A session stays bound to the token
+//! that opened it.
+
+use crate::XdotoolServer;
+
+/// Compare without returning early, so response timing doesn't reveal how much of a token matched
+pub fn same_token(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// The token in an `Authorization: Bearer ...` header value
+pub fn bearer(header: Option<&str>) -> Option<&str> {
+    let (scheme, token) = header?.trim().split_once(' ')?;
+    scheme.eq_ignore_ascii_case("bearer").then(|| token.trim())
+}
+
+impl XdotoolServer {
+    /// Refuse to serve a network transport without any token to check
+    pub fn check_network_auth(&self) -> anyhow::Result<()> {
+        if self.config.auth_token.as_deref().is_some_and(|token| !token.is_empty()) || !self.clients.is_empty() {
+            return Ok(());
+        }
+        Err(anyhow::anyhow!(
+            "Network transports require authentication: set auth_token in the config, \
+             RMCP_XDOTOOL_AUTH_TOKEN, or [[clients]] tokens"
+        ))
+    }
+
+    /// The handle a token grants: the whole server for the auth token, a client's scope for its token
+    pub(crate) fn authenticate(&self, token: &str) -> Option<Self> {
+        if token.is_empty() {
+            return None;
+        }
+        if self.config.auth_token.as_deref().is_some_and(|expected| same_token(expected, token)) {
+            return Some(self.clone());
+        }
+        let (_, scope) = self.clients.iter().find(|(expected, _)| same_token(expected, token))?;
+        let mut server = self.clone();
+        server.client = Some(scope.clone());
+        Some(server)
+    }
+}
@@ -0,0 +1,517 @@
+//! Backends carrying out the xdotool invocations the tools build.
+//!
+//! Tools describe what to do in xdotool's command language; an
+//! `InputBackend` decides how each invocation actually runs, and a
+//! `WindowBackend` answers window commands (search, activate, move, resize,
+//! desktops, geometry). Both are picked once at startup from
+//! `input_backend`; with "auto" that's ydotool/wtype on a Wayland session,
+//! with the compositor for windows, and libxdo on X11 when the library is
+//! installed, else the xdotool binary for everything. "uinput" sends input
+//! through virtual devices instead, see `uinput`, and "mock" simulates a
+//! desktop, see `mock`.
+//!
+//! A backend turns an invocation into `Step`s, and the steps run in order.
+//! Dry runs render the same steps, so they show what would really run.
+
+use crate::activity;
+#[cfg(feature = "wayland")]
+use crate::compositor;
+use crate::control;
+use crate::display;
+use crate::environment;
+#[cfg(feature = "libxdo")]
+use crate::libxdo::{self, Libxdo};
+use crate::mock::{self, Mock};
+use crate::process;
+use crate::windowcache;
+#[cfg(feature = "uinput")]
+use crate::uinput::{self, Uinput};
+#[cfg(feature = "wayland")]
+use crate::wayland::Wayland;
+use crate::{allowlist, XdotoolServer};
+use rmcp::ErrorData as McpError;
+use serde::Deserialize;
+use std::ffi::OsStr;
+use std::process::Output;
+use std::sync::OnceLock;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+/// The `input_backend` setting
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InputBackendKind {
+    /// Wayland when XDG_SESSION_TYPE says so, else libxdo if it loads, else xdotool
+    #[default]
+    Auto,
+    Xdotool,
+    /// xdotool's library in-process, see `libxdo`
+    Libxdo,
+    Wayland,
+    /// Virtual devices made through /dev/uinput, see `uinput`
+    Uinput,
+    /// A simulated desktop, nothing reaches a display
+    Mock,
+}
+
+/// One thing to run for an invocation
+pub enum Step<'a> {
+    /// A whole invocation for the xdotool binary, chained commands sharing its state
+    Xdotool(&'a [&'a str]),
+    /// Another program, as argv
+    Program(Vec<String>),
+    /// A window command, for the window backend
+    Window(&'a [&'a str]),
+    /// A libxdo call, and the command it does
+    #[cfg(feature = "libxdo")]
+    Libxdo(libxdo::Call, &'a [&'a str]),
+    /// Events for the uinput devices, and the command they do
+    #[cfg(feature = "uinput")]
+    Uinput(uinput::Action, &'a [&'a str]),
+    /// A command for the simulated desktop
+    Mock(&'a [&'a str]),
+}
+
+impl Step<'_> {
+    /// What the step runs, as argv
+    fn argv(&self) -> Vec<String> {
+        match self {
+            Step::Xdotool(args) | Step::Mock(args) => argv("xdotool", args),
+            #[cfg(feature = "libxdo")]
+            Step::Libxdo(_, args) => argv("xdotool", args),
+            #[cfg(feature = "uinput")]
+            Step::Uinput(_, args) => argv("xdotool", args),
+            Step::Program(argv) => argv.clone(),
+            Step::Window(command) => windows().describe(command),
+        }
+    }
+}
+
+/// Sends input, and runs anything in an invocation that isn't a window command
+pub trait InputBackend: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// How to carry out an xdotool invocation
+    fn plan<'a>(&self, args: &'a [&'a str]) -> Result<Vec<Step<'a>>, McpError>;
+}
+
+/// Finds, queries and arranges windows
+pub trait WindowBackend: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Run one window command, with xdotool-style output. A search matching nothing is empty, not an error.
+    fn run(&self, command: &[&str]) -> Result<String, McpError>;
+
+    /// What `run` runs for a command, as argv, for dry-run reports
+    fn describe(&self, command: &[&str]) -> Vec<String>;
+
+    /// Mapped top-level windows
+    fn visible_windows(&self) -> Result<Vec<String>, McpError>;
+
+    /// A window's instance and class names, empty if it has none
+    fn window_classes(&self, window_id: &str) -> Result<Vec<String>, McpError>;
+}
+
+/// The xdotool binary, one process per invocation
+pub struct Xdotool;
+
+impl InputBackend for Xdotool {
+    fn name(&self) -> &'static str {
+        "xdotool"
+    }
+
+    fn plan<'a>(&self, args: &'a [&'a str]) -> Result<Vec<Step<'a>>, McpError> {
+        Ok(vec![Step::Xdotool(args)])
+    }
+}
+
+impl WindowBackend for Xdotool {
+    fn name(&self) -> &'static str {
+        "xdotool"
+    }
+
+    fn run(&self, command: &[&str]) -> Result<String, McpError> {
+        let output = spawn("xdotool", command)?;
+        // xdotool search exits non-zero when nothing matches
+        if command[0] == "search" && !output.status.success() && output.stdout.is_empty() {
+            return Ok(String::new());
+        }
+        program_output("xdotool", output)
+    }
+
+    fn describe(&self, command: &[&str]) -> Vec<String> {
+        argv("xdotool", command)
+    }
+
+    fn visible_windows(&self) -> Result<Vec<String>, McpError> {
+        XdotoolServer::x11_visible_windows()
+    }
+
+    fn window_classes(&self, window_id: &str) -> Result<Vec<String>, McpError> {
+        allowlist::x11_window_classes(window_id)
+    }
+}
+
+/// xdotool commands about windows and desktops, which the window backend answers
+pub fn is_window_command(name: &str) -> bool {
+    name.starts_with("window") || name.starts_with("getwindow") || name.contains("desktop")
+        || matches!(name, "search" | "getactivewindow" | "getdisplaygeometry")
+}
+
+/// xdotool commands that only read, leaving the desktop as it was
+pub(crate) fn is_query(name: &str) -> bool {
+    name.starts_with("get") || name == "search"
+}
+
+/// A command's `--option value` pairs and its remaining arguments
+pub(crate) fn parse<'a>(command: &[&'a str], with_value: &[&str]) -> (Vec<(&'a str, &'a str)>, Vec<&'a str>) {
+    let mut options = vec![];
+    let mut i = 1;
+    while let Some(arg) = command.get(i).filter(|arg| arg.starts_with("--")) {
+        i += 1;
+        if *arg == "--" {
+            break;
+        }
+        if with_value.contains(arg) {
+            options.push((*arg, command.get(i).copied().unwrap_or_default()));
+            i += 1;
+        } else {
+            options.push((*arg, ""));
+        }
+    }
+    (options, command.get(i..).unwrap_or_default().to_vec())
+}
+
+struct Backends {
+    input: Box<dyn InputBackend>,
+    windows: Box<dyn WindowBackend>,
+}
+
+static BACKENDS: OnceLock<Backends> = OnceLock::new();
+
+/// Pick the backends for this process. Later calls have no effect.
+pub fn select(kind: InputBackendKind) {
+    let wayland_session = std::env::var("XDG_SESSION_TYPE").is_ok_and(|session| session.eq_ignore_ascii_case("wayland"));
+    let input: Box<dyn InputBackend> = match kind {
+        InputBackendKind::Auto if wayland_session => wayland(),
+        InputBackendKind::Auto => match libxdo() {
+            Ok(libxdo) => libxdo,
+            Err(e) => {
+                tracing::debug!("Not using libxdo: {}", e);
+                Box::new(Xdotool)
+            }
+        },
+        InputBackendKind::Xdotool => Box::new(Xdotool),
+        InputBackendKind::Libxdo => match libxdo() {
+            Ok(libxdo) => libxdo,
+            Err(e) => {
+                tracing::warn!("Can't use libxdo, running the xdotool binary instead: {}", e);
+                Box::new(Xdotool)
+            }
+        },
+        InputBackendKind::Wayland => wayland(),
+        InputBackendKind::Uinput => match uinput() {
+            Ok(uinput) => uinput,
+            Err(e) => {
+                tracing::warn!("Can't use uinput, running the xdotool binary instead: {}", e);
+                Box::new(Xdotool)
+            }
+        },
+        InputBackendKind::Mock => Box::new(Mock),
+    };
+    // Only Wayland input looks for a compositor; xdotool sees every window on X11
+    let windows = match input.name() {
+        #[cfg(feature = "wayland")]
+        "wayland" => compositor::detect(),
+        #[cfg(feature = "wayland")]
+        "uinput" if wayland_session => compositor::detect(),
+        "mock" => Some(Box::new(Mock) as Box<dyn WindowBackend>),
+        _ => None,
+    };
+    let windows = windows.unwrap_or_else(|| Box::new(Xdotool));
+    tracing::info!("Sending input through {}, window commands through {}", input.name(), windows.name());
+    let _ = BACKENDS.set(Backends { input, windows });
+}
+
+#[cfg(feature = "wayland")]
+fn wayland() -> Box<dyn InputBackend> {
+    Box::new(Wayland)
+}
+
+#[cfg(not(feature = "wayland"))]
+fn wayland() -> Box<dyn InputBackend> {
+    tracing::warn!("Built without the wayland feature, sending input through xdotool");
+    Box::new(Xdotool)
+}
+
+/// The libxdo backend, if the library loads
+#[cfg(feature = "libxdo")]
+fn libxdo() -> Result<Box<dyn InputBackend>, String> {
+    libxdo::load()?;
+    Ok(Box::new(Libxdo))
+}
+
+#[cfg(not(feature = "libxdo"))]
+fn libxdo() -> Result<Box<dyn InputBackend>, String> {
+    Err("built without the libxdo feature".to_string())
+}
+
+/// The uinput backend, if this process may create devices
+#[cfg(feature = "uinput")]
+fn uinput() -> Result<Box<dyn InputBackend>, String> {
+    uinput::check()?;
+    Ok(Box::new(Uinput))
+}
+
+#[cfg(not(feature = "uinput"))]
+fn uinput() -> Result<Box<dyn InputBackend>, String> {
+    Err("built without the uinput feature".to_string())
+}
+
+/// Undo what the backends set up, such as uinput devices. Call once the server has stopped.
+pub fn shutdown() {
+    #[cfg(feature = "uinput")]
+    uinput::teardown();
+}
+
+fn backends() -> &'static Backends {
+    BACKENDS.get_or_init(|| Backends { input: Box::new(Xdotool), windows: Box::new(Xdotool) })
+}
+
+pub fn input() -> &'static dyn InputBackend {
+    backends().input.as_ref()
+}
+
+pub fn windows() -> &'static dyn WindowBackend {
+    backends().windows.as_ref()
+}
+
+pub fn argv(program: &str, args: &[&str]) -> Vec<String> {
+    std::iter::once(program).chain(args.iter().copied()).map(str::to_string).collect()
+}
+
+fn spawn_error(program: &str, e: std::io::Error) -> McpError {
+    if process::killed_after(&e).is_some() || program == "xdotool" {
+        return process::error(program, e);
+    }
+    let hint = match program {
+        "ydotool" => " (install ydotool and start ydotoold)",
+        "wtype" => " (install wtype)",
+        _ => "",
+    };
+    McpError::internal_error(format!("Failed to run {}{}: {}", program, hint, e), None)
+}
+
+/// Longest an xdotool invocation may spend typing and sleeping, on top of the time limit
+const MAX_BUSY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Seconds an xdotool `sleep` command waits, or why it's refused
+fn sleep_seconds(command: &[&str]) -> Result<f64, String> {
+    let secs = command.get(1).ok_or_else(|| "sleep needs a number of seconds".to_string())?;
+    match secs.parse::<f64>() {
+        Ok(value) if value.is_finite() && value >= 0.0 && value <= MAX_BUSY.as_secs_f64() => Ok(value),
+        _ => Err(format!("sleep takes 0 to {} seconds, not '{}'", MAX_BUSY.as_secs(), secs)),
+    }
+}
+
+/// How long a program may run: the time limit, plus the typing and sleeping an xdotool invocation asks for
+fn limit<S: AsRef<OsStr>>(program: &str, args: &[S]) -> Duration {
+    if program != "xdotool" {
+        return process::timeout();
+    }
+    let args: Vec<&str> = args.iter().filter_map(|arg| arg.as_ref().to_str()).collect();
+    let busy: f64 = allowlist::split_commands(&args).iter().map(|command| match command[0] {
+        "type" => {
+            let (options, text) = parse(command, &["--delay", "--window", "--terminator", "--file"]);
+            let delay = options.iter().find(|(option, _)| *option == "--delay")
+                .and_then(|(_, delay)| delay.parse().ok())
+                .unwrap_or(12.0);
+            text.iter().map(|text| text.chars().count() as f64).sum::<f64>() * delay / 1000.0
+        }
+        "sleep" => sleep_seconds(command).unwrap_or(0.0),
+        _ => 0.0,
+    }).sum();
+    // Negative, NaN or unbounded estimates (a --delay of 1e300) get the cap rather than a panic
+    let busy = Duration::try_from_secs_f64(busy.max(0.0)).unwrap_or(MAX_BUSY).min(MAX_BUSY);
+    process::timeout() + busy
+}
+
+/// Refuse an xdotool invocation the installed xdotool can't run, or that sleeps for no sane time, before running it
+fn check_supported<S: AsRef<OsStr>>(program: &str, args: &[S]) -> Result<(), McpError> {
+    if program != "xdotool" {
+        return Ok(());
+    }
+    let args: Vec<&str> = args.iter().filter_map(|arg| arg.as_ref().to_str()).collect();
+    let commands = allowlist::split_commands(&args);
+    for command in commands.iter().filter(|command| command[0] == "sleep") {
+        sleep_seconds(command).map_err(|reason| McpError::invalid_params(reason, None))?;
+    }
+    environment::check_commands(&commands)
+}
+
+fn spawn<S: AsRef<OsStr>>(program: &str, args: &[S]) -> Result<Output, McpError> {
+    check_supported(program, args)?;
+    process::output_within(display::command(program).args(args), limit(program, args))
+        .map_err(|e| spawn_error(program, e))
+}
+
+/// `spawn`, killing the program if `cancel` fires before it exits
+async fn spawn_until<S: AsRef<OsStr>>(program: &str, args: &[S], cancel: &CancellationToken) -> Result<Output, McpError> {
+    check_supported(program, args)?;
+    let mut command = display::async_command(program);
+    command.args(args);
+    tokio::select! {
+        output = process::output_async(&mut command, limit(program, args)) => output.map_err(|e| spawn_error(program, e)),
+        _ = cancel.cancelled() => Err(control::cancelled_error()),
+    }
+}
+
+/// stdout of a finished program, or its stderr as the error if it failed. Any stderr is logged.
+fn program_output(program: &str, output: Output) -> Result<String, McpError> {
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !stderr.trim().is_empty() {
+        tracing::debug!("{} ({}): {}", program, output.status, stderr.trim());
+    }
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    } else {
+        Err(McpError::internal_error(
+            format!("{} error: {}", program, stderr),
+            None
+        ))
+    }
+}
+
+/// Run an xdotool invocation through the backends, returning its stdout
+pub fn run(args: &[&str]) -> Result<String, McpError> {
+    let result = run_steps(args);
+    after_run(args);
+    result
+}
+
+/// `run`, stopping the running step if `cancel` fires
+pub async fn run_until(args: &[&str], cancel: &CancellationToken) -> Result<String, McpError> {
+    let result = run_steps_until(args, cancel).await;
+    after_run(args);
+    result
+}
+
+/// After an invocation, even one that failed partway: drop cached window details if it may have
+/// changed windows, and note any input it sent, which the user's idle time then counts from
+fn after_run(args: &[&str]) {
+    let commands = allowlist::split_commands(args);
+    if commands.iter().any(|command| !is_query(command[0])) {
+        windowcache::invalidate();
+    }
+    if commands.iter().any(|command| is_input(command[0])) {
+        activity::note_input();
+    }
+}
+
+/// xdotool commands that send keyboard or mouse input
+fn is_input(name: &str) -> bool {
+    name.starts_with("mouse") || name.starts_with("key") || matches!(name, "click" | "type")
+}
+
+fn run_steps(args: &[&str]) -> Result<String, McpError> {
+    let mut stdout = String::new();
+    for step in input().plan(args)? {
+        stdout.push_str(&match step {
+            Step::Xdotool(args) => program_output("xdotool", spawn("xdotool", args)?)?,
+            Step::Program(argv) => program_output(&argv[0], spawn(&argv[0], &argv[1..])?)?,
+            Step::Window(command) => windows().run(command)?,
+            #[cfg(feature = "libxdo")]
+            Step::Libxdo(call, _) => libxdo::call(&call)?,
+            #[cfg(feature = "uinput")]
+            Step::Uinput(action, _) => uinput::run(&action)?,
+            Step::Mock(command) => mock::run(command)?,
+        });
+    }
+    Ok(stdout)
+}
+
+async fn run_steps_until(args: &[&str], cancel: &CancellationToken) -> Result<String, McpError> {
+    let mut stdout = String::new();
+    for step in input().plan(args)? {
+        if cancel.is_cancelled() {
+            return Err(control::cancelled_error());
+        }
+        stdout.push_str(&match step {
+            Step::Xdotool(args) => program_output("xdotool", spawn_until("xdotool", args, cancel).await?)?,
+            Step::Program(argv) => program_output(&argv[0], spawn_until(&argv[0], &argv[1..], cancel).await?)?,
+            Step::Window(command) => {
+                // Window commands block too; a cancelled batch stops waiting for one at once,
+                // and the command itself ends within its time limit
+                let command: Vec<String> = command.iter().map(|arg| arg.to_string()).collect();
+                let run = tokio::task::spawn_blocking(move || {
+                    let command: Vec<&str> = command.iter().map(String::as_str).collect();
+                    windows().run(&command)
+                });
+                tokio::select! {
+                    result = run => result.map_err(|e| McpError::internal_error(format!("Window command failed: {}", e), None))??,
+                    _ = cancel.cancelled() => return Err(control::cancelled_error()),
+                }
+            }
+            #[cfg(feature = "libxdo")]
+            Step::Libxdo(call, _) => {
+                // libxdo calls block, so they run off the runtime
+                let call = tokio::task::spawn_blocking(move || libxdo::call(&call));
+                tokio::select! {
+                    result = call => result.map_err(|e| McpError::internal_error(format!("libxdo call failed: {}", e), None))??,
+                    _ = cancel.cancelled() => return Err(control::cancelled_error()),
+                }
+            }
+            #[cfg(feature = "uinput")]
+            Step::Uinput(action, _) => {
+                // Typing sleeps between keys, so it runs off the runtime too
+                let action = tokio::task::spawn_blocking(move || uinput::run(&action));
+                tokio::select! {
+                    result = action => result.map_err(|e| McpError::internal_error(format!("uinput input failed: {}", e), None))??,
+                    _ = cancel.cancelled() => return Err(control::cancelled_error()),
+                }
+            }
+            Step::Mock(command) => mock::run(command)?,
+        });
+    }
+    Ok(stdout)
+}
+
+/// The programs an invocation would run, each as argv
+pub fn describe(args: &[&str]) -> Result<Vec<Vec<String>>, McpError> {
+    Ok(input().plan(args)?.iter().map(Step::argv).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sleep_seconds_refuses_unbounded_values() {
+        assert_eq!(sleep_seconds(&["sleep", "1.5"]), Ok(1.5));
+        for secs in ["inf", "-inf", "NaN", "1e30", "-1", "90000", "soon"] {
+            assert!(sleep_seconds(&["sleep", secs]).is_err(), "{}", secs);
+        }
+        assert!(sleep_seconds(&["sleep"]).is_err());
+    }
+
+    #[test]
+    fn limit_counts_sleeps_and_typing() {
+        assert_eq!(limit("xdotool", &["sleep", "2", "key", "a"]), process::timeout() + Duration::from_secs(2));
+        assert_eq!(limit("xdotool", &["type", "--delay", "100", "abcd"]), process::timeout() + Duration::from_millis(400));
+        assert_eq!(limit("xclip", &["-o"]), process::timeout());
+    }
+
+    #[test]
+    fn limit_caps_absurd_requests_without_panicking() {
+        assert_eq!(limit("xdotool", &["sleep", "inf"]), process::timeout());
+        assert_eq!(limit("xdotool", &["sleep", "1e30"]), process::timeout());
+        assert_eq!(limit("xdotool", &["type", "--delay", "1e300", "abc"]), process::timeout() + MAX_BUSY);
+        assert_eq!(limit("xdotool", &["type", "--delay", "-5", "abc"]), process::timeout());
+    }
+
+    #[test]
+    fn check_supported_rejects_bad_sleeps() {
+        assert!(check_supported("xdotool", &["sleep", "inf"]).is_err());
+        assert!(check_supported("xdotool", &["key", "a", "sleep", "1e30"]).is_err());
+    }
+}
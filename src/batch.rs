@@ -0,0 +1,505 @@
+//! Server-side execution of action sequences.
+//!
+//! A batch is validated up front (every step is turned into its xdotool
+//! arguments before anything runs), then executed step by step with a pause
+//! in between, so one tool call replaces a long chain of round trips.
+//! Wait steps (`wait_for_window`, `wait_for_text`, `wait_for_pixel`, `sleep`)
+//! let a whole "open, wait for it, fill in" flow run as one call, and `repeat`
+//! blocks loop nested steps a fixed number of times or until a condition holds.
+
+use crate::backend;
+use crate::control::{cancelled_error, sleep_or_cancel, ActionGuard, ActionRegistry};
+use crate::output;
+use crate::ratelimit::Cost;
+use crate::wait::{wait_for, Condition, POLL_INTERVAL};
+use crate::XdotoolServer;
+use rmcp::{
+    handler::server::wrapper::Parameters,
+    model::*,
+    service::{RequestContext, RoleServer},
+    ErrorData as McpError,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Step {
+    /// Move the cursor to x,y
+    Move { x: i32, y: i32 },
+    /// Click, optionally moving to x,y first
+    Click {
+        x: Option<i32>,
+        y: Option<i32>,
+        #[serde(default = "crate::default_button")]
+        button: u8,
+        #[serde(default = "default_repeat")]
+        repeat: u32,
+    },
+    /// Press a key or combo such as ctrl+s
+    Key { key: String },
+    /// Type text
+    Type {
+        text: String,
+        #[serde(default = "crate::default_delay")]
+        delay: u32,
+    },
+    /// Scroll the mouse wheel
+    Scroll {
+        direction: String,
+        #[serde(default = "crate::default_clicks")]
+        clicks: u32,
+    },
+    /// Pause for a number of milliseconds
+    #[serde(alias = "sleep")]
+    Wait { ms: u64 },
+    /// Wait until a window matching the query exists
+    WaitForWindow {
+        query: String,
+        #[serde(default = "crate::default_search_type")]
+        search_type: String,
+        #[serde(default = "default_wait_timeout")]
+        timeout_ms: u64,
+    },
+    /// Wait until OCR finds the text on screen or in a region (requires tesseract)
+    WaitForText {
+        text: String,
+        x: Option<i32>,
+        y: Option<i32>,
+        width: Option<u32>,
+        height: Option<u32>,
+        #[serde(default = "default_wait_timeout")]
+        timeout_ms: u64,
+    },
+    /// Wait until the pixel at x,y matches "#rrggbb" within tolerance
+    WaitForPixel {
+        x: i32,
+        y: i32,
+        color: String,
+        #[serde(default)]
+        tolerance: u8,
+        #[serde(default = "default_wait_timeout")]
+        timeout_ms: u64,
+    },
+    /// Run nested steps repeatedly: `count` times, or until the `until` condition holds
+    /// (checked before each pass), whichever comes first
+    Repeat {
+        steps: Vec<Step>,
+        count: Option<u32>,
+        until: Option<Condition>,
+        #[serde(default = "default_max_iterations")]
+        max_iterations: u32,
+    },
+}
+
+fn default_max_iterations() -> u32 { 100 }
+
+fn default_repeat() -> u32 { 1 }
+
+fn default_wait_timeout() -> u64 { 10000 }
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ExecuteBatchParams {
+    #[schemars(description = "Ordered steps. Each has an 'action' (move, click, key, type, scroll, wait/sleep, wait_for_window, wait_for_text, wait_for_pixel, repeat) plus its fields, e.g. {\"action\": \"click\", \"x\": 10, \"y\": 20}")]
+    pub steps: Vec<Step>,
+    #[schemars(description = "Pause between steps in milliseconds. Default: 50")]
+    #[serde(default = "default_step_delay")]
+    pub delay_ms: u64,
+    #[schemars(description = "ID to register the batch under for cancel_action. Default: generated")]
+    pub id: Option<String>,
+    #[schemars(description = "Validate and list the xdotool commands that would run, without running them. Default: the server's dry_run setting")]
+    pub dry_run: Option<bool>,
+}
+
+pub(crate) fn default_step_delay() -> u64 { 50 }
+
+/// What a batch or macro replay ran, or would run
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct BatchRun {
+    /// Steps in the batch; repeat blocks count once
+    pub steps: usize,
+    /// What each step did, with wait times and repeat pass counts
+    pub log: Vec<String>,
+    /// The commands that would run, in dry runs. Steps inside repeat blocks are indented.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dry_run: Option<Vec<String>>,
+}
+
+impl Step {
+    /// Arguments for the xdotool invocation this step performs, or `None` for steps
+    /// handled by the server itself
+    pub fn xdotool_args(&self) -> Result<Option<Vec<String>>, McpError> {
+        let args = match self {
+            Step::Move { x, y } => vec!["mousemove".to_string(), x.to_string(), y.to_string()],
+            Step::Click { x, y, button, repeat } => {
+                let mut args = vec![];
+                match (x, y) {
+                    (Some(x), Some(y)) => args.extend(["mousemove".to_string(), x.to_string(), y.to_string()]),
+                    (None, None) => {}
+                    _ => return Err(McpError::internal_error("click needs both x and y, or neither", None)),
+                }
+                args.extend(["click".to_string(), "--repeat".to_string(), repeat.to_string(), button.to_string()]);
+                args
+            }
+            Step::Key { key } => vec!["key".to_string(), key.clone()],
+            Step::Type { text, delay } => vec!["type".to_string(), "--delay".to_string(), delay.to_string(), text.clone()],
+            Step::Scroll { direction, clicks } => {
+                let button = XdotoolServer::scroll_button(direction)?;
+                vec!["click".to_string(), "--repeat".to_string(), clicks.to_string(), button.to_string()]
+            }
+            Step::Wait { .. } => return Ok(None),
+            Step::Repeat { steps, count, until, .. } => {
+                if count.is_none() && until.is_none() {
+                    return Err(McpError::internal_error("repeat needs a count, an until condition, or both", None));
+                }
+                if let Some(until) = until {
+                    until.validate()?;
+                }
+                plan_steps(steps)?;
+                return Ok(None);
+            }
+            step => {
+                if let Some((condition, _)) = step.condition() {
+                    condition.validate()?;
+                }
+                return Ok(None);
+            }
+        };
+        Ok(Some(args))
+    }
+
+    /// The condition a wait_for_* step polls for, with its timeout
+    pub fn condition(&self) -> Option<(Condition, Duration)> {
+        let (condition, timeout_ms) = match self {
+            Step::WaitForWindow { query, search_type, timeout_ms } => (
+                Condition::WindowExists { query: query.clone(), search_type: search_type.clone() },
+                timeout_ms,
+            ),
+            Step::WaitForText { text, x, y, width, height, timeout_ms } => (
+                Condition::TextVisible { text: text.clone(), x: *x, y: *y, width: *width, height: *height },
+                timeout_ms,
+            ),
+            Step::WaitForPixel { x, y, color, tolerance, timeout_ms } => (
+                Condition::PixelColor { x: *x, y: *y, color: color.clone(), tolerance: *tolerance },
+                timeout_ms,
+            ),
+            _ => return None,
+        };
+        Some((condition, Duration::from_millis(*timeout_ms)))
+    }
+
+    /// Copy of this step with waits sped up (factor > 1) or slowed down (factor < 1)
+    pub fn scaled(&self, speed_factor: f64) -> Step {
+        match self {
+            Step::Wait { ms } => Step::Wait { ms: (*ms as f64 / speed_factor).round() as u64 },
+            step => step.clone(),
+        }
+    }
+
+    /// The step with its coordinates passed through `fit`, including inside repeat blocks
+    pub fn bounded(&self, fit: &impl Fn(i32, i32) -> Result<(i32, i32), McpError>) -> Result<Step, McpError> {
+        Ok(match self {
+            Step::Move { x, y } => {
+                let (x, y) = fit(*x, *y)?;
+                Step::Move { x, y }
+            }
+            Step::Click { x: Some(x), y: Some(y), button, repeat } => {
+                let (x, y) = fit(*x, *y)?;
+                Step::Click { x: Some(x), y: Some(y), button: *button, repeat: *repeat }
+            }
+            Step::Repeat { steps, count, until, max_iterations } => Step::Repeat {
+                steps: steps.iter().map(|step| step.bounded(fit)).collect::<Result<_, _>>()?,
+                count: *count,
+                until: until.clone(),
+                max_iterations: *max_iterations,
+            },
+            step => step.clone(),
+        })
+    }
+
+    /// One-line description for batch reports
+    pub fn describe(&self) -> String {
+        match self {
+            Step::Move { x, y } => format!("move to ({}, {})", x, y),
+            Step::Click { x: Some(x), y: Some(y), button, .. } => {
+                format!("click {} at ({}, {})", XdotoolServer::button_name(*button), x, y)
+            }
+            Step::Click { button, .. } => format!("click {}", XdotoolServer::button_name(*button)),
+            Step::Key { key } => format!("key {}", key),
+            Step::Type { text, .. } => format!("type {} characters", text.chars().count()),
+            Step::Scroll { direction, clicks } => format!("scroll {} {} clicks", direction, clicks),
+            Step::Wait { ms } => format!("wait {}ms", ms),
+            Step::Repeat { steps, count, until, .. } => {
+                let mut description = format!("repeat {} step(s)", steps.len());
+                if let Some(count) = count {
+                    description.push_str(&format!(" {} times", count));
+                }
+                if let Some(until) = until {
+                    description.push_str(&format!(" until {}", until.describe()));
+                }
+                description
+            }
+            step => match step.condition() {
+                Some((condition, timeout)) => format!("wait up to {}ms for {}", timeout.as_millis(), condition.describe()),
+                None => String::new(),
+            },
+        }
+    }
+}
+
+/// Turn every step into its xdotool arguments, failing on the first invalid one
+pub fn plan_steps(steps: &[Step]) -> Result<Vec<Option<Vec<String>>>, McpError> {
+    steps.iter()
+        .enumerate()
+        .map(|(i, step)| step.xdotool_args().map_err(|e| McpError::internal_error(
+            format!("Step {} ({}) is invalid: {}", i + 1, step.describe(), e.message),
+            None
+        )))
+        .collect()
+}
+
+/// Validate steps and describe what each would do, one line per step,
+/// with the exact xdotool invocation for input steps
+pub fn dry_run_report(steps: &[Step]) -> Result<Vec<String>, McpError> {
+    fn render(steps: &[Step], planned: Vec<Option<Vec<String>>>, indent: usize, lines: &mut Vec<String>) {
+        for (i, (step, args)) in steps.iter().zip(planned).enumerate() {
+            let pad = "  ".repeat(indent);
+            match (step, args) {
+                (_, Some(args)) => lines.push(format!("{}{}. {}", pad, i + 1, XdotoolServer::format_invocation(&args))),
+                (Step::Repeat { steps: body, .. }, None) => {
+                    lines.push(format!("{}{}. {}:", pad, i + 1, step.describe()));
+                    // Already validated by the caller's plan_steps
+                    if let Ok(planned) = plan_steps(body) {
+                        render(body, planned, indent + 1, lines);
+                    }
+                }
+                (_, None) => lines.push(format!("{}{}. (server-side) {}", pad, i + 1, step.describe())),
+            }
+        }
+    }
+
+    let planned = plan_steps(steps)?;
+    let mut lines = vec![];
+    render(steps, planned, 1, &mut lines);
+    Ok(lines)
+}
+
+/// Error for a step that failed, listing the ones completed before it
+fn step_error(i: usize, step: &Step, reason: String, log: &[String]) -> McpError {
+    McpError::internal_error(
+        format!("Step {} ({}) failed: {}\nCompleted:\n{}", i + 1, step.describe(), reason, log.join("\n")),
+        None
+    )
+}
+
+/// End of the run of input steps from `start` that can share one xdotool invocation.
+/// `type` takes the rest of an invocation as its text, so it ends a chain.
+fn chain_end(steps: &[Step], planned: &[Option<Vec<String>>], start: usize) -> usize {
+    let mut end = start + 1;
+    while end < steps.len() && planned[end].is_some() && !matches!(steps[end - 1], Step::Type { .. }) {
+        end += 1;
+    }
+    end
+}
+
+/// How a sequence of steps is run
+#[derive(Debug, Clone, Default)]
+pub struct RunOptions {
+    /// Pause between steps
+    pub delay: Duration,
+    /// Abort if the active window's title stops containing this text (case-insensitive)
+    pub expect_window: Option<String>,
+    /// Checked between steps and while sleeping or waiting
+    pub cancel: CancellationToken,
+}
+
+impl XdotoolServer {
+    /// Fail if the active window doesn't look like the one a sequence was made for
+    fn check_focus(expected: &str) -> Result<(), McpError> {
+        let window_id = Self::run_xdotool(&["getactivewindow"])?;
+        let title = Self::window_name(window_id.trim())?;
+        if title.to_lowercase().contains(&expected.to_lowercase()) {
+            Ok(())
+        } else {
+            Err(McpError::internal_error(
+                format!("Focus mismatch: active window is \"{}\", expected one matching \"{}\"", title, expected),
+                None
+            ))
+        }
+    }
+
+    /// Validate and run steps in order.
+    /// Returns a line per completed step; on failure the error names the failed step.
+    ///
+    /// With the xdotool binary as the input backend, consecutive input steps run
+    /// as one chained invocation, with xdotool's own `sleep` for the delay, saving
+    /// a process per step. Pausing then takes effect after the chain; cancelling
+    /// still stops it.
+    pub(crate) async fn run_steps(&self, steps: &[Step], options: &RunOptions) -> Result<Vec<String>, McpError> {
+        let planned = plan_steps(steps)?;
+        // Between steps the focus is checked, which needs each step on its own
+        let chain = backend::input().name() == "xdotool" && options.expect_window.is_none();
+
+        let mut log = vec![];
+        let mut i = 0;
+        while i < steps.len() {
+            let step = &steps[i];
+            let fail = |reason: String, log: &[String]| step_error(i, step, reason, log);
+            if i > 0 && !options.delay.is_zero() {
+                sleep_or_cancel(options.delay, &options.cancel).await.map_err(|e| fail(e.message.to_string(), &log))?;
+            }
+            self.wait_while_paused(&options.cancel).await.map_err(|e| fail(e.message.to_string(), &log))?;
+            if options.cancel.is_cancelled() {
+                return Err(fail(cancelled_error().message.to_string(), &log));
+            }
+            if let Some(expected) = &options.expect_window {
+                Self::check_focus(expected).map_err(|e| fail(e.message.to_string(), &log))?;
+            }
+
+            let mut note = String::new();
+            let mut next = i + 1;
+            match (step, &planned[i]) {
+                (Step::Wait { ms }, _) => {
+                    sleep_or_cancel(Duration::from_millis(*ms), &options.cancel).await
+                        .map_err(|e| fail(e.message.to_string(), &log))?;
+                }
+                (_, Some(args)) => {
+                    if chain {
+                        next = chain_end(steps, &planned, i);
+                    }
+                    let mut invocation = args.clone();
+                    for (k, step) in steps.iter().enumerate().take(next).skip(i) {
+                        self.charge(Cost::for_step(step)).map_err(|e| step_error(k, step, e.message.to_string(), &log))?;
+                        if k > i {
+                            if !options.delay.is_zero() {
+                                invocation.extend(["sleep".to_string(), format!("{:.3}", options.delay.as_secs_f64())]);
+                            }
+                            invocation.extend(planned[k].iter().flatten().cloned());
+                        }
+                    }
+                    self.check_unlocked().map_err(|e| fail(e.message.to_string(), &log))?;
+                    self.check_user_idle(&options.cancel).await.map_err(|e| fail(e.message.to_string(), &log))?;
+                    let args: Vec<&str> = invocation.iter().map(String::as_str).collect();
+                    self.run_checked_until(&args, &options.cancel).await.map_err(|e| match next - i {
+                        1 => fail(e.message.to_string(), &log),
+                        _ => fail(format!("{} (ran chained with steps up to {})", e.message, next), &log),
+                    })?;
+                    steps[i..next].iter().for_each(|step| self.record_step(step));
+                }
+                (Step::Repeat { steps, count, until, max_iterations }, _) => {
+                    let limit = count.unwrap_or(*max_iterations).min(*max_iterations);
+                    let mut passes = 0;
+                    loop {
+                        if let Some(until) = until {
+                            if until.check().map_err(|e| fail(e.message.to_string(), &log))? {
+                                break;
+                            }
+                        }
+                        if passes == limit {
+                            if until.is_some() && count.is_none() {
+                                return Err(fail(format!("condition still false after {} passes", limit), &log));
+                            }
+                            break;
+                        }
+                        Box::pin(self.run_steps(steps, options)).await
+                            .map_err(|e| fail(format!("pass {}: {}", passes + 1, e.message), &log))?;
+                        passes += 1;
+                    }
+                    note = format!(" ({} passes)", passes);
+                }
+                (_, None) => {
+                    if let Some((condition, timeout)) = step.condition() {
+                        let waited = wait_for(&condition, POLL_INTERVAL, timeout, &options.cancel).await.map_err(|e| fail(e.message.to_string(), &log))?;
+                        note = format!(" (after {}ms)", waited.as_millis());
+                    }
+                }
+            }
+            log.push(format!("  {}. {}{}", i + 1, step.describe(), note));
+            for (k, step) in steps.iter().enumerate().take(next).skip(i + 1) {
+                log.push(format!("  {}. {}", k + 1, step.describe()));
+            }
+            i = next;
+        }
+        Ok(log)
+    }
+
+    /// Run a registered action's steps after checking their coordinates against the display,
+    /// releasing held input if it gets cancelled
+    pub(crate) async fn run_cancellable(
+        &self,
+        guard: &ActionGuard,
+        steps: &[Step],
+        options: &RunOptions,
+    ) -> Result<Vec<String>, McpError> {
+        let screen = Self::display_geometry()?;
+        let steps = steps.iter()
+            .enumerate()
+            .map(|(i, step)| step.bounded(&|x, y| self.fit_point(x, y, screen)).map_err(|e| McpError::internal_error(
+                format!("Step {} ({}): {}", i + 1, step.describe(), e.message),
+                None
+            )))
+            .collect::<Result<Vec<_>, _>>()?;
+        let result = self.run_steps(&steps, options).await;
+        if guard.token.is_cancelled() {
+            Self::release_input();
+            if let Err(e) = result {
+                return Err(McpError::internal_error(format!("Action {} cancelled. {}", guard.id, e.message), None));
+            }
+        }
+        result
+    }
+
+    /// Add clicks and typing from a batch to the action history
+    fn record_step(&self, step: &Step) {
+        match step {
+            Step::Click { x: Some(x), y: Some(y), button, .. } => {
+                self.history.lock().unwrap().record(format!("click {}", Self::button_name(*button)), *x, *y);
+            }
+            Step::Click { button, .. } => {
+                self.record_action_here(format!("click {}", Self::button_name(*button)));
+            }
+            Step::Type { .. } => {
+                self.record_action_here("type");
+            }
+            _ => {}
+        }
+    }
+}
+
+#[rmcp::tool_router(router = batch_router, vis = "pub(crate)")]
+impl XdotoolServer {
+    #[rmcp::tool(description = "Run an ordered list of actions (move, click, key, type, scroll, wait, wait_for_window/text/pixel) server-side in one call. All steps are validated before any run; stops at the first failure.", annotations(read_only_hint = false, destructive_hint = true), output_schema = output::schema::<BatchRun>())]
+    pub async fn execute_batch(
+        &self,
+        Parameters(params): Parameters<ExecuteBatchParams>,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        self.check_batch_limits(&params.steps)?;
+        if params.dry_run.unwrap_or(self.config.dry_run) {
+            let lines = dry_run_report(&params.steps)?;
+            return Ok(output::structured(
+                format!("Dry run, {} step(s) valid. Would run:\n{}", params.steps.len(), lines.join("\n")),
+                &BatchRun { steps: params.steps.len(), log: vec![], dry_run: Some(lines) }
+            ));
+        }
+
+        let label = format!("batch of {} step(s)", params.steps.len());
+        let guard = ActionRegistry::register(&self.actions, params.id, label, &context.ct)?;
+        let options = RunOptions {
+            delay: Duration::from_millis(params.delay_ms),
+            cancel: guard.token.clone(),
+            ..Default::default()
+        };
+        let log = self.run_cancellable(&guard, &params.steps, &options).await?;
+        Ok(output::structured(
+            format!("Executed {} step(s):\n{}", log.len(), log.join("\n")),
+            &BatchRun {
+                steps: params.steps.len(),
+                log: log.iter().map(|line| line.trim_start().to_string()).collect(),
+                dry_run: None,
+            }
+        ))
+    }
+}
@@ -0,0 +1,84 @@
+//! Command-line options: which transport to serve and where to listen.
+
+use crate::backend::InputBackendKind;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+const USAGE: &str = "Usage: rmcp-xdotool [--transport stdio|sse|http|tcp] [--bind ADDR:PORT | --host ADDR --port PORT]
+                    [--backend auto|xdotool|libxdo|wayland|uinput|mock]
+
+  --transport  stdio (default), sse (HTTP with Server-Sent Events), http (streamable HTTP)
+               or tcp (newline-delimited JSON-RPC)
+  --bind       Address and port the network transports listen on. Default: 127.0.0.1:8000
+  --host       Just the address to listen on
+  --port       Just the port to listen on
+  --backend    What carries out input and window commands, overriding input_backend
+               in the config. mock simulates a desktop and records what tools do";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Transport {
+    Stdio,
+    Sse,
+    Http,
+    Tcp,
+}
+
+#[derive(Debug)]
+pub struct Args {
+    pub transport: Transport,
+    pub host: IpAddr,
+    pub port: u16,
+    pub backend: Option<InputBackendKind>,
+}
+
+impl Args {
+    /// Parse the process arguments; `--help` prints the usage and exits
+    pub fn parse() -> anyhow::Result<Self> {
+        let mut args = Self {
+            transport: Transport::Stdio,
+            host: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            port: 8000,
+            backend: None,
+        };
+        let mut argv = std::env::args().skip(1);
+        while let Some(flag) = argv.next() {
+            if flag == "--help" || flag == "-h" {
+                println!("{}", USAGE);
+                std::process::exit(0);
+            }
+            let value = argv.next().ok_or_else(|| anyhow::anyhow!("{} needs a value\n\n{}", flag, USAGE))?;
+            match flag.as_str() {
+                "--transport" => args.transport = match value.as_str() {
+                    "stdio" => Transport::Stdio,
+                    "sse" => Transport::Sse,
+                    "http" => Transport::Http,
+                    "tcp" => Transport::Tcp,
+                    _ => return Err(anyhow::anyhow!("Unknown transport '{}'\n\n{}", value, USAGE)),
+                },
+                "--bind" => {
+                    let addr: SocketAddr = value.parse()
+                        .map_err(|_| anyhow::anyhow!("Invalid --bind '{}', expected ADDR:PORT", value))?;
+                    (args.host, args.port) = (addr.ip(), addr.port());
+                }
+                "--host" => args.host = value.parse()
+                    .map_err(|_| anyhow::anyhow!("Invalid --host '{}', expected an IP address", value))?,
+                "--port" => args.port = value.parse()
+                    .map_err(|_| anyhow::anyhow!("Invalid --port '{}'", value))?,
+                "--backend" => args.backend = Some(match value.as_str() {
+                    "auto" => InputBackendKind::Auto,
+                    "xdotool" => InputBackendKind::Xdotool,
+                    "libxdo" => InputBackendKind::Libxdo,
+                    "wayland" => InputBackendKind::Wayland,
+                    "uinput" => InputBackendKind::Uinput,
+                    "mock" => InputBackendKind::Mock,
+                    _ => return Err(anyhow::anyhow!("Unknown backend '{}'\n\n{}", value, USAGE)),
+                }),
+                _ => return Err(anyhow::anyhow!("Unknown option '{}'\n\n{}", flag, USAGE)),
+            }
+        }
+        Ok(args)
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        SocketAddr::new(self.host, self.port)
+    }
+}
@@ -0,0 +1,124 @@
+//! Per-client tool permissions and rate limits, keyed by auth token.
+//!
+//! Each `[[clients]]` entry in the config names a client, its token, the
+//! tools it may call and optionally its own rate limits (on top of the
+//! server-wide ones). A server handle bound to a client only lists and runs
+//! that client's tools, so a review-only client and a full-control client can
+//! share one server and its state. Over stdio the token comes from
+//! `$RMCP_XDOTOOL_TOKEN`; without one the client has full access.
+
+use crate::ratelimit::{Cost, RateLimiter, RateLimits};
+use crate::XdotoolServer;
+use rmcp::ErrorData as McpError;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// One `[[clients]]` config entry
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ClientConfig {
+    pub name: String,
+    pub token: String,
+    /// Tools the client may call. Unset allows every tool.
+    pub tools: Option<Vec<String>>,
+    /// Limits for this client alone
+    pub rate_limits: Option<RateLimits>,
+}
+
+#[derive(Debug)]
+pub struct ClientScope {
+    pub name: String,
+    tools: Option<HashSet<String>>,
+    rate_limiter: Option<Mutex<RateLimiter>>,
+}
+
+impl ClientScope {
+    fn new(config: &ClientConfig) -> Self {
+        ClientScope {
+            name: config.name.clone(),
+            tools: config.tools.as_ref().map(|tools| tools.iter().cloned().collect()),
+            rate_limiter: config.rate_limits.as_ref().map(|limits| Mutex::new(RateLimiter::new(limits))),
+        }
+    }
+
+    pub fn allows(&self, tool: &str) -> bool {
+        self.tools.as_ref().is_none_or(|tools| tools.contains(tool))
+    }
+}
+
+/// Scopes by token, built once so clients sharing a token share its rate limits
+pub fn scopes(clients: &[ClientConfig]) -> HashMap<String, Arc<ClientScope>> {
+    clients.iter()
+        .map(|client| (client.token.clone(), Arc::new(ClientScope::new(client))))
+        .collect()
+}
+
+impl XdotoolServer {
+    /// A handle on the same server limited to the client with `token`
+    pub fn for_client(&self, token: &str) -> Result<Self, McpError> {
+        let Some(scope) = self.clients.get(token) else {
+            return Err(McpError::invalid_request("Unknown client token", None));
+        };
+        let mut server = self.clone();
+        server.client = Some(scope.clone());
+        Ok(server)
+    }
+
+    /// Refuse tools outside the bound client's scope
+    pub(crate) fn check_client_scope(&self, tool: &str) -> Result<(), McpError> {
+        match &self.client {
+            Some(scope) if !scope.allows(tool) => Err(McpError::internal_error(
+                format!("Tool '{}' is not permitted for client '{}'", tool, scope.name),
+                None
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Charge the server-wide limits and the bound client's own. Nothing is recorded
+    /// unless the cost fits in both, so a call refused by one doesn't use up the other.
+    pub(crate) fn charge(&self, cost: Cost) -> Result<(), McpError> {
+        let Some(limiter) = self.client.as_ref().and_then(|scope| scope.rate_limiter.as_ref()) else {
+            return self.rate_limiter.lock().unwrap().charge(cost);
+        };
+        // Always the client's lock first, then the server's
+        let mut client = limiter.lock().unwrap();
+        let mut server = self.rate_limiter.lock().unwrap();
+        let now = Instant::now();
+        client.check(cost, now)?;
+        server.check(cost, now)?;
+        client.record(cost, now);
+        server.record(cost, now);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refused_calls_leave_the_client_budget_alone() {
+        let mut config = crate::config::Config::default();
+        config.rate_limits.clicks_per_second = Some(1);
+        config.clients = vec![ClientConfig {
+            name: "agent".to_string(),
+            token: "secret".to_string(),
+            tools: None,
+            rate_limits: Some(RateLimits { calls_per_second: Some(2), ..Default::default() }),
+        }];
+        let server = XdotoolServer::with_config(config);
+        let client = server.for_client("secret").unwrap();
+        let click = Cost { calls: 1, clicks: 1, ..Default::default() };
+        let call = Cost { calls: 1, ..Default::default() };
+
+        server.charge(click).unwrap();
+        // Over the server's click limit, so none of the client's calls are used up
+        assert!(client.charge(click).is_err());
+        client.charge(call).unwrap();
+        client.charge(call).unwrap();
+        assert!(client.charge(call).is_err());
+    }
+}
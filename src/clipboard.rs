@@ -0,0 +1,282 @@
+//! X selection access via xclip.
+
+use crate::display;
+use crate::output;
+use crate::process;
+use crate::XdotoolServer;
+use base64::Engine;
+use rmcp::{handler::server::wrapper::Parameters, model::*, ErrorData as McpError};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct SetClipboardParams {
+    #[schemars(description = "Text to put on the clipboard")]
+    pub text: String,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct SetPrimaryParams {
+    #[schemars(description = "Text to put in the PRIMARY selection (pasted with middle-click)")]
+    pub text: String,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct SelectionParams {
+    #[schemars(description = "Selection to inspect: 'clipboard' or 'primary'. Default: clipboard")]
+    #[serde(default = "default_selection")]
+    pub selection: String,
+}
+
+fn default_selection() -> String { "clipboard".to_string() }
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct CopySelectionParams {
+    #[schemars(description = "Window to copy from; it is activated first. Default: the active window")]
+    pub window_id: Option<String>,
+    #[schemars(description = "Use ctrl+shift+c instead of ctrl+c, for terminals. Default: false")]
+    #[serde(default)]
+    pub terminal: bool,
+    #[schemars(description = "How long to wait for the clipboard to change, in milliseconds. Default: 2000")]
+    #[serde(default = "default_copy_timeout")]
+    pub timeout_ms: u64,
+}
+
+fn default_copy_timeout() -> u64 { 2000 }
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct SelectionText {
+    /// clipboard or primary
+    pub selection: String,
+    /// The selection's text; absent if it is empty
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ClipboardImage {
+    pub mime_type: String,
+    pub bytes: usize,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct SelectionTargets {
+    /// clipboard or primary
+    pub selection: String,
+    /// What the targets amount to: text, html, image and/or files
+    pub kinds: Vec<String>,
+    /// Every target offered, e.g. UTF8_STRING or image/png
+    pub targets: Vec<String>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct SelectionSet {
+    /// clipboard or primary
+    pub selection: String,
+    pub characters: usize,
+}
+
+/// Placeholder put on the clipboard before copying, so an unchanged clipboard is detectable
+const COPY_SENTINEL: &str = "\u{0}rmcp-xdotool-copy-pending\u{0}";
+
+/// Validate a selection name from tool parameters
+fn selection_name(selection: &str) -> Result<&'static str, McpError> {
+    match selection.to_lowercase().as_str() {
+        "clipboard" => Ok("clipboard"),
+        "primary" => Ok("primary"),
+        _ => Err(McpError::internal_error("Invalid selection. Use: clipboard, primary", None)),
+    }
+}
+
+/// Read a selection ("clipboard", "primary") as raw bytes, optionally for a specific target.
+/// Returns `None` when the selection is empty or doesn't offer that target.
+pub fn read_selection(selection: &str, target: Option<&str>) -> Result<Option<Vec<u8>>, McpError> {
+    let mut command = display::command("xclip");
+    command.args(["-selection", selection, "-o"]);
+    if let Some(target) = target {
+        command.args(["-t", target]);
+    }
+    let output = process::output(&mut command)
+        .map_err(|e| process::error("xclip", e))?;
+
+    if output.status.success() {
+        Ok(Some(output.stdout))
+    } else {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        // xclip reports an unowned selection or missing target as "target ... not available"
+        if stderr.contains("not available") {
+            Ok(None)
+        } else {
+            Err(McpError::internal_error(format!("xclip error: {}", stderr), None))
+        }
+    }
+}
+
+/// Take ownership of a selection with the given contents.
+///
+/// xclip forks a child that keeps serving the selection until something else
+/// claims it, so its output isn't captured (the child would hold the pipes open).
+pub fn write_selection(selection: &str, bytes: &[u8]) -> Result<(), McpError> {
+    let status = process::status_with_stdin(display::command("xclip").args(["-selection", selection, "-i"]), bytes)
+        .map_err(|e| process::error("xclip", e))?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(McpError::internal_error(format!("xclip exited with {}", status), None))
+    }
+}
+
+impl XdotoolServer {
+    /// Press the copy keys and wait for the clipboard to stop holding the sentinel
+    async fn copy_to_clipboard(&self, params: &CopySelectionParams) -> Result<Vec<u8>, McpError> {
+        let combo = if params.terminal { "ctrl+shift+c" } else { "ctrl+c" };
+        let mut args = vec![];
+        if let Some(window_id) = &params.window_id {
+            args.extend(["windowactivate", "--sync", window_id.as_str()]);
+        }
+        args.extend(["key", "--clearmodifiers", combo]);
+        self.run_checked(&args)?;
+
+        let started = Instant::now();
+        let timeout = Duration::from_millis(params.timeout_ms);
+        loop {
+            if let Some(bytes) = read_selection("clipboard", None)? {
+                if bytes != COPY_SENTINEL.as_bytes() {
+                    return Ok(bytes);
+                }
+            }
+            if started.elapsed() >= timeout {
+                return Err(McpError::internal_error(
+                    format!("Clipboard did not change within {}ms after {}. Is anything selected?", params.timeout_ms, combo),
+                    None
+                ));
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    }
+}
+
+#[rmcp::tool_router(router = clipboard_router, vis = "pub(crate)")]
+impl XdotoolServer {
+    #[rmcp::tool(description = "Get the text contents of the clipboard (CLIPBOARD selection, requires xclip)", annotations(read_only_hint = true), output_schema = output::schema::<SelectionText>())]
+    pub async fn get_clipboard(&self) -> Result<CallToolResult, McpError> {
+        let text = read_selection("clipboard", None)?.map(|bytes| String::from_utf8_lossy(&bytes).into_owned());
+        Ok(output::structured(
+            text.clone().unwrap_or_else(|| "Clipboard is empty".to_string()),
+            &SelectionText { selection: "clipboard".to_string(), text }
+        ))
+    }
+
+    #[rmcp::tool(description = "Get an image from the clipboard (image/png target) as image content, e.g. a screenshot the user copied", annotations(read_only_hint = true), output_schema = output::schema::<ClipboardImage>())]
+    pub async fn get_clipboard_image(&self) -> Result<CallToolResult, McpError> {
+        match read_selection("clipboard", Some("image/png"))? {
+            Some(png) if !png.is_empty() => Ok(output::with_content(
+                vec![
+                    Content::text(format!("Clipboard image ({} bytes)", png.len())),
+                    Content::image(base64::engine::general_purpose::STANDARD.encode(&png), "image/png"),
+                ],
+                &ClipboardImage { mime_type: "image/png".to_string(), bytes: png.len() }
+            )),
+            _ => Err(McpError::internal_error("Clipboard does not contain an image/png", None)),
+        }
+    }
+
+    #[rmcp::tool(description = "List the targets (MIME types) the clipboard or PRIMARY selection currently offers, to tell text, HTML, images and file URIs apart before reading", annotations(read_only_hint = true), output_schema = output::schema::<SelectionTargets>())]
+    pub async fn get_clipboard_targets(
+        &self,
+        Parameters(params): Parameters<SelectionParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let selection = selection_name(&params.selection)?;
+        let Some(bytes) = read_selection(selection, Some("TARGETS"))? else {
+            return Ok(output::structured(
+                format!("{} selection is empty", selection),
+                &SelectionTargets { selection: selection.to_string(), kinds: vec![], targets: vec![] }
+            ));
+        };
+
+        let listing = String::from_utf8_lossy(&bytes);
+        let targets: Vec<&str> = listing.lines().map(str::trim).filter(|t| !t.is_empty()).collect();
+
+        let mut kinds = vec![];
+        if targets.iter().any(|t| *t == "UTF8_STRING" || *t == "STRING" || t.starts_with("text/plain")) {
+            kinds.push("text");
+        }
+        if targets.contains(&"text/html") {
+            kinds.push("html");
+        }
+        if targets.iter().any(|t| t.starts_with("image/")) {
+            kinds.push("image");
+        }
+        if targets.contains(&"text/uri-list") || targets.contains(&"x-special/gnome-copied-files") {
+            kinds.push("files");
+        }
+        let summary = if kinds.is_empty() { "unknown".to_string() } else { kinds.join(", ") };
+
+        Ok(output::structured(
+            format!("{} holds: {}\nTargets:\n{}", selection, summary, targets.join("\n")),
+            &SelectionTargets {
+                selection: selection.to_string(),
+                kinds: kinds.into_iter().map(str::to_string).collect(),
+                targets: targets.into_iter().map(str::to_string).collect(),
+            }
+        ))
+    }
+
+    #[rmcp::tool(description = "Copy the current selection in a window (ctrl+c, or ctrl+shift+c for terminals), wait for the clipboard to update, and return its text", annotations(read_only_hint = false, destructive_hint = true), output_schema = output::schema::<SelectionText>())]
+    pub async fn copy_selection(
+        &self,
+        Parameters(params): Parameters<CopySelectionParams>,
+    ) -> Result<CallToolResult, McpError> {
+        // Text only: an image or file list on the clipboard comes back as its text, if any
+        let previous = read_selection("clipboard", None)?;
+        write_selection("clipboard", COPY_SENTINEL.as_bytes())?;
+        let copied = self.copy_to_clipboard(&params).await;
+        if copied.is_err() && read_selection("clipboard", None).ok().flatten().as_deref() == Some(COPY_SENTINEL.as_bytes()) {
+            if let Err(e) = write_selection("clipboard", &previous.unwrap_or_default()) {
+                tracing::warn!("Couldn't restore the clipboard after a failed copy: {}", e.message);
+            }
+        }
+        let text = String::from_utf8_lossy(&copied?).into_owned();
+        Ok(output::structured(
+            text.clone(),
+            &SelectionText { selection: "clipboard".to_string(), text: Some(text) }
+        ))
+    }
+
+    #[rmcp::tool(description = "Set the clipboard (CLIPBOARD selection) to the given text, e.g. to paste it with ctrl+v or hand it to the user", annotations(read_only_hint = false, destructive_hint = true), output_schema = output::schema::<SelectionSet>())]
+    pub async fn set_clipboard(
+        &self,
+        Parameters(params): Parameters<SetClipboardParams>,
+    ) -> Result<CallToolResult, McpError> {
+        write_selection("clipboard", params.text.as_bytes())?;
+        let characters = params.text.chars().count();
+        Ok(output::structured(
+            format!("Clipboard set ({} characters)", characters),
+            &SelectionSet { selection: "clipboard".to_string(), characters }
+        ))
+    }
+
+    #[rmcp::tool(description = "Get the PRIMARY selection text: whatever is currently highlighted, pasted with middle-click. Separate from the clipboard", annotations(read_only_hint = true), output_schema = output::schema::<SelectionText>())]
+    pub async fn get_primary_selection(&self) -> Result<CallToolResult, McpError> {
+        let text = read_selection("primary", None)?.map(|bytes| String::from_utf8_lossy(&bytes).into_owned());
+        Ok(output::structured(
+            text.clone().unwrap_or_else(|| "PRIMARY selection is empty".to_string()),
+            &SelectionText { selection: "primary".to_string(), text }
+        ))
+    }
+
+    #[rmcp::tool(description = "Set the PRIMARY selection (middle-click paste, shift+Insert in many terminals). Does not touch the clipboard", annotations(read_only_hint = false, destructive_hint = true), output_schema = output::schema::<SelectionSet>())]
+    pub async fn set_primary_selection(
+        &self,
+        Parameters(params): Parameters<SetPrimaryParams>,
+    ) -> Result<CallToolResult, McpError> {
+        write_selection("primary", params.text.as_bytes())?;
+        let characters = params.text.chars().count();
+        Ok(output::structured(
+            format!("PRIMARY selection set ({} characters)", characters),
+            &SelectionSet { selection: "primary".to_string(), characters }
+        ))
+    }
+}
@@ -0,0 +1,299 @@
+//! Window management on Wayland through the compositor.
+//!
+//! xdotool's window commands only see XWayland windows, and on a Wayland
+//! session they fail silently for everything else. When input goes through
+//! the Wayland backend, window commands (search, activate, move, resize,
+//! desktops, geometry and title queries) go to the compositor instead:
+//!
+//! - Sway and other compositors speaking its IPC (SWAYSOCK set), through
+//!   `swaymsg`. Window IDs are sway container IDs and desktops are
+//!   workspaces, desktop 0 being workspace 1. wlroots compositors without it
+//!   only offer wlrctl, whose toplevels have no IDs or geometry, so they
+//!   aren't supported.
+//! - KDE Plasma (XDG_CURRENT_DESKTOP contains KDE), through `kdotool`, which
+//!   runs KWin scripts over D-Bus and takes xdotool's own command syntax.
+//!
+//! Output is shaped like xdotool's, so the tools parse it the same way.
+
+use crate::backend::{argv, WindowBackend};
+use crate::process;
+use regex_automata::{meta::Regex, util::syntax};
+use rmcp::ErrorData as McpError;
+use serde_json::Value;
+use std::process::Command;
+
+/// The window backend for this session's compositor, if it's one we know
+pub fn detect() -> Option<Box<dyn WindowBackend>> {
+    if std::env::var_os("SWAYSOCK").is_some() {
+        Some(Box::new(Sway))
+    } else if std::env::var("XDG_CURRENT_DESKTOP").is_ok_and(|desktop| desktop.to_uppercase().contains("KDE")) {
+        Some(Box::new(Kwin))
+    } else {
+        tracing::warn!("No supported compositor found; window commands only see XWayland windows");
+        None
+    }
+}
+
+fn output_of(program: &str, args: &[&str]) -> Result<String, McpError> {
+    let output = process::output(Command::new(program)
+        .args(args))
+        .map_err(|e| process::error(program, e))?;
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    } else {
+        Err(McpError::internal_error(
+            format!("{} error: {}{}", program, String::from_utf8_lossy(&output.stderr), String::from_utf8_lossy(&output.stdout)),
+            None
+        ))
+    }
+}
+
+fn kdotool(command: &[&str]) -> Result<String, McpError> {
+    let output = process::output(Command::new("kdotool")
+        .args(command))
+        .map_err(|e| match process::killed_after(&e) {
+            Some(_) => process::error("kdotool", e),
+            None => McpError::internal_error(format!("Failed to run kdotool (install it for window commands on KDE): {}", e), None),
+        })?;
+    // Like xdotool, search fails when nothing matches
+    if output.status.success() || (command[0] == "search" && output.stdout.is_empty()) {
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    } else {
+        Err(McpError::internal_error(
+            format!("kdotool error: {}", String::from_utf8_lossy(&output.stderr)),
+            None
+        ))
+    }
+}
+
+/// A window in sway's tree, with the workspace it's on
+struct SwayWindow<'a> {
+    node: &'a Value,
+    workspace: Option<i64>,
+}
+
+impl SwayWindow<'_> {
+    fn id(&self) -> String {
+        self.node["id"].to_string()
+    }
+
+    fn str(&self, key: &str) -> &str {
+        self.node[key].as_str().unwrap_or_default()
+    }
+
+    /// app_id for Wayland windows, WM_CLASS instance and class for XWayland ones
+    fn classes(&self) -> Vec<&str> {
+        let properties = &self.node["window_properties"];
+        [&self.node["app_id"], &properties["instance"], &properties["class"]].into_iter()
+            .filter_map(Value::as_str)
+            .collect()
+    }
+
+    fn rect(&self, key: &str) -> i64 {
+        self.node["rect"][key].as_i64().unwrap_or(0)
+    }
+}
+
+fn sway_json(kind: &str) -> Result<Value, McpError> {
+    let stdout = output_of("swaymsg", &["-r", "-t", kind])?;
+    serde_json::from_str(&stdout).map_err(|e| McpError::internal_error(
+        format!("Unexpected swaymsg -t {} output: {}", kind, e),
+        None
+    ))
+}
+
+/// Every window in the tree, in tree order
+fn sway_windows(tree: &Value) -> Vec<SwayWindow<'_>> {
+    fn walk<'a>(node: &'a Value, workspace: Option<i64>, windows: &mut Vec<SwayWindow<'a>>) {
+        let workspace = match node["type"].as_str() {
+            Some("workspace") => node["num"].as_i64(),
+            _ => workspace,
+        };
+        if node.get("pid").is_some() {
+            windows.push(SwayWindow { node, workspace });
+        }
+        for child in ["nodes", "floating_nodes"].iter().filter_map(|key| node[*key].as_array()).flatten() {
+            walk(child, workspace, windows);
+        }
+    }
+    let mut windows = vec![];
+    walk(tree, None, &mut windows);
+    windows
+}
+
+/// The arguments after a command's leading `--options`, skipping the values of those that take one
+fn positional<'a>(command: &[&'a str]) -> Vec<&'a str> {
+    let mut i = 1;
+    while let Some(arg) = command.get(i).filter(|arg| arg.starts_with("--")) {
+        i += if matches!(*arg, "--limit" | "--maxdepth" | "--desktop" | "--screen" | "--pid") { 2 } else { 1 };
+    }
+    command.get(i..).unwrap_or_default().to_vec()
+}
+
+fn missing(command: &[&str]) -> McpError {
+    McpError::internal_error(format!("{}: missing arguments", command[0]), None)
+}
+
+/// Workspace number for an xdotool desktop number
+fn workspace(desktop: &str) -> Result<i64, McpError> {
+    desktop.parse::<i64>().map(|desktop| desktop + 1).map_err(|_| McpError::internal_error(
+        format!("Invalid desktop '{}'", desktop),
+        None
+    ))
+}
+
+/// The sway command changing something for an xdotool command, or None for queries
+fn sway_command(command: &[&str]) -> Result<Option<String>, McpError> {
+    let args = positional(command);
+    let window = |i: usize| args.get(i).copied().ok_or_else(|| missing(command));
+    let sway = match command[0] {
+        "windowactivate" | "windowfocus" | "windowraise" => format!("[con_id={}] focus", window(0)?),
+        "windowminimize" => format!("[con_id={}] move scratchpad", window(0)?),
+        "windowmove" => format!("[con_id={}] move absolute position {} {}", window(0)?, window(1)?, window(2)?),
+        "windowsize" => format!("[con_id={}] resize set {} {}", window(0)?, window(1)?, window(2)?),
+        "set_desktop" => format!("workspace number {}", workspace(window(0)?)?),
+        "set_desktop_for_window" => format!("[con_id={}] move container to workspace number {}", window(0)?, workspace(window(1)?)?),
+        _ => return Ok(None),
+    };
+    Ok(Some(sway))
+}
+
+fn sway(command: &[&str]) -> Result<String, McpError> {
+    if let Some(sway) = sway_command(command)? {
+        output_of("swaymsg", &[&sway])?;
+        return Ok(String::new());
+    }
+    let args = positional(command);
+    let tree = sway_json("get_tree")?;
+    let windows = sway_windows(&tree);
+    let find = |id: Option<&&str>| {
+        let id = id.ok_or_else(|| missing(command))?;
+        windows.iter().find(|window| window.id() == *id).ok_or_else(|| McpError::internal_error(
+            format!("No window {} in sway", id),
+            None
+        ))
+    };
+    match command[0] {
+        "search" => {
+            let pattern = args.first().copied().unwrap_or_default();
+            let regex = Regex::builder()
+                .syntax(syntax::Config::new().case_insensitive(true))
+                .build(pattern)
+                .map_err(|e| McpError::internal_error(format!("Invalid search pattern '{}': {}", pattern, e), None))?;
+            let only_visible = command.contains(&"--onlyvisible");
+            let (name, class) = match (command.contains(&"--name"), command.contains(&"--class") || command.contains(&"--classname")) {
+                (false, false) => (true, true),
+                flags => flags,
+            };
+            Ok(windows.iter()
+                .filter(|window| !only_visible || window.node["visible"].as_bool() == Some(true))
+                .filter(|window| {
+                    (name && regex.is_match(window.str("name"))) || (class && window.classes().iter().any(|c| regex.is_match(c)))
+                })
+                .map(|window| format!("{}\n", window.id()))
+                .collect())
+        }
+        "getactivewindow" | "getwindowfocus" => windows.iter()
+            .find(|window| window.node["focused"].as_bool() == Some(true))
+            .map(|window| format!("{}\n", window.id()))
+            .ok_or_else(|| McpError::internal_error("No window has focus", None)),
+        "getwindowname" => Ok(format!("{}\n", find(args.first())?.str("name"))),
+        "getwindowclassname" => Ok(format!("{}\n", find(args.first())?.classes().first().copied().unwrap_or_default())),
+        "getwindowpid" => Ok(format!("{}\n", find(args.first())?.node["pid"])),
+        "getwindowgeometry" => {
+            let window = find(args.first())?;
+            Ok(format!(
+                "WINDOW={}\nX={}\nY={}\nWIDTH={}\nHEIGHT={}\nSCREEN=0\n",
+                window.id(), window.rect("x"), window.rect("y"), window.rect("width"), window.rect("height")
+            ))
+        }
+        "get_desktop_for_window" => Ok(format!("{}\n", find(args.first())?.workspace.unwrap_or(0) - 1)),
+        "get_desktop" | "get_num_desktops" => {
+            let workspaces = sway_json("get_workspaces")?;
+            let workspaces = workspaces.as_array().cloned().unwrap_or_default();
+            if command[0] == "get_num_desktops" {
+                return Ok(format!("{}\n", workspaces.len()));
+            }
+            let focused = workspaces.iter()
+                .find(|workspace| workspace["focused"].as_bool() == Some(true))
+                .and_then(|workspace| workspace["num"].as_i64())
+                .unwrap_or(1);
+            Ok(format!("{}\n", focused - 1))
+        }
+        "getdisplaygeometry" => {
+            let outputs = sway_json("get_outputs")?;
+            let (width, height) = outputs.as_array().into_iter().flatten()
+                .filter(|output| output["active"].as_bool() == Some(true))
+                .fold((0, 0), |(width, height), output| {
+                    let rect = &output["rect"];
+                    let right = rect["x"].as_i64().unwrap_or(0) + rect["width"].as_i64().unwrap_or(0);
+                    let bottom = rect["y"].as_i64().unwrap_or(0) + rect["height"].as_i64().unwrap_or(0);
+                    (width.max(right), height.max(bottom))
+                });
+            Ok(format!("{} {}\n", width, height))
+        }
+        _ => Err(McpError::internal_error(
+            format!("'{}' is not supported on sway", command[0]),
+            None
+        )),
+    }
+}
+
+/// Sway's IPC, through swaymsg
+pub struct Sway;
+
+impl WindowBackend for Sway {
+    fn name(&self) -> &'static str {
+        "sway"
+    }
+
+    fn run(&self, command: &[&str]) -> Result<String, McpError> {
+        sway(command)
+    }
+
+    fn describe(&self, command: &[&str]) -> Vec<String> {
+        match sway_command(command) {
+            Ok(Some(sway)) => vec!["swaymsg".to_string(), sway],
+            _ => argv("swaymsg", &["-t", "get_tree"]),
+        }
+    }
+
+    fn visible_windows(&self) -> Result<Vec<String>, McpError> {
+        Ok(sway(&["search", "--onlyvisible", "."])?.lines().map(str::to_string).collect())
+    }
+
+    fn window_classes(&self, window_id: &str) -> Result<Vec<String>, McpError> {
+        let tree = sway_json("get_tree")?;
+        let classes = sway_windows(&tree).iter()
+            .find(|window| window.id() == window_id)
+            .map(|window| window.classes().into_iter().map(str::to_string).collect())
+            .unwrap_or_default();
+        Ok(classes)
+    }
+}
+
+/// KWin scripting over D-Bus, through kdotool
+pub struct Kwin;
+
+impl WindowBackend for Kwin {
+    fn name(&self) -> &'static str {
+        "kdotool"
+    }
+
+    fn run(&self, command: &[&str]) -> Result<String, McpError> {
+        kdotool(command)
+    }
+
+    fn describe(&self, command: &[&str]) -> Vec<String> {
+        argv("kdotool", command)
+    }
+
+    fn visible_windows(&self) -> Result<Vec<String>, McpError> {
+        Ok(kdotool(&["search", "--onlyvisible", "."])?.lines().map(str::to_string).collect())
+    }
+
+    fn window_classes(&self, window_id: &str) -> Result<Vec<String>, McpError> {
+        let class = kdotool(&["getwindowclassname", window_id])?;
+        Ok(vec![class.trim().to_string()].into_iter().filter(|class| !class.is_empty()).collect())
+    }
+}
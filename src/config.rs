@@ -0,0 +1,189 @@
+//! Server configuration, read from TOML at startup.
+//!
+//! Looked up at `$RMCP_XDOTOOL_CONFIG`, else `$XDG_CONFIG_HOME/rmcp-xdotool/config.toml`
+//! (default `~/.config/rmcp-xdotool/config.toml`). A missing file means defaults.
+
+use crate::activity::UserActivity;
+use crate::backend::InputBackendKind;
+use crate::clients::ClientConfig;
+use crate::display::{self, DisplayProfile};
+use crate::headless::Headless;
+use crate::hotkeys::{self, Hotkey, ScreenEdge};
+use crate::injection::Injection;
+use crate::launch::{self, Launch};
+use crate::limits::Limits;
+use crate::lock::ScreenLock;
+use crate::plugins::{self, Plugin};
+use crate::policy::{self, Rule};
+use crate::ratelimit::RateLimits;
+use crate::redact::{Redaction, Redactor};
+use crate::timeouts::{self, Timeouts};
+use serde::Deserialize;
+use std::net::IpAddr;
+use std::path::PathBuf;
+
+/// What to do with coordinates outside the display
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutOfBounds {
+    /// Refuse the call with an error
+    #[default]
+    Reject,
+    /// Move the point to the nearest on-screen pixel
+    Clamp,
+}
+
+/// The `[idle_disarm]` config table
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct IdleDisarm {
+    /// Minutes without a tool call before input tools are disarmed. Unset never disarms.
+    pub after_minutes: Option<u64>,
+    /// Ask the user through elicitation before `rearm` takes effect
+    pub rearm_requires_approval: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Echo xdotool invocations instead of running them, unless a call overrides it
+    pub dry_run: bool,
+    /// Whether off-screen coordinates are rejected or clamped to the display
+    pub out_of_bounds: OutOfBounds,
+    /// Tools that must be approved by the user (via MCP elicitation) before each call
+    pub require_approval: Vec<String>,
+    /// Allow/deny/require-approval rules tried in order before each call
+    pub rules: Vec<Rule>,
+    /// WM_CLASS names (instance or class) input may be sent to. Empty allows every window.
+    pub allowed_window_classes: Vec<String>,
+    /// File to append a JSON line to for every tool call. Unset disables the audit log.
+    pub audit_log: Option<PathBuf>,
+    /// Caps on how fast clicks, keys and typing can be sent
+    pub rate_limits: RateLimits,
+    /// How long tool calls may run, overall and per tool
+    pub timeouts: Timeouts,
+    /// Maximum text length, batch size and macro duration
+    pub limits: Limits,
+    /// How typed text shows up in the audit log and server log
+    pub redaction: Redaction,
+    /// Clients with their own tokens, tool permissions and rate limits
+    pub clients: Vec<ClientConfig>,
+    /// Whether input is refused while the screen is locked
+    pub screen_lock: ScreenLock,
+    /// Disarming input tools when the server sits idle
+    pub idle_disarm: IdleDisarm,
+    /// Refusing or deferring input while the user is using the mouse or keyboard
+    pub user_activity: UserActivity,
+    /// While this file exists the server is paused
+    pub pause_file: Option<PathBuf>,
+    /// Hotkey that cancels everything and pauses the server. Empty disables it.
+    pub kill_switch: String,
+    /// Peer IPs the network transports accept connections from. Empty accepts any.
+    pub allowed_peers: Vec<IpAddr>,
+    /// Bearer token network clients must present for unrestricted access
+    pub auth_token: Option<String>,
+    /// Return only compact structured results, without the prose meant for models
+    pub structured_only: bool,
+    /// The xdotool binary. Unset looks it up in PATH.
+    pub xdotool_path: Option<PathBuf>,
+    /// What sends input: xdotool, libxdo, ydotool/wtype on Wayland, or uinput devices
+    pub input_backend: InputBackendKind,
+    /// How input reaches windows when a call doesn't say: XTEST, or XSendEvent to the active window
+    pub injection: Injection,
+    /// X display or profile tool calls work on when they don't name one. Unset uses $DISPLAY.
+    pub display: Option<String>,
+    /// Named displays, each with its own XAUTHORITY, for calls to pick by name
+    pub displays: Vec<DisplayProfile>,
+    /// Extra tools, each running an external command
+    pub plugins: Vec<Plugin>,
+    /// Seconds an external program (xdotool, xprop, ffmpeg...) may run before it's killed
+    pub command_timeout_secs: u64,
+    /// Virtual displays the server may start, and the programs it may run on them
+    pub headless: Headless,
+    /// Programs launch_and_wait and launch_app may start, and what open_uri may open
+    pub launch: Launch,
+    /// Key combos the user presses to signal the agent, watched like the kill switch
+    pub hotkeys: Vec<Hotkey>,
+    /// Screen corners and edges the user moves the pointer into to signal the agent
+    pub screen_edges: Vec<ScreenEdge>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            dry_run: false,
+            out_of_bounds: OutOfBounds::Reject,
+            require_approval: vec![],
+            rules: vec![],
+            allowed_window_classes: vec![],
+            audit_log: None,
+            rate_limits: RateLimits::default(),
+            timeouts: Timeouts::default(),
+            limits: Limits::default(),
+            redaction: Redaction::default(),
+            clients: vec![],
+            screen_lock: ScreenLock::default(),
+            idle_disarm: IdleDisarm::default(),
+            user_activity: UserActivity::default(),
+            pause_file: None,
+            kill_switch: "ctrl+alt+shift+Escape".to_string(),
+            allowed_peers: vec![],
+            auth_token: None,
+            structured_only: false,
+            xdotool_path: None,
+            input_backend: InputBackendKind::Auto,
+            injection: Injection::Xtest,
+            display: None,
+            displays: vec![],
+            plugins: vec![],
+            command_timeout_secs: 30,
+            headless: Headless::default(),
+            launch: Launch::default(),
+            hotkeys: vec![],
+            screen_edges: vec![],
+        }
+    }
+}
+
+impl Config {
+    pub fn path() -> PathBuf {
+        if let Some(path) = std::env::var_os("RMCP_XDOTOOL_CONFIG") {
+            return PathBuf::from(path);
+        }
+        let config_home = std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .filter(|path| path.is_absolute())
+            .unwrap_or_else(|| {
+                let home = std::env::var_os("HOME").map(PathBuf::from).unwrap_or_default();
+                home.join(".config")
+            });
+        config_home.join("rmcp-xdotool").join("config.toml")
+    }
+
+    /// Whether a network transport may serve a connection from `ip`
+    pub fn allows_peer(&self, ip: IpAddr) -> bool {
+        self.allowed_peers.is_empty() || self.allowed_peers.iter().any(|allowed| allowed.to_canonical() == ip.to_canonical())
+    }
+
+    pub fn load() -> anyhow::Result<Self> {
+        let path = Self::path();
+        let config: Self = match std::fs::read_to_string(&path) {
+            Ok(text) => toml::from_str(&text)
+                .map_err(|e| anyhow::anyhow!("Invalid config {}: {}", path.display(), e))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Self::default(),
+            Err(e) => return Err(anyhow::anyhow!("Failed to read config {}: {}", path.display(), e)),
+        };
+        Redactor::new(&config.redaction).map_err(|e| anyhow::anyhow!("Invalid config {}: {}", path.display(), e))?;
+        policy::validate(&config.rules).map_err(|e| anyhow::anyhow!("Invalid config {}: {}", path.display(), e))?;
+        display::validate(&config.displays, config.display.as_deref())
+            .map_err(|e| anyhow::anyhow!("Invalid config {}: {}", path.display(), e))?;
+        plugins::validate(&config.plugins).map_err(|e| anyhow::anyhow!("Invalid config {}: {}", path.display(), e))?;
+        hotkeys::validate(&config.hotkeys, &config.screen_edges).map_err(|e| anyhow::anyhow!("Invalid config {}: {}", path.display(), e))?;
+        launch::validate(&config.launch).map_err(|e| anyhow::anyhow!("Invalid config {}: {}", path.display(), e))?;
+        timeouts::validate(&config.timeouts).map_err(|e| anyhow::anyhow!("Invalid config {}: {}", path.display(), e))?;
+        if config.command_timeout_secs == 0 {
+            return Err(anyhow::anyhow!("Invalid config {}: command_timeout_secs must be at least 1", path.display()));
+        }
+        Ok(config)
+    }
+}
@@ -0,0 +1,441 @@
+//! Tracking and cancelling long-running actions (batches, macros).
+//!
+//! Each run registers a cancellation token under an ID. The token is a child
+//! of the MCP request's token, so both `cancel_action` and a client-side
+//! `notifications/cancelled` stop it at the next step boundary. Scheduled
+//! batches are registered while they wait, so they can be cancelled before
+//! they start.
+//!
+//! The server can also be paused, by `pause_automation`, SIGUSR1 or the
+//! configured pause file existing. While paused, tool calls are refused and
+//! running actions wait at their next step boundary, then carry on after
+//! `resume_automation`, SIGUSR2 or the file being removed. The kill switch
+//! pauses too, but cancels everything first.
+//!
+//! With `[idle_disarm]` configured, a server that sees no tool calls for a
+//! while disarms its input tools until `rearm` is called.
+
+use crate::output;
+use crate::XdotoolServer;
+use rmcp::{
+    handler::server::wrapper::Parameters,
+    model::*,
+    service::{RequestContext, RoleServer},
+    ErrorData as McpError,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct CancelActionParams {
+    #[schemars(description = "ID of the running action (from list_actions, or the id passed to execute_batch/replay_macro/schedule_batch)")]
+    pub id: String,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ActionList {
+    pub actions: Vec<ActionInfo>,
+    pub input: InputStatus,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ActionInfo {
+    pub id: String,
+    /// What it is, e.g. "batch of 3 step(s)" or "macro 'login'"
+    pub label: String,
+    /// Seconds until a scheduled action starts
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub starts_in_seconds: Option<f64>,
+    /// Seconds a started action has been running
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub running_seconds: Option<f64>,
+}
+
+/// Which clients are sending input or waiting for their turn
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct InputStatus {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sending: Option<String>,
+    /// Oldest first
+    pub waiting: Vec<String>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct AutomationState {
+    /// Whether this call paused or resumed the server
+    pub changed: bool,
+    pub paused: bool,
+    /// Why the server is paused, if it still is
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct Aborted {
+    /// Actions cancelled
+    pub cancelled: usize,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct Rearmed {
+    /// False if input tools were already armed
+    pub changed: bool,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct Cancelling {
+    pub id: String,
+    pub label: String,
+}
+
+#[derive(Debug)]
+struct RunningAction {
+    label: String,
+    token: CancellationToken,
+    /// When the action started, or will start if it is scheduled
+    started: Instant,
+}
+
+/// Tools that still work while the server is paused
+pub const PAUSE_EXEMPT: &[&str] = &["resume_automation", "pause_automation", "abort_all", "list_actions", "get_audit_log"];
+
+/// Tools that send input or change what's on the desktop, disarmed after an idle period
+pub const INPUT_TOOLS: &[&str] = &[
+    "move_mouse", "click", "click_at", "click_element", "activate_menu", "double_click", "type_text", "set_element_text", "key_press", "scroll",
+    "invoke_element_action", "smart_click", "focus_element",
+    "launch_app", "open_uri", "launch_and_wait", "quit_app", "signal_window_process", "focus_or_launch",
+    "start_virtual_display", "run_in_display",
+    "execute_batch", "schedule_batch", "replay_macro", "xdotool_script",
+    "copy_selection", "set_clipboard", "set_primary_selection",
+];
+
+/// Tools whose `dry_run` only reports what they would do. Any other tool ignores the flag,
+/// so a call to it is a real one whatever its arguments say.
+pub const DRY_RUN_TOOLS: &[&str] = &[
+    "move_mouse", "click", "click_at", "click_element", "activate_menu", "double_click", "type_text", "set_element_text", "key_press", "scroll",
+    "invoke_element_action", "smart_click", "focus_element",
+    "quit_app", "signal_window_process", "focus_or_launch",
+    "execute_batch", "schedule_batch", "replay_macro", "xdotool_script",
+];
+
+/// How often paused actions check whether they may continue
+const PAUSE_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Default)]
+pub struct ActionRegistry {
+    next_id: u64,
+    running: HashMap<String, RunningAction>,
+    /// Why the server is paused, if it is
+    paused: Option<String>,
+    /// When the last tool call arrived
+    last_call: Option<Instant>,
+    /// Input tools are off until `rearm` after an idle period
+    disarmed: bool,
+}
+
+/// Keeps an action registered while it runs and removes it when dropped
+pub struct ActionGuard {
+    registry: Arc<Mutex<ActionRegistry>>,
+    pub id: String,
+    pub token: CancellationToken,
+}
+
+impl Drop for ActionGuard {
+    fn drop(&mut self) {
+        self.registry.lock().unwrap().running.remove(&self.id);
+    }
+}
+
+impl ActionRegistry {
+    /// Register an action under `id` (or a generated one), cancelled along with `parent`
+    pub fn register(
+        registry: &Arc<Mutex<ActionRegistry>>,
+        id: Option<String>,
+        label: impl Into<String>,
+        parent: &CancellationToken,
+    ) -> Result<ActionGuard, McpError> {
+        Self::register_at(registry, id, label, parent, Instant::now())
+    }
+
+    /// Register an action that starts at `start`, which may be in the future
+    pub fn register_at(
+        registry: &Arc<Mutex<ActionRegistry>>,
+        id: Option<String>,
+        label: impl Into<String>,
+        parent: &CancellationToken,
+        start: Instant,
+    ) -> Result<ActionGuard, McpError> {
+        let mut actions = registry.lock().unwrap();
+        let id = match id {
+            Some(id) if actions.running.contains_key(&id) => {
+                return Err(McpError::internal_error(format!("An action with id '{}' is already running", id), None));
+            }
+            Some(id) => id,
+            None => {
+                actions.next_id += 1;
+                format!("action-{}", actions.next_id)
+            }
+        };
+        let token = parent.child_token();
+        actions.running.insert(id.clone(), RunningAction {
+            label: label.into(),
+            token: token.clone(),
+            started: start,
+        });
+        Ok(ActionGuard { registry: registry.clone(), id, token })
+    }
+
+    /// Pause for `reason`, unless already paused. Returns whether this paused it.
+    pub fn pause(&mut self, reason: impl Into<String>) -> bool {
+        if self.paused.is_some() {
+            return false;
+        }
+        self.paused = Some(reason.into());
+        true
+    }
+
+    /// Lift a pause, returning why it was paused
+    pub fn resume(&mut self) -> Option<String> {
+        self.paused.take()
+    }
+
+    /// Note a tool call, disarming first if none came for `idle_timeout` while nothing was running.
+    /// Returns whether input tools are disarmed.
+    pub fn touch(&mut self, idle_timeout: Option<Duration>) -> bool {
+        let now = Instant::now();
+        if let (Some(timeout), Some(last)) = (idle_timeout, self.last_call) {
+            if self.running.is_empty() && now.duration_since(last) >= timeout && !self.disarmed {
+                tracing::info!("Disarming input tools after {}s idle", timeout.as_secs());
+                self.disarmed = true;
+            }
+        }
+        self.last_call = Some(now);
+        self.disarmed
+    }
+}
+
+pub fn disarmed_error(tool: &str) -> McpError {
+    McpError::internal_error(
+        format!("Input tools are disarmed after an idle period, so '{}' was refused. Call rearm to enable them.", tool),
+        None
+    )
+}
+
+pub fn paused_error(reason: &str) -> McpError {
+    McpError::internal_error(format!("Server is paused ({}). Wait for the user to resume it.", reason), None)
+}
+
+/// Sleep, returning early with an error if the token is cancelled
+pub async fn sleep_or_cancel(duration: Duration, token: &CancellationToken) -> Result<(), McpError> {
+    tokio::select! {
+        _ = tokio::time::sleep(duration) => Ok(()),
+        _ = token.cancelled() => Err(cancelled_error()),
+    }
+}
+
+pub fn cancelled_error() -> McpError {
+    McpError::internal_error("Cancelled", None)
+}
+
+impl XdotoolServer {
+    /// Why the server is paused, if it is
+    pub(crate) fn paused_reason(&self) -> Option<String> {
+        if let Some(reason) = self.actions.lock().unwrap().paused.clone() {
+            return Some(reason);
+        }
+        self.config.pause_file.as_ref()
+            .filter(|path| path.exists())
+            .map(|path| format!("pause file {} exists", path.display()))
+    }
+
+    /// Hold a running action at a step boundary until the server is resumed
+    pub(crate) async fn wait_while_paused(&self, token: &CancellationToken) -> Result<(), McpError> {
+        while self.paused_reason().is_some() {
+            sleep_or_cancel(PAUSE_POLL_INTERVAL, token).await?;
+        }
+        Ok(())
+    }
+
+    /// Cancel every running and scheduled action and release held input. Returns how many were cancelled.
+    pub(crate) fn cancel_all(&self) -> usize {
+        let cancelled = {
+            let actions = self.actions.lock().unwrap();
+            actions.running.values().for_each(|action| action.token.cancel());
+            actions.running.len()
+        };
+        Self::release_input();
+        cancelled
+    }
+
+    /// `cancel_all`, then pause. Returns how many were cancelled.
+    pub(crate) fn halt(&self, reason: impl Into<String>) -> usize {
+        self.actions.lock().unwrap().paused = Some(reason.into());
+        self.cancel_all()
+    }
+
+    /// Pause on SIGUSR1 and resume on SIGUSR2, so the pause can be toggled from outside the client
+    pub fn watch_signals(&self) -> std::io::Result<()> {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut pause = signal(SignalKind::user_defined1())?;
+        let mut resume = signal(SignalKind::user_defined2())?;
+        let server = self.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    Some(()) = pause.recv() => {
+                        if server.actions.lock().unwrap().pause("paused by SIGUSR1") {
+                            tracing::info!("Paused by SIGUSR1");
+                        }
+                    }
+                    Some(()) = resume.recv() => {
+                        if server.actions.lock().unwrap().resume().is_some() {
+                            tracing::info!("Resumed by SIGUSR2");
+                        }
+                    }
+                    else => break,
+                }
+            }
+        });
+        Ok(())
+    }
+
+    /// Release modifiers and mouse buttons an interrupted action may have left pressed
+    pub(crate) fn release_input() {
+        let result = Self::run_xdotool(&[
+            "keyup", "Shift_L", "Shift_R", "Control_L", "Control_R", "Alt_L", "Alt_R",
+            "Super_L", "Super_R", "Meta_L", "Meta_R", "ISO_Level3_Shift",
+            "mouseup", "1", "mouseup", "2", "mouseup", "3", "mouseup", "8", "mouseup", "9",
+        ]);
+        if let Err(e) = result {
+            tracing::warn!("Failed to release held input: {}", e.message);
+        }
+    }
+}
+
+#[rmcp::tool_router(router = control_router, vis = "pub(crate)")]
+impl XdotoolServer {
+    #[rmcp::tool(description = "List running and scheduled batches and macros with their IDs", annotations(read_only_hint = true), output_schema = output::schema::<ActionList>())]
+    pub async fn list_actions(&self) -> Result<CallToolResult, McpError> {
+        let (queue, input) = {
+            let queue = self.input_queue.lock().unwrap();
+            let (holder, waiting) = queue.status();
+            let text = match holder {
+                Some(holder) if waiting.is_empty() => format!("\nInput: {} is sending", holder),
+                Some(holder) => format!("\nInput: {} is sending, waiting: {}", holder, waiting.join(", ")),
+                None => String::new(),
+            };
+            let input = InputStatus {
+                sending: holder.map(str::to_string),
+                waiting: waiting.into_iter().map(str::to_string).collect(),
+            };
+            (text, input)
+        };
+        let actions = self.actions.lock().unwrap();
+        if actions.running.is_empty() {
+            return Ok(output::structured(
+                format!("No actions running{}", queue),
+                &ActionList { actions: vec![], input }
+            ));
+        }
+
+        let now = Instant::now();
+        let mut infos: Vec<ActionInfo> = actions.running.iter()
+            .map(|(id, action)| {
+                let wait = action.started.checked_duration_since(now).filter(|wait| !wait.is_zero());
+                ActionInfo {
+                    id: id.clone(),
+                    label: action.label.clone(),
+                    starts_in_seconds: wait.map(|wait| wait.as_secs_f64()),
+                    running_seconds: wait.is_none().then(|| action.started.elapsed().as_secs_f64()),
+                }
+            })
+            .collect();
+        infos.sort_by(|a, b| a.id.cmp(&b.id));
+        let lines: Vec<String> = infos.iter()
+            .map(|info| match (info.starts_in_seconds, info.running_seconds) {
+                (Some(wait), _) => format!("  {}: {} (starts in {:.1}s)", info.id, info.label, wait),
+                (None, running) => format!("  {}: {} (running {:.1}s)", info.id, info.label, running.unwrap_or_default()),
+            })
+            .collect();
+        Ok(output::structured(
+            format!("{} action(s) running:\n{}{}", lines.len(), lines.join("\n"), queue),
+            &ActionList { actions: infos, input }
+        ))
+    }
+
+    #[rmcp::tool(description = "Freeze automation so the user can use the desktop: running batches and macros wait before their next step and other tool calls are refused until resume_automation", annotations(read_only_hint = false, destructive_hint = false), output_schema = output::schema::<AutomationState>())]
+    pub async fn pause_automation(&self) -> Result<CallToolResult, McpError> {
+        let changed = self.actions.lock().unwrap().pause("paused by pause_automation");
+        let state = AutomationState { changed, paused: true, reason: self.paused_reason() };
+        if !changed {
+            return Ok(output::structured("Already paused", &state));
+        }
+        Self::release_input();
+        Ok(output::structured("Paused. Running actions will wait before their next step.", &state))
+    }
+
+    #[rmcp::tool(description = "Let automation continue after pause_automation, SIGUSR1 or the kill switch paused it", annotations(read_only_hint = false, destructive_hint = false), output_schema = output::schema::<AutomationState>())]
+    pub async fn resume_automation(&self) -> Result<CallToolResult, McpError> {
+        let resumed = self.actions.lock().unwrap().resume();
+        let changed = resumed.is_some();
+        let mut message = match resumed {
+            Some(reason) => format!("Resumed (was {})", reason),
+            None => "Server was not paused".to_string(),
+        };
+        let still_paused = self.paused_reason();
+        if let Some(reason) = &still_paused {
+            message.push_str(&format!(". Still paused: {}", reason));
+        }
+        Ok(output::structured(message, &AutomationState {
+            changed,
+            paused: still_paused.is_some(),
+            reason: still_paused,
+        }))
+    }
+
+    #[rmcp::tool(description = "Emergency stop: cancel every running and scheduled batch or macro and release all held keys and mouse buttons (e.g. after an interrupted drag)", annotations(read_only_hint = false, destructive_hint = false), output_schema = output::schema::<Aborted>())]
+    pub async fn abort_all(&self) -> Result<CallToolResult, McpError> {
+        let cancelled = self.cancel_all();
+        Ok(output::structured(
+            format!("Cancelled {} action(s) and released held keys and buttons", cancelled),
+            &Aborted { cancelled }
+        ))
+    }
+
+    #[rmcp::tool(description = "Re-enable input tools after the server disarmed them for being idle. May ask the user to confirm.", annotations(read_only_hint = false, destructive_hint = false), output_schema = output::schema::<Rearmed>())]
+    pub async fn rearm(&self, context: RequestContext<RoleServer>) -> Result<CallToolResult, McpError> {
+        if !self.actions.lock().unwrap().disarmed {
+            return Ok(output::structured("Input tools are already armed", &Rearmed { changed: false }));
+        }
+        if self.config.idle_disarm.rearm_requires_approval {
+            Self::ask_user("rearm", "Re-enable mouse and keyboard input for the agent?".to_string(), &context).await?;
+        }
+        self.actions.lock().unwrap().disarmed = false;
+        Ok(output::structured("Input tools re-armed", &Rearmed { changed: true }))
+    }
+
+    #[rmcp::tool(description = "Cancel a running or scheduled batch or macro by ID. It stops before its next step and held keys/buttons are released.", annotations(read_only_hint = false, destructive_hint = false), output_schema = output::schema::<Cancelling>())]
+    pub async fn cancel_action(
+        &self,
+        Parameters(params): Parameters<CancelActionParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let actions = self.actions.lock().unwrap();
+        match actions.running.get(&params.id) {
+            Some(action) => {
+                action.token.cancel();
+                Ok(output::structured(
+                    format!("Cancelling {} ({})", params.id, action.label),
+                    &Cancelling { id: params.id.clone(), label: action.label.clone() }
+                ))
+            }
+            None => Err(McpError::internal_error(
+                format!("No running action with id '{}'", params.id),
+                None
+            )),
+        }
+    }
+}
@@ -0,0 +1,691 @@
+//! Live desktop state as subscribable resources.
+//!
+//! `desktop://active-window`, `desktop://current-desktop` and
+//! `desktop://windows` are JSON snapshots of the focused window, the current
+//! virtual desktop and the visible top-level windows. Clients that subscribe
+//! get `notifications/resources/updated` when one changes, so they can react
+//! to the user switching apps instead of polling. xdotool has no event
+//! stream, so while anyone is subscribed a single task polls the desktop and
+//! compares snapshots; it stops when the last subscription goes away.
+//!
+//! The same comparison yields window events: a window appeared, closed,
+//! gained or lost focus, or (while focused) changed its title. Subscribers to
+//! `desktop://window-events` are notified as they happen and read the most
+//! recent ones; `wait_for_window_event` instead blocks until one matching a
+//! filter occurs, and also sees title changes of the window it watches when
+//! that window isn't focused. `watch_window_events` collects the events about
+//! windows matching a search for a while, sending each as a progress
+//! notification when the client asked for progress.
+//!
+//! `desktop://focus` is the last focus change, with the window that lost
+//! focus and the one that gained it (title, WM_CLASS, PID), so a subscriber
+//! learns at once that its target window was left and for what. `wait_for_title` polls one window's title
+//! until it matches a regex.
+//!
+//! `desktop://hotkeys` holds the recent presses of the `[[hotkeys]]`
+//! triggers; subscribing to it alone doesn't start the polling.
+
+use crate::control::sleep_or_cancel;
+use crate::{allowlist, hotkeys, output, XdotoolServer};
+use regex_automata::meta::Regex;
+use rmcp::{
+    handler::server::wrapper::Parameters,
+    model::*,
+    service::{Peer, RequestContext, RoleServer},
+    ErrorData as McpError,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+pub const ACTIVE_WINDOW_URI: &str = "desktop://active-window";
+pub const CURRENT_DESKTOP_URI: &str = "desktop://current-desktop";
+pub const WINDOWS_URI: &str = "desktop://windows";
+pub const WINDOW_EVENTS_URI: &str = "desktop://window-events";
+pub const FOCUS_URI: &str = "desktop://focus";
+pub const HOTKEYS_URI: &str = "desktop://hotkeys";
+const URIS: &[&str] = &[ACTIVE_WINDOW_URI, CURRENT_DESKTOP_URI, WINDOWS_URI, WINDOW_EVENTS_URI, FOCUS_URI, HOTKEYS_URI];
+
+/// How often the desktop is checked while something is subscribed
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+/// How many window events `desktop://window-events` keeps
+const RECENT_EVENTS: usize = 50;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct Window {
+    id: String,
+    name: String,
+}
+
+/// Everything the resources report, taken at one moment
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Snapshot {
+    active_window: Option<Window>,
+    desktop: Option<u32>,
+    windows: Vec<Window>,
+}
+
+impl Snapshot {
+    /// Resources whose content differs from `other`. The window list only counts as
+    /// changed when windows come or go, not when a title changes.
+    fn changed(&self, other: &Snapshot) -> Vec<&'static str> {
+        let ids = |snapshot: &Snapshot| snapshot.windows.iter().map(|window| window.id.clone()).collect::<HashSet<_>>();
+        let mut changed = vec![];
+        if self.active_window != other.active_window {
+            changed.push(ACTIVE_WINDOW_URI);
+        }
+        if self.desktop != other.desktop {
+            changed.push(CURRENT_DESKTOP_URI);
+        }
+        if ids(self) != ids(other) {
+            changed.push(WINDOWS_URI);
+        }
+        changed
+    }
+
+    /// Window events between `previous` and this snapshot, with the window IDs they're about
+    fn events(&self, previous: &Snapshot) -> Vec<(WindowEventKind, String)> {
+        let ids = |snapshot: &Snapshot| snapshot.windows.iter().map(|window| window.id.clone()).collect::<HashSet<_>>();
+        let (before, after) = (ids(previous), ids(self));
+        let mut events: Vec<_> = self.windows.iter()
+            .filter(|window| !before.contains(&window.id))
+            .map(|window| (WindowEventKind::Appeared, window.id.clone()))
+            .collect();
+        events.extend(previous.windows.iter()
+            .filter(|window| !after.contains(&window.id))
+            .map(|window| (WindowEventKind::Closed, window.id.clone())));
+        match (&previous.active_window, &self.active_window) {
+            (Some(before), Some(now)) if before.id == now.id && before.name != now.name => {
+                events.push((WindowEventKind::TitleChanged, now.id.clone()));
+            }
+            (Some(before), Some(now)) if before.id == now.id => {}
+            (_, Some(now)) => events.push((WindowEventKind::Focused, now.id.clone())),
+            _ => {}
+        }
+        if let Some(left) = &previous.active_window {
+            let kept = self.active_window.as_ref().is_some_and(|now| now.id == left.id);
+            let closed = before.contains(&left.id) && !after.contains(&left.id);
+            if !kept && !closed {
+                events.push((WindowEventKind::Blurred, left.id.clone()));
+            }
+        }
+        events
+    }
+
+    fn json(&self, uri: &str) -> String {
+        match uri {
+            ACTIVE_WINDOW_URI => serde_json::to_string(&self.active_window),
+            CURRENT_DESKTOP_URI => serde_json::to_string(&serde_json::json!({ "desktop": self.desktop })),
+            _ => serde_json::to_string(&self.windows),
+        }.unwrap_or_default()
+    }
+}
+
+/// Something that happened to a window
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum WindowEventKind {
+    Appeared,
+    Closed,
+    TitleChanged,
+    Focused,
+    Blurred,
+}
+
+impl WindowEventKind {
+    fn describe(self) -> &'static str {
+        match self {
+            WindowEventKind::Appeared => "appeared",
+            WindowEventKind::Closed => "closed",
+            WindowEventKind::TitleChanged => "changed its title",
+            WindowEventKind::Focused => "gained focus",
+            WindowEventKind::Blurred => "lost focus",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct WindowEvent {
+    pub event: WindowEventKind,
+    /// The window, titled as it was when the event was noticed (its last known title if it closed)
+    pub window: output::Window,
+    /// Unix time in seconds
+    pub timestamp: f64,
+}
+
+impl WindowEvent {
+    /// E.g. `Window 123 "Save As" appeared`
+    fn describe(&self) -> String {
+        match &self.window.name {
+            Some(name) => format!("Window {} \"{}\" {}", self.window.id, name, self.event.describe()),
+            None => format!("Window {} {}", self.window.id, self.event.describe()),
+        }
+    }
+}
+
+/// A window in a focus change
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct FocusedWindow {
+    pub id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// WM_CLASS instance and class names
+    pub classes: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pid: Option<u32>,
+}
+
+impl FocusedWindow {
+    fn look_up(id: &str) -> Self {
+        FocusedWindow {
+            id: id.to_string(),
+            name: XdotoolServer::window_name(id).ok(),
+            classes: allowlist::window_classes(id).unwrap_or_default(),
+            pid: XdotoolServer::run_xdotool(&["getwindowpid", id]).ok().and_then(|pid| pid.trim().parse().ok()),
+        }
+    }
+}
+
+/// The active window changing, as `desktop://focus` reports it
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct FocusChange {
+    /// The window that had focus, absent if none did
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<FocusedWindow>,
+    /// The window that has it now, absent if none does
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub to: Option<FocusedWindow>,
+    /// Unix time in seconds
+    pub timestamp: f64,
+}
+
+impl FocusChange {
+    /// The change from `from` to the active window of `current`. A window `previous`
+    /// already described keeps its details, since it may have closed.
+    fn new(previous: Option<&FocusChange>, from: Option<&Window>, current: &Snapshot) -> Self {
+        let known = previous.and_then(|change| change.to.clone());
+        let from = from.map(|window| match known {
+            Some(known) if known.id == window.id => known,
+            _ => FocusedWindow::look_up(&window.id),
+        });
+        FocusChange {
+            from,
+            to: current.active_window.as_ref().map(|window| FocusedWindow::look_up(&window.id)),
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs_f64(),
+        }
+    }
+}
+
+/// Titles and WM_CLASS names of windows seen so far, so events about a closed
+/// window can still say which one it was
+#[derive(Debug, Default)]
+struct KnownWindows {
+    names: HashMap<String, String>,
+    classes: HashMap<String, Vec<String>>,
+}
+
+impl KnownWindows {
+    /// Look up every visible window now
+    fn current(with_classes: bool) -> Self {
+        let mut known = KnownWindows::default();
+        for id in XdotoolServer::visible_windows().unwrap_or_default() {
+            if let Ok(name) = XdotoolServer::window_name(&id) {
+                known.names.insert(id.clone(), name);
+            }
+            if with_classes {
+                known.classes.insert(id.clone(), allowlist::window_classes(&id).unwrap_or_default());
+            }
+        }
+        known
+    }
+
+    /// The event with its window's title, and the window's WM_CLASS names if `with_classes`
+    fn event(&mut self, kind: WindowEventKind, id: &str, current: &Snapshot, with_classes: bool) -> (WindowEvent, Vec<String>) {
+        let (name, classes) = if kind == WindowEventKind::Closed {
+            (self.names.remove(id), self.classes.remove(id).unwrap_or_default())
+        } else {
+            let name = current.active_window.as_ref()
+                .filter(|window| window.id == id)
+                .map(|window| window.name.clone())
+                .or_else(|| XdotoolServer::window_name(id).ok());
+            if let Some(name) = &name {
+                self.names.insert(id.to_string(), name.clone());
+            }
+            let classes = match self.classes.get(id) {
+                Some(classes) => classes.clone(),
+                None if with_classes => {
+                    let classes = allowlist::window_classes(id).unwrap_or_default();
+                    self.classes.insert(id.to_string(), classes.clone());
+                    classes
+                }
+                None => vec![],
+            };
+            (name, classes)
+        };
+        let event = WindowEvent {
+            event: kind,
+            window: output::Window { id: id.to_string(), name },
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs_f64(),
+        };
+        (event, classes)
+    }
+}
+
+#[derive(Debug)]
+struct Subscriber {
+    peer: Peer<RoleServer>,
+    uris: HashSet<String>,
+}
+
+/// Subscriptions by transport session
+#[derive(Debug, Default)]
+pub struct Subscriptions {
+    subscribers: HashMap<String, Subscriber>,
+    watching: bool,
+    /// Window events noticed while watching, oldest first
+    events: VecDeque<WindowEvent>,
+    /// The last focus change noticed while watching
+    focus: Option<FocusChange>,
+    pub(crate) hotkeys: hotkeys::Presses,
+}
+
+impl XdotoolServer {
+    /// Look up the desktop. Without `titles` only the active window's title is read,
+    /// which is all that change detection needs.
+    fn snapshot(titles: bool) -> Snapshot {
+        let window = |id: String| {
+            let name = Self::window_name_now(&id).unwrap_or_default();
+            Window { id, name }
+        };
+        let untitled = |id: String| Window { id, name: String::new() };
+        let active_window = Self::run_xdotool(&["getactivewindow"]).ok()
+            .map(|id| id.trim().to_string())
+            .filter(|id| !id.is_empty())
+            .map(window);
+        let desktop = Self::run_xdotool(&["get_desktop"]).ok().and_then(|desktop| desktop.trim().parse().ok());
+        let windows = Self::visible_windows().unwrap_or_default().into_iter()
+            .map(|id| if titles { window(id) } else { untitled(id) })
+            .collect();
+        Snapshot { active_window, desktop, windows }
+    }
+
+    /// The desktop resources, always listed
+    pub(crate) fn desktop_resources() -> Vec<Resource> {
+        [
+            (ACTIVE_WINDOW_URI, "active-window.json", "The focused window's ID and title"),
+            (CURRENT_DESKTOP_URI, "current-desktop.json", "The current virtual desktop number"),
+            (WINDOWS_URI, "windows.json", "Visible top-level windows with IDs and titles"),
+            (WINDOW_EVENTS_URI, "window-events.json", "Recent window events (appeared, closed, title_changed, focused, blurred), noticed while anything is subscribed"),
+            (FOCUS_URI, "focus.json", "The last focus change: the window that lost focus and the one that gained it, with titles, WM_CLASS and PIDs"),
+            (HOTKEYS_URI, "hotkeys.json", "Recent presses of the hotkeys the server's config defines, by name"),
+        ].into_iter().map(|(uri, name, description)| {
+            let mut resource = RawResource::new(uri, name);
+            resource.description = Some(format!("{}. Subscribe to be notified when it changes.", description));
+            resource.mime_type = Some("application/json".to_string());
+            resource.no_annotation()
+        }).collect()
+    }
+
+    /// Current content of a desktop resource, or None if `uri` isn't one
+    pub(crate) fn read_desktop(&self, uri: &str) -> Option<ReadResourceResult> {
+        if !URIS.contains(&uri) {
+            return None;
+        }
+        let text = if uri == WINDOW_EVENTS_URI {
+            serde_json::to_string(&self.subscriptions.lock().unwrap().events).unwrap_or_default()
+        } else if uri == FOCUS_URI {
+            // Before any change was noticed, just the window that has focus now
+            let focus = self.subscriptions.lock().unwrap().focus.clone();
+            let focus = focus.unwrap_or_else(|| FocusChange::new(None, None, &Self::snapshot(false)));
+            serde_json::to_string(&focus).unwrap_or_default()
+        } else if uri == HOTKEYS_URI {
+            serde_json::to_string(&self.subscriptions.lock().unwrap().hotkeys.recent).unwrap_or_default()
+        } else {
+            Self::snapshot(uri == WINDOWS_URI).json(uri)
+        };
+        Some(ReadResourceResult {
+            contents: vec![ResourceContents::TextResourceContents {
+                uri: uri.to_string(),
+                mime_type: Some("application/json".to_string()),
+                text,
+                meta: None,
+            }],
+        })
+    }
+
+    /// Key for per-session state like subscriptions: the transport session, or "stdio"
+    pub(crate) fn session_key(&self) -> String {
+        self.session.as_deref().unwrap_or("stdio").to_string()
+    }
+
+    pub(crate) fn subscribe_desktop(&self, uri: &str, peer: Peer<RoleServer>) -> Result<(), McpError> {
+        if !URIS.contains(&uri) {
+            return Err(McpError::resource_not_found(
+                format!("Can't subscribe to {}; subscribable resources: {}", uri, URIS.join(", ")),
+                None
+            ));
+        }
+        let mut subscriptions = self.subscriptions.lock().unwrap();
+        subscriptions.subscribers.entry(self.session_key())
+            .or_insert_with(|| Subscriber { peer, uris: HashSet::new() })
+            .uris.insert(uri.to_string());
+        // Hotkey presses are pushed when they happen, without polling the desktop
+        if !subscriptions.watching && uri != HOTKEYS_URI {
+            subscriptions.watching = true;
+            tokio::spawn(self.clone().watch_desktop());
+        }
+        Ok(())
+    }
+
+    pub(crate) fn unsubscribe_desktop(&self, uri: &str) {
+        let mut subscriptions = self.subscriptions.lock().unwrap();
+        let key = self.session_key();
+        if let Some(subscriber) = subscriptions.subscribers.get_mut(&key) {
+            subscriber.uris.remove(uri);
+            if subscriber.uris.is_empty() {
+                subscriptions.subscribers.remove(&key);
+            }
+        }
+    }
+
+    /// Poll the desktop and notify subscribers of changes until nobody is subscribed
+    async fn watch_desktop(self) {
+        let poll = || async { tokio::task::spawn_blocking(|| Self::snapshot(false)).await.unwrap_or_default() };
+        let mut previous = poll().await;
+        let mut known = tokio::task::spawn_blocking(|| KnownWindows::current(false)).await.unwrap_or_default();
+        loop {
+            tokio::time::sleep(POLL_INTERVAL).await;
+            {
+                let mut subscriptions = self.subscriptions.lock().unwrap();
+                subscriptions.subscribers.retain(|_, subscriber| !subscriber.peer.is_transport_closed());
+                if subscriptions.subscribers.is_empty() {
+                    subscriptions.watching = false;
+                    return;
+                }
+            }
+
+            let current = poll().await;
+            let mut changed = current.changed(&previous);
+            let events: Vec<WindowEvent> = current.events(&previous).into_iter()
+                .map(|(kind, id)| known.event(kind, &id, &current, false).0)
+                .collect();
+            let refocused = current.active_window.as_ref().map(|window| &window.id) != previous.active_window.as_ref().map(|window| &window.id);
+            if refocused {
+                let last = self.subscriptions.lock().unwrap().focus.clone();
+                let (from, snapshot) = (previous.active_window.clone(), current.clone());
+                let change = tokio::task::spawn_blocking(move || FocusChange::new(last.as_ref(), from.as_ref(), &snapshot)).await;
+                if let Ok(change) = change {
+                    self.subscriptions.lock().unwrap().focus = Some(change);
+                    changed.push(FOCUS_URI);
+                }
+            }
+            previous = current;
+            if !events.is_empty() {
+                let mut subscriptions = self.subscriptions.lock().unwrap();
+                subscriptions.events.extend(events);
+                while subscriptions.events.len() > RECENT_EVENTS {
+                    subscriptions.events.pop_front();
+                }
+                changed.push(WINDOW_EVENTS_URI);
+            }
+            if changed.is_empty() {
+                continue;
+            }
+            self.notify_updated(&changed).await;
+        }
+    }
+
+    /// Tell the subscribers of each of `changed` that it changed
+    pub(crate) async fn notify_updated(&self, changed: &[&str]) {
+        let notify: Vec<(Peer<RoleServer>, Vec<&str>)> = self.subscriptions.lock().unwrap().subscribers.values()
+            .filter(|subscriber| !subscriber.peer.is_transport_closed())
+            .map(|subscriber| {
+                let uris = changed.iter().copied().filter(|uri| subscriber.uris.contains(*uri)).collect();
+                (subscriber.peer.clone(), uris)
+            })
+            .collect();
+        for (peer, uris) in notify {
+            for uri in uris {
+                let param = ResourceUpdatedNotificationParam { uri: uri.to_string() };
+                if let Err(e) = peer.notify_resource_updated(param).await {
+                    tracing::debug!("Failed to notify a subscriber about {}: {}", uri, e);
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct WaitForWindowEventParams {
+    #[schemars(description = "Events to wait for: appeared, closed, title_changed, focused, blurred. Default: any of them")]
+    #[serde(default)]
+    pub events: Vec<WindowEventKind>,
+    #[schemars(description = "Only events about this window ID. Its title changes are seen even while it isn't focused")]
+    pub window_id: Option<String>,
+    #[schemars(description = "Only windows whose title contains this (case-insensitive)")]
+    pub name: Option<String>,
+    #[schemars(description = "Only windows with this WM_CLASS instance or class name (case-insensitive), e.g. 'firefox'")]
+    pub class: Option<String>,
+    #[schemars(description = "Seconds to wait before giving up. Default: 30")]
+    #[serde(default = "default_event_timeout")]
+    pub timeout_seconds: u64,
+}
+
+fn default_event_timeout() -> u64 { 30 }
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct WindowEventWait {
+    pub event: WindowEvent,
+    /// How long the call waited, in milliseconds
+    pub waited_ms: u64,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct WatchWindowEventsParams {
+    #[schemars(description = "Only windows matching this search, as in search_window, e.g. 'Save As'. Default: every window")]
+    pub query: Option<String>,
+    #[schemars(description = "Search by: 'name', 'class', 'classname', or 'any' (default: 'any')")]
+    #[serde(default = "crate::default_search_type")]
+    pub search_type: String,
+    #[schemars(description = "Events to report: appeared, closed, title_changed, focused, blurred. Default: all of them")]
+    #[serde(default)]
+    pub events: Vec<WindowEventKind>,
+    #[schemars(description = "Seconds to watch. Default: 10")]
+    #[serde(default = "default_watch_timeout")]
+    pub timeout_seconds: u64,
+    #[schemars(description = "Stop once this many events were seen. Default: watch for the whole timeout")]
+    pub max_events: Option<usize>,
+}
+
+fn default_watch_timeout() -> u64 { 10 }
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct WindowEventWatch {
+    /// In the order they were noticed
+    pub events: Vec<WindowEvent>,
+    /// How long the call watched, in milliseconds
+    pub watched_ms: u64,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct WaitForTitleParams {
+    #[schemars(description = "Window ID (from search_window or get_active_window)")]
+    pub window_id: String,
+    #[schemars(description = "Regex the title must match, e.g. ' - Mozilla Firefox$' or '^[^*]' for a saved document")]
+    pub pattern: String,
+    #[schemars(description = "Wait until the title stops matching instead, e.g. for 'Loading' to go away. Default: false")]
+    #[serde(default)]
+    pub until_gone: bool,
+    #[schemars(description = "Seconds to wait before giving up. Default: 30")]
+    #[serde(default = "default_event_timeout")]
+    pub timeout_seconds: u64,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct TitleWait {
+    /// The window, with the title that ended the wait
+    pub window: output::Window,
+    /// How long the call waited, in milliseconds
+    pub waited_ms: u64,
+}
+
+impl WaitForWindowEventParams {
+    fn matches(&self, event: &WindowEvent, classes: &[String]) -> bool {
+        let title = event.window.name.as_deref().unwrap_or_default().to_lowercase();
+        (self.events.is_empty() || self.events.contains(&event.event))
+            && self.window_id.as_ref().is_none_or(|id| *id == event.window.id)
+            && self.name.as_ref().is_none_or(|name| title.contains(&name.to_lowercase()))
+            && self.class.as_ref().is_none_or(|class| classes.iter().any(|c| c.eq_ignore_ascii_case(class)))
+    }
+}
+
+#[rmcp::tool_router(router = desktop_router, vis = "pub(crate)")]
+impl XdotoolServer {
+    #[rmcp::tool(description = "Wait until a window appears, closes, changes its title or gains focus, optionally only for a given window, title or WM_CLASS, and return the event", annotations(read_only_hint = true), output_schema = output::schema::<WindowEventWait>())]
+    pub async fn wait_for_window_event(
+        &self,
+        Parameters(params): Parameters<WaitForWindowEventParams>,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let started = Instant::now();
+        let timeout = Duration::from_secs(params.timeout_seconds);
+        let with_classes = params.class.is_some();
+        // A closed window can't be looked up any more, so filters need what it was while open
+        let filtered = params.name.is_some() || with_classes;
+        let closes = params.events.is_empty() || params.events.contains(&WindowEventKind::Closed);
+        let mut known = if filtered && closes { KnownWindows::current(with_classes) } else { KnownWindows::default() };
+        let mut previous = Self::snapshot(false);
+        let mut title = params.window_id.as_deref().and_then(|id| Self::window_name(id).ok());
+
+        while started.elapsed() < timeout {
+            sleep_or_cancel(crate::wait::POLL_INTERVAL, &context.ct).await?;
+            let current = Self::snapshot(false);
+            let mut events = current.events(&previous);
+            if let Some(id) = &params.window_id {
+                let now = Self::window_name_now(id).ok();
+                let focused = current.active_window.as_ref().is_some_and(|window| window.id == *id);
+                if now.is_some() && now != title && !focused {
+                    events.push((WindowEventKind::TitleChanged, id.clone()));
+                }
+                title = now;
+            }
+            for (kind, id) in events {
+                let (event, classes) = known.event(kind, &id, &current, with_classes);
+                if !params.matches(&event, &classes) {
+                    continue;
+                }
+                let waited_ms = started.elapsed().as_millis() as u64;
+                let text = format!("{} after {}ms", event.describe(), waited_ms);
+                return Ok(output::structured(text, &WindowEventWait { event, waited_ms }));
+            }
+            previous = current;
+        }
+        Err(McpError::internal_error(
+            format!("Timed out after {}s waiting for a window event", params.timeout_seconds),
+            None
+        ))
+    }
+
+    #[rmcp::tool(description = "Watch for windows appearing, closing, changing title, gaining or losing focus for a while, optionally only windows matching a search, and return the events seen. With a progress token, each event is also sent as a progress notification as it happens, e.g. to react to an unexpected dialog", annotations(read_only_hint = true), output_schema = output::schema::<WindowEventWatch>())]
+    pub async fn watch_window_events(
+        &self,
+        Parameters(params): Parameters<WatchWindowEventsParams>,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let started = Instant::now();
+        let timeout = Duration::from_secs(params.timeout_seconds);
+        let progress = context.meta.get_progress_token();
+        let search = |query: &str| Self::search_windows(query, &params.search_type, false);
+        // Windows that matched the search at some point, so their closing still counts
+        let mut matching: HashSet<String> = match &params.query {
+            Some(query) => search(query)?.into_iter().collect(),
+            None => HashSet::new(),
+        };
+        let mut known = KnownWindows::current(false);
+        let mut previous = Self::snapshot(false);
+        let mut seen = vec![];
+
+        while started.elapsed() < timeout && params.max_events.is_none_or(|max| seen.len() < max) {
+            sleep_or_cancel(crate::wait::POLL_INTERVAL, &context.ct).await?;
+            let current = Self::snapshot(false);
+            let events = current.events(&previous);
+            if let (Some(query), false) = (&params.query, events.is_empty()) {
+                matching.extend(search(query)?);
+            }
+            for (kind, id) in events {
+                if !params.events.is_empty() && !params.events.contains(&kind) {
+                    continue;
+                }
+                if params.query.is_some() && !matching.contains(&id) {
+                    continue;
+                }
+                let (event, _) = known.event(kind, &id, &current, false);
+                if let Some(token) = &progress {
+                    let notification = ProgressNotificationParam {
+                        progress_token: token.clone(),
+                        progress: (seen.len() + 1) as f64,
+                        total: params.max_events.map(|max| max as f64),
+                        message: Some(event.describe()),
+                    };
+                    if let Err(e) = context.peer.notify_progress(notification).await {
+                        tracing::debug!("Failed to send a window event as progress: {}", e);
+                    }
+                }
+                seen.push(event);
+                if params.max_events.is_some_and(|max| seen.len() >= max) {
+                    break;
+                }
+            }
+            previous = current;
+        }
+        let watched_ms = started.elapsed().as_millis() as u64;
+        let text = if seen.is_empty() {
+            format!("No window events in {}ms", watched_ms)
+        } else {
+            let lines: Vec<String> = seen.iter().map(WindowEvent::describe).collect();
+            format!("{} window event(s) in {}ms:\n{}", seen.len(), watched_ms, lines.join("\n"))
+        };
+        Ok(output::structured(text, &WindowEventWatch { events: seen, watched_ms }))
+    }
+
+    #[rmcp::tool(description = "Wait until a window's title matches a regex (or stops matching it), e.g. a browser tab's title once the page has loaded or an editor's once the '*' of unsaved changes is gone, and return the title", annotations(read_only_hint = true), output_schema = output::schema::<TitleWait>())]
+    pub async fn wait_for_title(
+        &self,
+        Parameters(params): Parameters<WaitForTitleParams>,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let pattern = Regex::new(&params.pattern).map_err(|e| McpError::internal_error(
+            format!("Invalid pattern '{}': {}", params.pattern, e),
+            None
+        ))?;
+        let started = Instant::now();
+        let timeout = Duration::from_secs(params.timeout_seconds);
+        loop {
+            let title = Self::window_name_now(&params.window_id).map_err(|e| McpError::internal_error(
+                format!("Can't read the title of window {}, it may have closed: {}", params.window_id, e.message),
+                None
+            ))?;
+            if pattern.is_match(&title) != params.until_gone {
+                let waited_ms = started.elapsed().as_millis() as u64;
+                let text = format!("Window {} is titled \"{}\" after {}ms", params.window_id, title, waited_ms);
+                let window = output::Window { id: params.window_id, name: Some(title) };
+                return Ok(output::structured(text, &TitleWait { window, waited_ms }));
+            }
+            if started.elapsed() >= timeout {
+                return Err(McpError::internal_error(
+                    format!(
+                        "Timed out after {}s waiting for the title of window {} to {} '{}'; it's \"{}\"",
+                        params.timeout_seconds,
+                        params.window_id,
+                        if params.until_gone { "stop matching" } else { "match" },
+                        params.pattern,
+                        title
+                    ),
+                    None
+                ));
+            }
+            sleep_or_cancel(crate::wait::POLL_INTERVAL, &context.ct).await?;
+        }
+    }
+}
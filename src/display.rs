@@ -0,0 +1,236 @@
+//! Which X display a tool call works on.
+//!
+//! Every tool takes an optional `display` argument, and the `display`
+//! setting gives the default; without either, calls use the server's own
+//! $DISPLAY. The argument is a display name (`:1`, `:0.1`, `host:10`) or the
+//! name of a `[[displays]]` profile, which pairs a display with the
+//! XAUTHORITY file holding its cookie. One server can so drive several X
+//! servers: Xvfb test displays, or a lab machine's session reached over an
+//! SSH tunnel. The display is held in a task-local for the length of the
+//! call and handed to every X program the call runs (xdotool, xprop, xclip,
+//! ffmpeg). libxdo keeps its one connection to $DISPLAY, so calls for another
+//! display run the xdotool binary instead. Background work (the desktop
+//! watcher, the kill switch) stays on $DISPLAY.
+//!
+//! Profiles are checked when the config loads; `check_displays` tries to
+//! reach each of them.
+
+use crate::{environment, output, XdotoolServer};
+use regex_automata::meta::Regex;
+use rmcp::model::{CallToolRequestParam, CallToolResult, Tool};
+use rmcp::service::{RequestContext, RoleServer};
+use rmcp::ErrorData as McpError;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+
+/// Name of the argument every tool accepts
+const ARGUMENT: &str = "display";
+
+/// How long `check_displays` waits for each display
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A `[[displays]]` entry
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DisplayProfile {
+    /// What calls pass as `display` to use this profile
+    pub name: String,
+    /// The X display, e.g. "localhost:10" for a tunnelled one
+    pub display: String,
+    /// Cookie file for the display. Unset uses the server's own XAUTHORITY.
+    pub xauthority: Option<PathBuf>,
+}
+
+/// The display a call works on
+#[derive(Debug, Clone)]
+struct Target {
+    display: String,
+    xauthority: Option<PathBuf>,
+}
+
+tokio::task_local! {
+    static TARGET: Target;
+}
+
+/// Whether a display name looks like `[host]:display[.screen]`
+fn valid(display: &str) -> bool {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| Regex::new(r"^[A-Za-z0-9._/-]*:[0-9]+(\.[0-9]+)?$").unwrap()).is_match(display)
+}
+
+/// Check the `[[displays]]` profiles and the `display` default
+pub fn validate(profiles: &[DisplayProfile], default: Option<&str>) -> Result<(), String> {
+    for (i, profile) in profiles.iter().enumerate() {
+        if profile.name.is_empty() || profile.name.contains(':') {
+            return Err(format!("displays[{}]: name '{}' must be non-empty and without ':'", i, profile.name));
+        }
+        if profiles[..i].iter().any(|other| other.name == profile.name) {
+            return Err(format!("displays[{}]: duplicate name '{}'", i, profile.name));
+        }
+        if !valid(&profile.display) {
+            return Err(format!("displays[{}]: invalid display '{}'", i, profile.display));
+        }
+        if let Some(path) = &profile.xauthority {
+            std::fs::metadata(path).map_err(|e| format!("displays[{}]: xauthority {}: {}", i, path.display(), e))?;
+        }
+    }
+    if let Some(default) = default {
+        if !valid(default) && !profiles.iter().any(|profile| profile.name == default) {
+            return Err(format!("display '{}' is neither a display name nor a [[displays]] profile", default));
+        }
+    }
+    Ok(())
+}
+
+/// The display a `display` value means: a profile's, or the value itself
+fn resolve(value: &str, profiles: &[DisplayProfile]) -> Result<Target, McpError> {
+    if let Some(profile) = profiles.iter().find(|profile| profile.name == value) {
+        return Ok(Target { display: profile.display.clone(), xauthority: profile.xauthority.clone() });
+    }
+    if !valid(value) {
+        return Err(McpError::internal_error(
+            format!("Invalid display '{}'; expected a display like ':1', ':0.1' or 'host:10', or a [[displays]] profile name", value),
+            None
+        ));
+    }
+    Ok(Target { display: value.to_string(), xauthority: None })
+}
+
+/// The display the current call asked for, if it isn't the server's own
+pub fn current() -> Option<String> {
+    TARGET.try_with(|target| target.display.clone()).ok()
+}
+
+/// Add the `display` argument to a tool's input schema
+pub fn add_argument(tool: &mut Tool) {
+    let schema = Arc::make_mut(&mut tool.input_schema);
+    let properties = schema.entry("properties").or_insert_with(|| json!({}));
+    if let Value::Object(properties) = properties {
+        properties.insert(ARGUMENT.to_string(), json!({
+            "type": "string",
+            "description": "X display to work on: a display such as ':1' or ':0.1', or a configured display profile name. Default: the server's display setting, else its $DISPLAY",
+        }));
+    }
+}
+
+impl XdotoolServer {
+    /// Take the `display` argument out of a call, falling back to the configured default
+    pub(crate) fn take_display(&self, request: &mut CallToolRequestParam) -> Result<Option<String>, McpError> {
+        match request.arguments.as_mut().and_then(|arguments| arguments.remove(ARGUMENT)) {
+            Some(Value::String(display)) => Ok(Some(display)),
+            Some(Value::Null) | None => Ok(self.config.display.clone()),
+            Some(other) => Err(McpError::internal_error(
+                format!("display must be a string like ':1', not {}", other),
+                None
+            )),
+        }
+    }
+
+    /// Run a call's future on `display`, if it has one
+    pub(crate) async fn on_display<F: Future<Output = Result<T, McpError>>, T>(&self, display: Option<String>, future: F) -> Result<T, McpError> {
+        let Some(display) = display else {
+            return future.await;
+        };
+        let target = resolve(&display, &self.config.displays)?;
+        // The server's own display needs no override
+        if target.xauthority.is_none() && std::env::var("DISPLAY").is_ok_and(|own| own == target.display) {
+            return future.await;
+        }
+        TARGET.scope(target, future).await
+    }
+}
+
+fn configure(command: &mut std::process::Command, target: &Target) {
+    command.env("DISPLAY", &target.display);
+    if let Some(xauthority) = &target.xauthority {
+        command.env("XAUTHORITY", xauthority);
+    }
+}
+
+/// A command for an X program, pointed at the current call's display
+pub fn command(program: &str) -> std::process::Command {
+    let mut command = std::process::Command::new(environment::program(program));
+    let _ = TARGET.try_with(|target| configure(&mut command, target));
+    command
+}
+
+/// `command`, for tokio
+pub fn async_command(program: &str) -> tokio::process::Command {
+    let mut command = std::process::Command::new(environment::program(program));
+    let _ = TARGET.try_with(|target| configure(&mut command, target));
+    command.into()
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct DisplayCheck {
+    /// Profile name, absent for the server's own display
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    pub display: String,
+    pub reachable: bool,
+    /// Screen size, when reachable
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
+    /// Round trip for the query, in milliseconds
+    pub latency_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct DisplayChecks {
+    pub displays: Vec<DisplayCheck>,
+}
+
+#[rmcp::tool_router(router = display_router, vis = "pub(crate)")]
+impl XdotoolServer {
+    #[rmcp::tool(description = "Check that the server's own X display and every configured display profile can be reached, with their screen size and round-trip time", annotations(read_only_hint = true), output_schema = output::schema::<DisplayChecks>())]
+    pub async fn check_displays(&self, context: RequestContext<RoleServer>) -> Result<CallToolResult, McpError> {
+        let own = std::env::var("DISPLAY").unwrap_or_else(|_| ":0".to_string());
+        let mut targets = vec![(None, own.clone(), Target { display: own, xauthority: None })];
+        for profile in &self.config.displays {
+            let target = Target { display: profile.display.clone(), xauthority: profile.xauthority.clone() };
+            targets.push((Some(profile.name.clone()), profile.display.clone(), target));
+        }
+
+        let mut displays = vec![];
+        let mut lines = vec![];
+        for (profile, display, target) in targets {
+            let started = Instant::now();
+            let query = TARGET.scope(target, Self::run_xdotool_until(&["getdisplaygeometry"], &context.ct));
+            let result = match tokio::time::timeout(CHECK_TIMEOUT, query).await {
+                Ok(result) => result.map_err(|e| e.message.trim().to_string()),
+                Err(_) => Err(format!("no answer within {}s", CHECK_TIMEOUT.as_secs())),
+            };
+            let latency_ms = started.elapsed().as_millis() as u64;
+            let size = result.as_ref().ok()
+                .and_then(|stdout| stdout.split_once(' ').map(|(w, h)| (w.trim().parse().ok(), h.trim().parse().ok())));
+            let (width, height) = size.unwrap_or((None, None));
+            let label = match &profile {
+                Some(name) => format!("{} ({})", name, display),
+                None => format!("{} (server's own)", display),
+            };
+            lines.push(match &result {
+                Ok(_) => format!("{}: ok, {}x{}, {}ms", label, width.unwrap_or(0), height.unwrap_or(0), latency_ms),
+                Err(e) => format!("{}: unreachable: {}", label, e),
+            });
+            displays.push(DisplayCheck {
+                profile,
+                display,
+                reachable: result.is_ok(),
+                width,
+                height,
+                latency_ms,
+                error: result.err(),
+            });
+        }
+        Ok(output::structured(lines.join("\n"), &DisplayChecks { displays }))
+    }
+}
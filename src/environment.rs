@@ -0,0 +1,250 @@
+//! The xdotool binary and the rest of the environment the server runs in.
+//!
+//! `xdotool_path` picks the binary; unset, it's looked up in PATH. It's
+//! probed once, on first use or at startup: `xdotool version` for its
+//! version, `xdotool help` for the commands it has. A missing binary then
+//! fails calls with how to install or point to it, and one too old for a
+//! command (Ubuntu 20.04's 3.20160805 has no `getwindowclassname`,
+//! `windowclose` or `windowstate`) fails calls using that command by name,
+//! instead of xdotool's usage text. `get_environment` reports the probe
+//! together with the session, backends and helper programs found.
+
+use crate::{backend, display, output, process, XdotoolServer};
+use rmcp::{model::*, ErrorData as McpError};
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::OnceLock;
+
+/// xdotool commands tools run, reported when the installed xdotool lacks them
+const USED_COMMANDS: &[&str] = &[
+    "click", "get_desktop", "get_desktop_for_window", "get_num_desktops", "getactivewindow",
+    "getdisplaygeometry", "getmouselocation", "getwindowclassname", "getwindowfocus",
+    "getwindowgeometry", "getwindowname", "getwindowpid", "key", "keydown", "keyup",
+    "mousedown", "mousemove", "mousemove_relative", "mouseup", "search", "set_desktop",
+    "set_desktop_for_window", "set_num_desktops", "sleep", "type", "windowactivate",
+    "windowclose", "windowfocus", "windowkill", "windowmap", "windowminimize", "windowmove",
+    "windowquit", "windowraise", "windowsize", "windowstate", "windowunmap",
+];
+
+/// Helper programs some tools need, listed by get_environment when found in PATH
+const HELPERS: &[&str] = &[
+    "xprop", "xclip", "ffmpeg", "tesseract", "xinput", "xmodmap", "xprintidle", "ydotool", "wtype",
+    "swaymsg", "kdotool", "Xvfb", "Xephyr", "dbus-send", "xdg-open", "notify-send",
+];
+
+static XDOTOOL_PATH: OnceLock<PathBuf> = OnceLock::new();
+
+static PROBE: OnceLock<XdotoolInfo> = OnceLock::new();
+
+/// Set the xdotool binary for this process. Later calls have no effect.
+pub fn set_xdotool_path(path: PathBuf) {
+    let _ = XDOTOOL_PATH.set(path);
+}
+
+fn xdotool_path() -> &'static Path {
+    XDOTOOL_PATH.get().map(PathBuf::as_path).unwrap_or(Path::new("xdotool"))
+}
+
+/// What to run for `program`: the configured binary for xdotool, else the name itself
+pub fn program(program: &str) -> &OsStr {
+    match program {
+        "xdotool" => xdotool_path().as_os_str(),
+        _ => OsStr::new(program),
+    }
+}
+
+/// What the probe found out about xdotool
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct XdotoolInfo {
+    /// The binary, as configured or looked up in PATH
+    pub path: String,
+    pub found: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// Commands `xdotool help` lists
+    pub commands: Vec<String>,
+    /// Commands tools use that this xdotool lacks
+    pub missing: Vec<String>,
+    /// Why xdotool couldn't be run, if it couldn't
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+fn run(args: &[&str]) -> Result<String, String> {
+    let output = process::output(Command::new(xdotool_path()).args(args)).map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => "not found".to_string(),
+        _ => e.to_string(),
+    })?;
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn run_probe() -> XdotoolInfo {
+    let mut info = XdotoolInfo {
+        path: xdotool_path().display().to_string(),
+        found: false,
+        version: None,
+        commands: vec![],
+        missing: vec![],
+        error: None,
+    };
+    let version = match run(&["version"]) {
+        Ok(version) => version,
+        Err(e) => {
+            info.error = Some(e);
+            return info;
+        }
+    };
+    info.found = true;
+    // "xdotool version 3.20160805.1"
+    info.version = version.split_whitespace().last().map(str::to_string);
+    // "Available commands:" followed by one indented command per line
+    if let Ok(help) = run(&["help"]) {
+        info.commands = help.lines()
+            .skip_while(|line| !line.starts_with("Available commands"))
+            .skip(1)
+            .map(str::trim)
+            .filter(|command| !command.is_empty() && !command.contains(' '))
+            .map(str::to_string)
+            .collect();
+    }
+    if !info.commands.is_empty() {
+        info.missing = USED_COMMANDS.iter().filter(|command| !info.commands.iter().any(|known| known == *command))
+            .map(|command| command.to_string())
+            .collect();
+    }
+    info
+}
+
+/// The probe's results, probing xdotool the first time
+pub fn xdotool() -> &'static XdotoolInfo {
+    PROBE.get_or_init(run_probe)
+}
+
+/// Probe xdotool now and log what's missing. Call once at startup.
+pub fn check() {
+    let info = xdotool();
+    match (&info.error, &info.version) {
+        (Some(e), _) => tracing::warn!("Can't run xdotool ({}): {}. Install xdotool or set xdotool_path in the config", info.path, e),
+        (None, version) => tracing::info!("Using xdotool {} at {}", version.as_deref().unwrap_or("(unknown version)"), info.path),
+    }
+    if !info.missing.is_empty() {
+        tracing::warn!("This xdotool is too old for some tools; it has no {} command(s)", info.missing.join(", "));
+    }
+}
+
+/// The error for running xdotool when it couldn't be found
+pub(crate) fn not_found_error(e: &std::io::Error) -> McpError {
+    McpError::internal_error(
+        format!(
+            "Can't run xdotool ({}): {}. Install it (apt install xdotool, pacman -S xdotool) or set xdotool_path in the server's config",
+            xdotool_path().display(),
+            e
+        ),
+        None
+    )
+}
+
+/// Refuse an invocation using a command the installed xdotool doesn't have
+pub(crate) fn check_commands(commands: &[&[&str]]) -> Result<(), McpError> {
+    let info = xdotool();
+    if info.commands.is_empty() {
+        return Ok(());
+    }
+    match commands.iter().find(|command| !info.commands.iter().any(|known| known == command[0])) {
+        Some(command) => Err(McpError::internal_error(
+            format!(
+                "The installed xdotool ({}) has no {} command; it needs a newer xdotool",
+                info.version.as_deref().unwrap_or("unknown version"),
+                command[0]
+            ),
+            None
+        )),
+        None => Ok(()),
+    }
+}
+
+fn in_path(program: &str) -> bool {
+    find_program(program).is_some()
+}
+
+/// Where the server's own PATH finds an executable named `program`
+pub(crate) fn find_program(program: &str) -> Option<PathBuf> {
+    use std::os::unix::fs::PermissionsExt;
+    let path = std::env::var_os("PATH")?;
+    std::env::split_paths(&path)
+        .map(|dir| dir.join(program))
+        .find(|candidate| candidate.metadata().is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0))
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct Environment {
+    pub xdotool: XdotoolInfo,
+    pub input_backend: String,
+    pub window_backend: String,
+    /// $XDG_SESSION_TYPE, e.g. "x11" or "wayland"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session_type: Option<String>,
+    /// The X display this call works on
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub wayland_display: Option<String>,
+    /// Cargo features this build has
+    pub features: Vec<String>,
+    /// Helper programs found in PATH
+    pub programs: Vec<String>,
+}
+
+fn features() -> Vec<String> {
+    let features = [
+        ("screenshots", cfg!(feature = "screenshots")),
+        ("ocr", cfg!(feature = "ocr")),
+        ("recording", cfg!(feature = "recording")),
+        ("wayland", cfg!(feature = "wayland")),
+        ("libxdo", cfg!(feature = "libxdo")),
+        ("uinput", cfg!(feature = "uinput")),
+        ("ping", cfg!(feature = "ping")),
+        ("accessibility", cfg!(feature = "accessibility")),
+    ];
+    features.iter().filter(|(_, enabled)| *enabled).map(|(feature, _)| feature.to_string()).collect()
+}
+
+#[rmcp::tool_router(router = environment_router, vis = "pub(crate)")]
+impl XdotoolServer {
+    #[rmcp::tool(description = "Report the xdotool version and commands available, the session type, display, input and window backends, build features and helper programs found, to see what tools can work here", annotations(read_only_hint = true), output_schema = output::schema::<Environment>())]
+    pub async fn get_environment(&self) -> Result<CallToolResult, McpError> {
+        let environment = Environment {
+            xdotool: xdotool().clone(),
+            input_backend: backend::input().name().to_string(),
+            window_backend: backend::windows().name().to_string(),
+            session_type: std::env::var("XDG_SESSION_TYPE").ok(),
+            display: display::current().or_else(|| std::env::var("DISPLAY").ok()),
+            wayland_display: std::env::var("WAYLAND_DISPLAY").ok(),
+            features: features(),
+            programs: HELPERS.iter().filter(|program| in_path(program)).map(|program| program.to_string()).collect(),
+        };
+        let xdotool = match (&environment.xdotool.error, &environment.xdotool.version) {
+            (Some(e), _) => format!("xdotool: can't run {} ({})", environment.xdotool.path, e),
+            (None, version) => format!("xdotool {} at {}", version.as_deref().unwrap_or("(unknown version)"), environment.xdotool.path),
+        };
+        let mut lines = vec![
+            xdotool,
+            format!("Input through {}, window commands through {}", environment.input_backend, environment.window_backend),
+            format!(
+                "Session: {}, DISPLAY={}, WAYLAND_DISPLAY={}",
+                environment.session_type.as_deref().unwrap_or("unknown"),
+                environment.display.as_deref().unwrap_or("unset"),
+                environment.wayland_display.as_deref().unwrap_or("unset")
+            ),
+            format!("Features: {}", environment.features.join(", ")),
+            format!("Programs found: {}", environment.programs.join(", ")),
+        ];
+        if !environment.xdotool.missing.is_empty() {
+            lines.insert(1, format!("Missing xdotool commands: {}", environment.xdotool.missing.join(", ")));
+        }
+        Ok(output::structured(lines.join("\n"), &environment))
+    }
+}
@@ -0,0 +1,114 @@
+//! Linux evdev key codes, for backends that send input below X.
+//!
+//! ydotool and uinput devices take the kernel's key codes rather than X
+//! keysyms, so keys are looked up in a table of the usual keysym names; a
+//! key that isn't there is an error rather than a guess. The codes are
+//! physical keys, so what they type depends on the keyboard layout.
+
+use rmcp::ErrorData as McpError;
+
+pub const KEY_LEFTSHIFT: u16 = 42;
+
+/// Linux evdev key code for a keysym name
+pub fn key_code(name: &str) -> Option<u16> {
+    const LETTERS: &str = "qwertyuiopasdfghjklzxcvbnm";
+    const LETTER_CODES: [u16; 26] = [16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 30, 31, 32, 33, 34, 35, 36, 37, 38, 44, 45, 46, 47, 48, 49, 50];
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        if let Some(i) = LETTERS.find(c.to_ascii_lowercase()) {
+            return Some(LETTER_CODES[i]);
+        }
+        if let Some(digit) = c.to_digit(10) {
+            return Some(if digit == 0 { 11 } else { digit as u16 + 1 });
+        }
+    }
+    if let Some(n) = name.strip_prefix('F').and_then(|n| n.parse::<u16>().ok()) {
+        return match n {
+            1..=10 => Some(58 + n),
+            11 | 12 => Some(76 + n),
+            13..=24 => Some(170 + n),
+            _ => None,
+        };
+    }
+    let code = match name.to_lowercase().as_str() {
+        "escape" | "esc" => 1,
+        "minus" => 12,
+        "equal" => 13,
+        "backspace" => 14,
+        "tab" => 15,
+        "bracketleft" => 26,
+        "bracketright" => 27,
+        "return" | "enter" => 28,
+        "control_l" | "ctrl" | "control" => 29,
+        "semicolon" => 39,
+        "apostrophe" => 40,
+        "grave" => 41,
+        "shift_l" | "shift" => 42,
+        "backslash" => 43,
+        "comma" => 51,
+        "period" => 52,
+        "slash" => 53,
+        "shift_r" => 54,
+        "kp_multiply" => 55,
+        "alt_l" | "alt" => 56,
+        "space" => 57,
+        "caps_lock" => 58,
+        "num_lock" => 69,
+        "scroll_lock" => 70,
+        "kp_7" => 71,
+        "kp_8" => 72,
+        "kp_9" => 73,
+        "kp_subtract" => 74,
+        "kp_4" => 75,
+        "kp_5" => 76,
+        "kp_6" => 77,
+        "kp_add" => 78,
+        "kp_1" => 79,
+        "kp_2" => 80,
+        "kp_3" => 81,
+        "kp_0" => 82,
+        "kp_decimal" => 83,
+        "kp_enter" => 96,
+        "control_r" => 97,
+        "kp_divide" => 98,
+        "print" => 99,
+        "alt_r" | "iso_level3_shift" => 100,
+        "home" => 102,
+        "up" => 103,
+        "prior" | "page_up" => 104,
+        "left" => 105,
+        "right" => 106,
+        "end" => 107,
+        "down" => 108,
+        "next" | "page_down" => 109,
+        "insert" => 110,
+        "delete" => 111,
+        "xf86audiomute" => 113,
+        "xf86audiolowervolume" => 114,
+        "xf86audioraisevolume" => 115,
+        "pause" => 119,
+        "super_l" | "super" | "meta_l" | "meta" => 125,
+        "super_r" | "meta_r" => 126,
+        "menu" => 127,
+        _ => return None,
+    };
+    Some(code)
+}
+
+/// Key codes for a combo like "ctrl+shift+t", in press order. Uppercase letters add shift.
+pub fn combo_codes(combo: &str) -> Result<Vec<u16>, McpError> {
+    let mut codes = vec![];
+    for key in combo.split('+') {
+        let code = key_code(key).ok_or_else(|| McpError::internal_error(
+            format!("Key '{}' has no evdev key code; use a keysym name like Return, ctrl or F5", key),
+            None
+        ))?;
+        let shifted = key.len() == 1 && key.chars().all(|c| c.is_ascii_uppercase());
+        for code in shifted.then_some(KEY_LEFTSHIFT).into_iter().chain([code]) {
+            if !codes.contains(&code) {
+                codes.push(code);
+            }
+        }
+    }
+    Ok(codes)
+}
@@ -0,0 +1,316 @@
+//! Virtual X displays the server starts and stops itself.
+//!
+//! `start_virtual_display` runs Xvfb (invisible, for CI-style automation) or
+//! Xephyr (a nested display in a window on your own) and returns its display
+//! name, which every tool takes as its `display` argument. Called with it,
+//! `run_in_display` starts programs on the display and `stop_virtual_display`
+//! ends them and the display. Nothing done there reaches the real desktop.
+//!
+//! Only programs named in `[headless] programs` can be started, and at most
+//! `max_displays` displays run at once. The X servers listen only on their
+//! local socket, without an authorization cookie. Displays and the programs
+//! on them are killed when the server stops.
+
+use crate::{display, output, XdotoolServer};
+use rmcp::{
+    handler::server::wrapper::Parameters,
+    model::*,
+    ErrorData as McpError,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::{Child, Command};
+
+/// How long an X server gets to start accepting connections
+const START_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The `[headless]` config table
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Headless {
+    /// Programs run_in_display may start, as the command names them: a name looked up in PATH, or a path. Empty allows none.
+    pub programs: Vec<String>,
+    /// Virtual displays that may run at once
+    pub max_displays: usize,
+}
+
+impl Default for Headless {
+    fn default() -> Self {
+        Self { programs: vec![], max_displays: 4 }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum XServer {
+    /// Invisible, in memory
+    Xvfb,
+    /// In a window on the server's own display
+    Xephyr,
+}
+
+impl XServer {
+    fn program(self) -> &'static str {
+        match self {
+            XServer::Xvfb => "Xvfb",
+            XServer::Xephyr => "Xephyr",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct StartVirtualDisplayParams {
+    #[schemars(description = "Screen width in pixels. Default: 1280")]
+    #[serde(default = "default_width")]
+    pub width: u32,
+    #[schemars(description = "Screen height in pixels. Default: 800")]
+    #[serde(default = "default_height")]
+    pub height: u32,
+    #[schemars(description = "Color depth in bits, Xvfb only. Default: 24")]
+    #[serde(default = "default_depth")]
+    pub depth: u32,
+    #[schemars(description = "'xvfb' for an invisible display, 'xephyr' for one in a window on the real desktop. Default: xvfb")]
+    #[serde(default = "default_server")]
+    pub server: XServer,
+}
+
+fn default_width() -> u32 { 1280 }
+
+fn default_height() -> u32 { 800 }
+
+fn default_depth() -> u32 { 24 }
+
+fn default_server() -> XServer { XServer::Xvfb }
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct RunInDisplayParams {
+    #[schemars(description = "Program and arguments, e.g. [\"xterm\", \"-geometry\", \"80x24\"]. The program must be in the server's headless programs list")]
+    pub command: Vec<String>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct VirtualDisplay {
+    /// What to pass as other tools' `display` argument
+    pub display: String,
+    pub server: XServer,
+    pub width: u32,
+    pub height: u32,
+    /// Programs started on it, with whether they're still running
+    pub programs: Vec<DisplayProgram>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct DisplayProgram {
+    pub pid: Option<u32>,
+    pub command: Vec<String>,
+    pub running: bool,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct VirtualDisplays {
+    pub displays: Vec<VirtualDisplay>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct VirtualDisplayStopped {
+    pub display: String,
+    /// Programs that were still running and got killed
+    pub killed: usize,
+}
+
+/// A running virtual display and what was started on it
+#[derive(Debug)]
+struct Session {
+    display: String,
+    server: XServer,
+    width: u32,
+    height: u32,
+    child: Child,
+    programs: Vec<(Vec<String>, Child)>,
+}
+
+impl Session {
+    fn describe(&mut self) -> VirtualDisplay {
+        VirtualDisplay {
+            display: self.display.clone(),
+            server: self.server,
+            width: self.width,
+            height: self.height,
+            programs: self.programs.iter_mut().map(|(command, child)| DisplayProgram {
+                pid: child.id(),
+                command: command.clone(),
+                running: matches!(child.try_wait(), Ok(None)),
+            }).collect(),
+        }
+    }
+}
+
+/// The virtual displays this server runs. Dropping it kills them.
+#[derive(Debug, Default)]
+pub struct Sessions {
+    sessions: Vec<Session>,
+}
+
+/// Start an X server and wait until it says which display it took
+async fn start(params: &StartVirtualDisplayParams) -> Result<(Child, String), McpError> {
+    let program = params.server.program();
+    let mut command = Command::new(program);
+    // -displayfd picks a free display and writes its number once the server accepts connections
+    command.args(["-displayfd", "1", "-nolisten", "tcp", "-screen"]);
+    match params.server {
+        XServer::Xvfb => command.args(["0", &format!("{}x{}x{}", params.width, params.height, params.depth)]),
+        XServer::Xephyr => command.arg(format!("{}x{}", params.width, params.height)),
+    };
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| McpError::internal_error(format!("Failed to run {}: {}", program, e), None))?;
+
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    let mut line = String::new();
+    match tokio::time::timeout(START_TIMEOUT, stdout.read_line(&mut line)).await {
+        Ok(Ok(_)) if line.trim().parse::<u32>().is_ok() => Ok((child, format!(":{}", line.trim()))),
+        Ok(_) => {
+            let status = child.wait().await.map(|status| status.to_string()).unwrap_or_default();
+            Err(McpError::internal_error(
+                format!("{} exited ({}) before it was ready", program, status),
+                None
+            ))
+        }
+        Err(_) => Err(McpError::internal_error(
+            format!("{} didn't start within {}s", program, START_TIMEOUT.as_secs()),
+            None
+        )),
+    }
+}
+
+/// The virtual display a call named as its `display`
+fn target() -> Result<String, McpError> {
+    display::current().ok_or_else(|| McpError::invalid_params(
+        "Pass the display start_virtual_display returned as the display argument",
+        None
+    ))
+}
+
+fn not_started(display: &str) -> McpError {
+    McpError::internal_error(
+        format!("{} isn't a virtual display this server started", display),
+        None
+    )
+}
+
+#[rmcp::tool_router(router = headless_router, vis = "pub(crate)")]
+impl XdotoolServer {
+    #[rmcp::tool(description = "Start a virtual X display (Xvfb, or Xephyr in a window) to automate apps without touching the real desktop. Returns its display name; pass it as other tools' `display` argument and start apps on it with run_in_display", annotations(read_only_hint = false, destructive_hint = false), output_schema = output::schema::<VirtualDisplay>())]
+    pub async fn start_virtual_display(
+        &self,
+        Parameters(params): Parameters<StartVirtualDisplayParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let max = self.config.headless.max_displays;
+        if self.virtual_displays.lock().unwrap().sessions.len() >= max {
+            return Err(McpError::internal_error(
+                format!("Already running {} virtual displays, the max_displays limit; stop one first", max),
+                None
+            ));
+        }
+        let (child, display) = start(&params).await?;
+        let mut session = Session {
+            display,
+            server: params.server,
+            width: params.width,
+            height: params.height,
+            child,
+            programs: vec![],
+        };
+        let started = session.describe();
+        tracing::info!("Started {} on {}", params.server.program(), started.display);
+        self.virtual_displays.lock().unwrap().sessions.push(session);
+        Ok(output::structured(
+            format!("Virtual display {} ({}x{}) is running", started.display, started.width, started.height),
+            &started
+        ))
+    }
+
+    #[rmcp::tool(description = "Start a program on a virtual display from start_virtual_display, given as the display argument. Only programs the server's config allows can be started", annotations(read_only_hint = false, destructive_hint = false, open_world_hint = true), output_schema = output::schema::<DisplayProgram>())]
+    pub async fn run_in_display(
+        &self,
+        Parameters(params): Parameters<RunInDisplayParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let Some(program) = params.command.first().cloned() else {
+            return Err(McpError::invalid_params("command must name a program", None));
+        };
+        if !self.config.headless.programs.contains(&program) {
+            return Err(McpError::internal_error(
+                format!("{} isn't in the headless programs list of the server's config", program),
+                None
+            ));
+        }
+        let display = target()?;
+        let mut sessions = self.virtual_displays.lock().unwrap();
+        let Some(session) = sessions.sessions.iter_mut().find(|session| session.display == display) else {
+            return Err(not_started(&display));
+        };
+        let child = Command::new(&program)
+            .args(&params.command[1..])
+            .env("DISPLAY", &session.display)
+            .env_remove("WAYLAND_DISPLAY")
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| McpError::internal_error(format!("Failed to run {}: {}", program, e), None))?;
+        let started = DisplayProgram { pid: child.id(), command: params.command.clone(), running: true };
+        session.programs.push((params.command, child));
+        Ok(output::structured(
+            format!("Started {} on {} (pid {})", program, display, started.pid.unwrap_or_default()),
+            &started
+        ))
+    }
+
+    #[rmcp::tool(description = "List the virtual displays this server started, with the programs running on them", annotations(read_only_hint = true), output_schema = output::schema::<VirtualDisplays>())]
+    pub async fn list_virtual_displays(&self) -> Result<CallToolResult, McpError> {
+        let displays: Vec<VirtualDisplay> = self.virtual_displays.lock().unwrap().sessions.iter_mut().map(Session::describe).collect();
+        let text = if displays.is_empty() {
+            "No virtual displays running".to_string()
+        } else {
+            displays.iter().map(|display| {
+                let running = display.programs.iter().filter(|program| program.running).count();
+                format!("{}: {:?} {}x{}, {} program(s) running", display.display, display.server, display.width, display.height, running)
+            }).collect::<Vec<_>>().join("\n")
+        };
+        Ok(output::structured(text, &VirtualDisplays { displays }))
+    }
+
+    #[rmcp::tool(description = "Stop a virtual display from start_virtual_display, given as the display argument, killing the programs on it", annotations(read_only_hint = false, destructive_hint = true), output_schema = output::schema::<VirtualDisplayStopped>())]
+    pub async fn stop_virtual_display(&self) -> Result<CallToolResult, McpError> {
+        let display = target()?;
+        let session = {
+            let mut sessions = self.virtual_displays.lock().unwrap();
+            let index = sessions.sessions.iter().position(|session| session.display == display);
+            index.map(|index| sessions.sessions.remove(index))
+        };
+        let Some(mut session) = session else {
+            return Err(not_started(&display));
+        };
+        let mut killed = 0;
+        for (_, child) in &mut session.programs {
+            if matches!(child.try_wait(), Ok(None)) {
+                let _ = child.kill().await;
+                killed += 1;
+            }
+        }
+        let _ = session.child.kill().await;
+        tracing::info!("Stopped virtual display {}", session.display);
+        Ok(output::structured(
+            format!("Stopped {}, killing {} program(s)", session.display, killed),
+            &VirtualDisplayStopped { display: session.display, killed }
+        ))
+    }
+}
@@ -0,0 +1,38 @@
+//! Record of recent pointer/keyboard actions, used to draw markers on
+//! screenshots when debugging missed clicks.
+
+use std::collections::VecDeque;
+use std::time::Instant;
+
+/// How many actions are remembered
+const HISTORY_CAPACITY: usize = 50;
+
+#[derive(Debug, Clone)]
+#[cfg_attr(not(feature = "screenshots"), allow(dead_code))]
+pub struct Action {
+    /// Short label such as "click left" or "type"
+    pub kind: String,
+    pub x: i32,
+    pub y: i32,
+    pub at: Instant,
+}
+
+#[derive(Debug, Default)]
+pub struct ActionHistory {
+    actions: VecDeque<Action>,
+}
+
+impl ActionHistory {
+    pub fn record(&mut self, kind: impl Into<String>, x: i32, y: i32) {
+        if self.actions.len() == HISTORY_CAPACITY {
+            self.actions.pop_front();
+        }
+        self.actions.push_back(Action { kind: kind.into(), x, y, at: Instant::now() });
+    }
+
+    /// The most recent `count` actions, newest first
+    #[cfg_attr(not(feature = "screenshots"), allow(dead_code))]
+    pub fn recent(&self, count: usize) -> Vec<Action> {
+        self.actions.iter().rev().take(count).cloned().collect()
+    }
+}
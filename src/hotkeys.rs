@@ -0,0 +1,379 @@
+//! Hotkeys and screen edges the user triggers to signal the agent.
+//!
+//! Each `[[hotkeys]]` entry names a key combo, e.g. F9 for "hand control to
+//! the agent" or ctrl+alt+c for "continue". Combos are watched the way the
+//! kill switch is, through `xinput test-xi2 --root`, so they aren't grabbed:
+//! the focused window still gets the keys, and nothing fails when another
+//! program has the same shortcut. A press is logged (sent to clients that
+//! set a log level), added to `desktop://hotkeys`, whose subscribers are
+//! notified, and ends any `wait_for_hotkey` call waiting for it.
+//!
+//! Each `[[screen_edges]]` entry is a corner or edge the user flings the
+//! pointer into, watched with one `xdotool behave_screen_edge` per entry. It
+//! is reported the same way, and can also pause or resume the server, or
+//! replay a stored macro.
+
+use crate::batch::RunOptions;
+use crate::control::{sleep_or_cancel, ActionRegistry};
+use crate::desktop::HOTKEYS_URI;
+use crate::{display, killswitch, macros, output, XdotoolServer};
+use rmcp::{
+    handler::server::wrapper::Parameters,
+    model::*,
+    service::{RequestContext, RoleServer},
+    ErrorData as McpError,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader};
+use std::process::Stdio;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio_util::sync::CancellationToken;
+
+/// How many presses `desktop://hotkeys` keeps
+const RECENT_PRESSES: usize = 20;
+
+/// How often wait_for_hotkey looks for a new press
+const WAIT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A `[[hotkeys]]` entry
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Hotkey {
+    /// What tools and resources call it, e.g. "continue"
+    pub name: String,
+    /// The combo, e.g. "F9" or "ctrl+alt+c"
+    pub keys: String,
+    /// What pressing it means, shown to the agent
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+/// Where on the screen a `[[screen_edges]]` trigger is, as behave_screen_edge names it
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum Edge {
+    Left,
+    TopLeft,
+    Top,
+    TopRight,
+    Right,
+    BottomRight,
+    Bottom,
+    BottomLeft,
+}
+
+impl Edge {
+    fn name(self) -> &'static str {
+        match self {
+            Edge::Left => "left",
+            Edge::TopLeft => "top-left",
+            Edge::Top => "top",
+            Edge::TopRight => "top-right",
+            Edge::Right => "right",
+            Edge::BottomRight => "bottom-right",
+            Edge::Bottom => "bottom",
+            Edge::BottomLeft => "bottom-left",
+        }
+    }
+}
+
+/// What a screen edge does to the server's pause, besides being reported
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EdgePause {
+    Pause,
+    Resume,
+    /// Pause if running, resume if paused
+    Toggle,
+}
+
+/// A `[[screen_edges]]` entry
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScreenEdge {
+    /// What tools and resources call it, e.g. "pause"
+    pub name: String,
+    pub edge: Edge,
+    /// Milliseconds the pointer must stay at the edge before it fires
+    #[serde(default = "default_edge_delay")]
+    pub delay_ms: u64,
+    /// Milliseconds after firing before it can fire again
+    #[serde(default = "default_edge_quiesce")]
+    pub quiesce_ms: u64,
+    /// What triggering it means, shown to the agent
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub pause: Option<EdgePause>,
+    /// Stored macro to replay, without parameters
+    #[serde(default)]
+    pub run_macro: Option<String>,
+}
+
+fn default_edge_delay() -> u64 { 300 }
+
+fn default_edge_quiesce() -> u64 { 2000 }
+
+pub fn validate(hotkeys: &[Hotkey], edges: &[ScreenEdge]) -> Result<(), String> {
+    for (i, hotkey) in hotkeys.iter().enumerate() {
+        if hotkey.name.is_empty() {
+            return Err(format!("hotkeys[{}]: name must not be empty", i));
+        }
+        if hotkeys[..i].iter().any(|other| other.name == hotkey.name) {
+            return Err(format!("hotkeys[{}]: duplicate name '{}'", i, hotkey.name));
+        }
+        if hotkey.keys.split('+').any(|part| part.trim().is_empty()) {
+            return Err(format!("hotkeys[{}]: keys '{}' must be key names joined by '+'", i, hotkey.keys));
+        }
+    }
+    for (i, edge) in edges.iter().enumerate() {
+        if edge.name.is_empty() {
+            return Err(format!("screen_edges[{}]: name must not be empty", i));
+        }
+        if edges[..i].iter().any(|other| other.name == edge.name) || hotkeys.iter().any(|hotkey| hotkey.name == edge.name) {
+            return Err(format!("screen_edges[{}]: duplicate name '{}'", i, edge.name));
+        }
+        if let Some(name) = &edge.run_macro {
+            macros::macro_path(name).map_err(|e| format!("screen_edges[{}]: {}", i, e.message))?;
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct HotkeyPress {
+    pub name: String,
+    /// The combo, for a hotkey
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keys: Option<String>,
+    /// The corner or edge, for a screen edge
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub edge: Option<Edge>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Seconds since the Unix epoch
+    pub timestamp: f64,
+}
+
+impl HotkeyPress {
+    /// What the user did, e.g. "pressed F9" or "moved the pointer to the top-left edge"
+    fn action(&self) -> String {
+        match (&self.keys, self.edge) {
+            (Some(keys), _) => format!("pressed {}", keys),
+            (None, Some(edge)) => format!("moved the pointer to the {} edge", edge.name()),
+            (None, None) => "triggered".to_string(),
+        }
+    }
+}
+
+/// Hotkey presses noticed so far
+#[derive(Debug, Default)]
+pub struct Presses {
+    /// The latest presses, oldest first
+    pub recent: VecDeque<HotkeyPress>,
+    /// Presses ever noticed, so waiters can tell which are new
+    count: u64,
+}
+
+/// Watch the configured hotkeys for as long as the server runs. Problems are logged, not fatal.
+pub fn spawn(server: XdotoolServer) {
+    let runtime = tokio::runtime::Handle::current();
+    std::thread::spawn(move || {
+        let hotkeys = server.config.hotkeys.clone();
+        let combos: Vec<String> = hotkeys.iter().map(|hotkey| hotkey.keys.clone()).collect();
+        let watched = killswitch::watch_combos(&combos, |i| {
+            let hotkey = &hotkeys[i];
+            tracing::info!("Hotkey {} ({}) pressed", hotkey.name, hotkey.keys);
+            server.record_press(HotkeyPress {
+                name: hotkey.name.clone(),
+                keys: Some(hotkey.keys.clone()),
+                edge: None,
+                description: hotkey.description.clone(),
+                timestamp: now(),
+            });
+            let server = server.clone();
+            runtime.spawn(async move { server.notify_updated(&[HOTKEYS_URI]).await });
+        });
+        if let Err(e) = watched {
+            tracing::warn!("Hotkeys unavailable: {}", e);
+        }
+    });
+}
+
+/// Watch each configured screen edge for as long as the server runs. Problems are logged, not fatal.
+pub fn spawn_edges(server: XdotoolServer) {
+    for edge in server.config.screen_edges.clone() {
+        let runtime = tokio::runtime::Handle::current();
+        let server = server.clone();
+        std::thread::spawn(move || {
+            if let Err(e) = watch_edge(&server, &edge, &runtime) {
+                tracing::warn!("Screen edge {} unavailable: {}", edge.name, e);
+            }
+        });
+    }
+}
+
+fn watch_edge(server: &XdotoolServer, edge: &ScreenEdge, runtime: &tokio::runtime::Handle) -> anyhow::Result<()> {
+    // behave_screen_edge runs the chained command each time the edge fires; its output marks the event
+    let mut child = display::command("xdotool")
+        .arg("behave_screen_edge")
+        .args(["--delay", &edge.delay_ms.to_string(), "--quiesce", &edge.quiesce_ms.to_string()])
+        .args([edge.edge.name(), "getmouselocation"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| anyhow::anyhow!("Failed to run xdotool: {}", e))?;
+    let stdout = child.stdout.take().ok_or_else(|| anyhow::anyhow!("No output from xdotool"))?;
+    tracing::info!("Watching the {} screen edge for {}", edge.edge.name(), edge.name);
+
+    for line in BufReader::new(stdout).lines() {
+        line?;
+        tracing::info!("Screen edge {} ({}) triggered", edge.name, edge.edge.name());
+        server.record_press(HotkeyPress {
+            name: edge.name.clone(),
+            keys: None,
+            edge: Some(edge.edge),
+            description: edge.description.clone(),
+            timestamp: now(),
+        });
+        server.apply_pause(edge);
+        let server = server.clone();
+        let edge = edge.clone();
+        runtime.spawn(async move {
+            server.notify_updated(&[HOTKEYS_URI]).await;
+            if let Some(name) = &edge.run_macro {
+                if server.config.dry_run {
+                    tracing::info!("Dry run: screen edge {} would replay macro '{}'", edge.name, name);
+                    return;
+                }
+                match server.run_edge_macro(name).await {
+                    Ok(steps) => tracing::info!("Screen edge {} replayed macro '{}' ({} step(s))", edge.name, name, steps),
+                    Err(e) => tracing::warn!("Screen edge {} couldn't replay macro '{}': {}", edge.name, name, e.message),
+                }
+            }
+        });
+    }
+
+    let status = child.wait()?;
+    anyhow::bail!("xdotool exited ({})", status)
+}
+
+fn now() -> f64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs_f64()
+}
+
+impl XdotoolServer {
+    fn record_press(&self, press: HotkeyPress) {
+        let presses = &mut self.subscriptions.lock().unwrap().hotkeys;
+        presses.recent.push_back(press);
+        presses.count += 1;
+        while presses.recent.len() > RECENT_PRESSES {
+            presses.recent.pop_front();
+        }
+    }
+
+    /// Presses after the first `seen`, oldest first, and how many there have been in all
+    fn presses_since(&self, seen: u64) -> (Vec<HotkeyPress>, u64) {
+        let presses = &self.subscriptions.lock().unwrap().hotkeys;
+        let new = (presses.count - seen).min(presses.recent.len() as u64) as usize;
+        (presses.recent.iter().skip(presses.recent.len() - new).cloned().collect(), presses.count)
+    }
+
+    fn apply_pause(&self, edge: &ScreenEdge) {
+        let reason = format!("screen edge {}", edge.name);
+        let mut actions = self.actions.lock().unwrap();
+        let (pause, resume) = match edge.pause {
+            Some(EdgePause::Pause) => (true, false),
+            Some(EdgePause::Resume) => (false, true),
+            Some(EdgePause::Toggle) => (true, true),
+            None => (false, false),
+        };
+        if pause && actions.pause(format!("paused by {}", reason)) {
+            tracing::info!("Paused by {}", reason);
+        } else if resume && actions.resume().is_some() {
+            tracing::info!("Resumed by {}", reason);
+        }
+    }
+
+    /// Replay a stored macro once at its recorded timing, as its own action. Returns how many steps ran.
+    async fn run_edge_macro(&self, name: &str) -> Result<usize, McpError> {
+        let stored = macros::load_macro(name)?;
+        let steps = stored.resolve(&serde_json::Map::new())?;
+        self.check_batch_limits(&steps)?;
+        let guard = ActionRegistry::register(&self.actions, None, format!("macro '{}'", name), &CancellationToken::new())?;
+        let options = RunOptions {
+            delay: Duration::from_millis(stored.delay_ms),
+            cancel: guard.token.clone(),
+            ..Default::default()
+        };
+        let _turn = self.input_turn(&guard.token).await?;
+        self.run_cancellable(&guard, &steps, &options).await.map(|log| log.len())
+    }
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct WaitForHotkeyParams {
+    #[schemars(description = "Only this hotkey or screen edge, by its name in the config. Default: any of them")]
+    pub name: Option<String>,
+    #[schemars(description = "Seconds to wait for the user before giving up. Default: 300")]
+    #[serde(default = "default_hotkey_timeout")]
+    pub timeout_seconds: u64,
+}
+
+fn default_hotkey_timeout() -> u64 { 300 }
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct HotkeyWait {
+    pub press: HotkeyPress,
+    /// How long the user took, in milliseconds
+    pub waited_ms: u64,
+}
+
+#[rmcp::tool_router(router = hotkeys_router, vis = "pub(crate)")]
+impl XdotoolServer {
+    #[rmcp::tool(description = "Wait until the user presses one of the hotkeys the server's config defines (e.g. F9 to hand control back or signal to continue), or moves the pointer into one of its screen edges, returning which. Fails at once if none are configured", annotations(read_only_hint = true), output_schema = output::schema::<HotkeyWait>())]
+    pub async fn wait_for_hotkey(
+        &self,
+        Parameters(params): Parameters<WaitForHotkeyParams>,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let names: Vec<&str> = self.config.hotkeys.iter().map(|hotkey| hotkey.name.as_str())
+            .chain(self.config.screen_edges.iter().map(|edge| edge.name.as_str()))
+            .collect();
+        if names.is_empty() {
+            return Err(McpError::internal_error(
+                "No hotkeys are configured; add [[hotkeys]] or [[screen_edges]] entries to the server's config",
+                None
+            ));
+        }
+        if let Some(name) = &params.name {
+            if !names.contains(&name.as_str()) {
+                return Err(McpError::invalid_params(
+                    format!("No hotkey named '{}'; configured: {}", name, names.join(", ")),
+                    None
+                ));
+            }
+        }
+        let started = Instant::now();
+        let timeout = Duration::from_secs(params.timeout_seconds);
+        let (_, mut seen) = self.presses_since(0);
+        loop {
+            sleep_or_cancel(WAIT_INTERVAL, &context.ct).await?;
+            let (presses, count) = self.presses_since(seen);
+            seen = count;
+            if let Some(press) = presses.into_iter().find(|press| params.name.as_ref().is_none_or(|name| &press.name == name)) {
+                let waited_ms = started.elapsed().as_millis() as u64;
+                let text = format!("The user {} ({}) after {}ms", press.action(), press.name, waited_ms);
+                return Ok(output::structured(text, &HotkeyWait { press, waited_ms }));
+            }
+            if started.elapsed() >= timeout {
+                return Err(McpError::internal_error(
+                    format!("No hotkey pressed within {}s", params.timeout_seconds),
+                    None
+                ));
+            }
+        }
+    }
+}
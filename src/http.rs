@@ -0,0 +1,344 @@
+//! Just enough HTTP/1.1 for the network transports.
+//!
+//! One request is read per connection and every response closes it, except
+//! event streams, which stay open until either side hangs up. Request heads
+//! and bodies are size-limited and must arrive within a time limit so a
+//! client can't make the server buffer or wait without bound, and the body is
+//! only read once the transport has checked the head's bearer token.
+
+use crate::XdotoolServer;
+use rmcp::ServiceExt;
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader, DuplexStream, Lines, ReadHalf};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+/// Largest request head (request line and headers) accepted
+const MAX_HEAD: usize = 16 * 1024;
+/// Largest request body accepted
+const MAX_BODY: usize = 16 * 1024 * 1024;
+/// How long a client has to send the request head, and then the body
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+/// Buffer between an HTTP session and its MCP service
+const PIPE_CAPACITY: usize = 64 * 1024;
+
+#[derive(Debug)]
+pub struct Request {
+    pub method: String,
+    /// Path without the query string
+    pub path: String,
+    pub query: HashMap<String, String>,
+    /// Header names are lowercased
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).map(String::as_str)
+    }
+}
+
+/// Decode `%XX` escapes and `+` in a query string component
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' if i + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok()
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+                match hex {
+                    Some(byte) => {
+                        out.push(byte);
+                        i += 3;
+                        continue;
+                    }
+                    None => out.push(b'%'),
+                }
+            }
+            b'+' => out.push(b' '),
+            byte => out.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+fn parse_query(query: &str) -> HashMap<String, String> {
+    query.split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(key), percent_decode(value))
+        })
+        .collect()
+}
+
+fn invalid(message: impl Into<String>) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message.into())
+}
+
+/// Read a request's head, leaving its body for `read_body`. `Ok(None)` means the client closed the connection first.
+pub async fn read_head<R: AsyncBufRead + Unpin>(reader: &mut R) -> std::io::Result<Option<Request>> {
+    let mut limited = reader.take(MAX_HEAD as u64);
+    tokio::time::timeout(READ_TIMEOUT, parse_head(&mut limited)).await
+        .map_err(|_| std::io::Error::new(std::io::ErrorKind::TimedOut, "timed out reading the request head"))?
+}
+
+/// Read one head line, which must end within the MAX_HEAD bytes `reader` may still give
+async fn head_line<R: AsyncBufRead + Unpin>(reader: &mut tokio::io::Take<R>, line: &mut String) -> std::io::Result<usize> {
+    line.clear();
+    let read = reader.read_line(line).await?;
+    if read > 0 && !line.ends_with('\n') && reader.limit() == 0 {
+        return Err(invalid("request head too large"));
+    }
+    Ok(read)
+}
+
+async fn parse_head<R: AsyncBufRead + Unpin>(reader: &mut tokio::io::Take<R>) -> std::io::Result<Option<Request>> {
+    let mut line = String::new();
+    if head_line(reader, &mut line).await? == 0 {
+        return Ok(None);
+    }
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Err(invalid("malformed request line"));
+    };
+    let method = method.to_string();
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let (path, query) = (path.to_string(), parse_query(query));
+
+    let mut headers = HashMap::new();
+    loop {
+        if head_line(reader, &mut line).await? == 0 {
+            return Err(invalid("connection closed inside the request head"));
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            headers.insert(name.trim().to_lowercase(), value.trim().to_string());
+        }
+    }
+    Ok(Some(Request { method, path, query, headers, body: vec![] }))
+}
+
+/// Read the body the head's Content-Length announces into `request`
+pub async fn read_body<R: AsyncBufRead + Unpin>(reader: &mut R, request: &mut Request) -> std::io::Result<()> {
+    let length = match request.header("content-length") {
+        Some(value) => value.parse::<usize>().map_err(|_| invalid("invalid Content-Length"))?,
+        None => 0,
+    };
+    if length > MAX_BODY {
+        return Err(invalid("request body too large"));
+    }
+    let mut body = vec![0; length];
+    tokio::time::timeout(READ_TIMEOUT, reader.read_exact(&mut body)).await
+        .map_err(|_| std::io::Error::new(std::io::ErrorKind::TimedOut, "timed out reading the request body"))??;
+    request.body = body;
+    Ok(())
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        202 => "Accepted",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        _ => "Error",
+    }
+}
+
+fn extra_headers(headers: &[(&str, &str)]) -> String {
+    headers.iter().map(|(name, value)| format!("{}: {}\r\n", name, value)).collect()
+}
+
+/// Write a complete response and close the connection
+pub async fn respond<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    status: u16,
+    content_type: &str,
+    body: &[u8],
+) -> std::io::Result<()> {
+    respond_with(writer, status, &[], content_type, body).await
+}
+
+/// `respond` with extra response headers
+pub async fn respond_with<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    status: u16,
+    headers: &[(&str, &str)],
+    content_type: &str,
+    body: &[u8],
+) -> std::io::Result<()> {
+    let head = format!(
+        "HTTP/1.1 {} {}\r\n{}Content-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status, reason(status), extra_headers(headers), content_type, body.len()
+    );
+    writer.write_all(head.as_bytes()).await?;
+    writer.write_all(body).await?;
+    writer.shutdown().await
+}
+
+/// Write the head of a `text/event-stream` response; events follow with `send_event`
+pub async fn start_event_stream<W: AsyncWrite + Unpin>(writer: &mut W, headers: &[(&str, &str)]) -> std::io::Result<()> {
+    let head = format!(
+        "HTTP/1.1 200 OK\r\n{}Content-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: keep-alive\r\n\r\n",
+        extra_headers(headers)
+    );
+    writer.write_all(head.as_bytes()).await?;
+    writer.flush().await
+}
+
+/// Write an SSE comment, which clients ignore, to keep an idle stream open
+pub async fn send_keepalive<W: AsyncWrite + Unpin>(writer: &mut W) -> std::io::Result<()> {
+    writer.write_all(b": keepalive\n\n").await?;
+    writer.flush().await
+}
+
+/// Write one server-sent event. `data` must not contain newlines.
+pub async fn send_event<W: AsyncWrite + Unpin>(writer: &mut W, event: &str, data: &str) -> std::io::Result<()> {
+    writer.write_all(format!("event: {}\ndata: {}\n\n", event, data).as_bytes()).await?;
+    writer.flush().await
+}
+
+/// A random hex string for session IDs, from the kernel's CSPRNG
+pub fn random_id() -> std::io::Result<String> {
+    let mut bytes = [0u8; 16];
+    std::io::Read::read_exact(&mut std::fs::File::open("/dev/urandom")?, &mut bytes)?;
+    Ok(bytes.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+/// Whether a browser `Origin` header is the local machine. Requests from other
+/// origins are refused so a web page can't reach the server through DNS rebinding.
+pub fn local_origin(origin: &str) -> bool {
+    let host = origin.strip_prefix("http://").or_else(|| origin.strip_prefix("https://")).unwrap_or("");
+    let host = host.rsplit_once(':').filter(|(_, port)| port.parse::<u16>().is_ok()).map(|(host, _)| host).unwrap_or(host);
+    matches!(host, "localhost" | "127.0.0.1" | "[::1]")
+}
+
+/// What an MCP service sends back, one JSON value per line
+pub type Output = Lines<BufReader<ReadHalf<DuplexStream>>>;
+
+/// An MCP service running on its own clone of the server, fed and read as JSON lines
+pub struct Pipe {
+    /// Messages for the service, one JSON value per string
+    pub input: mpsc::UnboundedSender<String>,
+    pub output: Output,
+    pub service: JoinHandle<()>,
+}
+
+/// Start serving `server` over an in-memory pipe
+pub fn spawn_service(server: XdotoolServer) -> Pipe {
+    let (service_io, session_io) = tokio::io::duplex(PIPE_CAPACITY);
+    let (service_read, service_write) = tokio::io::split(service_io);
+    let (session_read, mut session_write) = tokio::io::split(session_io);
+
+    let (input, mut incoming) = mpsc::unbounded_channel::<String>();
+    tokio::spawn(async move {
+        while let Some(message) = incoming.recv().await {
+            if session_write.write_all(format!("{}\n", message).as_bytes()).await.is_err() {
+                break;
+            }
+        }
+    });
+    let service = tokio::spawn(async move {
+        match server.serve((service_read, service_write)).await {
+            Ok(service) => { let _ = service.waiting().await; }
+            Err(e) => tracing::warn!("Session failed to start: {}", e),
+        }
+    });
+    Pipe { input, output: BufReader::new(session_read).lines(), service }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn read(raw: &[u8]) -> std::io::Result<Option<Request>> {
+        let mut reader = raw;
+        let Some(mut request) = read_head(&mut reader).await? else {
+            return Ok(None);
+        };
+        read_body(&mut reader, &mut request).await?;
+        Ok(Some(request))
+    }
+
+    #[tokio::test]
+    async fn head_and_body_are_parsed() {
+        let raw = b"POST /message?sessionId=ab%20c&x=1+2 HTTP/1.1\r\nContent-Length: 4\r\nAuthorization: Bearer t\r\n\r\nbodystray";
+        let request = read(raw).await.unwrap().unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/message");
+        assert_eq!(request.query["sessionId"], "ab c");
+        assert_eq!(request.query["x"], "1 2");
+        assert_eq!(request.header("authorization"), Some("Bearer t"));
+        assert_eq!(request.body, b"body");
+    }
+
+    #[tokio::test]
+    async fn head_leaves_the_body_unread() {
+        let mut reader: &[u8] = b"POST /mcp HTTP/1.1\r\nContent-Length: 2\r\n\r\n{}";
+        let request = read_head(&mut reader).await.unwrap().unwrap();
+        assert!(request.body.is_empty());
+        assert_eq!(reader, b"{}");
+    }
+
+    #[tokio::test]
+    async fn closed_connections_and_bad_heads() {
+        assert!(read(b"").await.unwrap().is_none());
+        assert!(read(b"GET\r\n\r\n").await.is_err());
+        assert!(read(b"GET /sse HTTP/1.1\r\nHost: x\r\n").await.is_err());
+        assert!(read(b"POST /mcp HTTP/1.1\r\nContent-Length: lots\r\n\r\n").await.is_err());
+        assert!(read(b"POST /mcp HTTP/1.1\r\nContent-Length: 10\r\n\r\nshort").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn oversized_requests_are_refused() {
+        let mut raw = b"GET /sse HTTP/1.1\r\nX-Pad: ".to_vec();
+        raw.extend(vec![b'a'; MAX_HEAD]);
+        raw.extend(b"\r\n\r\n");
+        let error = read(&raw).await.unwrap_err();
+        assert_eq!(error.to_string(), "request head too large");
+
+        let raw = format!("POST /mcp HTTP/1.1\r\nContent-Length: {}\r\n\r\n", MAX_BODY + 1);
+        let error = read(raw.as_bytes()).await.unwrap_err();
+        assert_eq!(error.to_string(), "request body too large");
+    }
+
+    #[tokio::test]
+    async fn a_head_at_the_limit_is_accepted() {
+        let start = b"GET /sse HTTP/1.1\r\nX-Pad: ";
+        let mut raw = start.to_vec();
+        raw.extend(vec![b'a'; MAX_HEAD - start.len() - 4]);
+        raw.extend(b"\r\n\r\n");
+        assert_eq!(raw.len(), MAX_HEAD);
+        assert!(read(&raw).await.unwrap().is_some());
+    }
+
+    #[test]
+    fn percent_escapes() {
+        assert_eq!(percent_decode("a%2Fb%zz%4"), "a/b%zz%4");
+        assert_eq!(parse_query("a=1&&b&c=%3D"), HashMap::from([
+            ("a".to_string(), "1".to_string()),
+            ("b".to_string(), String::new()),
+            ("c".to_string(), "=".to_string()),
+        ]));
+    }
+
+    #[test]
+    fn only_local_origins() {
+        assert!(local_origin("http://localhost:8080"));
+        assert!(local_origin("https://127.0.0.1"));
+        assert!(local_origin("http://[::1]:3000"));
+        assert!(!local_origin("http://localhost.evil.example"));
+        assert!(!local_origin("null"));
+    }
+}
@@ -0,0 +1,74 @@
+//! How clicks, keys and typed text reach their window.
+//!
+//! By default input is faked with XTEST: it goes wherever the pointer or
+//! keyboard focus is, exactly as if the user did it, which every application
+//! accepts. With `sendevent` it's delivered with XSendEvent to one window
+//! instead (xdotool's `--window`), which needn't be focused or raised, so a
+//! background window can be driven without disturbing the one in front.
+//! Many applications (most toolkits' text fields, anything checking the
+//! `send_event` flag) ignore such events, so that's opt-in: per call with
+//! `injection` or `window_id`, or for the server with the `injection` setting.
+
+use crate::allowlist::split_commands;
+use crate::XdotoolServer;
+use rmcp::ErrorData as McpError;
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+/// The `injection` setting and tool parameter
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Injection {
+    /// Fake input through XTEST, to whatever has the pointer or focus
+    #[default]
+    Xtest,
+    /// XSendEvent to one window, without focusing or raising it
+    Sendevent,
+}
+
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct InjectionParams {
+    #[schemars(description = "'xtest' fakes real input where the pointer/focus is; 'sendevent' sends X events to one window without focusing or raising it, which some apps ignore. Default: 'sendevent' when window_id is given, else the server's injection setting")]
+    pub injection: Option<Injection>,
+    #[schemars(description = "Window to send the input to with sendevent. Default: the active window")]
+    pub window_id: Option<String>,
+}
+
+/// xdotool commands taking `--window` to send their input with XSendEvent
+const TARGETABLE: &[&str] = &["click", "mousedown", "mouseup", "key", "keydown", "keyup", "type"];
+
+/// `args` with `--window <id>` added to each input command, when there's a window
+pub fn target<'a>(args: &'a [&'a str], window: Option<&'a str>) -> Vec<&'a str> {
+    let Some(window) = window else {
+        return args.to_vec();
+    };
+    let mut targeted = vec![];
+    for command in split_commands(args) {
+        targeted.push(command[0]);
+        if TARGETABLE.contains(&command[0]) {
+            targeted.extend(["--window", window]);
+        }
+        targeted.extend(&command[1..]);
+    }
+    targeted
+}
+
+impl XdotoolServer {
+    /// The window a call's input is sent to with XSendEvent, or None for XTEST
+    pub(crate) fn injection_window(&self, params: &InjectionParams) -> Result<Option<String>, McpError> {
+        let injection = match (params.injection, &params.window_id) {
+            (Some(injection), _) => injection,
+            (None, Some(_)) => Injection::Sendevent,
+            (None, None) => self.config.injection,
+        };
+        match (injection, &params.window_id) {
+            (Injection::Xtest, Some(_)) => Err(McpError::internal_error(
+                "window_id needs injection 'sendevent'; XTEST input goes wherever the focus is",
+                None
+            )),
+            (Injection::Xtest, None) => Ok(None),
+            (Injection::Sendevent, Some(window_id)) => Ok(Some(window_id.clone())),
+            (Injection::Sendevent, None) => Ok(Some(Self::run_xdotool(&["getactivewindow"])?.trim().to_string())),
+        }
+    }
+}
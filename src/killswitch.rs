@@ -0,0 +1,125 @@
+//! Emergency stop hotkey.
+//!
+//! A background thread watches raw key events from `xinput test-xi2 --root`,
+//! which sees every keyboard without grabbing anything, and tracks which keys
+//! are held. When the whole combo is down, every running and scheduled action
+//! is cancelled, held input is released and the server pauses until
+//! `resume_automation` is called. Key names are resolved to keycodes with
+//! `xmodmap -pke`. The `[[hotkeys]]` triggers watch keys the same way.
+
+use crate::XdotoolServer;
+use std::collections::HashSet;
+use std::io::{BufRead, BufReader};
+use std::process::{Command, Stdio};
+
+/// Keysyms each modifier name in a combo can be satisfied by
+fn modifier_keysyms(name: &str) -> Option<&'static [&'static str]> {
+    match name.to_lowercase().as_str() {
+        "ctrl" | "control" => Some(&["Control_L", "Control_R"]),
+        "alt" => Some(&["Alt_L", "Alt_R", "Meta_L", "Meta_R"]),
+        "shift" => Some(&["Shift_L", "Shift_R"]),
+        "super" | "win" => Some(&["Super_L", "Super_R"]),
+        _ => None,
+    }
+}
+
+/// Keycodes for each part of a combo such as `ctrl+alt+shift+Escape`; any keycode of a part counts
+pub(crate) fn resolve_combo(combo: &str) -> anyhow::Result<Vec<HashSet<u32>>> {
+    let output = Command::new("xmodmap").arg("-pke").output()
+        .map_err(|e| anyhow::anyhow!("Failed to run xmodmap: {}", e))?;
+    if !output.status.success() {
+        anyhow::bail!("xmodmap error: {}", String::from_utf8_lossy(&output.stderr));
+    }
+
+    // Lines look like: keycode   9 = Escape NoSymbol Escape
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let keymap: Vec<(u32, Vec<&str>)> = stdout.lines().filter_map(|line| {
+        let (code, syms) = line.strip_prefix("keycode")?.split_once('=')?;
+        Some((code.trim().parse().ok()?, syms.split_whitespace().collect()))
+    }).collect();
+
+    combo.split('+').map(|part| {
+        let part = part.trim();
+        let names: Vec<&str> = match modifier_keysyms(part) {
+            Some(names) => names.to_vec(),
+            None => vec![part],
+        };
+        let codes: HashSet<u32> = keymap.iter()
+            .filter(|(_, syms)| syms.iter().any(|sym| names.iter().any(|name| sym.eq_ignore_ascii_case(name))))
+            .map(|(code, _)| *code)
+            .collect();
+        if codes.is_empty() {
+            anyhow::bail!("No keycode for '{}' in '{}'", part, combo);
+        }
+        Ok(codes)
+    }).collect()
+}
+
+/// Watch for `combo` and halt the server whenever it's pressed. Problems are logged, not fatal.
+pub fn spawn(server: XdotoolServer, combo: String) {
+    std::thread::spawn(move || {
+        if let Err(e) = watch(&server, &combo) {
+            tracing::warn!("Kill switch {} unavailable: {}", combo, e);
+        }
+    });
+}
+
+fn watch(server: &XdotoolServer, combo: &str) -> anyhow::Result<()> {
+    watch_combos(&[combo.to_string()], |_| {
+        let halted = server.halt(format!("kill switch {} pressed", combo));
+        tracing::warn!("Kill switch pressed: cancelled {} action(s), server paused", halted);
+    })
+}
+
+/// Call `pressed` with a combo's index each time it goes down, until xinput stops
+pub(crate) fn watch_combos(combos: &[String], mut pressed: impl FnMut(usize)) -> anyhow::Result<()> {
+    let parts: Vec<Vec<HashSet<u32>>> = combos.iter().map(|combo| resolve_combo(combo)).collect::<anyhow::Result<_>>()?;
+    let mut child = Command::new("xinput")
+        .args(["test-xi2", "--root"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| anyhow::anyhow!("Failed to run xinput: {}", e))?;
+    let stdout = child.stdout.take().ok_or_else(|| anyhow::anyhow!("No output from xinput"))?;
+    tracing::info!("Watching {}", combos.join(", "));
+
+    // Events look like "EVENT type 13 (RawKeyPress)" followed by indented fields including "detail: <keycode>"
+    let mut held = HashSet::new();
+    let mut press = None;
+    for line in BufReader::new(stdout).lines() {
+        let line = line?;
+        if line.starts_with("EVENT") {
+            press = if line.contains("(RawKeyPress)") {
+                Some(true)
+            } else if line.contains("(RawKeyRelease)") {
+                Some(false)
+            } else {
+                None
+            };
+            continue;
+        }
+        let (Some(down), Some(code)) = (press, line.trim().strip_prefix("detail:")) else {
+            continue;
+        };
+        let Ok(code) = code.trim().parse::<u32>() else {
+            continue;
+        };
+        press = None;
+        if !down {
+            held.remove(&code);
+            continue;
+        }
+        // Auto-repeat of a key already held doesn't press a combo again
+        if !held.insert(code) {
+            continue;
+        }
+        for (i, combo) in parts.iter().enumerate() {
+            if combo.iter().any(|codes| codes.contains(&code)) && combo.iter().all(|codes| codes.iter().any(|code| held.contains(code))) {
+                pressed(i);
+            }
+        }
+    }
+
+    let status = child.wait()?;
+    anyhow::bail!("xinput exited ({})", status)
+}
@@ -0,0 +1,725 @@
+//! Starting a program and waiting for its window.
+//!
+//! `launch_and_wait` replaces "launch, sleep a few seconds, search by name":
+//! it remembers which windows exist, starts the program and polls until a
+//! new window appears whose `_NET_WM_PID` is the program's or one of its
+//! descendants' (read from /proc), then returns its ID. Programs that hand
+//! their work to an instance already running (many browsers and terminals)
+//! open their window from that instance's PID and aren't found.
+//!
+//! `launch_app` only starts a program, with the arguments, environment and
+//! working directory given, and returns its PID. Neither runs a shell: the
+//! command is the program's argv.
+//!
+//! Only programs named in `[launch] programs` can be started, by either.
+//!
+//! `open_uri` hands a URL or file to xdg-open, which starts the desktop's
+//! handler for it. Only URL schemes in `[launch] uri_schemes` and files under
+//! `[launch] open_paths` (symlinks resolved) can be opened.
+//!
+//! `quit_app` ends an app politely first: it asks each of its windows to
+//! close (WM_DELETE_WINDOW, via `xdotool windowquit`) and waits, then sends
+//! SIGTERM and waits again, and only then SIGKILL. It only quits processes
+//! that own a window, and never the server itself.
+//!
+//! `signal_window_process` sends one of SIGTERM, SIGHUP, SIGSTOP or SIGCONT
+//! to a window's process, to end, reload, pause or resume a hung or noisy
+//! app by its window, under the same rules.
+
+use crate::control::sleep_or_cancel;
+use crate::{display, environment, output, process, XdotoolServer};
+use rmcp::{
+    handler::server::wrapper::Parameters,
+    model::*,
+    service::{RequestContext, RoleServer},
+    ErrorData as McpError,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::time::{Duration, Instant};
+
+/// The `[launch]` config table
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Launch {
+    /// Programs launch_and_wait, launch_app and focus_or_launch may start, as the command names them: a name looked up in PATH, or a path. Empty allows none.
+    pub programs: Vec<String>,
+    /// Environment variables launch_app may set besides the locale ones (LANG, LANGUAGE, LC_*) and TZ
+    pub env: Vec<String>,
+    /// URL schemes open_uri may open, e.g. "https" or "mailto". Empty allows none.
+    pub uri_schemes: Vec<String>,
+    /// Directories whose files (and subdirectories' files) open_uri may open. Empty allows none.
+    pub open_paths: Vec<String>,
+}
+
+/// How long xdg-open gets to fail before open_uri reports the handler started
+const OPEN_WAIT: Duration = Duration::from_secs(3);
+
+pub fn validate(launch: &Launch) -> Result<(), String> {
+    if let Some(name) = launch.env.iter().find(|name| never_set(name)) {
+        return Err(format!("launch.env: {} can't be allowed; it would change which program runs or what it loads", name));
+    }
+    if let Some(scheme) = launch.uri_schemes.iter().find(|scheme| !valid_scheme(scheme)) {
+        return Err(format!("launch.uri_schemes: '{}' isn't a URL scheme", scheme));
+    }
+    if launch.uri_schemes.iter().any(|scheme| scheme.eq_ignore_ascii_case("file")) {
+        return Err("launch.uri_schemes: list directories in launch.open_paths instead of allowing file".to_string());
+    }
+    if let Some(path) = launch.open_paths.iter().find(|path| !path.starts_with('/')) {
+        return Err(format!("launch.open_paths: '{}' isn't an absolute path", path));
+    }
+    Ok(())
+}
+
+/// RFC 3986: a letter followed by letters, digits, '+', '-' or '.'
+fn valid_scheme(scheme: &str) -> bool {
+    let mut chars = scheme.chars();
+    chars.next().is_some_and(|first| first.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+}
+
+/// Decode %XX escapes, or None if one is malformed or the result isn't UTF-8
+fn percent_decode(text: &str) -> Option<String> {
+    let bytes = text.as_bytes();
+    let (mut decoded, mut i) = (vec![], 0);
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct LaunchAndWaitParams {
+    #[schemars(description = "Program and arguments, e.g. [\"gedit\", \"notes.txt\"]. The program must be in the server's launch programs list")]
+    pub command: Vec<String>,
+    #[schemars(description = "Seconds to wait for the program's window. Default: 30")]
+    #[serde(default = "default_launch_timeout")]
+    pub timeout_seconds: u64,
+}
+
+fn default_launch_timeout() -> u64 { 30 }
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct LaunchAppParams {
+    #[schemars(description = "Program to start, as named in the server's launch programs list: a name looked up in PATH, or a path")]
+    pub command: String,
+    #[schemars(description = "Arguments, each passed as-is (no shell, so no quoting or globbing)")]
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[schemars(description = "Environment variables to set for it, on top of the server's own")]
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    #[schemars(description = "Directory to start it in. Default: the server's working directory")]
+    pub workdir: Option<String>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct OpenUriParams {
+    #[schemars(description = "A URL (e.g. https://example.com), a file: URI or an absolute file path")]
+    pub uri: String,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct OpenedUri {
+    /// The URL, or the file's full path with symlinks resolved
+    pub target: String,
+    /// "url" or "file"
+    pub kind: String,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct QuitAppParams {
+    #[schemars(description = "A window of the app; its _NET_WM_PID is the process to quit. Give this or pid")]
+    pub window_id: Option<String>,
+    #[schemars(description = "Process ID of the app, e.g. from launch_app. Give this or window_id")]
+    pub pid: Option<u32>,
+    #[schemars(description = "Milliseconds to wait after closing the windows, and again after SIGTERM, before escalating. Default: 5000")]
+    #[serde(default = "default_grace_ms")]
+    pub grace_ms: u64,
+    #[schemars(description = "Only report what would be closed. Default: the server's dry_run setting")]
+    pub dry_run: Option<bool>,
+}
+
+fn default_grace_ms() -> u64 { 5000 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum QuitStage {
+    /// It exited once its windows were asked to close
+    Closed,
+    /// It exited on SIGTERM
+    Terminated,
+    /// It had to be killed with SIGKILL
+    Killed,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct QuitApp {
+    pub pid: u32,
+    /// The windows asked to close
+    pub windows: Vec<output::Window>,
+    /// The stage that ended it; absent in dry runs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stage: Option<QuitStage>,
+    /// From the first close request until it was gone, in milliseconds
+    pub waited_ms: u64,
+    /// What would have been done, in dry runs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dry_run: Option<String>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct SignalWindowProcessParams {
+    #[schemars(description = "A window of the app; its _NET_WM_PID is the process signalled")]
+    pub window_id: String,
+    #[schemars(description = "TERM to end it, HUP to hang it up (many daemons reload), STOP to pause it, CONT to resume it after STOP")]
+    pub signal: AppSignal,
+    #[schemars(description = "Only report what would be sent. Default: the server's dry_run setting")]
+    pub dry_run: Option<bool>,
+}
+
+/// The signals signal_window_process may send
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum AppSignal {
+    #[serde(alias = "SIGTERM")]
+    Term,
+    #[serde(alias = "SIGHUP")]
+    Hup,
+    #[serde(alias = "SIGSTOP")]
+    Stop,
+    #[serde(alias = "SIGCONT")]
+    Cont,
+}
+
+impl AppSignal {
+    fn name(self) -> &'static str {
+        match self {
+            AppSignal::Term => "TERM",
+            AppSignal::Hup => "HUP",
+            AppSignal::Stop => "STOP",
+            AppSignal::Cont => "CONT",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct SignalledProcess {
+    pub window_id: String,
+    pub pid: u32,
+    pub signal: AppSignal,
+    /// Whether the process was still running right after; a stopped one counts
+    pub running: bool,
+    /// Whether it's stopped (paused by STOP) right after
+    pub stopped: bool,
+    /// What would have been done, in dry runs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dry_run: Option<String>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct LaunchedApp {
+    pub pid: u32,
+    /// The program followed by its arguments
+    pub command: Vec<String>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct LaunchedWindow {
+    pub pid: u32,
+    /// The first new window the program (or a child of it) mapped
+    pub window: output::Window,
+    /// How long the window took to appear, in milliseconds
+    pub waited_ms: u64,
+}
+
+/// Environment variables launch_app sets without them being listed in `[launch] env`
+const SAFE_ENV: &[&str] = &["LANG", "LANGUAGE", "TZ"];
+
+/// Variables no config may allow: the program is looked up in PATH before it runs, and the
+/// loader's variables would run code the programs list never allowed
+fn never_set(name: &str) -> bool {
+    name == "PATH" || name.starts_with("LD_") || name == "DISPLAY" || name == "WAYLAND_DISPLAY"
+}
+
+/// Check the environment launch_app was asked to set. Only the locale, TZ and the names in
+/// `[launch] env` pass; variables like GTK_MODULES or PYTHONPATH would load other code.
+fn check_env(env: &BTreeMap<String, String>, allowed: &[String]) -> Result<(), McpError> {
+    for name in env.keys() {
+        if name.is_empty() || name.contains('=') || name.contains('\0') {
+            return Err(McpError::invalid_params(format!("'{}' isn't an environment variable name", name), None));
+        }
+        if name == "DISPLAY" || name == "WAYLAND_DISPLAY" {
+            return Err(McpError::invalid_params(format!("Choose the display with the display argument, not {}", name), None));
+        }
+        let listed = SAFE_ENV.contains(&name.as_str()) || name.starts_with("LC_") || allowed.iter().any(|allowed| allowed == name);
+        if never_set(name) || !listed {
+            return Err(McpError::invalid_params(
+                format!("{} can't be set for a launched program; the server's launch env list allows {}", name, allowed_env(allowed)),
+                None
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// The variables check_env lets through, for its error
+fn allowed_env(allowed: &[String]) -> String {
+    SAFE_ENV.iter().copied().chain(["LC_*"]).chain(allowed.iter().map(String::as_str)).collect::<Vec<_>>().join(", ")
+}
+
+/// The program an allowed launch command names, as an absolute path found with the
+/// server's PATH, so the environment given to the program can't change which one runs
+fn resolve_program(program: &str) -> Result<PathBuf, McpError> {
+    if program.contains('/') {
+        return Ok(PathBuf::from(program));
+    }
+    environment::find_program(program).ok_or_else(|| McpError::internal_error(
+        format!("{} wasn't found in the server's PATH", program),
+        None
+    ))
+}
+
+/// A process's state letter from /proc (R, S, T, Z...), or None if it's gone
+pub(crate) fn state(pid: u32) -> Option<String> {
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // The state is the first field after the parenthesized name
+    stat.rsplit_once(')').and_then(|(_, rest)| rest.split_whitespace().next()).map(str::to_string)
+}
+
+/// Whether a process is still running (zombies, which are only waiting to be reaped, aren't)
+fn alive(pid: u32) -> bool {
+    state(pid).is_some_and(|state| !matches!(state.as_str(), "Z" | "X"))
+}
+
+/// Wait up to `grace` for the process to exit, returning whether it did
+async fn exited_within(pid: u32, grace: Duration, cancel: &tokio_util::sync::CancellationToken) -> Result<bool, McpError> {
+    let started = Instant::now();
+    loop {
+        if !alive(pid) {
+            return Ok(true);
+        }
+        if started.elapsed() >= grace {
+            return Ok(false);
+        }
+        sleep_or_cancel(crate::wait::POLL_INTERVAL, cancel).await?;
+    }
+}
+
+fn signal(pid: u32, signal: &str) -> Result<(), McpError> {
+    let output = process::output(std::process::Command::new("kill").args([signal, "--", &pid.to_string()]))
+        .map_err(|e| process::error("kill", e))?;
+    // It may have exited in the meantime, which is what we're after anyway
+    if !output.status.success() && alive(pid) {
+        return Err(McpError::internal_error(
+            format!("kill {} {} failed: {}", signal, pid, String::from_utf8_lossy(&output.stderr).trim()),
+            None
+        ));
+    }
+    Ok(())
+}
+
+/// The process owning a window, from its _NET_WM_PID
+fn window_pid(window_id: &str) -> Result<u32, McpError> {
+    XdotoolServer::run_xdotool(&["getwindowpid", window_id])?.trim().parse().map_err(|_| McpError::internal_error(
+        format!("Window {} has no _NET_WM_PID", window_id),
+        None
+    ))
+}
+
+/// `pid` and every process descended from it
+pub(crate) fn process_tree(pid: u32) -> HashSet<u32> {
+    // Parent of each process, from the 4th field of /proc/<pid>/stat (after the parenthesized name)
+    let parents: Vec<(u32, u32)> = std::fs::read_dir("/proc").into_iter().flatten().flatten()
+        .filter_map(|entry| {
+            let child: u32 = entry.file_name().to_str()?.parse().ok()?;
+            let stat = std::fs::read_to_string(entry.path().join("stat")).ok()?;
+            let parent = stat.rsplit_once(')')?.1.split_whitespace().nth(1)?.parse().ok()?;
+            Some((child, parent))
+        })
+        .collect();
+    let mut tree = HashSet::from([pid]);
+    loop {
+        let before = tree.len();
+        tree.extend(parents.iter().filter(|(_, parent)| tree.contains(parent)).map(|(child, _)| *child).collect::<Vec<_>>());
+        if tree.len() == before {
+            return tree;
+        }
+    }
+}
+
+impl XdotoolServer {
+    /// What open_uri may pass to xdg-open for `uri`, with its kind, or why not
+    fn check_openable(&self, uri: &str) -> Result<(String, &'static str), McpError> {
+        let refuse = |reason: String| Err(McpError::invalid_params(reason, None));
+        if uri.is_empty() || uri.starts_with('-') || uri.chars().any(|c| c.is_control()) {
+            return refuse(format!("'{}' isn't a URL or path", uri));
+        }
+        let path = if uri.starts_with('/') {
+            uri.to_string()
+        } else if uri.get(..7).is_some_and(|scheme| scheme.eq_ignore_ascii_case("file://")) {
+            // file:///path or file://localhost/path
+            let path = uri[7..].strip_prefix("localhost").unwrap_or(&uri[7..]);
+            if !path.starts_with('/') {
+                return refuse(format!("'{}' names another host; only local files can be opened", uri));
+            }
+            match percent_decode(path) {
+                Some(path) => path,
+                None => return refuse(format!("'{}' has a malformed %-escape", uri)),
+            }
+        } else {
+            let Some((scheme, _)) = uri.split_once(':').filter(|(scheme, _)| valid_scheme(scheme)) else {
+                return refuse(format!("'{}' has no URL scheme; give a URL like https://... or an absolute path", uri));
+            };
+            if uri.chars().any(char::is_whitespace) {
+                return refuse(format!("'{}' contains whitespace; %-escape it", uri));
+            }
+            if !self.config.launch.uri_schemes.iter().any(|allowed| allowed.eq_ignore_ascii_case(scheme)) {
+                return Err(McpError::internal_error(
+                    format!("{}: URLs aren't in the uri_schemes list of the server's launch config", scheme),
+                    None
+                ));
+            }
+            return Ok((uri.to_string(), "url"));
+        };
+
+        let resolved = std::fs::canonicalize(&path)
+            .map_err(|e| McpError::invalid_params(format!("Can't open {}: {}", path, e), None))?;
+        let allowed = self.config.launch.open_paths.iter()
+            .filter_map(|dir| std::fs::canonicalize(dir).ok())
+            .any(|dir| resolved.starts_with(dir));
+        if !allowed {
+            return Err(McpError::internal_error(
+                format!("{} isn't under a directory in the open_paths list of the server's launch config", resolved.display()),
+                None
+            ));
+        }
+        Ok((resolved.to_string_lossy().into_owned(), "file"))
+    }
+
+    pub(crate) fn check_launchable(&self, program: &str) -> Result<(), McpError> {
+        if !self.config.launch.programs.iter().any(|allowed| allowed == program) {
+            return Err(McpError::internal_error(
+                format!("{} isn't in the launch programs list of the server's config", program),
+                None
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[rmcp::tool_router(router = launch_router, vis = "pub(crate)")]
+impl XdotoolServer {
+    #[rmcp::tool(description = "Start a program and wait until it opens a window (matched by the window's _NET_WM_PID), returning the window ID. Use instead of launching, sleeping and searching by title. Only programs the server's config allows can be started", annotations(read_only_hint = false, destructive_hint = false, open_world_hint = true), output_schema = output::schema::<LaunchedWindow>())]
+    pub async fn launch_and_wait(
+        &self,
+        Parameters(params): Parameters<LaunchAndWaitParams>,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let Some(program) = params.command.first() else {
+            return Err(McpError::invalid_params("command must name a program", None));
+        };
+        self.check_launchable(program)?;
+        let existing: HashSet<String> = Self::visible_windows()?.into_iter().collect();
+        let started = Instant::now();
+        // Not killed with the call: the program is meant to outlive it
+        let mut child = display::async_command(program)
+            .args(&params.command[1..])
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| McpError::internal_error(format!("Failed to run {}: {}", program, e), None))?;
+        let pid = child.id().unwrap_or_default();
+        let timeout = Duration::from_secs(params.timeout_seconds);
+        let mut checked = existing;
+        let mut exited = None;
+
+        loop {
+            sleep_or_cancel(crate::wait::POLL_INTERVAL, &context.ct).await?;
+            if exited.is_none() {
+                exited = child.try_wait().ok().flatten();
+                if let Some(status) = exited.filter(|status| !status.success()) {
+                    return Err(McpError::internal_error(
+                        format!("{} exited ({}) without opening a window", program, status),
+                        None
+                    ));
+                }
+            }
+            let new: Vec<String> = Self::visible_windows()?.into_iter().filter(|id| !checked.contains(id)).collect();
+            if !new.is_empty() {
+                let tree = process_tree(pid);
+                for id in new {
+                    let owner = Self::run_xdotool(&["getwindowpid", &id]).ok().and_then(|owner| owner.trim().parse().ok());
+                    let Some(owner) = owner else {
+                        // Not set yet; look again next time
+                        continue;
+                    };
+                    if tree.contains(&owner) {
+                        let waited_ms = started.elapsed().as_millis() as u64;
+                        let name = Self::window_name(&id).ok();
+                        let text = format!(
+                            "{} (pid {}) opened window {} \"{}\" after {}ms",
+                            program, pid, id, name.as_deref().unwrap_or_default(), waited_ms
+                        );
+                        let window = output::Window { id, name };
+                        return Ok(output::structured(text, &LaunchedWindow { pid, window, waited_ms }));
+                    }
+                    checked.insert(id);
+                }
+            }
+            if started.elapsed() >= timeout {
+                let hint = match exited {
+                    Some(_) => "; it exited, so it may have handed off to an instance already running",
+                    None => "",
+                };
+                return Err(McpError::internal_error(
+                    format!("{} (pid {}) opened no window within {}s{}", program, pid, params.timeout_seconds, hint),
+                    None
+                ));
+            }
+        }
+    }
+
+    #[rmcp::tool(description = "Start a GUI program with arguments, environment variables and a working directory, without a shell, and return its PID. Only programs the server's config allows can be started; use launch_and_wait instead to also get the window it opens", annotations(read_only_hint = false, destructive_hint = false, open_world_hint = true), output_schema = output::schema::<LaunchedApp>())]
+    pub async fn launch_app(
+        &self,
+        Parameters(params): Parameters<LaunchAppParams>,
+    ) -> Result<CallToolResult, McpError> {
+        self.check_launchable(&params.command)?;
+        check_env(&params.env, &self.config.launch.env)?;
+        let program = resolve_program(&params.command)?;
+        let Some(program) = program.to_str() else {
+            return Err(McpError::internal_error(format!("{} isn't a UTF-8 path", program.display()), None));
+        };
+        let mut command = display::async_command(program);
+        command.args(&params.args).envs(&params.env);
+        if let Some(workdir) = &params.workdir {
+            if !std::path::Path::new(workdir).is_dir() {
+                return Err(McpError::invalid_params(format!("{} isn't a directory", workdir), None));
+            }
+            command.current_dir(workdir);
+        }
+        // Not killed with the call: the program is meant to outlive it
+        let child = command
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| McpError::internal_error(format!("Failed to run {}: {}", params.command, e), None))?;
+        let pid = child.id().unwrap_or_default();
+        tracing::info!("Launched {} (pid {})", params.command, pid);
+        let command: Vec<String> = std::iter::once(params.command).chain(params.args).collect();
+        Ok(output::structured(
+            format!("Started {} (pid {})", command[0], pid),
+            &LaunchedApp { pid, command }
+        ))
+    }
+
+    #[rmcp::tool(description = "Open a URL or a file with the desktop's default handler (xdg-open), e.g. a web page in the browser or a PDF in the viewer, then automate the window that appears. Only URL schemes and directories the server's config allows can be opened", annotations(read_only_hint = false, destructive_hint = false, open_world_hint = true), output_schema = output::schema::<OpenedUri>())]
+    pub async fn open_uri(
+        &self,
+        Parameters(params): Parameters<OpenUriParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let (target, kind) = self.check_openable(&params.uri)?;
+        // Not killed with the call: some xdg-open fallbacks run the handler in the foreground
+        let mut child = display::async_command("xdg-open")
+            .arg(&target)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| process::error("xdg-open", e))?;
+        let mut stderr = child.stderr.take();
+        if let Ok(status) = tokio::time::timeout(OPEN_WAIT, child.wait()).await {
+            let status = status.map_err(|e| process::error("xdg-open", e))?;
+            if !status.success() {
+                let mut message = String::new();
+                if let Some(stderr) = &mut stderr {
+                    let _ = tokio::io::AsyncReadExt::read_to_string(stderr, &mut message).await;
+                }
+                // xdg-open's exit codes: 2 the file doesn't exist, 3 no handler tool, 4 the handler failed
+                let reason = match status.code() {
+                    Some(3) => "no program to open it was found".to_string(),
+                    Some(4) => "the program opening it failed".to_string(),
+                    _ => status.to_string(),
+                };
+                let detail = match message.trim() {
+                    "" => String::new(),
+                    message => format!(" ({})", message),
+                };
+                return Err(McpError::internal_error(
+                    format!("xdg-open couldn't open {}: {}{}", target, reason, detail),
+                    None
+                ));
+            }
+        }
+        tracing::info!("Opened {}", target);
+        Ok(output::structured(
+            format!("Opened {}; wait_for_window_event can wait for its window", target),
+            &OpenedUri { target, kind: kind.to_string() }
+        ))
+    }
+
+    #[rmcp::tool(description = "Quit an app gracefully, escalating only if needed: ask all its windows to close (WM_DELETE_WINDOW), wait grace_ms, then SIGTERM, wait again, then SIGKILL. Reports which stage ended it. Takes a window of the app or its PID", annotations(read_only_hint = false, destructive_hint = true), output_schema = output::schema::<QuitApp>())]
+    pub async fn quit_app(
+        &self,
+        Parameters(params): Parameters<QuitAppParams>,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let pid = match (&params.window_id, params.pid) {
+            (Some(window_id), None) => window_pid(window_id)?,
+            (None, Some(pid)) => pid,
+            _ => return Err(McpError::invalid_params("Give either window_id or pid", None)),
+        };
+        if pid <= 1 || pid == std::process::id() {
+            return Err(McpError::invalid_params(format!("pid {} can't be quit", pid), None));
+        }
+        let window_ids = Self::pid_windows(pid, false)?;
+        if window_ids.is_empty() {
+            return Err(McpError::internal_error(
+                format!("pid {} has no windows; quit_app only quits apps", pid),
+                None
+            ));
+        }
+        let windows: Vec<output::Window> = window_ids.into_iter()
+            .map(|id| {
+                let name = Self::window_name(&id).ok();
+                output::Window { id, name }
+            })
+            .collect();
+        let mut quit = QuitApp { pid, windows, stage: None, waited_ms: 0, dry_run: None };
+        if params.dry_run.unwrap_or(self.config.dry_run) {
+            let report = format!(
+                "close {} window(s) of pid {}, then if it keeps running SIGTERM it after {}ms and SIGKILL it {}ms later",
+                quit.windows.len(), pid, params.grace_ms, params.grace_ms
+            );
+            quit.dry_run = Some(report.clone());
+            return Ok(output::structured(format!("Dry run, would {}", report), &quit));
+        }
+
+        let started = Instant::now();
+        let grace = Duration::from_millis(params.grace_ms);
+        for window in &quit.windows {
+            // A window closing on its own meanwhile is fine
+            if let Err(e) = Self::run_xdotool(&["windowquit", &window.id]) {
+                tracing::debug!("Couldn't ask window {} to close: {}", window.id, e.message);
+            }
+        }
+        let stage = if exited_within(pid, grace, &context.ct).await? {
+            QuitStage::Closed
+        } else {
+            signal(pid, "-TERM")?;
+            if exited_within(pid, grace, &context.ct).await? {
+                QuitStage::Terminated
+            } else {
+                signal(pid, "-KILL")?;
+                if !exited_within(pid, Duration::from_secs(1), &context.ct).await? {
+                    return Err(McpError::internal_error(format!("pid {} is still running after SIGKILL", pid), None));
+                }
+                QuitStage::Killed
+            }
+        };
+        quit.stage = Some(stage);
+        quit.waited_ms = started.elapsed().as_millis() as u64;
+        tracing::info!("Quit pid {} ({:?})", pid, stage);
+        let how = match stage {
+            QuitStage::Closed => "closing its windows",
+            QuitStage::Terminated => "SIGTERM, after its windows didn't close",
+            QuitStage::Killed => "SIGKILL, after closing its windows and SIGTERM didn't",
+        };
+        Ok(output::structured(format!("pid {} quit on {} ({}ms)", pid, how, quit.waited_ms), &quit))
+    }
+
+    #[rmcp::tool(description = "Send a signal to the process owning a window: TERM to end it, HUP, STOP to pause a hung or noisy app (it stops drawing and responding) and CONT to resume it. Use quit_app to close an app gracefully", annotations(read_only_hint = false, destructive_hint = true), output_schema = output::schema::<SignalledProcess>())]
+    pub async fn signal_window_process(
+        &self,
+        Parameters(params): Parameters<SignalWindowProcessParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let pid = window_pid(&params.window_id)?;
+        if pid <= 1 || pid == std::process::id() {
+            return Err(McpError::invalid_params(format!("pid {} can't be signalled", pid), None));
+        }
+        let name = params.signal.name();
+        let mut signalled = SignalledProcess {
+            window_id: params.window_id,
+            pid,
+            signal: params.signal,
+            running: alive(pid),
+            stopped: false,
+            dry_run: None,
+        };
+        if params.dry_run.unwrap_or(self.config.dry_run) {
+            let report = format!("send SIG{} to pid {}, which owns window {}", name, pid, signalled.window_id);
+            signalled.dry_run = Some(report.clone());
+            return Ok(output::structured(format!("Dry run, would {}", report), &signalled));
+        }
+
+        signal(pid, &format!("-{}", name))?;
+        // A stop only shows in /proc once the process has been scheduled
+        if params.signal == AppSignal::Stop {
+            tokio::time::sleep(crate::wait::POLL_INTERVAL).await;
+        }
+        signalled.running = alive(pid);
+        signalled.stopped = state(pid).is_some_and(|state| state == "T");
+        tracing::info!("Sent SIG{} to pid {} (window {})", name, pid, signalled.window_id);
+        let now = if signalled.stopped {
+            "it's stopped"
+        } else if signalled.running {
+            "it's running"
+        } else {
+            "it has exited"
+        };
+        Ok(output::structured(
+            format!("Sent SIG{} to pid {} (window {}); {}", name, pid, signalled.window_id, now),
+            &signalled
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env(names: &[&str]) -> BTreeMap<String, String> {
+        names.iter().map(|name| (name.to_string(), "x".to_string())).collect()
+    }
+
+    #[test]
+    fn only_listed_variables_can_be_set() {
+        assert!(check_env(&env(&["LANG", "LC_ALL", "TZ"]), &[]).is_ok());
+        for name in ["PATH", "LD_PRELOAD", "GTK_MODULES", "GIO_EXTRA_MODULES", "PYTHONPATH", "DISPLAY", "", "A=B"] {
+            assert!(check_env(&env(&[name]), &[]).is_err(), "{}", name);
+        }
+        let allowed = vec!["GTK_THEME".to_string(), "PATH".to_string()];
+        assert!(check_env(&env(&["GTK_THEME"]), &allowed).is_ok());
+        // Never, even if listed
+        assert!(check_env(&env(&["PATH"]), &allowed).is_err());
+    }
+
+    #[test]
+    fn config_cant_allow_loader_variables() {
+        let launch = |names: &[&str]| Launch { env: names.iter().map(|name| name.to_string()).collect(), ..Default::default() };
+        assert!(validate(&launch(&["GTK_THEME"])).is_ok());
+        assert!(validate(&launch(&["PATH"])).is_err());
+        assert!(validate(&launch(&["LD_LIBRARY_PATH"])).is_err());
+    }
+
+    #[test]
+    fn programs_resolve_with_the_server_path() {
+        let sh = resolve_program("sh").unwrap();
+        assert!(sh.is_absolute() && sh.ends_with("sh"));
+        assert_eq!(resolve_program("./run.sh").unwrap(), PathBuf::from("./run.sh"));
+        assert!(resolve_program("no-such-program-anywhere").is_err());
+    }
+}
@@ -0,0 +1,1220 @@
+//! rmcp-xdotool: MCP server for mouse and keyboard automation via xdotool
+//!
+//! Gives Claude the power to interact with your desktop.
+//! Use responsibly. Or don't. You're a pioneer.
+//!
+//! The library is the whole server: `XdotoolServer` is an rmcp
+//! `ServerHandler`, so another MCP server can serve it as is, or add its
+//! `tool_router` to its own. Call `backend::select` once at startup to pick
+//! how commands run (the xdotool binary by default), and build the server
+//! from a `config::Config`.
+
+#[cfg(feature = "accessibility")]
+mod accessibility;
+mod activity;
+mod apps;
+mod allowlist;
+mod approval;
+pub mod audit;
+mod auth;
+pub mod backend;
+pub mod batch;
+pub mod cli;
+mod clients;
+pub mod clipboard;
+#[cfg(feature = "wayland")]
+mod compositor;
+pub mod config;
+pub mod desktop;
+pub mod display;
+pub mod environment;
+#[cfg(any(feature = "wayland", feature = "uinput"))]
+mod evdev;
+pub mod control;
+mod headless;
+pub mod hotkeys;
+mod history;
+mod http;
+pub mod injection;
+pub mod killswitch;
+mod launch;
+#[cfg(feature = "libxdo")]
+mod libxdo;
+mod limits;
+mod listing;
+mod lock;
+pub mod logging;
+pub mod macros;
+pub mod mock;
+mod notify;
+#[cfg(feature = "ocr")]
+mod ocr;
+pub mod output;
+#[cfg(feature = "ping")]
+mod ping;
+pub mod plugins;
+mod policy;
+mod process;
+mod prompts;
+mod queue;
+mod ratelimit;
+#[cfg(feature = "recording")]
+pub mod recording;
+pub mod redact;
+#[cfg(feature = "screenshots")]
+pub mod sampling;
+pub mod schedule;
+#[cfg(feature = "screenshots")]
+pub mod screenshot;
+pub mod script;
+mod smartclick;
+pub mod sse;
+pub mod streamable;
+pub mod tcp;
+pub mod timeouts;
+#[cfg(feature = "uinput")]
+mod uinput;
+pub mod wait;
+mod windowcache;
+#[cfg(feature = "wayland")]
+mod wayland;
+
+use rmcp::{
+    handler::server::{prompt::PromptContext, router::{prompt::PromptRouter, tool::ToolRouter}, tool::ToolCallContext, ServerHandler, wrapper::Parameters},
+    model::*,
+    ErrorData as McpError,
+};
+#[cfg(feature = "screenshots")]
+use base64::Engine;
+use rmcp::service::{RequestContext, RoleServer};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio_util::sync::CancellationToken;
+use tracing::Instrument;
+
+// === Parameter Types ===
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct MoveMouseParams {
+    #[schemars(description = "X coordinate")]
+    pub x: i32,
+    #[schemars(description = "Y coordinate")]
+    pub y: i32,
+    #[schemars(description = "Only report the xdotool command that would run. Default: the server's dry_run setting")]
+    pub dry_run: Option<bool>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ClickParams {
+    #[schemars(description = "Button to click: 1 (left), 2 (middle), 3 (right). Default: 1")]
+    #[serde(default = "default_button")]
+    pub button: u8,
+    #[serde(flatten)]
+    pub injection: injection::InjectionParams,
+    #[schemars(description = "Only report the xdotool command that would run. Default: the server's dry_run setting")]
+    pub dry_run: Option<bool>,
+}
+
+fn default_button() -> u8 { 1 }
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ClickAtParams {
+    #[schemars(description = "X coordinate")]
+    pub x: i32,
+    #[schemars(description = "Y coordinate")]
+    pub y: i32,
+    #[schemars(description = "Button to click: 1 (left), 2 (middle), 3 (right). Default: 1")]
+    #[serde(default = "default_button")]
+    pub button: u8,
+    #[schemars(description = "Don't click; return a screenshot with a crosshair on the target instead. Default: false")]
+    #[serde(default)]
+    pub preview: bool,
+    #[serde(flatten)]
+    pub injection: injection::InjectionParams,
+    #[schemars(description = "Only report the xdotool command that would run. Default: the server's dry_run setting")]
+    pub dry_run: Option<bool>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct TypeTextParams {
+    #[schemars(description = "Text to type")]
+    pub text: String,
+    #[schemars(description = "Delay between keystrokes in milliseconds. Default: 12")]
+    #[serde(default = "default_delay")]
+    pub delay: u32,
+    #[schemars(description = "The text is a password or other secret: it is never echoed back or logged. Default: false")]
+    #[serde(default)]
+    pub secret: bool,
+    #[serde(flatten)]
+    pub injection: injection::InjectionParams,
+    #[schemars(description = "Only report the xdotool command that would run. Default: the server's dry_run setting")]
+    pub dry_run: Option<bool>,
+}
+
+fn default_delay() -> u32 { 12 }
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct KeyPressParams {
+    #[schemars(description = "Key(s) to press. Examples: Return, Escape, ctrl+c, alt+Tab, super+1")]
+    pub key: String,
+    #[serde(flatten)]
+    pub injection: injection::InjectionParams,
+    #[schemars(description = "Only report the xdotool command that would run. Default: the server's dry_run setting")]
+    pub dry_run: Option<bool>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ScrollParams {
+    #[schemars(description = "Scroll direction: up, down, left, right")]
+    pub direction: String,
+    #[schemars(description = "Number of clicks to scroll. Default: 3")]
+    #[serde(default = "default_clicks")]
+    pub clicks: u32,
+    #[serde(flatten)]
+    pub injection: injection::InjectionParams,
+    #[schemars(description = "Only report the xdotool command that would run. Default: the server's dry_run setting")]
+    pub dry_run: Option<bool>,
+}
+
+fn default_clicks() -> u32 { 3 }
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct DryRunParams {
+    #[schemars(description = "Only report the xdotool command that would run. Default: the server's dry_run setting")]
+    pub dry_run: Option<bool>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct SearchWindowParams {
+    #[schemars(description = "Search query (window name, class, or pattern)")]
+    pub query: String,
+    #[schemars(description = "Search by: 'name', 'class', 'classname', or 'any' (default: 'any')")]
+    #[serde(default = "default_search_type")]
+    pub search_type: String,
+    #[schemars(description = "Only windows that are mapped (shown). Default: false")]
+    #[serde(default)]
+    pub only_visible: bool,
+    #[serde(flatten)]
+    pub filter: listing::WindowFilterParams,
+    #[serde(flatten)]
+    pub page: listing::PageParams,
+}
+
+fn default_search_type() -> String { "any".to_string() }
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct WindowIdParams {
+    #[schemars(description = "Window ID (from search_window or get_active_window)")]
+    pub window_id: String,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct WindowsForPidParams {
+    #[schemars(description = "Process ID, e.g. from launch_app or get_window_pid")]
+    pub pid: u32,
+    #[schemars(description = "Also windows of the process's children (helpers, subprocesses). Default: false")]
+    #[serde(default)]
+    pub include_children: bool,
+    #[schemars(description = "Only windows that are mapped (shown). Default: false")]
+    #[serde(default)]
+    pub only_visible: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, JsonSchema)]
+pub struct WindowGeometry {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    pub screen: u32,
+}
+
+// === Result Types ===
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct MouseMoved {
+    /// Where the cursor was moved to
+    pub position: output::Point,
+    /// The requested position, if it was off screen and got clamped
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub clamped_from: Option<output::Point>,
+    /// The xdotool command that would have run, in dry runs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dry_run: Option<String>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct Clicked {
+    /// left, middle or right
+    pub button: String,
+    /// 2 for a double-click
+    pub count: u32,
+    /// Where the click landed, if known
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub position: Option<output::Point>,
+    /// The requested position, if it was off screen and got clamped
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub clamped_from: Option<output::Point>,
+    /// The crosshair screenshot, when click_at only previewed the click
+    #[cfg(feature = "screenshots")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub preview: Option<screenshot::Screenshot>,
+    /// The xdotool command that would have run, in dry runs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dry_run: Option<String>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct Typed {
+    /// Number of characters typed
+    pub characters: usize,
+    /// The text typed; absent if it was secret
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    /// The xdotool command that would have run, in dry runs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dry_run: Option<String>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct KeyPressed {
+    pub key: String,
+    /// The xdotool command that would have run, in dry runs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dry_run: Option<String>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct Scrolled {
+    pub direction: String,
+    pub clicks: u32,
+    /// The xdotool command that would have run, in dry runs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dry_run: Option<String>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct WindowPid {
+    pub window_id: String,
+    /// From the window's _NET_WM_PID
+    pub pid: u32,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct PidWindows {
+    pub pid: u32,
+    /// Windows whose _NET_WM_PID is the process (or with include_children, one of its children), in ID order
+    pub windows: Vec<output::Window>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct WindowList {
+    pub windows: Vec<output::Window>,
+    /// Pass as cursor to get the next page; absent on the last one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+}
+
+/// Error for a call needing something this build left out
+#[cfg(not(all(feature = "screenshots", feature = "ocr")))]
+pub(crate) fn feature_disabled(feature: &str) -> McpError {
+    McpError::internal_error(
+        format!("This build of rmcp-xdotool leaves out the '{}' feature", feature),
+        None
+    )
+}
+
+// === Server ===
+
+#[derive(Debug, Clone)]
+pub struct XdotoolServer {
+    pub tool_router: ToolRouter<Self>,
+    pub prompt_router: PromptRouter<Self>,
+    #[cfg(feature = "recording")]
+    recording: Arc<Mutex<Option<recording::Recording>>>,
+    #[cfg(feature = "screenshots")]
+    screenshots: Arc<Mutex<screenshot::ScreenshotCache>>,
+    history: Arc<Mutex<history::ActionHistory>>,
+    actions: Arc<Mutex<control::ActionRegistry>>,
+    rate_limiter: Arc<Mutex<ratelimit::RateLimiter>>,
+    redactor: Arc<redact::Redactor>,
+    clients: Arc<HashMap<String, Arc<clients::ClientScope>>>,
+    /// The client this handle serves, if it authenticated as one
+    client: Option<Arc<clients::ClientScope>>,
+    /// The transport session this handle serves, for attributing its calls
+    session: Option<Arc<str>>,
+    input_queue: Arc<Mutex<queue::InputQueue>>,
+    subscriptions: Arc<Mutex<desktop::Subscriptions>>,
+    virtual_displays: Arc<Mutex<headless::Sessions>>,
+    config: config::Config,
+}
+
+impl Default for XdotoolServer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl XdotoolServer {
+    pub fn new() -> Self {
+        Self::with_config(config::Config::default())
+    }
+
+    pub fn with_config(config: config::Config) -> Self {
+        // Config::load has already rejected bad patterns; hide everything if one slips through
+        let redactor = redact::Redactor::new(&config.redaction)
+            .unwrap_or_else(|_| redact::Redactor::new(&redact::Redaction::default()).unwrap());
+        let mut tool_router = Self::tool_router()
+            + Self::clipboard_router() + Self::batch_router() + Self::macro_router()
+            + Self::script_router() + Self::control_router() + Self::schedule_router() + Self::audit_router() + Self::desktop_router()
+            + Self::display_router() + Self::mock_router() + Self::headless_router() + Self::environment_router() + Self::activity_router() + Self::launch_router() + Self::wait_router() + Self::hotkeys_router()
+            + Self::smartclick_router() + Self::apps_router() + Self::notify_router();
+        #[cfg(feature = "accessibility")]
+        {
+            tool_router += Self::accessibility_router();
+        }
+        #[cfg(feature = "screenshots")]
+        {
+            tool_router += Self::screenshot_router() + Self::sampling_router();
+        }
+        #[cfg(feature = "recording")]
+        {
+            tool_router += Self::recording_router();
+        }
+        plugins::add_routes(&mut tool_router, &config.plugins);
+        // Process-wide, like the backend: the first server's setting holds
+        process::set_timeout(std::time::Duration::from_secs(config.command_timeout_secs));
+        if let Some(path) = &config.xdotool_path {
+            environment::set_xdotool_path(path.clone());
+        }
+        Self {
+            tool_router,
+            prompt_router: Self::prompt_router(),
+            #[cfg(feature = "recording")]
+            recording: Arc::new(Mutex::new(None)),
+            #[cfg(feature = "screenshots")]
+            screenshots: Arc::new(Mutex::new(screenshot::ScreenshotCache::default())),
+            history: Arc::new(Mutex::new(history::ActionHistory::default())),
+            actions: Arc::new(Mutex::new(control::ActionRegistry::default())),
+            rate_limiter: Arc::new(Mutex::new(ratelimit::RateLimiter::new(&config.rate_limits))),
+            redactor: Arc::new(redactor),
+            clients: Arc::new(clients::scopes(&config.clients)),
+            client: None,
+            session: None,
+            input_queue: Arc::new(Mutex::new(queue::InputQueue::default())),
+            subscriptions: Arc::new(Mutex::new(desktop::Subscriptions::default())),
+            virtual_displays: Arc::new(Mutex::new(headless::Sessions::default())),
+            config,
+        }
+    }
+
+    /// Mouse button number xdotool uses for a scroll direction
+    fn scroll_button(direction: &str) -> Result<&'static str, McpError> {
+        match direction.to_lowercase().as_str() {
+            "up" => Ok("4"),
+            "down" => Ok("5"),
+            "left" => Ok("6"),
+            "right" => Ok("7"),
+            _ => Err(McpError::internal_error(
+                "Invalid direction. Use: up, down, left, right",
+                None
+            ))
+        }
+    }
+
+    /// Run an input-injecting xdotool command, or in dry-run mode just describe it.
+    /// Returns the command line that would have run when nothing was executed.
+    fn run_input(&self, args: &[&str], dry_run: Option<bool>) -> Result<Option<String>, McpError> {
+        if dry_run.unwrap_or(self.config.dry_run) {
+            return Ok(Some(Self::format_invocation(args)));
+        }
+        self.run_checked(args)?;
+        Ok(None)
+    }
+
+    /// Reply text for a dry run of `command`
+    fn dry_run_report(command: &str) -> String {
+        format!("Dry run, would run: {}", command)
+    }
+
+    /// Shell-style rendering of an xdotool invocation, for dry-run reports:
+    /// the programs the backends would run for it.
+    fn format_invocation<S: AsRef<str>>(args: &[S]) -> String {
+        let args: Vec<&str> = args.iter().map(AsRef::as_ref).collect();
+        match backend::describe(&args) {
+            Ok(plan) => plan.iter().map(|argv| Self::format_argv(argv)).collect::<Vec<_>>().join(" && "),
+            Err(_) => Self::format_argv(&[&["xdotool"], args.as_slice()].concat()),
+        }
+    }
+
+    fn format_argv<S: AsRef<str>>(argv: &[S]) -> String {
+        let mut rendered = vec![];
+        for arg in argv {
+            let arg = arg.as_ref();
+            let plain = !arg.is_empty()
+                && arg.chars().all(|c| c.is_ascii_alphanumeric() || "+-_.,:/=@%".contains(c));
+            if plain {
+                rendered.push(arg.to_string());
+            } else {
+                rendered.push(format!("'{}'", arg.replace('\'', "'\\''")));
+            }
+        }
+        rendered.join(" ")
+    }
+
+    /// Run an xdotool invocation through the backends and return its stdout, turning a failed exit into an error
+    fn run_xdotool(args: &[&str]) -> Result<String, McpError> {
+        backend::run(args)
+    }
+
+    /// `run_xdotool`, killing the running program if `cancel` fires before it exits
+    async fn run_xdotool_until(args: &[&str], cancel: &CancellationToken) -> Result<String, McpError> {
+        backend::run_until(args, cancel).await
+    }
+
+    /// Current cursor position, via `xdotool getmouselocation --shell`
+    fn mouse_position() -> Result<(i32, i32), McpError> {
+        let stdout = Self::run_xdotool(&["getmouselocation", "--shell"])?;
+        let mut x = 0;
+        let mut y = 0;
+        for line in stdout.lines() {
+            if let Some(value) = line.strip_prefix("X=") {
+                x = value.parse().unwrap_or(0);
+            } else if let Some(value) = line.strip_prefix("Y=") {
+                y = value.parse().unwrap_or(0);
+            }
+        }
+        Ok((x, y))
+    }
+
+    /// ID of the window under the cursor
+    fn window_under_cursor() -> Result<String, McpError> {
+        let stdout = Self::run_xdotool(&["getmouselocation", "--shell"])?;
+        Ok(stdout.lines()
+            .find_map(|line| line.strip_prefix("WINDOW="))
+            .unwrap_or_default()
+            .to_string())
+    }
+
+    /// Remember an action at the current cursor position for screenshot markers.
+    /// Returns the position, if it could be read.
+    fn record_action_here(&self, kind: impl Into<String>) -> Option<output::Point> {
+        match Self::mouse_position() {
+            Ok((x, y)) => {
+                self.history.lock().unwrap().record(kind, x, y);
+                Some(output::Point { x, y })
+            }
+            Err(e) => {
+                tracing::debug!("Not recording action, cursor position unknown: {}", e.message);
+                None
+            }
+        }
+    }
+
+    /// Position and size of a window, via `xdotool getwindowgeometry --shell`, or from the window cache
+    fn window_geometry(window_id: &str) -> Result<WindowGeometry, McpError> {
+        windowcache::geometry(window_id, || Self::read_window_geometry(window_id))
+    }
+
+    fn read_window_geometry(window_id: &str) -> Result<WindowGeometry, McpError> {
+        let stdout = Self::run_xdotool(&["getwindowgeometry", "--shell", window_id])?;
+        let mut geometry = WindowGeometry::default();
+        for line in stdout.lines() {
+            if let Some(value) = line.strip_prefix("X=") {
+                geometry.x = value.parse().unwrap_or(0);
+            } else if let Some(value) = line.strip_prefix("Y=") {
+                geometry.y = value.parse().unwrap_or(0);
+            } else if let Some(value) = line.strip_prefix("WIDTH=") {
+                geometry.width = value.parse().unwrap_or(0);
+            } else if let Some(value) = line.strip_prefix("HEIGHT=") {
+                geometry.height = value.parse().unwrap_or(0);
+            } else if let Some(value) = line.strip_prefix("SCREEN=") {
+                geometry.screen = value.parse().unwrap_or(0);
+            }
+        }
+        Ok(geometry)
+    }
+
+    /// Window IDs matching a query, via `xdotool search`.
+    /// search_type is 'name', 'class', 'classname', or anything else for xdotool's default.
+    fn search_windows(query: &str, search_type: &str, only_visible: bool) -> Result<Vec<String>, McpError> {
+        let mut args = vec!["search"];
+        if only_visible {
+            args.push("--onlyvisible");
+        }
+
+        match search_type.to_lowercase().as_str() {
+            "name" => args.push("--name"),
+            "class" => args.push("--class"),
+            "classname" => args.push("--classname"),
+            _ => {} // 'any' uses default behavior
+        }
+
+        args.push(query);
+
+        Ok(backend::windows().run(&args)?.lines().map(str::to_string).collect())
+    }
+
+    /// Windows whose _NET_WM_PID is `pid`, via `xdotool search --pid`
+    fn pid_windows(pid: u32, only_visible: bool) -> Result<Vec<String>, McpError> {
+        let pid = pid.to_string();
+        let mut args = vec!["search"];
+        if only_visible {
+            args.push("--onlyvisible");
+        }
+        // No pattern: every window with the PID
+        args.extend(["--pid", &pid]);
+        Ok(backend::windows().run(&args)?.lines().map(str::to_string).collect())
+    }
+
+    /// Title of a window, via `xdotool getwindowname`, or from the window cache
+    fn window_name(window_id: &str) -> Result<String, McpError> {
+        windowcache::name(window_id, || Self::read_window_name(window_id))
+    }
+
+    /// `window_name`, always asking xdotool, for pollers watching the title change
+    fn window_name_now(window_id: &str) -> Result<String, McpError> {
+        windowcache::fresh_name(window_id, || Self::read_window_name(window_id))
+    }
+
+    fn read_window_name(window_id: &str) -> Result<String, McpError> {
+        Ok(Self::run_xdotool(&["getwindowname", window_id])?.trim().to_string())
+    }
+
+    /// Mapped top-level windows, as decimal IDs
+    fn visible_windows() -> Result<Vec<String>, McpError> {
+        backend::windows().visible_windows()
+    }
+
+    /// Managed top-level windows (`_NET_CLIENT_LIST`) that are currently mapped, as decimal IDs
+    fn x11_visible_windows() -> Result<Vec<String>, McpError> {
+        let output = process::output(display::command("xprop")
+            .args(["-root", "_NET_CLIENT_LIST"]))
+            .map_err(|e| process::error("xprop", e))?;
+
+        if !output.status.success() {
+            return Err(McpError::internal_error(
+                format!("xprop error: {}", String::from_utf8_lossy(&output.stderr)),
+                None
+            ));
+        }
+
+        // xdotool search exits non-zero when nothing matches, which just means nothing is visible
+        let visible = process::output(display::command("xdotool")
+            .args(["search", "--onlyvisible", "--name", ""]))
+            .map_err(|e| process::error("xdotool", e))?;
+        let visible = String::from_utf8_lossy(&visible.stdout);
+        let visible: Vec<&str> = visible.lines().collect();
+
+        // Output looks like: _NET_CLIENT_LIST(WINDOW): window id # 0x1e00003, 0x2200007
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let list = stdout.split_once('#').map(|(_, ids)| ids).unwrap_or("");
+        Ok(list.split(',')
+            .filter_map(|id| u64::from_str_radix(id.trim().trim_start_matches("0x"), 16).ok())
+            .map(|id| id.to_string())
+            .filter(|id| visible.contains(&id.as_str()))
+            .collect())
+    }
+
+    /// X display to capture from, as ffmpeg's x11grab expects it
+    #[cfg(any(feature = "screenshots", feature = "recording"))]
+    fn x_display() -> String {
+        display::current()
+            .or_else(|| std::env::var("DISPLAY").ok())
+            .unwrap_or_else(|| ":0".to_string())
+    }
+
+    /// Size of the whole X screen, via `xdotool getdisplaygeometry`
+    fn display_geometry() -> Result<(u32, u32), McpError> {
+        let stdout = Self::run_xdotool(&["getdisplaygeometry"])?;
+        let mut parts = stdout.split_whitespace().map(|v| v.parse::<u32>());
+        match (parts.next(), parts.next()) {
+            (Some(Ok(width)), Some(Ok(height))) => Ok((width, height)),
+            _ => Err(McpError::internal_error(
+                format!("Unexpected getdisplaygeometry output: {}", stdout.trim()),
+                None
+            )),
+        }
+    }
+
+    /// Check a point against the display, clamping or rejecting it per the `out_of_bounds` setting
+    fn fit_point(&self, x: i32, y: i32, screen: (u32, u32)) -> Result<(i32, i32), McpError> {
+        let (width, height) = (screen.0 as i32, screen.1 as i32);
+        if (0..width).contains(&x) && (0..height).contains(&y) {
+            return Ok((x, y));
+        }
+        match self.config.out_of_bounds {
+            config::OutOfBounds::Clamp => Ok((x.clamp(0, width - 1), y.clamp(0, height - 1))),
+            config::OutOfBounds::Reject => Err(McpError::internal_error(
+                format!("({}, {}) is outside the {}x{} display", x, y, width, height),
+                None
+            )),
+        }
+    }
+
+    /// `fit_point` for a tool's target, skipped in dry runs since they don't need a display
+    fn bound_point(&self, x: i32, y: i32, dry_run: Option<bool>) -> Result<(i32, i32), McpError> {
+        if dry_run.unwrap_or(self.config.dry_run) {
+            return Ok((x, y));
+        }
+        self.fit_point(x, y, Self::display_geometry()?)
+    }
+
+    /// Note for replies when a point was clamped
+    fn clamp_note(requested: (i32, i32), actual: (i32, i32)) -> String {
+        if requested == actual {
+            String::new()
+        } else {
+            format!(" (clamped from ({}, {}))", requested.0, requested.1)
+        }
+    }
+
+    /// The requested point for structured results, if it was clamped
+    fn clamped_from(requested: (i32, i32), actual: (i32, i32)) -> Option<output::Point> {
+        (requested != actual).then(|| requested.into())
+    }
+
+    fn button_name(button: u8) -> &'static str {
+        match button {
+            1 => "left",
+            2 => "middle",
+            3 => "right",
+            _ => "unknown"
+        }
+    }
+}
+
+#[rmcp::tool_router]
+impl XdotoolServer {
+    #[rmcp::tool(description = "Move mouse cursor to x,y coordinates on screen", annotations(read_only_hint = false, destructive_hint = true), output_schema = output::schema::<MouseMoved>())]
+    pub async fn move_mouse(
+        &self,
+        Parameters(params): Parameters<MoveMouseParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let (px, py) = self.bound_point(params.x, params.y, params.dry_run)?;
+        let (x, y) = (px.to_string(), py.to_string());
+        let dry_run = self.run_input(&["mousemove", &x, &y], params.dry_run)?;
+        let text = match &dry_run {
+            Some(command) => Self::dry_run_report(command),
+            None => format!("Mouse moved to ({}, {}){}", px, py, Self::clamp_note((params.x, params.y), (px, py))),
+        };
+
+        Ok(output::structured(text, &MouseMoved {
+            position: (px, py).into(),
+            clamped_from: Self::clamped_from((params.x, params.y), (px, py)),
+            dry_run,
+        }))
+    }
+
+    #[rmcp::tool(description = "Click mouse button at current cursor position. Button: 1=left, 2=middle, 3=right", annotations(read_only_hint = false, destructive_hint = true), output_schema = output::schema::<Clicked>())]
+    pub async fn click(
+        &self,
+        Parameters(params): Parameters<ClickParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let window = self.injection_window(&params.injection)?;
+        let button = params.button.to_string();
+        let mut clicked = Clicked {
+            button: Self::button_name(params.button).to_string(),
+            count: 1,
+            position: None,
+            clamped_from: None,
+            #[cfg(feature = "screenshots")]
+            preview: None,
+            dry_run: self.run_input(&injection::target(&["click", &button], window.as_deref()), params.dry_run)?,
+        };
+        if let Some(command) = &clicked.dry_run {
+            return Ok(output::structured(Self::dry_run_report(command), &clicked));
+        }
+
+        clicked.position = self.record_action_here(format!("click {}", Self::button_name(params.button)));
+        Ok(output::structured(
+            format!("Clicked {} mouse button", Self::button_name(params.button)),
+            &clicked
+        ))
+    }
+
+    #[rmcp::tool(description = "Move mouse to x,y coordinates and click. Button: 1=left, 2=middle, 3=right. Set preview=true to get a crosshair screenshot of the target instead of clicking", annotations(read_only_hint = false, destructive_hint = true), output_schema = output::schema::<Clicked>())]
+    pub async fn click_at(
+        &self,
+        Parameters(params): Parameters<ClickAtParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let mut clicked = Clicked {
+            button: Self::button_name(params.button).to_string(),
+            count: 1,
+            position: Some((params.x, params.y).into()),
+            clamped_from: None,
+            #[cfg(feature = "screenshots")]
+            preview: None,
+            dry_run: None,
+        };
+        #[cfg(not(feature = "screenshots"))]
+        if params.preview {
+            return Err(feature_disabled("screenshots"));
+        }
+        #[cfg(feature = "screenshots")]
+        if params.preview {
+            let screen = Self::display_geometry()?;
+            let region = screenshot::region_around(params.x, params.y, screen);
+            let png = screenshot::capture_png(region, Some(&screenshot::crosshair_filter(region, params.x, params.y)))?;
+            let details = format!(
+                "Preview only, nothing clicked. Crosshair marks where the {} click would land at ({}, {})",
+                Self::button_name(params.button), params.x, params.y
+            );
+            let (content, shot) = self.screenshot_contents(png, region, &details, true);
+            clicked.preview = Some(shot);
+            return Ok(output::with_content(content, &clicked));
+        }
+
+        let (px, py) = self.bound_point(params.x, params.y, params.dry_run)?;
+        let (x, y, button) = (px.to_string(), py.to_string(), params.button.to_string());
+        clicked.position = Some((px, py).into());
+        clicked.clamped_from = Self::clamped_from((params.x, params.y), (px, py));
+        let window = self.injection_window(&params.injection)?;
+        clicked.dry_run = self.run_input(&injection::target(&["mousemove", &x, &y, "click", &button], window.as_deref()), params.dry_run)?;
+        if let Some(command) = &clicked.dry_run {
+            return Ok(output::structured(Self::dry_run_report(command), &clicked));
+        }
+
+        self.history.lock().unwrap().record(format!("click {}", Self::button_name(params.button)), px, py);
+        Ok(output::structured(
+            format!(
+                "Clicked {} at ({}, {}){}",
+                Self::button_name(params.button), px, py, Self::clamp_note((params.x, params.y), (px, py))
+            ),
+            &clicked
+        ))
+    }
+
+    #[rmcp::tool(description = "Type text as keyboard input. Use for filling forms, search boxes, etc.", annotations(read_only_hint = false, destructive_hint = true), output_schema = output::schema::<Typed>())]
+    pub async fn type_text(
+        &self,
+        Parameters(params): Parameters<TypeTextParams>,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        self.check_text_length(&params.text)?;
+        let delay = params.delay.to_string();
+        let window = self.injection_window(&params.injection)?;
+        let command = ["type", "--delay", &delay, &params.text];
+        let args = injection::target(&command, window.as_deref());
+        let mut typed = Typed {
+            characters: params.text.chars().count(),
+            text: (!params.secret).then(|| params.text.clone()),
+            dry_run: None,
+        };
+        if params.dry_run.unwrap_or(self.config.dry_run) {
+            let mut command = Self::format_invocation(&args);
+            if params.secret {
+                command = command.replace(&params.text, "[secret]");
+            }
+            let text = Self::dry_run_report(&command);
+            typed.dry_run = Some(command);
+            return Ok(output::structured(text, &typed));
+        }
+        // Long text takes a while to type; a cancelled call stops mid-way
+        if let Err(e) = self.run_checked_until(&args, &context.ct).await {
+            if context.ct.is_cancelled() {
+                Self::release_input();
+            }
+            return Err(e);
+        }
+
+        self.record_action_here("type");
+        if params.secret {
+            return Ok(output::structured(
+                format!("Typed {} characters (secret)", typed.characters),
+                &typed
+            ));
+        }
+        Ok(output::structured(format!("Typed: \"{}\"", params.text), &typed))
+    }
+
+    #[rmcp::tool(description = "Press a key or combo. Examples: Return, Escape, ctrl+c, alt+Tab, super+1, ctrl+shift+t", annotations(read_only_hint = false, destructive_hint = true), output_schema = output::schema::<KeyPressed>())]
+    pub async fn key_press(
+        &self,
+        Parameters(params): Parameters<KeyPressParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let window = self.injection_window(&params.injection)?;
+        let dry_run = self.run_input(&injection::target(&["key", &params.key], window.as_deref()), params.dry_run)?;
+        let text = match &dry_run {
+            Some(command) => Self::dry_run_report(command),
+            None => format!("Pressed key: {}", params.key),
+        };
+
+        Ok(output::structured(text, &KeyPressed { key: params.key, dry_run }))
+    }
+
+    #[rmcp::tool(description = "Scroll mouse wheel. Direction: up, down, left, right", annotations(read_only_hint = false, destructive_hint = true), output_schema = output::schema::<Scrolled>())]
+    pub async fn scroll(
+        &self,
+        Parameters(params): Parameters<ScrollParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let button = Self::scroll_button(&params.direction)?;
+        let clicks = params.clicks.to_string();
+        let window = self.injection_window(&params.injection)?;
+        let dry_run = self.run_input(&injection::target(&["click", "--repeat", &clicks, button], window.as_deref()), params.dry_run)?;
+        let text = match &dry_run {
+            Some(command) => Self::dry_run_report(command),
+            None => format!("Scrolled {} {} clicks", params.direction, params.clicks),
+        };
+
+        Ok(output::structured(text, &Scrolled {
+            direction: params.direction.to_lowercase(),
+            clicks: params.clicks,
+            dry_run,
+        }))
+    }
+
+    #[rmcp::tool(description = "Get current mouse cursor position", annotations(read_only_hint = true), output_schema = output::schema::<output::Point>())]
+    pub async fn get_mouse_position(&self) -> Result<CallToolResult, McpError> {
+        let (x, y) = Self::mouse_position()?;
+        Ok(output::structured(
+            format!("Mouse position: ({}, {})", x, y),
+            &output::Point { x, y }
+        ))
+    }
+
+    #[rmcp::tool(description = "Double-click at current mouse position", annotations(read_only_hint = false, destructive_hint = true), output_schema = output::schema::<Clicked>())]
+    pub async fn double_click(
+        &self,
+        Parameters(params): Parameters<DryRunParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let mut clicked = Clicked {
+            button: Self::button_name(1).to_string(),
+            count: 2,
+            position: None,
+            clamped_from: None,
+            #[cfg(feature = "screenshots")]
+            preview: None,
+            dry_run: self.run_input(&["click", "--repeat", "2", "1"], params.dry_run)?,
+        };
+        if let Some(command) = &clicked.dry_run {
+            return Ok(output::structured(Self::dry_run_report(command), &clicked));
+        }
+
+        clicked.position = self.record_action_here("double-click");
+        Ok(output::structured("Double-clicked", &clicked))
+    }
+
+    #[rmcp::tool(description = "Search for windows by name, class, or pattern, optionally only visible ones, on one desktop or with a WM_CLASS regex. Returns window IDs in pages of up to 50.", annotations(read_only_hint = true), output_schema = output::schema::<WindowList>())]
+    pub async fn search_window(
+        &self,
+        Parameters(params): Parameters<SearchWindowParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let window_ids = Self::search_windows(&params.query, &params.search_type, params.only_visible)?;
+        let page = listing::page(window_ids, &params.page, &params.filter, 50)?;
+        let mut text = if page.window_ids.is_empty() {
+            format!("No windows found matching '{}'", params.query)
+        } else {
+            format!("Found {} window(s):\n{}", page.window_ids.len(), page.window_ids.join("\n"))
+        };
+        if let Some(cursor) = &page.next_cursor {
+            text.push_str(&format!("\nMore windows match; pass cursor \"{}\" for the next page", cursor));
+        }
+
+        let windows = page.window_ids.into_iter().map(|id| output::Window { id, name: None }).collect();
+        Ok(output::structured(text, &WindowList { windows, next_cursor: page.next_cursor }))
+    }
+
+    #[rmcp::tool(description = "Get the currently focused/active window ID", annotations(read_only_hint = true), output_schema = output::schema::<output::Window>())]
+    pub async fn get_active_window(&self) -> Result<CallToolResult, McpError> {
+        let window_id = Self::run_xdotool(&["getactivewindow"])?.trim().to_string();
+        let name = Self::window_name(&window_id).ok();
+        Ok(output::structured(
+            format!("Active window ID: {}", window_id),
+            &output::Window { id: window_id, name }
+        ))
+    }
+
+    #[rmcp::tool(description = "Get window geometry (position and size) for a window ID", annotations(read_only_hint = true), output_schema = output::schema::<WindowGeometry>())]
+    pub async fn get_window_geometry(
+        &self,
+        Parameters(params): Parameters<WindowIdParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let geometry = Self::window_geometry(&params.window_id)?;
+
+        Ok(output::structured(
+            format!("Window {} geometry:\n  Position: ({}, {})\n  Size: {}x{}\n  Screen: {}",
+                params.window_id, geometry.x, geometry.y, geometry.width, geometry.height, geometry.screen),
+            &geometry
+        ))
+    }
+
+    #[rmcp::tool(description = "Get the window title/name for a window ID", annotations(read_only_hint = true), output_schema = output::schema::<output::Window>())]
+    pub async fn get_window_name(
+        &self,
+        Parameters(params): Parameters<WindowIdParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let name = Self::window_name(&params.window_id)?;
+        Ok(output::structured(
+            format!("Window {} title: {}", params.window_id, name),
+            &output::Window { id: params.window_id, name: Some(name) }
+        ))
+    }
+
+    #[rmcp::tool(description = "Get the process ID that owns a window, from its _NET_WM_PID", annotations(read_only_hint = true), output_schema = output::schema::<WindowPid>())]
+    pub async fn get_window_pid(
+        &self,
+        Parameters(params): Parameters<WindowIdParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let pid = backend::windows().run(&["getwindowpid", &params.window_id])?.trim().parse().map_err(|_| McpError::internal_error(
+            format!("Window {} has no _NET_WM_PID", params.window_id),
+            None
+        ))?;
+        Ok(output::structured(
+            format!("Window {} belongs to pid {}", params.window_id, pid),
+            &WindowPid { window_id: params.window_id, pid }
+        ))
+    }
+
+    #[rmcp::tool(description = "List every window a process owns (by _NET_WM_PID), optionally its children's too, e.g. to find the dialogs an app launched with launch_app opened. Call again to pick up windows that appear later", annotations(read_only_hint = true), output_schema = output::schema::<PidWindows>())]
+    pub async fn get_windows_for_pid(
+        &self,
+        Parameters(params): Parameters<WindowsForPidParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let mut pids: Vec<u32> = if params.include_children {
+            launch::process_tree(params.pid).into_iter().collect()
+        } else {
+            vec![params.pid]
+        };
+        pids.sort_unstable();
+        let mut window_ids = vec![];
+        for pid in pids {
+            window_ids.extend(Self::pid_windows(pid, params.only_visible)?);
+        }
+        window_ids.sort_by_key(|id| id.parse::<u64>().unwrap_or(u64::MAX));
+        window_ids.dedup();
+
+        let windows: Vec<output::Window> = window_ids.into_iter()
+            .map(|id| {
+                let name = Self::window_name(&id).ok();
+                output::Window { id, name }
+            })
+            .collect();
+        let text = if windows.is_empty() {
+            format!("pid {} has no windows", params.pid)
+        } else {
+            let lines: Vec<String> = windows.iter()
+                .map(|window| format!("{} \"{}\"", window.id, window.name.as_deref().unwrap_or_default()))
+                .collect();
+            format!("pid {} has {} window(s):\n{}", params.pid, windows.len(), lines.join("\n"))
+        };
+        Ok(output::structured(text, &PidWindows { pid: params.pid, windows }))
+    }
+}
+
+impl XdotoolServer {
+    /// Apply rate limits and approval, then run the tool
+    async fn dispatch(
+        &self,
+        request: CallToolRequestParam,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        self.check_client_scope(&request.name)?;
+        let idle_timeout = self.config.idle_disarm.after_minutes.map(|minutes| std::time::Duration::from_secs(minutes * 60));
+        let disarmed = self.actions.lock().unwrap().touch(idle_timeout);
+        let input = control::INPUT_TOOLS.contains(&request.name.as_ref()) || self.is_input_plugin(&request.name);
+        if disarmed && input {
+            return Err(control::disarmed_error(&request.name));
+        }
+        // Only a tool that really does a dry run skips the lock, idle and queue checks
+        let dry_run = self.is_dry_run(&request);
+        if input && !dry_run {
+            self.check_unlocked()?;
+        }
+        if let Some(reason) = self.paused_reason() {
+            if !control::PAUSE_EXEMPT.contains(&request.name.as_ref()) {
+                return Err(control::paused_error(&reason));
+            }
+        }
+        let cost = ratelimit::Cost::for_tool(&request.name, request.arguments.as_ref(), dry_run);
+        self.charge(cost)?;
+        self.check_policy(&request, &context).await?;
+        if input && !dry_run {
+            self.check_user_idle(&context.ct).await?;
+        }
+        // Held until the call returns so input from different clients never interleaves
+        let _turn = if input && !dry_run {
+            let turn = self.input_turn(&context.ct).await?;
+            if let Some(reason) = self.paused_reason() {
+                return Err(control::paused_error(&reason));
+            }
+            Some(turn)
+        } else {
+            None
+        };
+        let structured_only = self.structured_only(&context);
+        let limit = self.config.timeouts.limit(&request.name);
+        let tool = request.name.clone();
+        let tool_context = ToolCallContext::new(self, request, context);
+        let result = timeouts::run(&tool, limit, self.tool_router.call(tool_context)).await;
+        if structured_only {
+            return result.map(output::without_prose);
+        }
+        result
+    }
+}
+
+impl ServerHandler for XdotoolServer {
+    fn get_info(&self) -> ServerInfo {
+        ServerInfo {
+            protocol_version: ProtocolVersion::V_2024_11_05,
+            capabilities: ServerCapabilities::builder()
+                .enable_experimental_with(output::capabilities())
+                .enable_logging()
+                .enable_tools()
+                .enable_resources()
+                .enable_resources_subscribe()
+                .enable_prompts()
+                .build(),
+            server_info: Implementation::from_build_env(),
+            instructions: Some("Mouse and keyboard automation via xdotool. Move, click, type, scroll.".into()),
+        }
+    }
+
+    async fn call_tool(
+        &self,
+        mut request: CallToolRequestParam,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let tool = request.name.clone();
+        let arguments = request.arguments.clone();
+        // Events inside the span are attributed to this client, for log notifications
+        let span = tracing::info_span!("call", client = %self.client_name(), tool = %tool);
+        let started = std::time::Instant::now();
+        let result = async {
+            tracing::debug!("Tool call started");
+            let result = match self.take_display(&mut request) {
+                Ok(display) => self.on_display(display, self.dispatch(request, context)).await,
+                Err(e) => Err(e),
+            };
+            let elapsed_ms = started.elapsed().as_millis() as u64;
+            match &result {
+                Ok(_) => tracing::info!(elapsed_ms, "Tool call finished"),
+                Err(e) => tracing::info!(elapsed_ms, "Tool call failed: {}", e.message),
+            }
+            result
+        }.instrument(span).await;
+        self.audit(&tool, &arguments, &result);
+        result
+    }
+
+    async fn set_level(
+        &self,
+        request: SetLevelRequestParam,
+        context: RequestContext<RoleServer>,
+    ) -> Result<(), McpError> {
+        self.set_log_level(request.level, context.peer);
+        Ok(())
+    }
+
+    async fn list_tools(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListToolsResult, McpError> {
+        let mut tools = self.tool_router.list_all();
+        if let Some(scope) = &self.client {
+            tools.retain(|tool| scope.allows(&tool.name));
+        }
+        tools.iter_mut().for_each(display::add_argument);
+        Ok(ListToolsResult {
+            tools,
+            next_cursor: None,
+            meta: None,
+        })
+    }
+
+    async fn list_resources(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListResourcesResult, McpError> {
+        let mut resources = vec![];
+        #[cfg(feature = "screenshots")]
+        resources.extend(self.screenshots.lock().unwrap().resources());
+        resources.extend(self.audit_resources());
+        resources.extend(Self::desktop_resources());
+        Ok(ListResourcesResult {
+            resources,
+            next_cursor: None,
+            meta: None,
+        })
+    }
+
+    async fn list_prompts(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListPromptsResult, McpError> {
+        Ok(ListPromptsResult {
+            prompts: self.prompt_router.list_all(),
+            next_cursor: None,
+            meta: None,
+        })
+    }
+
+    async fn get_prompt(
+        &self,
+        request: GetPromptRequestParam,
+        context: RequestContext<RoleServer>,
+    ) -> Result<GetPromptResult, McpError> {
+        let prompt_context = PromptContext::new(self, request.name, request.arguments, context);
+        self.prompt_router.get_prompt(prompt_context).await
+    }
+
+    async fn subscribe(
+        &self,
+        request: SubscribeRequestParam,
+        context: RequestContext<RoleServer>,
+    ) -> Result<(), McpError> {
+        self.subscribe_desktop(&request.uri, context.peer)
+    }
+
+    async fn unsubscribe(
+        &self,
+        request: UnsubscribeRequestParam,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<(), McpError> {
+        self.unsubscribe_desktop(&request.uri);
+        Ok(())
+    }
+
+    async fn read_resource(
+        &self,
+        request: ReadResourceRequestParam,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ReadResourceResult, McpError> {
+        if let Some(result) = self.read_desktop(&request.uri) {
+            return Ok(result);
+        }
+        if let (audit::RECENT_URI, Some(path)) = (request.uri.as_str(), &self.config.audit_log) {
+            return Ok(ReadResourceResult {
+                contents: vec![ResourceContents::TextResourceContents {
+                    uri: request.uri.clone(),
+                    mime_type: Some("application/jsonl".to_string()),
+                    text: audit::read_recent(path, 50)?.join("\n"),
+                    meta: None,
+                }],
+            });
+        }
+
+        #[cfg(feature = "screenshots")]
+        if let Some(png) = self.screenshots.lock().unwrap().get(&request.uri) {
+            return Ok(ReadResourceResult {
+                contents: vec![ResourceContents::BlobResourceContents {
+                    uri: request.uri.clone(),
+                    mime_type: Some("image/png".to_string()),
+                    blob: base64::engine::general_purpose::STANDARD.encode(png),
+                    meta: None,
+                }],
+            });
+        }
+
+        Err(McpError::resource_not_found(
+            format!("Unknown or expired resource: {}", request.uri),
+            None
+        ))
+    }
+}
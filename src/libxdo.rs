@@ -0,0 +1,289 @@
+//! xdotool's library, called in-process instead of spawning the binary.
+//!
+//! Each xdotool run pays for a fork and exec and a new X connection, 10–30ms
+//! that dominates tight interaction loops. With `input_backend = "libxdo"`,
+//! libxdo (libxdo.so.3, from the xdotool package) is loaded at startup and
+//! one X connection is kept for the life of the server. Invocations made only
+//! of the commands below are done through it:
+//!
+//! - mousemove, mousemove_relative, click, mousedown, mouseup
+//! - key, keydown, keyup, type
+//! - getmouselocation, getactivewindow, windowactivate, getdisplaygeometry
+//!
+//! Anything else (searches, window stacks like `%1`, `--clearmodifiers`,
+//! other options) runs through the xdotool binary as before, the whole
+//! invocation at once so chained commands still share state. The library is
+//! looked up with dlopen, so the server builds and runs without it; "auto"
+//! uses it on X11 whenever it loads.
+
+use crate::allowlist::split_commands;
+use crate::backend::{parse, InputBackend, Step};
+use crate::display;
+use rmcp::ErrorData as McpError;
+use std::ffi::{c_char, c_int, c_uint, c_ulong, c_void, CString};
+use std::sync::{Mutex, OnceLock};
+
+/// X window ID; 0 is libxdo's CURRENTWINDOW, i.e. XTEST input wherever focus is
+type Window = c_ulong;
+
+/// The libxdo functions used, resolved once
+struct Api {
+    free: unsafe extern "C" fn(*mut c_void),
+    move_mouse: unsafe extern "C" fn(*const c_void, c_int, c_int, c_int) -> c_int,
+    move_mouse_relative: unsafe extern "C" fn(*const c_void, c_int, c_int) -> c_int,
+    mouse_down: unsafe extern "C" fn(*const c_void, Window, c_int) -> c_int,
+    mouse_up: unsafe extern "C" fn(*const c_void, Window, c_int) -> c_int,
+    click_window_multiple: unsafe extern "C" fn(*const c_void, Window, c_int, c_int, c_uint) -> c_int,
+    get_mouse_location2: unsafe extern "C" fn(*const c_void, *mut c_int, *mut c_int, *mut c_int, *mut Window) -> c_int,
+    enter_text_window: unsafe extern "C" fn(*const c_void, Window, *const c_char, c_uint) -> c_int,
+    send_keysequence_window: unsafe extern "C" fn(*const c_void, Window, *const c_char, c_uint) -> c_int,
+    send_keysequence_window_down: unsafe extern "C" fn(*const c_void, Window, *const c_char, c_uint) -> c_int,
+    send_keysequence_window_up: unsafe extern "C" fn(*const c_void, Window, *const c_char, c_uint) -> c_int,
+    get_active_window: unsafe extern "C" fn(*const c_void, *mut Window) -> c_int,
+    activate_window: unsafe extern "C" fn(*const c_void, Window) -> c_int,
+    wait_for_window_active: unsafe extern "C" fn(*const c_void, Window, c_int) -> c_int,
+    get_viewport_dimensions: unsafe extern "C" fn(*mut c_void, *mut c_uint, *mut c_uint, c_int) -> c_int,
+}
+
+/// A libxdo context (`xdo_t`) and its X connection
+struct Xdo {
+    api: Api,
+    xdo: *mut c_void,
+}
+
+// Only ever used behind the mutex, one call at a time
+unsafe impl Send for Xdo {}
+
+impl Drop for Xdo {
+    fn drop(&mut self) {
+        unsafe { (self.api.free)(self.xdo) }
+    }
+}
+
+static XDO: OnceLock<Mutex<Xdo>> = OnceLock::new();
+
+/// Look up a function in the loaded library
+unsafe fn symbol<T: Copy>(library: *mut c_void, name: &str) -> Result<T, String> {
+    let c_name = CString::new(name).unwrap();
+    let pointer = libc::dlsym(library, c_name.as_ptr());
+    if pointer.is_null() {
+        return Err(format!("libxdo has no {}", name));
+    }
+    assert_eq!(size_of::<T>(), size_of::<*mut c_void>());
+    Ok(std::mem::transmute_copy(&pointer))
+}
+
+fn open() -> Result<Xdo, String> {
+    let library = ["libxdo.so.3", "libxdo.so"].iter()
+        .map(|name| CString::new(*name).unwrap())
+        .map(|name| unsafe { libc::dlopen(name.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL) })
+        .find(|library| !library.is_null())
+        .ok_or_else(|| "libxdo.so.3 not found".to_string())?;
+    let api = unsafe {
+        Api {
+            free: symbol(library, "xdo_free")?,
+            move_mouse: symbol(library, "xdo_move_mouse")?,
+            move_mouse_relative: symbol(library, "xdo_move_mouse_relative")?,
+            mouse_down: symbol(library, "xdo_mouse_down")?,
+            mouse_up: symbol(library, "xdo_mouse_up")?,
+            click_window_multiple: symbol(library, "xdo_click_window_multiple")?,
+            get_mouse_location2: symbol(library, "xdo_get_mouse_location2")?,
+            enter_text_window: symbol(library, "xdo_enter_text_window")?,
+            send_keysequence_window: symbol(library, "xdo_send_keysequence_window")?,
+            send_keysequence_window_down: symbol(library, "xdo_send_keysequence_window_down")?,
+            send_keysequence_window_up: symbol(library, "xdo_send_keysequence_window_up")?,
+            get_active_window: symbol(library, "xdo_get_active_window")?,
+            activate_window: symbol(library, "xdo_activate_window")?,
+            wait_for_window_active: symbol(library, "xdo_wait_for_window_active")?,
+            get_viewport_dimensions: symbol(library, "xdo_get_viewport_dimensions")?,
+        }
+    };
+    let xdo_new: unsafe extern "C" fn(*const c_char) -> *mut c_void = unsafe { symbol(library, "xdo_new")? };
+    // NULL means $DISPLAY
+    let xdo = unsafe { xdo_new(std::ptr::null()) };
+    if xdo.is_null() {
+        return Err("xdo_new couldn't open the X display".to_string());
+    }
+    Ok(Xdo { api, xdo })
+}
+
+/// Load libxdo and connect to the display, once
+pub fn load() -> Result<(), String> {
+    if XDO.get().is_none() {
+        let _ = XDO.set(Mutex::new(open()?));
+    }
+    Ok(())
+}
+
+/// Which keysequence function a key command uses
+#[derive(Debug, Clone, Copy)]
+pub enum KeyAction {
+    Press,
+    Down,
+    Up,
+}
+
+/// One xdotool command as a libxdo call. Delays are in milliseconds, like xdotool's.
+#[derive(Debug)]
+pub enum Call {
+    Move { x: c_int, y: c_int },
+    MoveRelative { x: c_int, y: c_int },
+    Click { window: Window, button: c_int, repeat: c_int, delay: c_uint },
+    Button { window: Window, button: c_int, down: bool },
+    Keys { window: Window, action: KeyAction, keys: Vec<CString>, delay: c_uint },
+    Type { window: Window, texts: Vec<CString>, delay: c_uint },
+    MouseLocation { shell: bool },
+    ActiveWindow,
+    Activate { window: Window, sync: bool },
+    DisplayGeometry,
+}
+
+/// A window ID as xdotool takes it, decimal or 0x hex
+fn window_id(id: &str) -> Option<Window> {
+    match id.strip_prefix("0x") {
+        Some(hex) => Window::from_str_radix(hex, 16).ok(),
+        None => id.parse().ok(),
+    }
+}
+
+/// The libxdo call for one command, or None if only the binary can do it
+fn translate(command: &[&str]) -> Option<Call> {
+    let (options, args) = parse(command, &["--window", "--delay", "--repeat"]);
+    let mut window = 0;
+    let mut delay = None;
+    let mut repeat = 1;
+    let mut sync = false;
+    let mut shell = false;
+    for (option, value) in options {
+        match option {
+            "--window" => window = window_id(value)?,
+            "--delay" => delay = Some(value.parse().ok()?),
+            "--repeat" => repeat = value.parse().ok()?,
+            "--sync" => sync = true,
+            "--shell" => shell = true,
+            _ => return None,
+        }
+    }
+    let strings = |args: &[&str]| args.iter().map(|arg| CString::new(*arg).ok()).collect::<Option<Vec<_>>>();
+    let call = match (command[0], args.as_slice()) {
+        ("mousemove", [x, y]) if window == 0 && !sync => Call::Move { x: x.parse().ok()?, y: y.parse().ok()? },
+        ("mousemove_relative", [x, y]) if window == 0 && !sync => Call::MoveRelative { x: x.parse().ok()?, y: y.parse().ok()? },
+        ("click", [button]) => Call::Click { window, button: button.parse().ok()?, repeat, delay: delay.unwrap_or(100) },
+        ("mousedown" | "mouseup", [button]) => Call::Button { window, button: button.parse().ok()?, down: command[0] == "mousedown" },
+        ("key" | "keydown" | "keyup", keys) if !keys.is_empty() => {
+            let action = match command[0] {
+                "key" => KeyAction::Press,
+                "keydown" => KeyAction::Down,
+                _ => KeyAction::Up,
+            };
+            Call::Keys { window, action, keys: strings(keys)?, delay: delay.unwrap_or(12) }
+        }
+        ("type", texts) => Call::Type { window, texts: strings(texts)?, delay: delay.unwrap_or(12) },
+        ("getmouselocation", []) => Call::MouseLocation { shell },
+        ("getactivewindow", []) => Call::ActiveWindow,
+        ("windowactivate", [id]) => Call::Activate { window: window_id(id)?, sync },
+        ("getdisplaygeometry", []) if !shell => Call::DisplayGeometry,
+        _ => return None,
+    };
+    Some(call)
+}
+
+/// libxdo as the input backend
+pub struct Libxdo;
+
+impl InputBackend for Libxdo {
+    fn name(&self) -> &'static str {
+        "libxdo"
+    }
+
+    fn plan<'a>(&self, args: &'a [&'a str]) -> Result<Vec<Step<'a>>, McpError> {
+        // The connection is to $DISPLAY; other displays get their own xdotool
+        if display::current().is_some() {
+            return Ok(vec![Step::Xdotool(args)]);
+        }
+        let calls: Option<Vec<Step>> = split_commands(args).into_iter()
+            .map(|command| translate(command).map(|call| Step::Libxdo(call, command)))
+            .collect();
+        Ok(calls.unwrap_or_else(|| vec![Step::Xdotool(args)]))
+    }
+}
+
+fn check(function: &str, status: c_int) -> Result<(), McpError> {
+    if status == 0 {
+        Ok(())
+    } else {
+        Err(McpError::internal_error(
+            format!("libxdo error: {} failed ({})", function, status),
+            None
+        ))
+    }
+}
+
+impl Xdo {
+    /// Do one call, returning what xdotool would have printed
+    fn call(&self, call: &Call) -> Result<String, McpError> {
+        let api = &self.api;
+        let xdo = self.xdo;
+        unsafe {
+            match call {
+                Call::Move { x, y } => check("xdo_move_mouse", (api.move_mouse)(xdo, *x, *y, 0))?,
+                Call::MoveRelative { x, y } => check("xdo_move_mouse_relative", (api.move_mouse_relative)(xdo, *x, *y))?,
+                Call::Click { window, button, repeat, delay } => check(
+                    "xdo_click_window_multiple",
+                    (api.click_window_multiple)(xdo, *window, *button, *repeat, delay * 1000)
+                )?,
+                Call::Button { window, button, down: true } => check("xdo_mouse_down", (api.mouse_down)(xdo, *window, *button))?,
+                Call::Button { window, button, down: false } => check("xdo_mouse_up", (api.mouse_up)(xdo, *window, *button))?,
+                Call::Keys { window, action, keys, delay } => {
+                    let (name, send) = match action {
+                        KeyAction::Press => ("xdo_send_keysequence_window", api.send_keysequence_window),
+                        KeyAction::Down => ("xdo_send_keysequence_window_down", api.send_keysequence_window_down),
+                        KeyAction::Up => ("xdo_send_keysequence_window_up", api.send_keysequence_window_up),
+                    };
+                    for key in keys {
+                        check(name, send(xdo, *window, key.as_ptr(), delay * 1000))?;
+                    }
+                }
+                Call::Type { window, texts, delay } => {
+                    for text in texts {
+                        check("xdo_enter_text_window", (api.enter_text_window)(xdo, *window, text.as_ptr(), delay * 1000))?;
+                    }
+                }
+                Call::MouseLocation { shell } => {
+                    let (mut x, mut y, mut screen, mut window) = (0, 0, 0, 0);
+                    check("xdo_get_mouse_location2", (api.get_mouse_location2)(xdo, &mut x, &mut y, &mut screen, &mut window))?;
+                    return Ok(if *shell {
+                        format!("X={}\nY={}\nSCREEN={}\nWINDOW={}\n", x, y, screen, window)
+                    } else {
+                        format!("x:{} y:{} screen:{} window:{}\n", x, y, screen, window)
+                    });
+                }
+                Call::ActiveWindow => {
+                    let mut window = 0;
+                    check("xdo_get_active_window", (api.get_active_window)(xdo, &mut window))?;
+                    return Ok(format!("{}\n", window));
+                }
+                Call::Activate { window, sync } => {
+                    check("xdo_activate_window", (api.activate_window)(xdo, *window))?;
+                    if *sync {
+                        check("xdo_wait_for_window_active", (api.wait_for_window_active)(xdo, *window, 1))?;
+                    }
+                }
+                Call::DisplayGeometry => {
+                    let (mut width, mut height) = (0, 0);
+                    check("xdo_get_viewport_dimensions", (api.get_viewport_dimensions)(xdo, &mut width, &mut height, 0))?;
+                    return Ok(format!("{} {}\n", width, height));
+                }
+            }
+        }
+        Ok(String::new())
+    }
+}
+
+/// Do one call, returning what xdotool would have printed
+pub fn call(call: &Call) -> Result<String, McpError> {
+    let Some(xdo) = XDO.get() else {
+        return Err(McpError::internal_error("libxdo is not loaded", None));
+    };
+    xdo.lock().unwrap().call(call)
+}
@@ -0,0 +1,134 @@
+//! Size and duration limits on what a single request can ask for, so a
+//! malformed call can't tie up the desktop typing a 2MB string or looping a
+//! macro for hours.
+
+use crate::batch::Step;
+use crate::XdotoolServer;
+use rmcp::ErrorData as McpError;
+use serde::Deserialize;
+use std::time::Duration;
+
+/// The `[limits]` config table
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Limits {
+    /// Characters a single type_text call, type step or script type command may type
+    pub max_text_length: usize,
+    /// Steps in a batch or macro, counting steps nested in repeat blocks
+    pub max_batch_steps: usize,
+    /// How long a macro replay may run, all repeats included
+    pub max_macro_seconds: u64,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_text_length: 10_000,
+            max_batch_steps: 500,
+            max_macro_seconds: 600,
+        }
+    }
+}
+
+/// Steps including everything nested in repeat blocks
+fn count_steps(steps: &[Step]) -> usize {
+    steps.iter().map(|step| match step {
+        Step::Repeat { steps, .. } => 1 + count_steps(steps),
+        _ => 1,
+    }).sum()
+}
+
+/// The least time a run can take: its waits plus the pauses between steps, with repeats
+/// multiplied out. None if that's too long to represent, which is longer than any limit.
+pub fn min_duration(steps: &[Step], delay: Duration) -> Option<Duration> {
+    let pauses = delay.checked_mul(u32::try_from(steps.len().saturating_sub(1)).ok()?)?;
+    steps.iter().try_fold(pauses, |total, step| total.checked_add(match step {
+        Step::Wait { ms } => Duration::from_millis(*ms),
+        Step::Repeat { steps, count: Some(count), .. } => min_duration(steps, delay)?.checked_mul(*count)?,
+        _ => Duration::ZERO,
+    }))
+}
+
+impl XdotoolServer {
+    pub(crate) fn check_text_length(&self, text: &str) -> Result<(), McpError> {
+        let length = text.chars().count();
+        let max = self.config.limits.max_text_length;
+        if length > max {
+            return Err(McpError::internal_error(
+                format!("Text is {} characters, more than max_text_length ({})", length, max),
+                None
+            ));
+        }
+        Ok(())
+    }
+
+    /// Check a batch's size and the text of its type steps
+    pub(crate) fn check_batch_limits(&self, steps: &[Step]) -> Result<(), McpError> {
+        let count = count_steps(steps);
+        let max = self.config.limits.max_batch_steps;
+        if count > max {
+            return Err(McpError::internal_error(
+                format!("Batch has {} steps (including nested), more than max_batch_steps ({})", count, max),
+                None
+            ));
+        }
+        for (i, step) in steps.iter().enumerate() {
+            let checked = match step {
+                Step::Type { text, .. } => self.check_text_length(text),
+                Step::Repeat { steps, .. } => self.check_batch_limits(steps),
+                _ => Ok(()),
+            };
+            checked.map_err(|e| McpError::internal_error(format!("Step {}: {}", i + 1, e.message), None))?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn max_macro_duration(&self) -> Duration {
+        Duration::from_secs(self.config.limits.max_macro_seconds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn steps(json: &str) -> Vec<Step> {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn min_duration_adds_waits_pauses_and_repeats() {
+        let batch = steps(r#"[
+            {"action": "wait", "ms": 100},
+            {"action": "key", "key": "a"},
+            {"action": "repeat", "count": 3, "steps": [{"action": "wait", "ms": 10}, {"action": "key", "key": "b"}]}
+        ]"#);
+        // 2 pauses of 5ms, 100ms, and 3 x (10ms + one 5ms pause)
+        assert_eq!(min_duration(&batch, Duration::from_millis(5)), Some(Duration::from_millis(155)));
+    }
+
+    #[test]
+    fn min_duration_overflows_to_none() {
+        let batch = steps(r#"[{"action": "repeat", "count": 4294967295, "steps": [{"action": "wait", "ms": 18446744073709551615}]}]"#);
+        assert_eq!(min_duration(&batch, Duration::ZERO), None);
+        let long = steps(r#"[{"action": "key", "key": "a"}, {"action": "key", "key": "b"}]"#);
+        assert_eq!(min_duration(&long, Duration::MAX), Some(Duration::MAX));
+        assert_eq!(min_duration(&[long.clone(), long].concat(), Duration::MAX), None);
+    }
+
+    #[test]
+    fn count_steps_includes_nested() {
+        let batch = steps(r#"[{"action": "key", "key": "a"}, {"action": "repeat", "count": 2, "steps": [{"action": "key", "key": "b"}, {"action": "wait", "ms": 1}]}]"#);
+        assert_eq!(count_steps(&batch), 4);
+    }
+
+    #[test]
+    fn batch_limits_apply_to_nested_text() {
+        let mut config = crate::config::Config::default();
+        config.limits.max_text_length = 3;
+        let server = XdotoolServer::with_config(config);
+        assert!(server.check_batch_limits(&steps(r#"[{"action": "type", "text": "abc"}]"#)).is_ok());
+        let nested = steps(r#"[{"action": "repeat", "count": 1, "steps": [{"action": "type", "text": "abcd"}]}]"#);
+        assert!(server.check_batch_limits(&nested).is_err());
+    }
+}
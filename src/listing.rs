@@ -0,0 +1,113 @@
+//! Paging and filters for window listings.
+//!
+//! A desktop with hundreds of windows (browser profiles, terminals) would
+//! otherwise make search_window and window_thumbnails return unbounded
+//! results. Both list windows in ID order, at most `limit` per call, with a
+//! `next_cursor` to pass back for the next page. The cursor is the last ID
+//! returned, so windows opening or closing between calls don't shift the
+//! pages. `desktop` and `class_pattern` are checked on the server, window by
+//! window, only until the page is full.
+
+use crate::{allowlist, XdotoolServer};
+use regex_automata::meta::Regex;
+use rmcp::ErrorData as McpError;
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+/// Upper bound for `limit`
+pub const MAX_LIMIT: usize = 500;
+
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct PageParams {
+    #[schemars(description = "next_cursor from the previous call, to get the windows after it")]
+    pub cursor: Option<String>,
+    #[schemars(description = "Most windows to return, up to 500. Default: 50 for search_window, 20 for window_thumbnails")]
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct WindowFilterParams {
+    #[schemars(description = "Only windows on this virtual desktop (numbered from 0)")]
+    pub desktop: Option<u32>,
+    #[schemars(description = "Only windows whose WM_CLASS instance or class name matches this regex, e.g. '^(firefox|chromium)$'")]
+    pub class_pattern: Option<String>,
+}
+
+/// Checks from `WindowFilterParams`, ready to run
+struct WindowFilter {
+    desktop: Option<u32>,
+    class: Option<Regex>,
+}
+
+impl WindowFilter {
+    fn new(params: &WindowFilterParams) -> Result<Self, McpError> {
+        let class = params.class_pattern.as_deref()
+            .map(|pattern| Regex::new(pattern).map_err(|e| McpError::internal_error(
+                format!("Invalid class_pattern '{}': {}", pattern, e),
+                None
+            )))
+            .transpose()?;
+        Ok(WindowFilter { desktop: params.desktop, class })
+    }
+
+    fn matches(&self, window_id: &str) -> Result<bool, McpError> {
+        if let Some(desktop) = self.desktop {
+            // Windows on every desktop (sticky) report -1 and count as on this one
+            let on = XdotoolServer::run_xdotool(&["get_desktop_for_window", window_id]).ok()
+                .and_then(|on| on.trim().parse::<i64>().ok());
+            if !matches!(on, Some(on) if on == desktop as i64 || on == -1) {
+                return Ok(false);
+            }
+        }
+        if let Some(class) = &self.class {
+            let classes = allowlist::window_classes(window_id)?;
+            if !classes.iter().any(|name| class.is_match(name)) {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// One page of window IDs
+pub struct Page {
+    pub window_ids: Vec<String>,
+    /// Where the next page starts, if there are more windows
+    pub next_cursor: Option<String>,
+}
+
+/// The windows in `window_ids` after the cursor that pass the filter, in ID order
+pub fn page(
+    window_ids: Vec<String>,
+    page: &PageParams,
+    filter: &WindowFilterParams,
+    default_limit: usize,
+) -> Result<Page, McpError> {
+    let filter = WindowFilter::new(filter)?;
+    let limit = page.limit.unwrap_or(default_limit).clamp(1, MAX_LIMIT);
+    let after = match &page.cursor {
+        Some(cursor) => cursor.parse::<u64>().map_err(|_| McpError::internal_error(
+            format!("Invalid cursor '{}'; pass next_cursor from the previous call", cursor),
+            None
+        ))?,
+        None => 0,
+    };
+    let mut ids: Vec<u64> = window_ids.iter().filter_map(|id| id.parse().ok()).filter(|id| *id > after).collect();
+    ids.sort_unstable();
+    ids.dedup();
+
+    let mut window_ids = vec![];
+    let mut next_cursor = None;
+    for id in ids {
+        let id = id.to_string();
+        if !filter.matches(&id)? {
+            continue;
+        }
+        if window_ids.len() == limit {
+            next_cursor = window_ids.last().cloned();
+            break;
+        }
+        window_ids.push(id);
+    }
+    Ok(Page { window_ids, next_cursor })
+}
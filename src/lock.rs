@@ -0,0 +1,79 @@
+//! Detecting a locked screen, so input isn't typed blindly into a lock screen.
+//!
+//! Checked in order: logind's `LockedHint` for the session (`loginctl`), then
+//! the freedesktop and GNOME screensaver D-Bus interfaces (`dbus-send`). The
+//! first source that answers decides; if none does, the screen is assumed to
+//! be unlocked.
+
+use crate::process;
+use crate::XdotoolServer;
+use rmcp::ErrorData as McpError;
+use serde::Deserialize;
+use std::process::Command;
+
+/// The `[screen_lock]` config table
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScreenLock {
+    /// Refuse input tools while the screen is locked
+    pub refuse_input: bool,
+}
+
+impl Default for ScreenLock {
+    fn default() -> Self {
+        Self { refuse_input: true }
+    }
+}
+
+/// `LockedHint` of the current logind session
+fn logind_locked() -> Option<bool> {
+    let session = std::env::var("XDG_SESSION_ID").unwrap_or_else(|_| "auto".to_string());
+    let output = process::output(Command::new("loginctl")
+        .args(["show-session", &session, "--property=LockedHint", "--value"]))
+        .ok()?;
+    match String::from_utf8_lossy(&output.stdout).trim() {
+        "yes" => Some(true),
+        "no" => Some(false),
+        _ => None,
+    }
+}
+
+/// `GetActive` of a screensaver D-Bus service, which is true while it's showing or locked
+fn screensaver_active(service: &str, path: &str) -> Option<bool> {
+    let output = process::output(Command::new("dbus-send")
+        .args(["--session", "--print-reply", "--reply-timeout=500", &format!("--dest={}", service), path])
+        .arg(format!("{}.GetActive", service)))
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    // Reply looks like: method return ... \n   boolean true
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    if stdout.contains("boolean true") {
+        Some(true)
+    } else if stdout.contains("boolean false") {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+/// Whether the screen is locked, if any source can tell
+pub fn screen_locked() -> Option<bool> {
+    logind_locked()
+        .or_else(|| screensaver_active("org.freedesktop.ScreenSaver", "/org/freedesktop/ScreenSaver"))
+        .or_else(|| screensaver_active("org.gnome.ScreenSaver", "/org/gnome/ScreenSaver"))
+}
+
+impl XdotoolServer {
+    /// Refuse input while the screen is locked, if configured to
+    pub(crate) fn check_unlocked(&self) -> Result<(), McpError> {
+        if self.config.screen_lock.refuse_input && screen_locked() == Some(true) {
+            return Err(McpError::internal_error(
+                "The screen is locked, so input was refused. Wait for the user to unlock it.",
+                None
+            ));
+        }
+        Ok(())
+    }
+}
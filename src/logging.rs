@@ -0,0 +1,190 @@
+//! Server logs streamed to clients as MCP log notifications.
+//!
+//! A client that sends `logging/setLevel` receives this server's log events at
+//! that level and above as `notifications/message`, alongside the usual
+//! stderr output: tool calls starting and finishing, what xdotool wrote to
+//! stderr, policy denials, sessions coming and going. Only the server's own
+//! events are forwarded; rmcp's debug logs echo whole requests, typed text
+//! included.
+//!
+//! Events during a tool call carry the calling client's name (from the
+//! `call` span opened around each call) and only go to that client. Events
+//! that belong to no call go to clients with full access, never to scoped
+//! ones, so a scoped client doesn't learn about the others.
+
+use crate::XdotoolServer;
+use rmcp::model::{LoggingLevel, LoggingMessageNotificationParam};
+use rmcp::service::{Peer, RoleServer};
+use serde_json::{Map, Value};
+use std::sync::Mutex;
+use tokio::sync::mpsc;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+/// Name of the field attributing an event or span to a client
+const CLIENT_FIELD: &str = "client";
+
+/// A session that asked for log notifications
+struct Sink {
+    session: String,
+    client: String,
+    /// Whether the session authenticated as a scoped client
+    scoped: bool,
+    level: LoggingLevel,
+    /// Messages for the task forwarding them to the client, in order
+    sender: mpsc::UnboundedSender<LoggingMessageNotificationParam>,
+}
+
+impl Sink {
+    fn wants(&self, level: LoggingLevel, client: Option<&str>) -> bool {
+        if rank(level) < rank(self.level) {
+            return false;
+        }
+        match client {
+            Some(client) => client == self.client,
+            None => !self.scoped,
+        }
+    }
+}
+
+/// Every session's sink. Global because the tracing subscriber is.
+static SINKS: Mutex<Vec<Sink>> = Mutex::new(Vec::new());
+
+/// Order of the MCP levels, least severe first
+fn rank(level: LoggingLevel) -> u8 {
+    match level {
+        LoggingLevel::Debug => 0,
+        LoggingLevel::Info => 1,
+        LoggingLevel::Notice => 2,
+        LoggingLevel::Warning => 3,
+        LoggingLevel::Error => 4,
+        LoggingLevel::Critical => 5,
+        LoggingLevel::Alert => 6,
+        LoggingLevel::Emergency => 7,
+    }
+}
+
+fn mcp_level(level: &Level) -> LoggingLevel {
+    match *level {
+        Level::ERROR => LoggingLevel::Error,
+        Level::WARN => LoggingLevel::Warning,
+        Level::INFO => LoggingLevel::Info,
+        _ => LoggingLevel::Debug,
+    }
+}
+
+/// Event and span fields as JSON, the message under "message"
+#[derive(Default)]
+struct Fields(Map<String, Value>);
+
+impl Visit for Fields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0.insert(field.name().to_string(), Value::from(format!("{:?}", value)));
+    }
+}
+
+/// A span's fields, kept in its extensions and added to the events inside it
+struct SpanFields(Map<String, Value>);
+
+/// Layer forwarding this crate's events to the sessions that asked for them
+pub struct McpLayer;
+
+impl<S> Layer<S> for McpLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let mut fields = Fields::default();
+        attrs.record(&mut fields);
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(SpanFields(fields.0));
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        if !metadata.target().starts_with(env!("CARGO_CRATE_NAME")) {
+            return;
+        }
+        let sinks = SINKS.lock().unwrap();
+        if sinks.is_empty() {
+            return;
+        }
+        let level = mcp_level(metadata.level());
+        let mut fields = Fields::default();
+        for span in ctx.event_scope(event).into_iter().flat_map(|scope| scope.from_root()) {
+            if let Some(span_fields) = span.extensions().get::<SpanFields>() {
+                fields.0.extend(span_fields.0.clone());
+            }
+        }
+        event.record(&mut fields);
+        let client = match fields.0.get(CLIENT_FIELD) {
+            Some(Value::String(client)) => Some(client.clone()),
+            _ => None,
+        };
+        let data = Value::Object(fields.0);
+        for sink in sinks.iter().filter(|sink| sink.wants(level, client.as_deref())) {
+            let _ = sink.sender.send(LoggingMessageNotificationParam {
+                level,
+                logger: Some(metadata.target().to_string()),
+                data: data.clone(),
+            });
+        }
+    }
+}
+
+/// Send a session's messages until it goes away
+async fn forward(
+    session: String,
+    peer: Peer<RoleServer>,
+    mut receiver: mpsc::UnboundedReceiver<LoggingMessageNotificationParam>,
+) {
+    while let Some(message) = receiver.recv().await {
+        if let Err(e) = peer.notify_logging_message(message).await {
+            // Dropped before logging, or the failure would be queued for this same sink
+            SINKS.lock().unwrap().retain(|sink| sink.session != session);
+            tracing::debug!("Stopped log notifications for {}: {}", session, e);
+            return;
+        }
+    }
+}
+
+impl XdotoolServer {
+    /// Send this session log notifications at `level` and above
+    pub(crate) fn set_log_level(&self, level: LoggingLevel, peer: Peer<RoleServer>) {
+        let session = self.session_key();
+        let mut sinks = SINKS.lock().unwrap();
+        if let Some(sink) = sinks.iter_mut().find(|sink| sink.session == session) {
+            sink.level = level;
+            return;
+        }
+        let (sender, receiver) = mpsc::unbounded_channel();
+        sinks.push(Sink {
+            session: session.clone(),
+            client: self.client_name(),
+            scoped: self.client.is_some(),
+            level,
+            sender,
+        });
+        tokio::spawn(forward(session, peer, receiver));
+    }
+}
@@ -0,0 +1,701 @@
+//! Stored macros: named step sequences kept as JSON files under
+//! `$XDG_DATA_HOME/rmcp-xdotool/macros` (default `~/.local/share/rmcp-xdotool/macros`).
+//!
+//! Steps are kept as raw JSON so any string or number field can be a
+//! `{{variable}}` placeholder, filled in from the replay's params map.
+
+use crate::batch::{dry_run_report, plan_steps, RunOptions, Step};
+use crate::control::ActionRegistry;
+use crate::limits::min_duration;
+use crate::output;
+use crate::XdotoolServer;
+use rmcp::{
+    handler::server::wrapper::Parameters,
+    model::*,
+    service::{RequestContext, RoleServer},
+    ErrorData as McpError,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+pub struct Macro {
+    pub name: String,
+    /// Steps in execute_batch format, possibly containing placeholders
+    pub steps: Vec<Value>,
+    /// Pause between steps as recorded, in milliseconds
+    #[serde(default = "default_delay_ms")]
+    pub delay_ms: u64,
+    /// Title (substring) of the window the macro was made for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub window: Option<String>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ReplayMacroParams {
+    #[schemars(description = "Macro name")]
+    pub name: String,
+    #[schemars(description = "Playback speed: 2.0 is twice as fast, 0.5 half speed. Scales waits and step delays. Default: 1.0")]
+    #[serde(default = "default_speed_factor")]
+    pub speed_factor: f64,
+    #[schemars(description = "How many times to play the macro. Default: 1")]
+    #[serde(default = "default_repeat")]
+    pub repeat: u32,
+    #[schemars(description = "Abort if the active window stops matching the macro's window (before every step). Default: false")]
+    #[serde(default)]
+    pub abort_on_focus_mismatch: bool,
+    #[schemars(description = "Values for the macro's {{variable}} placeholders")]
+    #[serde(default)]
+    pub params: Map<String, Value>,
+    #[schemars(description = "ID to register the replay under for cancel_action. Default: generated")]
+    pub id: Option<String>,
+    #[schemars(description = "List the xdotool commands the macro would run, without running them. Default: the server's dry_run setting")]
+    pub dry_run: Option<bool>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct SaveMacroParams {
+    #[schemars(description = "Macro name (letters, digits, '-', '_', '.')")]
+    pub name: String,
+    #[schemars(description = "Steps, in the same format as execute_batch. Any string or number field may be a {{variable}} placeholder, e.g. {\"action\": \"type\", \"text\": \"{{username}}\"} or \"x\": \"{{x}}\"")]
+    pub steps: Vec<Value>,
+    #[schemars(description = "Pause between steps in milliseconds. Default: 50")]
+    #[serde(default = "default_delay_ms")]
+    pub delay_ms: u64,
+    #[schemars(description = "Title (substring) of the window this macro is meant for, used for focus checks on replay")]
+    pub window: Option<String>,
+    #[schemars(description = "Replace an existing macro with the same name. Default: false")]
+    #[serde(default)]
+    pub overwrite: bool,
+}
+
+fn default_delay_ms() -> u64 { 50 }
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct MacroNameParams {
+    #[schemars(description = "Macro name")]
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct RenameMacroParams {
+    #[schemars(description = "Current macro name")]
+    pub name: String,
+    #[schemars(description = "New macro name")]
+    pub new_name: String,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ImportMacroParams {
+    #[schemars(description = "Macro JSON document, as produced by export_macro")]
+    pub json: String,
+    #[schemars(description = "Store under this name instead of the one in the document")]
+    pub name: Option<String>,
+    #[schemars(description = "Replace an existing macro with the same name. Default: false")]
+    #[serde(default)]
+    pub overwrite: bool,
+}
+
+/// Identifies exported documents
+const EXPORT_FORMAT: &str = "rmcp-xdotool-macro";
+const EXPORT_VERSION: u32 = 1;
+
+/// Portable form of a macro, for sharing and version control
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+struct MacroExport {
+    format: String,
+    version: u32,
+    #[serde(rename = "macro")]
+    stored: Macro,
+}
+
+/// Imports accept an export envelope or a bare macro file
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum ImportDocument {
+    Export(MacroExport),
+    Bare(Macro),
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct MacroReplay {
+    pub name: String,
+    /// Steps per run
+    pub steps: usize,
+    /// How many times it ran, or would run
+    pub repeat: u32,
+    pub speed_factor: f64,
+    /// The commands one run would send, in dry runs. Steps inside repeat blocks are indented.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dry_run: Option<Vec<String>>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct MacroSaved {
+    pub name: String,
+    pub steps: usize,
+    /// The macro's file
+    pub path: String,
+    /// Placeholders replay_macro needs values for
+    pub params: Vec<String>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct MacroList {
+    /// Where macros are stored
+    pub directory: String,
+    pub macros: Vec<MacroInfo>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct MacroInfo {
+    pub name: String,
+    /// Absent if the file couldn't be read
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub steps: Option<usize>,
+    /// Window the macro was made for
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub window: Option<String>,
+    pub params: Vec<String>,
+    /// Why the file couldn't be read
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct MacroDeleted {
+    pub name: String,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct MacroRenamed {
+    pub name: String,
+    pub new_name: String,
+}
+
+fn default_speed_factor() -> f64 { 1.0 }
+
+fn default_repeat() -> u32 { 1 }
+
+/// Names of `{{variable}}` placeholders in a string
+fn placeholders(text: &str) -> Vec<&str> {
+    let mut names = vec![];
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        let Some(len) = rest[start + 2..].find("}}") else { break };
+        names.push(rest[start + 2..start + 2 + len].trim());
+        rest = &rest[start + 2 + len + 2..];
+    }
+    names
+}
+
+/// Replace placeholders in a JSON value. A string that is exactly one placeholder
+/// takes the parameter's JSON value (so numbers stay numbers); placeholders inside
+/// longer strings are replaced textually.
+fn substitute(value: &Value, params: &Map<String, Value>, missing: &mut BTreeSet<String>) -> Value {
+    match value {
+        Value::String(text) => {
+            // Whole only if the first `}}` closes the placeholder at the very end
+            let whole = text.trim().strip_prefix("{{")
+                .filter(|rest| rest.find("}}").is_some_and(|end| end + 2 == rest.len()))
+                .map(|rest| rest[..rest.len() - 2].trim());
+            if let Some(name) = whole {
+                return match params.get(name) {
+                    Some(param) => param.clone(),
+                    None => {
+                        missing.insert(name.to_string());
+                        value.clone()
+                    }
+                };
+            }
+            // One pass, so `{{ name }}` works too and values aren't searched for placeholders
+            let mut result = String::new();
+            let mut rest = text.as_str();
+            while let Some(start) = rest.find("{{") {
+                let Some(len) = rest[start + 2..].find("}}") else { break };
+                let end = start + 2 + len + 2;
+                let name = rest[start + 2..start + 2 + len].trim();
+                result.push_str(&rest[..start]);
+                match params.get(name) {
+                    Some(Value::String(param)) => result.push_str(param),
+                    Some(param) => result.push_str(&param.to_string()),
+                    None => {
+                        missing.insert(name.to_string());
+                        result.push_str(&rest[start..end]);
+                    }
+                }
+                rest = &rest[end..];
+            }
+            result.push_str(rest);
+            Value::String(result)
+        }
+        Value::Array(items) => Value::Array(items.iter().map(|item| substitute(item, params, missing)).collect()),
+        Value::Object(fields) => Value::Object(
+            fields.iter().map(|(key, field)| (key.clone(), substitute(field, params, missing))).collect()
+        ),
+        other => other.clone(),
+    }
+}
+
+impl Macro {
+    /// Placeholder names used anywhere in the steps
+    pub fn variables(&self) -> BTreeSet<String> {
+        fn collect(value: &Value, names: &mut BTreeSet<String>) {
+            match value {
+                Value::String(text) => names.extend(placeholders(text).into_iter().map(str::to_string)),
+                Value::Array(items) => items.iter().for_each(|item| collect(item, names)),
+                Value::Object(fields) => fields.values().for_each(|field| collect(field, names)),
+                _ => {}
+            }
+        }
+        let mut names = BTreeSet::new();
+        self.steps.iter().for_each(|step| collect(step, &mut names));
+        names
+    }
+
+    /// Fill in placeholders and parse the steps
+    pub fn resolve(&self, params: &Map<String, Value>) -> Result<Vec<Step>, McpError> {
+        let mut missing = BTreeSet::new();
+        let steps: Vec<Value> = self.steps.iter().map(|step| substitute(step, params, &mut missing)).collect();
+        if !missing.is_empty() {
+            return Err(McpError::internal_error(
+                format!("Macro '{}' needs params: {}", self.name, missing.into_iter().collect::<Vec<_>>().join(", ")),
+                None
+            ));
+        }
+        let steps: Vec<Step> = serde_json::from_value(Value::Array(steps)).map_err(|e| McpError::internal_error(
+            format!("Macro '{}' has invalid steps: {}", self.name, e),
+            None
+        ))?;
+        plan_steps(&steps)?;
+        Ok(steps)
+    }
+
+    /// Check the steps when they can be checked without params
+    fn validate(&self) -> Result<(), McpError> {
+        if self.variables().is_empty() {
+            self.resolve(&Map::new())?;
+        }
+        Ok(())
+    }
+}
+
+/// Directory macros are stored in
+pub fn macro_dir() -> PathBuf {
+    let data_home = std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .unwrap_or_else(|| {
+            let home = std::env::var_os("HOME").map(PathBuf::from).unwrap_or_default();
+            home.join(".local/share")
+        });
+    data_home.join("rmcp-xdotool").join("macros")
+}
+
+/// File a macro is stored in. Names are restricted so they can't escape the macro directory.
+pub fn macro_path(name: &str) -> Result<PathBuf, McpError> {
+    let valid = !name.is_empty()
+        && name.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_' || c == '.')
+        && !name.starts_with('.');
+    if !valid {
+        return Err(McpError::internal_error(
+            "Invalid macro name. Use letters, digits, '-', '_' and '.' (not leading)",
+            None
+        ));
+    }
+    Ok(macro_dir().join(format!("{}.json", name)))
+}
+
+pub fn load_macro(name: &str) -> Result<Macro, McpError> {
+    let path = macro_path(name)?;
+    let json = std::fs::read_to_string(&path).map_err(|e| McpError::internal_error(
+        format!("Failed to read macro '{}' from {}: {}", name, path.display(), e),
+        None
+    ))?;
+    serde_json::from_str(&json).map_err(|e| McpError::internal_error(
+        format!("Macro '{}' is not valid: {}", name, e),
+        None
+    ))
+}
+
+pub fn store_macro(stored: &Macro) -> Result<PathBuf, McpError> {
+    let path = macro_path(&stored.name)?;
+    std::fs::create_dir_all(macro_dir())
+        .map_err(|e| McpError::internal_error(format!("Failed to create macro directory: {}", e), None))?;
+    let json = serde_json::to_string_pretty(stored)
+        .map_err(|e| McpError::internal_error(format!("Failed to serialize macro: {}", e), None))?;
+    std::fs::write(&path, json)
+        .map_err(|e| McpError::internal_error(format!("Failed to write {}: {}", path.display(), e), None))?;
+    Ok(path)
+}
+
+#[rmcp::tool_router(router = macro_router, vis = "pub(crate)")]
+impl XdotoolServer {
+    #[rmcp::tool(description = "Play back a stored macro with its original timing scaled by speed_factor, optionally several times. Can abort when the focused window no longer matches the macro's window.", annotations(read_only_hint = false, destructive_hint = true), output_schema = output::schema::<MacroReplay>())]
+    pub async fn replay_macro(
+        &self,
+        Parameters(params): Parameters<ReplayMacroParams>,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        if !(params.speed_factor > 0.0 && params.speed_factor.is_finite()) {
+            return Err(McpError::internal_error("speed_factor must be a positive number", None));
+        }
+        let stored = load_macro(&params.name)?;
+        if params.abort_on_focus_mismatch && stored.window.is_none() {
+            return Err(McpError::internal_error(
+                format!("Macro '{}' has no window to check focus against", params.name),
+                None
+            ));
+        }
+
+        let steps: Vec<Step> = stored.resolve(&params.params)?
+            .iter()
+            .map(|step| step.scaled(params.speed_factor))
+            .collect();
+        self.check_batch_limits(&steps)?;
+        let max_duration = self.max_macro_duration();
+        let too_long = |at_least: String| McpError::internal_error(
+            format!(
+                "Macro '{}' would take at least {}, more than max_macro_seconds ({}s)",
+                params.name, at_least, max_duration.as_secs()
+            ),
+            None
+        );
+        // A tiny speed_factor stretches the delay past what a Duration holds
+        let delay = Duration::try_from_secs_f64(stored.delay_ms as f64 / 1000.0 / params.speed_factor)
+            .map_err(|_| too_long("forever between steps".to_string()))?;
+        let Some(min_duration) = min_duration(&steps, delay).and_then(|duration| duration.checked_mul(params.repeat)) else {
+            return Err(too_long("longer than can be counted".to_string()));
+        };
+        if min_duration > max_duration {
+            return Err(too_long(format!("{:.1}s", min_duration.as_secs_f64())));
+        }
+        let mut replay = MacroReplay {
+            name: params.name.clone(),
+            steps: steps.len(),
+            repeat: params.repeat,
+            speed_factor: params.speed_factor,
+            dry_run: None,
+        };
+        if params.dry_run.unwrap_or(self.config.dry_run) {
+            let lines = dry_run_report(&steps)?;
+            let text = format!("Dry run of macro '{}' ({} time(s)). Would run:\n{}", params.name, params.repeat, lines.join("\n"));
+            replay.dry_run = Some(lines);
+            return Ok(output::structured(text, &replay));
+        }
+
+        let guard = ActionRegistry::register(&self.actions, params.id, format!("macro '{}'", params.name), &context.ct)?;
+        let options = RunOptions {
+            delay,
+            expect_window: stored.window.clone().filter(|_| params.abort_on_focus_mismatch),
+            cancel: guard.token.clone(),
+        };
+
+        // Stop the replay like cancel_action would once it runs past the limit
+        let started = Instant::now();
+        let timer = {
+            let token = guard.token.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(max_duration).await;
+                token.cancel();
+            })
+        };
+        let mut result = Ok(());
+        for run in 1..=params.repeat {
+            result = self.run_cancellable(&guard, &steps, &options).await.map(|_| ()).map_err(|e| {
+                let mut message = format!("Macro '{}' run {}/{}: {}", params.name, run, params.repeat, e.message);
+                if started.elapsed() >= max_duration {
+                    message.push_str(&format!(" (stopped after max_macro_seconds, {}s)", max_duration.as_secs()));
+                }
+                McpError::internal_error(message, None)
+            });
+            if result.is_err() {
+                break;
+            }
+        }
+        timer.abort();
+        result?;
+
+        Ok(output::structured(
+            format!("Replayed macro '{}' ({} steps) {} time(s) at {}x speed",
+                params.name, steps.len(), params.repeat, params.speed_factor),
+            &replay
+        ))
+    }
+
+    #[rmcp::tool(description = "Save a named macro (a list of execute_batch steps) so it survives server restarts", annotations(read_only_hint = false, destructive_hint = true), output_schema = output::schema::<MacroSaved>())]
+    pub async fn save_macro(
+        &self,
+        Parameters(params): Parameters<SaveMacroParams>,
+    ) -> Result<CallToolResult, McpError> {
+        if !params.overwrite && macro_path(&params.name)?.exists() {
+            return Err(McpError::internal_error(
+                format!("Macro '{}' already exists. Pass overwrite=true to replace it", params.name),
+                None
+            ));
+        }
+
+        let stored = Macro {
+            name: params.name,
+            steps: params.steps,
+            delay_ms: params.delay_ms,
+            window: params.window,
+        };
+        stored.validate()?;
+        let path = store_macro(&stored)?;
+
+        let variables: Vec<String> = stored.variables().into_iter().collect();
+        let mut message = format!("Saved macro '{}' ({} steps) to {}", stored.name, stored.steps.len(), path.display());
+        if !variables.is_empty() {
+            message.push_str(&format!("\nParams: {}", variables.join(", ")));
+        }
+        Ok(output::structured(message, &MacroSaved {
+            name: stored.name,
+            steps: stored.steps.len(),
+            path: path.display().to_string(),
+            params: variables,
+        }))
+    }
+
+    #[rmcp::tool(description = "List stored macros with their step counts and target windows", annotations(read_only_hint = true), output_schema = output::schema::<MacroList>())]
+    pub async fn list_macros(&self) -> Result<CallToolResult, McpError> {
+        let dir = macro_dir();
+        let mut list = MacroList { directory: dir.display().to_string(), macros: vec![] };
+        let entries = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(output::structured("No macros saved", &list));
+            }
+            Err(e) => return Err(McpError::internal_error(
+                format!("Failed to read {}: {}", dir.display(), e),
+                None
+            )),
+        };
+
+        let mut names: Vec<String> = entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| entry.file_name().to_str()?.strip_suffix(".json").map(str::to_string))
+            .collect();
+        names.sort();
+
+        if names.is_empty() {
+            return Ok(output::structured("No macros saved", &list));
+        }
+
+        let mut lines = vec![];
+        for name in &names {
+            match load_macro(name) {
+                Ok(stored) => {
+                    let mut line = format!("  {} ({} steps", name, stored.steps.len());
+                    if let Some(window) = &stored.window {
+                        line.push_str(&format!(", window: {}", window));
+                    }
+                    let variables: Vec<String> = stored.variables().into_iter().collect();
+                    if !variables.is_empty() {
+                        line.push_str(&format!(", params: {}", variables.join(", ")));
+                    }
+                    line.push(')');
+                    lines.push(line);
+                    list.macros.push(MacroInfo {
+                        name: name.clone(),
+                        steps: Some(stored.steps.len()),
+                        window: stored.window,
+                        params: variables,
+                        error: None,
+                    });
+                }
+                Err(e) => {
+                    lines.push(format!("  {} (unreadable: {})", name, e.message));
+                    list.macros.push(MacroInfo {
+                        name: name.clone(),
+                        steps: None,
+                        window: None,
+                        params: vec![],
+                        error: Some(e.message.to_string()),
+                    });
+                }
+            }
+        }
+
+        Ok(output::structured(
+            format!("{} macro(s) in {}:\n{}", names.len(), dir.display(), lines.join("\n")),
+            &list
+        ))
+    }
+
+    #[rmcp::tool(description = "Delete a stored macro", annotations(read_only_hint = false, destructive_hint = true), output_schema = output::schema::<MacroDeleted>())]
+    pub async fn delete_macro(
+        &self,
+        Parameters(params): Parameters<MacroNameParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let path = macro_path(&params.name)?;
+        std::fs::remove_file(&path).map_err(|e| McpError::internal_error(
+            format!("Failed to delete macro '{}': {}", params.name, e),
+            None
+        ))?;
+        Ok(output::structured(
+            format!("Deleted macro '{}'", params.name),
+            &MacroDeleted { name: params.name }
+        ))
+    }
+
+    #[rmcp::tool(description = "Rename a stored macro", annotations(read_only_hint = false, destructive_hint = false), output_schema = output::schema::<MacroRenamed>())]
+    pub async fn rename_macro(
+        &self,
+        Parameters(params): Parameters<RenameMacroParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let mut stored = load_macro(&params.name)?;
+        if macro_path(&params.new_name)?.exists() {
+            return Err(McpError::internal_error(
+                format!("Macro '{}' already exists", params.new_name),
+                None
+            ));
+        }
+
+        stored.name = params.new_name.clone();
+        store_macro(&stored)?;
+        std::fs::remove_file(macro_path(&params.name)?).map_err(|e| McpError::internal_error(
+            format!("Saved '{}' but failed to remove '{}': {}", params.new_name, params.name, e),
+            None
+        ))?;
+
+        Ok(output::structured(
+            format!("Renamed macro '{}' to '{}'", params.name, params.new_name),
+            &MacroRenamed { name: params.name, new_name: params.new_name }
+        ))
+    }
+
+    #[rmcp::tool(description = "Export a stored macro as a portable JSON document for sharing or checking into version control", annotations(read_only_hint = true), output_schema = output::schema::<MacroExport>())]
+    pub async fn export_macro(
+        &self,
+        Parameters(params): Parameters<MacroNameParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let export = MacroExport {
+            format: EXPORT_FORMAT.to_string(),
+            version: EXPORT_VERSION,
+            stored: load_macro(&params.name)?,
+        };
+        let json = serde_json::to_string_pretty(&export)
+            .map_err(|e| McpError::internal_error(format!("Failed to serialize macro: {}", e), None))?;
+        Ok(output::structured(json, &export))
+    }
+
+    #[rmcp::tool(description = "Import a macro from a JSON document (from export_macro) and store it", annotations(read_only_hint = false, destructive_hint = true), output_schema = output::schema::<MacroSaved>())]
+    pub async fn import_macro(
+        &self,
+        Parameters(params): Parameters<ImportMacroParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let document: ImportDocument = serde_json::from_str(&params.json)
+            .map_err(|e| McpError::internal_error(format!("Not a macro document: {}", e), None))?;
+        let mut stored = match document {
+            ImportDocument::Export(export) => {
+                if export.format != EXPORT_FORMAT || export.version > EXPORT_VERSION {
+                    return Err(McpError::internal_error(
+                        format!("Unsupported document format '{}' version {}", export.format, export.version),
+                        None
+                    ));
+                }
+                export.stored
+            }
+            ImportDocument::Bare(stored) => stored,
+        };
+        if let Some(name) = params.name {
+            stored.name = name;
+        }
+
+        stored.validate()?;
+        if !params.overwrite && macro_path(&stored.name)?.exists() {
+            return Err(McpError::internal_error(
+                format!("Macro '{}' already exists. Pass overwrite=true to replace it", stored.name),
+                None
+            ));
+        }
+        let path = store_macro(&stored)?;
+
+        Ok(output::structured(
+            format!("Imported macro '{}' ({} steps) to {}", stored.name, stored.steps.len(), path.display()),
+            &MacroSaved {
+                params: stored.variables().into_iter().collect(),
+                name: stored.name,
+                steps: stored.steps.len(),
+                path: path.display().to_string(),
+            }
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn params(value: Value) -> Map<String, Value> {
+        value.as_object().cloned().unwrap()
+    }
+
+    fn filled(value: Value, params: &Map<String, Value>) -> (Value, BTreeSet<String>) {
+        let mut missing = BTreeSet::new();
+        (substitute(&value, params, &mut missing), missing)
+    }
+
+    #[test]
+    fn placeholders_are_found_and_trimmed() {
+        assert_eq!(placeholders("{{user}} at {{ host }}"), ["user", "host"]);
+        assert_eq!(placeholders("no placeholders"), Vec::<&str>::new());
+        assert_eq!(placeholders("{{unclosed"), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn whole_placeholders_keep_their_type() {
+        let params = params(json!({"x": 10, "name": "bob", "keys": ["a", "b"]}));
+        assert_eq!(filled(json!({"action": "move", "x": "{{x}}", "y": 5}), &params).0, json!({"action": "move", "x": 10, "y": 5}));
+        assert_eq!(filled(json!(" {{ name }} "), &params).0, json!("bob"));
+        assert_eq!(filled(json!(["{{keys}}"]), &params).0, json!([["a", "b"]]));
+    }
+
+    #[test]
+    fn placeholders_inside_text_are_replaced() {
+        let params = params(json!({"user": "bob", "n": 3, "a": "{{n}}"}));
+        assert_eq!(filled(json!("Hi {{user}}, {{n}} new"), &params).0, json!("Hi bob, 3 new"));
+        assert_eq!(filled(json!("Hi {{ user }}!"), &params).0, json!("Hi bob!"));
+        // Values aren't searched for placeholders themselves
+        assert_eq!(filled(json!("{{a}} and {{n}}"), &params).0, json!("{{n}} and 3"));
+    }
+
+    #[test]
+    fn whole_placeholders_end_at_the_first_closing_braces() {
+        let params = params(json!({"a": 1}));
+        assert_eq!(filled(json!("{{a}}x}}"), &params).0, json!("1x}}"));
+        assert_eq!(filled(json!("{{a}}}"), &params).0, json!("1}"));
+        assert_eq!(filled(json!("{{a}} {{a}}"), &params).0, json!("1 1"));
+        assert_eq!(filled(json!("{{a}}"), &params).0, json!(1));
+    }
+
+    #[test]
+    fn stored_macros_default_to_the_save_delay() {
+        let stored: Macro = serde_json::from_value(json!({"name": "m", "steps": []})).unwrap();
+        assert_eq!(stored.delay_ms, default_delay_ms());
+    }
+
+    #[test]
+    fn missing_params_are_reported() {
+        let params = params(json!({"user": "bob"}));
+        let (value, missing) = filled(json!([{"text": "{{user}} {{password}}"}, {"x": "{{x}}"}]), &params);
+        assert_eq!(value, json!([{"text": "bob {{password}}"}, {"x": "{{x}}"}]));
+        assert_eq!(missing.into_iter().collect::<Vec<_>>(), ["password", "x"]);
+
+        let stored = Macro {
+            name: "login".to_string(),
+            steps: vec![json!({"action": "type", "text": "{{user}}"}), json!({"action": "move", "x": "{{x}}", "y": 0})],
+            delay_ms: 0,
+            window: None,
+        };
+        assert_eq!(stored.variables().into_iter().collect::<Vec<_>>(), ["user", "x"]);
+        assert!(stored.resolve(&params).unwrap_err().message.contains("needs params: x"));
+        assert_eq!(stored.resolve(&self::params(json!({"user": "bob", "x": 4}))).unwrap().len(), 2);
+    }
+}
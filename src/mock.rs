@@ -0,0 +1,375 @@
+//! A simulated desktop, for testing without an X session.
+//!
+//! With `--backend mock` (or `input_backend = "mock"`) no xdotool command
+//! reaches a display. Queries are answered from a fake desktop: a 1920x1080
+//! screen, two virtual desktops and three windows. Commands that change
+//! something (pointer moves, clicks, keys, typed text, window moves and
+//! focus changes) are recorded, and the window ones update the fake desktop,
+//! so a later search or geometry query sees the result. `get_mock_log`
+//! returns the recorded commands, which is what integration tests and client
+//! developers check against.
+//!
+//! Only xdotool's commands are simulated: screenshots, OCR and the clipboard
+//! still run their programs.
+
+use crate::allowlist::split_commands;
+use crate::backend::{argv, parse, InputBackend, Step, WindowBackend};
+use crate::{backend, output, XdotoolServer};
+use regex_automata::{meta::Regex, util::syntax};
+use rmcp::{handler::server::wrapper::Parameters, model::*, ErrorData as McpError};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::{Mutex, MutexGuard, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// How many recorded commands are kept
+const LOG_CAPACITY: usize = 1000;
+
+const SCREEN: (i32, i32) = (1920, 1080);
+
+/// What getmouselocation reports over the desktop background
+const ROOT_WINDOW: u64 = 1;
+
+#[derive(Debug, Clone)]
+struct Window {
+    id: u64,
+    name: String,
+    instance: String,
+    class: String,
+    pid: u32,
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+    desktop: i64,
+    mapped: bool,
+}
+
+impl Window {
+    fn new(id: u64, name: &str, (instance, class): (&str, &str), pid: u32, (x, y, width, height): (i32, i32, i32, i32), desktop: i64) -> Self {
+        Window {
+            id,
+            name: name.to_string(),
+            instance: instance.to_string(),
+            class: class.to_string(),
+            pid,
+            x,
+            y,
+            width,
+            height,
+            desktop,
+            mapped: true,
+        }
+    }
+
+    fn contains(&self, (x, y): (i32, i32)) -> bool {
+        x >= self.x && x < self.x + self.width && y >= self.y && y < self.y + self.height
+    }
+}
+
+/// A recorded command
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct MockAction {
+    /// Unix time in seconds
+    pub timestamp: f64,
+    /// The xdotool command, as argv without the program
+    pub command: Vec<String>,
+}
+
+struct Desktop {
+    /// Bottom to top
+    windows: Vec<Window>,
+    active: u64,
+    pointer: (i32, i32),
+    desktop: i64,
+    desktops: i64,
+    log: VecDeque<MockAction>,
+}
+
+impl Default for Desktop {
+    fn default() -> Self {
+        Desktop {
+            windows: vec![
+                Window::new(4194311, "Terminal", ("xterm", "XTerm"), 1001, (0, 0, 800, 600), 0),
+                Window::new(31457283, "Notes.txt - Text Editor", ("gedit", "Gedit"), 1003, (400, 200, 1000, 700), 1),
+                Window::new(56623107, "Mozilla Firefox", ("Navigator", "firefox"), 1002, (100, 50, 1280, 900), 0),
+            ],
+            active: 56623107,
+            pointer: (SCREEN.0 / 2, SCREEN.1 / 2),
+            desktop: 0,
+            desktops: 2,
+            log: VecDeque::new(),
+        }
+    }
+}
+
+fn desktop() -> MutexGuard<'static, Desktop> {
+    static DESKTOP: OnceLock<Mutex<Desktop>> = OnceLock::new();
+    DESKTOP.get_or_init(Default::default).lock().unwrap()
+}
+
+fn missing(command: &[&str]) -> McpError {
+    McpError::internal_error(format!("{}: missing arguments", command[0]), None)
+}
+
+fn number<T: std::str::FromStr>(command: &[&str], value: Option<&&str>) -> Result<T, McpError> {
+    let value = value.ok_or_else(|| missing(command))?;
+    value.parse().map_err(|_| McpError::internal_error(
+        format!("{}: '{}' is not a number", command[0], value),
+        None
+    ))
+}
+
+/// Options of window and search commands that take a value
+const WITH_VALUE: &[&str] = &["--limit", "--maxdepth", "--desktop", "--screen", "--pid", "--window", "--delay", "--repeat"];
+
+impl Desktop {
+    fn find(&mut self, id: Option<&&str>, command: &[&str]) -> Result<&mut Window, McpError> {
+        let id = id.ok_or_else(|| missing(command))?;
+        self.windows.iter_mut().find(|window| window.id.to_string() == *id).ok_or_else(|| McpError::internal_error(
+            format!("No window {} on the mock desktop", id),
+            None
+        ))
+    }
+
+    /// Put a window on top of the others
+    fn raise(&mut self, id: u64) {
+        if let Some(i) = self.windows.iter().position(|window| window.id == id) {
+            let window = self.windows.remove(i);
+            self.windows.push(window);
+        }
+    }
+
+    /// The topmost mapped window under the pointer on the current desktop
+    fn under_pointer(&self) -> u64 {
+        self.windows.iter().rev()
+            .find(|window| window.mapped && window.desktop == self.desktop && window.contains(self.pointer))
+            .map(|window| window.id)
+            .unwrap_or(ROOT_WINDOW)
+    }
+
+    fn search(&self, command: &[&str]) -> Result<String, McpError> {
+        let (options, args) = parse(command, WITH_VALUE);
+        let option = |name: &str| options.iter().find(|(option, _)| *option == name).map(|(_, value)| *value);
+        let pattern = args.first().copied().unwrap_or_default();
+        let regex = Regex::builder()
+            .syntax(syntax::Config::new().case_insensitive(true))
+            .build(pattern)
+            .map_err(|e| McpError::internal_error(format!("Invalid search pattern '{}': {}", pattern, e), None))?;
+        let (name, class) = match (option("--name").is_some(), option("--class").is_some() || option("--classname").is_some()) {
+            (false, false) => (true, true),
+            flags => flags,
+        };
+        let limit = option("--limit").and_then(|limit| limit.parse().ok()).filter(|limit| *limit > 0).unwrap_or(usize::MAX);
+        Ok(self.windows.iter()
+            .filter(|window| option("--onlyvisible").is_none() || (window.mapped && window.desktop == self.desktop))
+            .filter(|window| option("--desktop").is_none_or(|desktop| desktop == window.desktop.to_string()))
+            .filter(|window| option("--pid").is_none_or(|pid| pid == window.pid.to_string()))
+            .filter(|window| {
+                (name && regex.is_match(&window.name))
+                    || (class && [&window.instance, &window.class].iter().any(|c| regex.is_match(c)))
+            })
+            .take(limit)
+            .map(|window| format!("{}\n", window.id))
+            .collect())
+    }
+
+    /// Run one command, xdotool-style output
+    fn run(&mut self, command: &[&str]) -> Result<String, McpError> {
+        let (options, args) = parse(command, WITH_VALUE);
+        let shell = options.iter().any(|(option, _)| *option == "--shell");
+        let output = match command[0] {
+            "search" => return self.search(command),
+            "getactivewindow" | "getwindowfocus" => format!("{}\n", self.active),
+            "getmouselocation" => {
+                let ((x, y), window) = (self.pointer, self.under_pointer());
+                if shell {
+                    format!("X={}\nY={}\nSCREEN=0\nWINDOW={}\n", x, y, window)
+                } else {
+                    format!("x:{} y:{} screen:0 window:{}\n", x, y, window)
+                }
+            }
+            "getdisplaygeometry" => format!("{} {}\n", SCREEN.0, SCREEN.1),
+            "getwindowname" => format!("{}\n", self.find(args.first(), command)?.name),
+            "getwindowclassname" => format!("{}\n", self.find(args.first(), command)?.class),
+            "getwindowpid" => format!("{}\n", self.find(args.first(), command)?.pid),
+            "getwindowgeometry" => {
+                let window = self.find(args.first(), command)?;
+                if shell {
+                    format!("WINDOW={}\nX={}\nY={}\nWIDTH={}\nHEIGHT={}\nSCREEN=0\n", window.id, window.x, window.y, window.width, window.height)
+                } else {
+                    format!("Window {}\n  Position: {},{} (screen: 0)\n  Geometry: {}x{}\n", window.id, window.x, window.y, window.width, window.height)
+                }
+            }
+            "get_desktop" => format!("{}\n", self.desktop),
+            "get_num_desktops" => format!("{}\n", self.desktops),
+            "get_desktop_for_window" => format!("{}\n", self.find(args.first(), command)?.desktop),
+            "set_desktop" => {
+                self.desktop = number(command, args.first())?;
+                String::new()
+            }
+            "set_num_desktops" => {
+                self.desktops = number(command, args.first())?;
+                String::new()
+            }
+            "set_desktop_for_window" => {
+                let desktop = number(command, args.get(1))?;
+                self.find(args.first(), command)?.desktop = desktop;
+                String::new()
+            }
+            "windowactivate" | "windowfocus" | "windowraise" | "windowmap" => {
+                let window = self.find(args.first(), command)?;
+                window.mapped = true;
+                let (id, desktop) = (window.id, window.desktop);
+                if command[0] != "windowmap" {
+                    self.raise(id);
+                }
+                if command[0] == "windowactivate" || command[0] == "windowfocus" {
+                    self.active = id;
+                    self.desktop = desktop;
+                }
+                String::new()
+            }
+            "windowminimize" | "windowunmap" => {
+                self.find(args.first(), command)?.mapped = false;
+                String::new()
+            }
+            "windowmove" => {
+                let (x, y) = (number(command, args.get(1))?, number(command, args.get(2))?);
+                let window = self.find(args.first(), command)?;
+                (window.x, window.y) = (x, y);
+                String::new()
+            }
+            "windowsize" => {
+                let (width, height) = (number(command, args.get(1))?, number(command, args.get(2))?);
+                let window = self.find(args.first(), command)?;
+                (window.width, window.height) = (width, height);
+                String::new()
+            }
+            "windowclose" | "windowkill" | "windowquit" => {
+                let id = self.find(args.first(), command)?.id;
+                self.windows.retain(|window| window.id != id);
+                if self.active == id {
+                    self.active = self.windows.last().map(|window| window.id).unwrap_or(ROOT_WINDOW);
+                }
+                String::new()
+            }
+            "mousemove" => {
+                let (x, y): (i32, i32) = (number(command, args.first())?, number(command, args.get(1))?);
+                self.pointer = (x.clamp(0, SCREEN.0 - 1), y.clamp(0, SCREEN.1 - 1));
+                String::new()
+            }
+            "mousemove_relative" => {
+                let (dx, dy): (i32, i32) = (number(command, args.first())?, number(command, args.get(1))?);
+                self.pointer = ((self.pointer.0 + dx).clamp(0, SCREEN.0 - 1), (self.pointer.1 + dy).clamp(0, SCREEN.1 - 1));
+                String::new()
+            }
+            // Clicks, keys, typed text and the rest only get recorded
+            _ => String::new(),
+        };
+        Ok(output)
+    }
+
+    fn record(&mut self, command: &[&str]) {
+        if self.log.len() == LOG_CAPACITY {
+            self.log.pop_front();
+        }
+        self.log.push_back(MockAction {
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs_f64(),
+            command: command.iter().map(|arg| arg.to_string()).collect(),
+        });
+    }
+}
+
+/// Run one command on the fake desktop, recording it unless it's a query
+pub fn run(command: &[&str]) -> Result<String, McpError> {
+    let mut desktop = desktop();
+    // Queries leave nothing in the log, so the desktop resources' polling doesn't drown the actions
+    if !backend::is_query(command[0]) {
+        tracing::debug!("mock: {}", command.join(" "));
+        desktop.record(command);
+    }
+    desktop.run(command)
+}
+
+/// Input and windows both on the fake desktop
+pub struct Mock;
+
+impl InputBackend for Mock {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    fn plan<'a>(&self, args: &'a [&'a str]) -> Result<Vec<Step<'a>>, McpError> {
+        Ok(split_commands(args).into_iter().map(Step::Mock).collect())
+    }
+}
+
+impl WindowBackend for Mock {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    fn run(&self, command: &[&str]) -> Result<String, McpError> {
+        run(command)
+    }
+
+    fn describe(&self, command: &[&str]) -> Vec<String> {
+        argv("xdotool", command)
+    }
+
+    fn visible_windows(&self) -> Result<Vec<String>, McpError> {
+        let desktop = desktop();
+        Ok(desktop.windows.iter()
+            .filter(|window| window.mapped && window.desktop == desktop.desktop)
+            .map(|window| window.id.to_string())
+            .collect())
+    }
+
+    fn window_classes(&self, window_id: &str) -> Result<Vec<String>, McpError> {
+        Ok(desktop().windows.iter()
+            .find(|window| window.id.to_string() == window_id)
+            .map(|window| vec![window.instance.clone(), window.class.clone()])
+            .unwrap_or_default())
+    }
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct GetMockLogParams {
+    #[schemars(description = "Empty the log after reading it. Default: false")]
+    #[serde(default)]
+    pub clear: bool,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct MockLog {
+    /// Oldest first; queries aren't recorded
+    pub actions: Vec<MockAction>,
+}
+
+#[rmcp::tool_router(router = mock_router, vis = "pub(crate)")]
+impl XdotoolServer {
+    #[rmcp::tool(description = "With the mock backend, read the xdotool commands tool calls have run on the simulated desktop (oldest first; queries aren't recorded)", annotations(read_only_hint = true), output_schema = output::schema::<MockLog>())]
+    pub async fn get_mock_log(
+        &self,
+        Parameters(params): Parameters<GetMockLogParams>,
+    ) -> Result<CallToolResult, McpError> {
+        if backend::input().name() != "mock" {
+            return Err(McpError::internal_error(
+                "The mock backend isn't in use. Start the server with --backend mock.",
+                None
+            ));
+        }
+        let mut desktop = desktop();
+        let log = MockLog { actions: desktop.log.iter().cloned().collect() };
+        if params.clear {
+            desktop.log.clear();
+        }
+        if log.actions.is_empty() {
+            return Ok(output::structured("Mock log is empty", &log));
+        }
+        let lines: Vec<String> = log.actions.iter().map(|action| action.command.join(" ")).collect();
+        Ok(output::structured(lines.join("\n"), &log))
+    }
+}
@@ -0,0 +1,97 @@
+//! Desktop notifications for the person at the desk.
+//!
+//! `notify` shows a notification through notify-send, which hands it to the
+//! desktop's notification daemon (org.freedesktop.Notifications on the
+//! session bus). It's how an agent says "I'm about to close 12 windows"
+//! without typing into a window or taking focus; a `critical` one typically
+//! stays until someone dismisses it.
+
+use crate::{output, process, XdotoolServer};
+use rmcp::{
+    handler::server::wrapper::Parameters,
+    model::*,
+    ErrorData as McpError,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::process::Command;
+
+/// Sender name the notification daemon shows
+const APP_NAME: &str = "rmcp-xdotool";
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct NotifyParams {
+    #[schemars(description = "Headline, e.g. \"About to close 12 windows\"")]
+    pub summary: String,
+    #[schemars(description = "More detail, shown under the summary")]
+    pub body: Option<String>,
+    #[schemars(description = "'low', 'normal' or 'critical'; critical ones usually stay until dismissed. Default: normal")]
+    #[serde(default = "default_urgency")]
+    pub urgency: Urgency,
+    #[schemars(description = "Milliseconds until it disappears, 0 for never. Default: the notification daemon's")]
+    pub timeout_ms: Option<u32>,
+}
+
+fn default_urgency() -> Urgency { Urgency::Normal }
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Urgency {
+    Low,
+    Normal,
+    Critical,
+}
+
+impl Urgency {
+    fn name(self) -> &'static str {
+        match self {
+            Urgency::Low => "low",
+            Urgency::Normal => "normal",
+            Urgency::Critical => "critical",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct Notified {
+    pub summary: String,
+    pub urgency: Urgency,
+    /// The timeout asked for; absent when left to the daemon
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout_ms: Option<u32>,
+}
+
+#[rmcp::tool_router(router = notify_router, vis = "pub(crate)")]
+impl XdotoolServer {
+    #[rmcp::tool(description = "Show a desktop notification to the user without touching any window, e.g. to warn before a destructive step or to say a long job finished. Urgency low, normal or critical", annotations(read_only_hint = false, destructive_hint = false), output_schema = output::schema::<Notified>())]
+    pub async fn notify(
+        &self,
+        Parameters(params): Parameters<NotifyParams>,
+    ) -> Result<CallToolResult, McpError> {
+        if params.summary.trim().is_empty() {
+            return Err(McpError::invalid_params("summary can't be empty", None));
+        }
+        let mut command = Command::new("notify-send");
+        command.arg(format!("--app-name={}", APP_NAME)).arg(format!("--urgency={}", params.urgency.name()));
+        if let Some(timeout_ms) = params.timeout_ms {
+            command.arg(format!("--expire-time={}", timeout_ms));
+        }
+        // The summary and body are never read as options
+        command.arg("--").arg(&params.summary);
+        if let Some(body) = &params.body {
+            command.arg(body);
+        }
+        let output = process::output(&mut command).map_err(|e| process::error("notify-send", e))?;
+        if !output.status.success() {
+            return Err(McpError::internal_error(
+                format!("notify-send failed (is a notification daemon running?): {}", String::from_utf8_lossy(&output.stderr).trim()),
+                None
+            ));
+        }
+        tracing::info!("Notified: {}", params.summary);
+        Ok(output::structured(
+            format!("Showed a {} notification: {}", params.urgency.name(), params.summary),
+            &Notified { summary: params.summary, urgency: params.urgency, timeout_ms: params.timeout_ms }
+        ))
+    }
+}
@@ -0,0 +1,81 @@
+//! Text recognition via the tesseract CLI: plain text, or where on an image a
+//! piece of text is from its word boxes.
+
+use crate::process;
+use rmcp::ErrorData as McpError;
+use std::process::Command;
+
+/// Run tesseract on a PNG and return the recognized text
+pub fn recognize_text(png: &[u8]) -> Result<String, McpError> {
+    let output = process::output_with_stdin(Command::new("tesseract").args(["stdin", "stdout"]), png)
+        .map_err(|e| process::error("tesseract", e))?;
+
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    } else {
+        Err(McpError::internal_error(
+            format!("tesseract error: {}", String::from_utf8_lossy(&output.stderr)),
+            None
+        ))
+    }
+}
+
+/// A recognized word, lowercased, with its (left, top, width, height) box
+type Word = (String, [i32; 4]);
+
+/// Where tesseract reads `text` (ignoring case, possibly several words on one line) in a PNG,
+/// as (x, y, width, height) in the image
+pub fn find_text(png: &[u8], text: &str) -> Result<Option<(i32, i32, u32, u32)>, McpError> {
+    let output = process::output_with_stdin(Command::new("tesseract").args(["stdin", "stdout", "tsv"]), png)
+        .map_err(|e| process::error("tesseract", e))?;
+    if !output.status.success() {
+        return Err(McpError::internal_error(
+            format!("tesseract error: {}", String::from_utf8_lossy(&output.stderr)),
+            None
+        ));
+    }
+    let wanted = text.trim().to_lowercase();
+    if wanted.is_empty() {
+        return Ok(None);
+    }
+    // level page block par line word left top width height conf text, one word per level-5 row
+    let tsv = String::from_utf8_lossy(&output.stdout);
+    let (mut lines, mut last): (Vec<Vec<Word>>, _) = (vec![], None);
+    for row in tsv.lines().skip(1) {
+        let fields: Vec<&str> = row.split('\t').collect();
+        let [level, _, block, par, line, _, left, top, width, height, _, word] = fields[..] else {
+            continue;
+        };
+        let parse = |field: &str| field.parse::<i32>().ok();
+        let (Some(left), Some(top), Some(width), Some(height)) = (parse(left), parse(top), parse(width), parse(height)) else {
+            continue;
+        };
+        if level != "5" || word.trim().is_empty() {
+            continue;
+        }
+        let key = (block, par, line);
+        let word = (word.trim().to_lowercase(), [left, top, width, height]);
+        match lines.last_mut() {
+            Some(words) if last == Some(key) => words.push(word),
+            _ => lines.push(vec![word]),
+        }
+        last = Some(key);
+    }
+    // The first line, and in it the fewest words, that read as the text
+    for words in &lines {
+        for end in 0..words.len() {
+            for start in (0..=end).rev() {
+                let boxes = &words[start..=end];
+                let joined = boxes.iter().map(|(word, _)| word.as_str()).collect::<Vec<_>>().join(" ");
+                if joined.contains(&wanted) {
+                    let left = boxes.iter().map(|(_, b)| b[0]).min().unwrap_or_default();
+                    let top = boxes.iter().map(|(_, b)| b[1]).min().unwrap_or_default();
+                    let right = boxes.iter().map(|(_, b)| b[0] + b[2]).max().unwrap_or_default();
+                    let bottom = boxes.iter().map(|(_, b)| b[1] + b[3]).max().unwrap_or_default();
+                    return Ok(Some((left, top, (right - left) as u32, (bottom - top) as u32)));
+                }
+            }
+        }
+    }
+    Ok(None)
+}
@@ -0,0 +1,121 @@
+//! Structured tool results.
+//!
+//! Every tool declares an output schema and returns, next to its prose for
+//! the model, a `structuredContent` object matching it: positions as
+//! `{x, y}`, windows as objects, sizes and durations as numbers. Programs
+//! read those fields instead of parsing "Mouse position: (123, 456)". Dry
+//! runs fill in the same object with a `dry_run` field naming what would
+//! have run. Optional fields are left out rather than sent as null, and
+//! errors stay plain MCP errors.
+//!
+//! Every object carries `schema_version`, so code consuming results can
+//! check it understands them. Clients that only want data can turn the prose
+//! off, with `structured_only` in the config or by declaring the
+//! `rmcp-xdotool/structuredOnly` experimental capability when they
+//! initialize; their results' text is replaced by the compact JSON.
+
+use crate::XdotoolServer;
+use rmcp::{
+    model::{CallToolResult, Content, ExperimentalCapabilities, JsonObject, RawContent},
+    service::{RequestContext, RoleServer},
+};
+use schemars::JsonSchema;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+/// Version of the result schemas. It goes up when a field is removed, renamed or
+/// changes meaning; new fields don't change it.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Experimental client capability asking for results without prose
+pub const STRUCTURED_ONLY_CAPABILITY: &str = "rmcp-xdotool/structuredOnly";
+
+/// A screen position
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, JsonSchema)]
+pub struct Point {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl From<(i32, i32)> for Point {
+    fn from((x, y): (i32, i32)) -> Self {
+        Point { x, y }
+    }
+}
+
+/// An X window
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct Window {
+    /// Decimal window ID, as the window_id parameters take it
+    pub id: String,
+    /// Window title, when the tool looked it up
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+/// Output schema for a tool returning `T`, for `#[rmcp::tool(output_schema = ...)]`
+pub fn schema<T: JsonSchema + 'static>() -> Arc<JsonObject> {
+    let mut schema = (*rmcp::handler::server::common::schema_for_output::<T>()
+        .unwrap_or_else(|e| panic!("Invalid output schema {}: {}", std::any::type_name::<T>(), e)))
+        .clone();
+    if let Some(Value::Object(properties)) = schema.get_mut("properties") {
+        properties.insert("schema_version".to_string(), json!({
+            "description": "Version of this result's schema",
+            "type": "integer",
+            "const": SCHEMA_VERSION,
+        }));
+    }
+    match schema.get_mut("required") {
+        Some(Value::Array(required)) => required.push(json!("schema_version")),
+        _ => {
+            schema.insert("required".to_string(), json!(["schema_version"]));
+        }
+    }
+    Arc::new(schema)
+}
+
+/// A successful result with `text` for the model and `value` as its structured content
+pub fn structured<T: Serialize>(text: impl Into<String>, value: &T) -> CallToolResult {
+    with_content(vec![Content::text(text.into())], value)
+}
+
+/// `structured` for results with more content than one text, like screenshots
+pub fn with_content<T: Serialize>(content: Vec<Content>, value: &T) -> CallToolResult {
+    let mut result = CallToolResult::success(content);
+    match serde_json::to_value(value) {
+        Ok(Value::Object(mut object)) => {
+            object.insert("schema_version".to_string(), json!(SCHEMA_VERSION));
+            result.structured_content = Some(Value::Object(object));
+        }
+        Ok(_) => tracing::warn!("Structured result is not an object"),
+        Err(e) => tracing::warn!("Failed to serialize structured result: {}", e),
+    }
+    result
+}
+
+/// Replace a result's text with its structured content as compact JSON, keeping images and links
+pub fn without_prose(mut result: CallToolResult) -> CallToolResult {
+    let Some(value) = &result.structured_content else {
+        return result;
+    };
+    result.content.retain(|content| !matches!(content.raw, RawContent::Text(_)));
+    result.content.insert(0, Content::text(value.to_string()));
+    result
+}
+
+/// The capability advertised to clients, with the schema version they'd get
+pub fn capabilities() -> ExperimentalCapabilities {
+    let mut capability = JsonObject::new();
+    capability.insert("schemaVersion".to_string(), json!(SCHEMA_VERSION));
+    ExperimentalCapabilities::from([(STRUCTURED_ONLY_CAPABILITY.to_string(), capability)])
+}
+
+impl XdotoolServer {
+    /// Whether this call's results should leave out the prose
+    pub(crate) fn structured_only(&self, context: &RequestContext<RoleServer>) -> bool {
+        self.config.structured_only || context.peer.peer_info()
+            .and_then(|info| info.capabilities.experimental.as_ref())
+            .is_some_and(|experimental| experimental.contains_key(STRUCTURED_ONLY_CAPABILITY))
+    }
+}
@@ -0,0 +1,213 @@
+//! `_NET_WM_PING`, the window manager's "are you still there?".
+//!
+//! A client that lists `_NET_WM_PING` in its window's WM_PROTOCOLS answers a
+//! ping ClientMessage by sending it back to the root window, from its event
+//! loop, so an app whose main thread is stuck doesn't answer. No command-line
+//! tool sends one, so libX11 (libX11.so.6) is loaded with dlopen and each
+//! ping opens its own connection: it selects SubstructureNotify on the root
+//! window to see the reply, sends the ping and waits for its echo.
+
+use crate::display;
+use std::ffi::{c_char, c_int, c_long, c_uint, c_ulong, c_void, CString};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+type Display = c_void;
+type Window = c_ulong;
+type Atom = c_ulong;
+
+/// The event type of a ClientMessage
+const CLIENT_MESSAGE: c_int = 33;
+const SUBSTRUCTURE_NOTIFY_MASK: c_long = 1 << 19;
+
+/// `XClientMessageEvent`, with format 32 data
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct ClientMessage {
+    kind: c_int,
+    serial: c_ulong,
+    send_event: c_int,
+    display: *mut Display,
+    window: Window,
+    message_type: Atom,
+    format: c_int,
+    data: [c_long; 5],
+}
+
+/// `XEvent`: any event fits in 24 longs
+#[repr(C)]
+union Event {
+    client: ClientMessage,
+    pad: [c_long; 24],
+}
+
+type ErrorHandler = unsafe extern "C" fn(*mut Display, *mut c_void) -> c_int;
+
+/// The libX11 functions used, resolved once
+struct Api {
+    open_display: unsafe extern "C" fn(*const c_char) -> *mut Display,
+    close_display: unsafe extern "C" fn(*mut Display) -> c_int,
+    default_root_window: unsafe extern "C" fn(*mut Display) -> Window,
+    intern_atom: unsafe extern "C" fn(*mut Display, *const c_char, c_int) -> Atom,
+    get_wm_protocols: unsafe extern "C" fn(*mut Display, Window, *mut *mut Atom, *mut c_int) -> c_int,
+    free: unsafe extern "C" fn(*mut c_void) -> c_int,
+    select_input: unsafe extern "C" fn(*mut Display, Window, c_long) -> c_int,
+    send_event: unsafe extern "C" fn(*mut Display, Window, c_int, c_long, *mut Event) -> c_int,
+    sync: unsafe extern "C" fn(*mut Display, c_int) -> c_int,
+    pending: unsafe extern "C" fn(*mut Display) -> c_int,
+    next_event: unsafe extern "C" fn(*mut Display, *mut Event) -> c_int,
+    connection_number: unsafe extern "C" fn(*mut Display) -> c_int,
+}
+
+static API: OnceLock<Result<Api, String>> = OnceLock::new();
+
+/// Set by the error handler when a request fails, e.g. on a window that has gone
+static X_ERROR: AtomicBool = AtomicBool::new(false);
+
+/// Xlib's default handler exits the process; note the error instead. This is process-wide,
+/// so it also covers the libxdo backend's connection when both are loaded
+unsafe extern "C" fn on_error(_display: *mut Display, _event: *mut c_void) -> c_int {
+    X_ERROR.store(true, Ordering::SeqCst);
+    0
+}
+
+unsafe fn symbol<T: Copy>(library: *mut c_void, name: &str) -> Result<T, String> {
+    let c_name = CString::new(name).unwrap();
+    let pointer = libc::dlsym(library, c_name.as_ptr());
+    if pointer.is_null() {
+        return Err(format!("libX11 has no {}", name));
+    }
+    assert_eq!(size_of::<T>(), size_of::<*mut c_void>());
+    Ok(std::mem::transmute_copy(&pointer))
+}
+
+fn load() -> Result<Api, String> {
+    let library = ["libX11.so.6", "libX11.so"].iter()
+        .map(|name| CString::new(*name).unwrap())
+        .map(|name| unsafe { libc::dlopen(name.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL) })
+        .find(|library| !library.is_null())
+        .ok_or_else(|| "libX11.so.6 not found".to_string())?;
+    unsafe {
+        let set_error_handler: unsafe extern "C" fn(Option<ErrorHandler>) -> Option<ErrorHandler> = symbol(library, "XSetErrorHandler")?;
+        set_error_handler(Some(on_error));
+        Ok(Api {
+            open_display: symbol(library, "XOpenDisplay")?,
+            close_display: symbol(library, "XCloseDisplay")?,
+            default_root_window: symbol(library, "XDefaultRootWindow")?,
+            intern_atom: symbol(library, "XInternAtom")?,
+            get_wm_protocols: symbol(library, "XGetWMProtocols")?,
+            free: symbol(library, "XFree")?,
+            select_input: symbol(library, "XSelectInput")?,
+            send_event: symbol(library, "XSendEvent")?,
+            sync: symbol(library, "XSync")?,
+            pending: symbol(library, "XPending")?,
+            next_event: symbol(library, "XNextEvent")?,
+            connection_number: symbol(library, "XConnectionNumber")?,
+        })
+    }
+}
+
+/// An open display, closed on drop
+struct Connection<'a> {
+    api: &'a Api,
+    display: *mut Display,
+}
+
+impl Drop for Connection<'_> {
+    fn drop(&mut self) {
+        unsafe { (self.api.close_display)(self.display) };
+    }
+}
+
+impl Connection<'_> {
+    fn atom(&self, name: &str) -> Atom {
+        let name = CString::new(name).unwrap();
+        unsafe { (self.api.intern_atom)(self.display, name.as_ptr(), 0) }
+    }
+
+    /// Whether the window's WM_PROTOCOLS lists `protocol`
+    fn supports(&self, window: Window, protocol: Atom) -> Result<bool, String> {
+        let (mut protocols, mut count) = (std::ptr::null_mut(), 0);
+        X_ERROR.store(false, Ordering::SeqCst);
+        let found = unsafe { (self.api.get_wm_protocols)(self.display, window, &mut protocols, &mut count) };
+        if X_ERROR.load(Ordering::SeqCst) {
+            return Err(format!("window {} doesn't exist", window));
+        }
+        if found == 0 || protocols.is_null() {
+            return Ok(false);
+        }
+        let supported = unsafe { std::slice::from_raw_parts(protocols, count as usize) }.contains(&protocol);
+        unsafe { (self.api.free)(protocols.cast()) };
+        Ok(supported)
+    }
+
+    /// Wait until an event is readable, at most `timeout`
+    fn wait(&self, timeout: Duration) -> bool {
+        let mut poll = libc::pollfd { fd: unsafe { (self.api.connection_number)(self.display) }, events: libc::POLLIN, revents: 0 };
+        unsafe { libc::poll(&mut poll, 1, timeout.as_millis().min(c_int::MAX as u128) as c_int) > 0 }
+    }
+}
+
+/// Ping `window` and wait up to `timeout` for its reply. Ok(None) means it didn't answer in
+/// time; Err says why it can't be pinged (no libX11 or display, or the window doesn't support it).
+pub(crate) fn ping(window: Window, timeout: Duration) -> Result<Option<Duration>, String> {
+    let api = API.get_or_init(load).as_ref().map_err(Clone::clone)?;
+    let name = display::current().map(|name| CString::new(name).map_err(|e| e.to_string())).transpose()?;
+    // NULL means $DISPLAY
+    let display = unsafe { (api.open_display)(name.as_ref().map_or(std::ptr::null(), |name| name.as_ptr())) };
+    if display.is_null() {
+        return Err("can't open the X display".to_string());
+    }
+    let connection = Connection { api, display };
+    let (protocols, ping) = (connection.atom("WM_PROTOCOLS"), connection.atom("_NET_WM_PING"));
+    if !connection.supports(window, ping)? {
+        return Err(format!("window {} doesn't support _NET_WM_PING", window));
+    }
+
+    let root = unsafe { (api.default_root_window)(display) };
+    // Only compared with the reply, which echoes it; X timestamps are 32-bit
+    let stamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u32;
+    let message = ClientMessage {
+        kind: CLIENT_MESSAGE,
+        serial: 0,
+        send_event: 1,
+        display,
+        window,
+        message_type: protocols,
+        format: 32,
+        data: [ping as c_long, stamp as c_long, window as c_long, 0, 0],
+    };
+    let started = Instant::now();
+    unsafe {
+        (api.select_input)(display, root, SUBSTRUCTURE_NOTIFY_MASK);
+        // An empty mask sends it to the window's own client
+        (api.send_event)(display, window, 0, 0, &mut Event { client: message });
+        X_ERROR.store(false, Ordering::SeqCst);
+        (api.sync)(display, 0);
+    }
+    if X_ERROR.load(Ordering::SeqCst) {
+        return Err(format!("couldn't send window {} a ping", window));
+    }
+    loop {
+        while unsafe { (api.pending)(display) } > 0 {
+            let mut event = Event { pad: [0; 24] };
+            unsafe { (api.next_event)(display, &mut event) };
+            let reply = unsafe { event.client };
+            // The window manager's own pings come back on the root window too
+            let ours = reply.kind == CLIENT_MESSAGE
+                && reply.message_type == protocols
+                && reply.data[0] as Atom == ping
+                && reply.data[1] as c_uint == stamp
+                && reply.data[2] as Window == window;
+            if ours {
+                return Ok(Some(started.elapsed()));
+            }
+        }
+        let left = timeout.saturating_sub(started.elapsed());
+        if left.is_zero() {
+            return Ok(None);
+        }
+        connection.wait(left);
+    }
+}
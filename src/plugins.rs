@@ -0,0 +1,314 @@
+//! Site-specific tools declared in the config.
+//!
+//! Each `[[plugins]]` entry becomes a tool running one external command, so
+//! a deployment can offer "connect the VPN" or "open the ticket system"
+//! without forking the crate. Parameters are strings, substituted into the
+//! command's arguments where they name them as `{parameter}`; the command
+//! runs directly, never through a shell, so a value can't add arguments or
+//! commands of its own. Plugin tools take `dry_run` and `display` like the
+//! built-in ones, and client scopes, policy rules and rate limits see them
+//! by name. Marking one `input = true` also subjects it to pausing, the
+//! screen lock and idle disarming, and queues it with other input.
+//!
+//! Embedding programs can add routes to `XdotoolServer::tool_router`
+//! themselves instead.
+
+use crate::{control, display, output, timeouts, XdotoolServer};
+use regex_automata::meta::Regex;
+use rmcp::handler::server::router::tool::{ToolRoute, ToolRouter};
+use rmcp::handler::server::tool::ToolCallContext;
+use rmcp::model::{CallToolResult, JsonObject, Tool, ToolAnnotations};
+use rmcp::ErrorData as McpError;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+/// A `[[plugins]]` entry
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Plugin {
+    /// Tool name clients call
+    pub name: String,
+    /// What the tool does, for the model
+    pub description: String,
+    /// Program and arguments. `{name}` in an argument is replaced by that parameter's value.
+    pub command: Vec<String>,
+    #[serde(default)]
+    pub parameters: BTreeMap<String, PluginParameter>,
+    /// Whether the tool sends input or changes the desktop
+    #[serde(default)]
+    pub input: bool,
+    /// Whether the tool only reads, for clients deciding what needs confirmation
+    #[serde(default)]
+    pub read_only: bool,
+    /// How long the command may run before it's killed
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+fn default_timeout_secs() -> u64 { 30 }
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PluginParameter {
+    pub description: String,
+    /// Used when a call leaves the parameter out; without one it's required
+    pub default: Option<String>,
+}
+
+/// Arguments every tool takes, which parameters can't be named
+const RESERVED: &[&str] = &["dry_run", "display"];
+
+fn valid_name(name: &str) -> bool {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| Regex::new(r"^[A-Za-z0-9_-]{1,64}$").unwrap()).is_match(name)
+}
+
+/// The `{name}` placeholders in an argument
+fn placeholders(arg: &str) -> Vec<&str> {
+    arg.split('{').skip(1).filter_map(|rest| rest.split_once('}')).map(|(name, _)| name).collect()
+}
+
+/// Check the `[[plugins]]` entries
+pub fn validate(plugins: &[Plugin]) -> Result<(), String> {
+    for (i, plugin) in plugins.iter().enumerate() {
+        if !valid_name(&plugin.name) {
+            return Err(format!("plugins[{}]: name '{}' must be 1-64 letters, digits, '_' or '-'", i, plugin.name));
+        }
+        if plugins[..i].iter().any(|other| other.name == plugin.name) {
+            return Err(format!("plugins[{}]: duplicate name '{}'", i, plugin.name));
+        }
+        if plugin.command.is_empty() || plugin.command[0].is_empty() {
+            return Err(format!("plugins[{}]: command must name a program", i));
+        }
+        if plugin.timeout_secs == 0 {
+            return Err(format!("plugins[{}]: timeout_secs must be at least 1", i));
+        }
+        for name in plugin.parameters.keys() {
+            if !valid_name(name) || RESERVED.contains(&name.as_str()) {
+                return Err(format!("plugins[{}]: invalid parameter name '{}'", i, name));
+            }
+        }
+        for name in plugin.command.iter().flat_map(|arg| placeholders(arg)) {
+            if !plugin.parameters.contains_key(name) {
+                return Err(format!("plugins[{}]: command uses {{{}}}, which isn't one of its parameters", i, name));
+            }
+        }
+    }
+    Ok(())
+}
+
+impl Plugin {
+    fn tool(&self) -> Tool {
+        let mut properties = JsonObject::new();
+        for (name, parameter) in &self.parameters {
+            let description = match &parameter.default {
+                Some(default) => format!("{}. Default: {}", parameter.description, default),
+                None => parameter.description.clone(),
+            };
+            properties.insert(name.clone(), json!({"type": "string", "description": description}));
+        }
+        properties.insert("dry_run".to_string(), json!({
+            "type": "boolean",
+            "description": "Only report the command that would run. Default: the server's dry_run setting",
+        }));
+        let required: Vec<&String> = self.parameters.iter()
+            .filter(|(_, parameter)| parameter.default.is_none())
+            .map(|(name, _)| name)
+            .collect();
+        let mut schema = JsonObject::new();
+        schema.insert("type".to_string(), json!("object"));
+        schema.insert("properties".to_string(), Value::Object(properties));
+        schema.insert("required".to_string(), json!(required));
+        let mut tool = Tool::new(self.name.clone(), self.description.clone(), Arc::new(schema));
+        tool.output_schema = Some(output::schema::<PluginOutput>());
+        if self.read_only {
+            tool = tool.annotate(ToolAnnotations::new().read_only(true));
+        }
+        tool
+    }
+
+    /// The command with a call's arguments substituted
+    fn argv(&self, arguments: &JsonObject) -> Result<Vec<String>, McpError> {
+        let mut values = BTreeMap::new();
+        for (name, value) in arguments {
+            if RESERVED.contains(&name.as_str()) {
+                continue;
+            }
+            if !self.parameters.contains_key(name) {
+                return Err(McpError::invalid_params(format!("{} has no parameter '{}'", self.name, name), None));
+            }
+            let value = match value {
+                Value::String(value) => value.clone(),
+                Value::Number(_) | Value::Bool(_) => value.to_string(),
+                _ => return Err(McpError::invalid_params(format!("{} must be a string", name), None)),
+            };
+            values.insert(name.as_str(), value);
+        }
+        for (name, parameter) in &self.parameters {
+            if !values.contains_key(name.as_str()) {
+                let default = parameter.default.clone()
+                    .ok_or_else(|| McpError::invalid_params(format!("{} needs '{}'", self.name, name), None))?;
+                values.insert(name.as_str(), default);
+            }
+        }
+        Ok(self.command.iter().map(|arg| fill(arg, &values)).collect())
+    }
+}
+
+/// Replace each `{name}` in an argument with its value, in one left-to-right pass so a
+/// value is never searched for placeholders itself. Unknown names are left as they are.
+fn fill(arg: &str, values: &BTreeMap<&str, String>) -> String {
+    let mut filled = String::new();
+    let mut rest = arg;
+    while let Some(start) = rest.find('{') {
+        filled.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let value = rest[1..].find('}').and_then(|len| Some((len, values.get(&rest[1..1 + len])?)));
+        match value {
+            Some((len, value)) => {
+                filled.push_str(value);
+                rest = &rest[len + 2..];
+            }
+            None => {
+                filled.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    filled.push_str(rest);
+    filled
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct PluginOutput {
+    pub stdout: String,
+    /// What the command wrote to stderr, if anything
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stderr: Option<String>,
+    /// The command that would have run, in dry runs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dry_run: Option<String>,
+}
+
+type CallFuture<'a> = Pin<Box<dyn Future<Output = Result<CallToolResult, McpError>> + Send + 'a>>;
+
+fn call(plugin: Arc<Plugin>, context: ToolCallContext<'_, XdotoolServer>) -> CallFuture<'_> {
+    Box::pin(async move {
+        let arguments = context.arguments.unwrap_or_default();
+        context.service.run_plugin(&plugin, &arguments, &context.request_context.ct).await
+    })
+}
+
+/// Add a route for each plugin, skipping any named like a built-in tool
+pub fn add_routes(router: &mut ToolRouter<XdotoolServer>, plugins: &[Plugin]) {
+    for plugin in plugins {
+        if router.has_route(&plugin.name) {
+            tracing::warn!("Plugin {} not added: a built-in tool has that name", plugin.name);
+            continue;
+        }
+        let tool = plugin.tool();
+        let plugin = Arc::new(plugin.clone());
+        router.add_route(ToolRoute::new_dyn(tool, move |context| call(plugin.clone(), context)));
+    }
+}
+
+impl XdotoolServer {
+    /// Whether `name` is a plugin declared with `input = true`
+    pub(crate) fn is_input_plugin(&self, name: &str) -> bool {
+        self.config.plugins.iter().any(|plugin| plugin.input && plugin.name == name)
+    }
+
+    async fn run_plugin(&self, plugin: &Plugin, arguments: &JsonObject, cancel: &CancellationToken) -> Result<CallToolResult, McpError> {
+        let argv = plugin.argv(arguments)?;
+        let dry_run = arguments.get("dry_run").and_then(Value::as_bool).unwrap_or(self.config.dry_run);
+        if dry_run {
+            let command = Self::format_argv(&argv);
+            return Ok(output::structured(Self::dry_run_report(&command), &PluginOutput {
+                stdout: String::new(),
+                stderr: None,
+                dry_run: Some(command),
+            }));
+        }
+
+        let child = display::async_command(&argv[0])
+            .args(&argv[1..])
+            .kill_on_drop(true)
+            .output();
+        let timeout = Duration::from_secs(plugin.timeout_secs);
+        let output = tokio::select! {
+            output = tokio::time::timeout(timeout, child) => match output {
+                Ok(output) => output.map_err(|e| McpError::internal_error(format!("Failed to run {}: {}", argv[0], e), None))?,
+                Err(_) => return Err(timeouts::timeout_error(&plugin.name, timeout)),
+            },
+            _ = cancel.cancelled() => return Err(control::cancelled_error()),
+        };
+        let stdout = String::from_utf8_lossy(&output.stdout).trim_end().to_string();
+        let stderr = String::from_utf8_lossy(&output.stderr).trim_end().to_string();
+        if !output.status.success() {
+            return Err(McpError::internal_error(
+                format!("{} failed ({}): {}", plugin.name, output.status, if stderr.is_empty() { &stdout } else { &stderr }),
+                None
+            ));
+        }
+        let text = if stdout.is_empty() { format!("{} finished", plugin.name) } else { stdout.clone() };
+        Ok(output::structured(text, &PluginOutput {
+            stdout,
+            stderr: Some(stderr).filter(|stderr| !stderr.is_empty()),
+            dry_run: None,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plugin(command: &[&str], parameters: &[&str]) -> Plugin {
+        Plugin {
+            name: "test".to_string(),
+            description: "A test".to_string(),
+            command: command.iter().map(|arg| arg.to_string()).collect(),
+            parameters: parameters.iter()
+                .map(|name| (name.to_string(), PluginParameter { description: String::new(), default: None }))
+                .collect(),
+            input: false,
+            read_only: true,
+            timeout_secs: 30,
+        }
+    }
+
+    fn arguments(value: Value) -> JsonObject {
+        value.as_object().cloned().unwrap()
+    }
+
+    #[test]
+    fn values_fill_their_own_placeholders_only() {
+        let plugin = plugin(&["vpn", "--user={a}", "{b}", "{a}{b}", "{c}"], &["a", "b"]);
+        let argv = plugin.argv(&arguments(json!({"a": "{b}", "b": "secret"}))).unwrap();
+        assert_eq!(argv, ["vpn", "--user={b}", "secret", "{b}secret", "{c}"]);
+    }
+
+    #[test]
+    fn fill_handles_stray_braces() {
+        let values = BTreeMap::from([("x", "1".to_string())]);
+        assert_eq!(fill("{{x}}", &values), "{1}");
+        assert_eq!(fill("{x", &values), "{x");
+        assert_eq!(fill("}{x}{", &values), "}1{");
+    }
+
+    #[test]
+    fn arguments_are_checked() {
+        let plugin = plugin(&["open", "{ticket}"], &["ticket"]);
+        assert!(plugin.argv(&arguments(json!({}))).is_err());
+        assert!(plugin.argv(&arguments(json!({"ticket": "1", "other": "2"}))).is_err());
+        assert!(plugin.argv(&arguments(json!({"ticket": ["1"]}))).is_err());
+        assert_eq!(plugin.argv(&arguments(json!({"ticket": 42, "dry_run": true}))).unwrap(), ["open", "42"]);
+    }
+}
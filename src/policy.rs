@@ -0,0 +1,360 @@
+//! Config-driven rules evaluated before every tool call.
+//!
+//! Each `[[rules]]` entry lists conditions (tool names, the target window's
+//! WM_CLASS or title, a screen region, a time-of-day range) and an outcome.
+//! Rules are tried in order and the first one whose conditions all hold
+//! decides: `allow` runs the call, `deny` refuses it, `require_approval` asks
+//! the user through elicitation first. The `require_approval` list is folded
+//! in as rules placed after the configured ones, and a call no rule matches
+//! is allowed.
+//!
+//! The target window is the call's `window_id` argument when it has one, else
+//! the one under the cursor for pointer tools and the active window otherwise. Coordinates are every `x`/`y` pair in the call's
+//! arguments, including those inside batch steps.
+
+use crate::XdotoolServer;
+use chrono::{NaiveTime, Timelike};
+use rmcp::{
+    model::*,
+    service::{RequestContext, RoleServer},
+    ErrorData as McpError,
+};
+use serde::Deserialize;
+use serde_json::Value;
+
+/// Tools whose target window is the one under the cursor
+const POINTER_TOOLS: &[&str] = &["click", "double_click", "scroll", "click_at", "move_mouse"];
+
+/// What happens to a call a rule matches
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Allow,
+    Deny,
+    RequireApproval,
+}
+
+/// A rectangle in screen coordinates
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Area {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Area {
+    fn contains(&self, (x, y): (i64, i64)) -> bool {
+        x >= self.x as i64 && x < (self.x + self.width) as i64
+            && y >= self.y as i64 && y < (self.y + self.height) as i64
+    }
+}
+
+/// One `[[rules]]` entry. Unset conditions match anything.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    /// Tool names the rule applies to
+    #[serde(default)]
+    pub tools: Vec<String>,
+    /// WM_CLASS names (instance or class) of the target window, case-insensitive
+    #[serde(default)]
+    pub window_class: Vec<String>,
+    /// Text the target window's title must contain, case-insensitive
+    pub window_title: Option<String>,
+    /// Matches when any coordinate in the call falls inside this area
+    pub region: Option<Area>,
+    /// Local time range as "HH:MM-HH:MM"; may wrap past midnight
+    pub hours: Option<String>,
+    pub outcome: Outcome,
+    /// Shown in the error when the rule denies a call
+    pub message: Option<String>,
+}
+
+impl Rule {
+    fn needs_window(&self) -> bool {
+        !self.window_class.is_empty() || self.window_title.is_some()
+    }
+}
+
+/// Parse "HH:MM-HH:MM" into its start and end
+fn parse_hours(hours: &str) -> Result<(NaiveTime, NaiveTime), String> {
+    let parse = |time: &str| NaiveTime::parse_from_str(time.trim(), "%H:%M")
+        .map_err(|_| format!("invalid time '{}' in hours '{}', expected HH:MM", time.trim(), hours));
+    let (start, end) = hours.split_once('-')
+        .ok_or_else(|| format!("invalid hours '{}', expected HH:MM-HH:MM", hours))?;
+    Ok((parse(start)?, parse(end)?))
+}
+
+/// Whether `now` is in [start, end), wrapping past midnight when end is before start
+fn in_hours(now: NaiveTime, (start, end): (NaiveTime, NaiveTime)) -> bool {
+    if start <= end {
+        now >= start && now < end
+    } else {
+        now >= start || now < end
+    }
+}
+
+/// Check every rule's hours at startup so a typo doesn't surface on the first call
+pub fn validate(rules: &[Rule]) -> Result<(), String> {
+    for (i, rule) in rules.iter().enumerate() {
+        if let Some(hours) = &rule.hours {
+            parse_hours(hours).map_err(|e| format!("rules[{}]: {}", i, e))?;
+        }
+    }
+    Ok(())
+}
+
+/// Every x/y pair anywhere in the arguments
+fn points(value: &Value, out: &mut Vec<(i64, i64)>) {
+    match value {
+        Value::Object(map) => {
+            if let (Some(x), Some(y)) = (map.get("x").and_then(Value::as_i64), map.get("y").and_then(Value::as_i64)) {
+                out.push((x, y));
+            }
+            map.values().for_each(|value| points(value, out));
+        }
+        Value::Array(items) => items.iter().for_each(|value| points(value, out)),
+        _ => {}
+    }
+}
+
+/// The `window_id` argument of a call, which names its target window outright
+fn window_argument(arguments: Option<&JsonObject>) -> Option<String> {
+    match arguments?.get("window_id")? {
+        Value::String(id) if !id.trim().is_empty() => Some(id.trim().to_string()),
+        Value::Number(id) => Some(id.to_string()),
+        _ => None,
+    }
+}
+
+/// The window a call would go to, looked up once and only if a rule asks
+#[derive(Default)]
+struct TargetWindow {
+    classes: Vec<String>,
+    title: String,
+}
+
+impl XdotoolServer {
+    fn target_window(request: &CallToolRequestParam) -> Result<TargetWindow, McpError> {
+        let window_id = if let Some(id) = window_argument(request.arguments.as_ref()) {
+            id
+        } else if POINTER_TOOLS.contains(&request.name.as_ref()) {
+            Self::window_under_cursor()?
+        } else {
+            Self::run_xdotool(&["getactivewindow"])?.trim().to_string()
+        };
+        if window_id.is_empty() {
+            return Ok(TargetWindow::default());
+        }
+        Ok(TargetWindow {
+            classes: crate::allowlist::window_classes(&window_id)?,
+            title: Self::window_name(&window_id).unwrap_or_default(),
+        })
+    }
+
+    /// The configured rules followed by one per `require_approval` entry
+    fn rules(&self) -> impl Iterator<Item = Rule> + '_ {
+        self.config.rules.iter().cloned().chain(self.config.require_approval.iter().map(|tool| Rule {
+            tools: vec![tool.clone()],
+            window_class: vec![],
+            window_title: None,
+            region: None,
+            hours: None,
+            outcome: Outcome::RequireApproval,
+            message: None,
+        }))
+    }
+
+    /// The first matching rule, if any
+    fn matching_rule(&self, request: &CallToolRequestParam) -> Result<Option<Rule>, McpError> {
+        let tool = request.name.as_ref();
+        let mut coordinates = vec![];
+        if let Some(args) = &request.arguments {
+            points(&Value::Object(args.clone()), &mut coordinates);
+        }
+        let now = chrono::Local::now().time().with_nanosecond(0).unwrap_or_default();
+        let mut window: Option<TargetWindow> = None;
+
+        for rule in self.rules() {
+            if !rule.tools.is_empty() && !rule.tools.iter().any(|name| name == tool) {
+                continue;
+            }
+            if let Some(area) = &rule.region {
+                if !coordinates.iter().any(|&point| area.contains(point)) {
+                    continue;
+                }
+            }
+            if let Some(hours) = &rule.hours {
+                let range = parse_hours(hours).map_err(|e| McpError::internal_error(e, None))?;
+                if !in_hours(now, range) {
+                    continue;
+                }
+            }
+            if rule.needs_window() {
+                if window.is_none() {
+                    window = Some(Self::target_window(request)?);
+                }
+                let target = window.as_ref().unwrap();
+                let class_matches = rule.window_class.is_empty() || target.classes.iter()
+                    .any(|class| rule.window_class.iter().any(|wanted| wanted.eq_ignore_ascii_case(class)));
+                let title_matches = rule.window_title.as_ref()
+                    .is_none_or(|text| target.title.to_lowercase().contains(&text.to_lowercase()));
+                if !class_matches || !title_matches {
+                    continue;
+                }
+            }
+            return Ok(Some(rule));
+        }
+        Ok(None)
+    }
+
+    /// The rule that applies to a call. Dry runs don't touch the desktop and skip the rules,
+    /// but only for tools that really do a dry run.
+    fn rule_for(&self, request: &CallToolRequestParam) -> Result<Option<Rule>, McpError> {
+        if self.is_dry_run(request) {
+            return Ok(None);
+        }
+        self.matching_rule(request)
+    }
+
+    /// Apply the first matching rule to a call
+    pub(crate) async fn check_policy(
+        &self,
+        request: &CallToolRequestParam,
+        context: &RequestContext<RoleServer>,
+    ) -> Result<(), McpError> {
+        let Some(rule) = self.rule_for(request)? else {
+            return Ok(());
+        };
+        self.apply_rule(request, rule, context).await
+    }
+
+    /// Apply the rules to a point a tool worked out itself (like click_element's target), which
+    /// check_policy couldn't see in the call's arguments. Only region rules apply; the rest already did.
+    pub(crate) async fn check_policy_at(
+        &self,
+        tool: &str,
+        (x, y): (i32, i32),
+        context: &RequestContext<RoleServer>,
+    ) -> Result<(), McpError> {
+        let mut arguments = JsonObject::new();
+        arguments.insert("x".to_string(), x.into());
+        arguments.insert("y".to_string(), y.into());
+        let request = CallToolRequestParam { name: tool.to_string().into(), arguments: Some(arguments) };
+        match self.matching_rule(&request)? {
+            Some(rule) if rule.region.is_some() => self.apply_rule(&request, rule, context).await,
+            _ => Ok(()),
+        }
+    }
+
+    async fn apply_rule(
+        &self,
+        request: &CallToolRequestParam,
+        rule: Rule,
+        context: &RequestContext<RoleServer>,
+    ) -> Result<(), McpError> {
+        match rule.outcome {
+            Outcome::Allow => Ok(()),
+            Outcome::Deny => {
+                tracing::warn!("{} denied by policy", request.name);
+                Err(McpError::internal_error(
+                    match rule.message {
+                        Some(message) => format!("'{}' denied by policy: {}", request.name, message),
+                        None => format!("'{}' denied by policy", request.name),
+                    },
+                    None
+                ))
+            }
+            Outcome::RequireApproval => {
+                let call = match request.arguments.as_ref().filter(|args| !args.is_empty()) {
+                    Some(args) => format!("{} with {}", request.name, serde_json::to_string(args).unwrap_or_default()),
+                    None => request.name.to_string(),
+                };
+                let message = match rule.message {
+                    Some(reason) => format!("Allow {}? ({})", call, reason),
+                    None => format!("Allow {}?", call),
+                };
+                Self::ask_user(&request.name, message, context).await
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(text: &str) -> NaiveTime {
+        NaiveTime::parse_from_str(text, "%H:%M").unwrap()
+    }
+
+    #[test]
+    fn hours_parse_and_wrap_past_midnight() {
+        let day = parse_hours("09:00-17:30").unwrap();
+        assert!(in_hours(time("09:00"), day));
+        assert!(!in_hours(time("17:30"), day));
+        let night = parse_hours("22:00 - 06:00").unwrap();
+        assert!(in_hours(time("23:15"), night));
+        assert!(in_hours(time("05:59"), night));
+        assert!(!in_hours(time("12:00"), night));
+    }
+
+    #[test]
+    fn bad_hours_are_rejected() {
+        assert!(parse_hours("9-5").is_err());
+        assert!(parse_hours("09:00").is_err());
+        assert!(parse_hours("25:00-26:00").is_err());
+    }
+
+    #[test]
+    fn points_include_batch_steps() {
+        let args = serde_json::json!({"x": 1, "y": 2, "steps": [{"action": "click", "x": 30, "y": 40}, {"x": 5}]});
+        let mut found = vec![];
+        points(&args, &mut found);
+        assert_eq!(found, vec![(1, 2), (30, 40)]);
+    }
+
+    #[test]
+    fn area_excludes_its_far_edges() {
+        let area = Area { x: 10, y: 10, width: 100, height: 50 };
+        assert!(area.contains((10, 10)));
+        assert!(area.contains((109, 59)));
+        assert!(!area.contains((110, 30)));
+        assert!(!area.contains((50, 60)));
+    }
+
+    #[test]
+    fn window_id_argument_names_the_target() {
+        let args = |value: Value| value.as_object().cloned();
+        assert_eq!(window_argument(args(serde_json::json!({"window_id": "4194311"})).as_ref()), Some("4194311".to_string()));
+        assert_eq!(window_argument(args(serde_json::json!({"window_id": 4194311})).as_ref()), Some("4194311".to_string()));
+        assert_eq!(window_argument(args(serde_json::json!({"window_id": " "})).as_ref()), None);
+        assert_eq!(window_argument(args(serde_json::json!({"x": 1})).as_ref()), None);
+        assert_eq!(window_argument(None), None);
+    }
+
+    #[test]
+    fn rules_need_a_real_outcome() {
+        let rules: Result<Vec<Rule>, _> = serde_json::from_str(r#"[{"tools": ["click"], "outcome": "maybe"}]"#);
+        assert!(rules.is_err());
+        let rules: Vec<Rule> = serde_json::from_str(r#"[{"hours": "08:00-18:00", "outcome": "deny"}]"#).unwrap();
+        assert!(validate(&rules).is_ok());
+        let rules: Vec<Rule> = serde_json::from_str(r#"[{"hours": "8am-6pm", "outcome": "deny"}]"#).unwrap();
+        assert!(validate(&rules).unwrap_err().starts_with("rules[0]"));
+    }
+
+    #[test]
+    fn dry_run_flag_doesnt_skip_rules_for_tools_without_one() {
+        let rules: Vec<Rule> = serde_json::from_str(r#"[{"tools": ["launch_app", "click"], "outcome": "deny"}]"#).unwrap();
+        let server = XdotoolServer::with_config(crate::config::Config { rules, ..Default::default() });
+        let call = |name: &str| CallToolRequestParam {
+            name: name.to_string().into(),
+            arguments: serde_json::json!({"command": "gedit", "dry_run": true}).as_object().cloned(),
+        };
+        let rule = server.rule_for(&call("launch_app")).unwrap().unwrap();
+        assert!(matches!(rule.outcome, Outcome::Deny));
+        assert!(server.rule_for(&call("click")).unwrap().is_none());
+    }
+}
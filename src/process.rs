@@ -0,0 +1,216 @@
+//! Running external programs with a time limit.
+//!
+//! Every program a tool call runs (xdotool, xprop, xclip, ffmpeg,
+//! tesseract, the compositor tools) gets `command_timeout_secs` to finish.
+//! One that hangs, such as `search --sync` for a window that never appears,
+//! is killed and its call fails with the `timeouts` error code instead of
+//! holding up the others. An xdotool invocation that types or sleeps gets
+//! that time on top. Programs run as tokio child processes with
+//! `kill_on_drop`, so a timed-out one is killed as soon as its wait is
+//! abandoned; synchronous callers block in place, handing their runtime
+//! worker's other tasks to the rest of the runtime while they wait.
+
+use crate::{environment, timeouts};
+use rmcp::ErrorData as McpError;
+use std::future::Future;
+use std::io;
+use std::process::{Command, Output, Stdio};
+use std::sync::OnceLock;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::runtime::{Builder, Handle, Runtime, RuntimeFlavor};
+
+/// Until a server sets one
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+static TIMEOUT: OnceLock<Duration> = OnceLock::new();
+
+/// Set the time limit for this process. Later calls have no effect.
+pub fn set_timeout(timeout: Duration) {
+    let _ = TIMEOUT.set(timeout);
+}
+
+pub fn timeout() -> Duration {
+    *TIMEOUT.get().unwrap_or(&DEFAULT_TIMEOUT)
+}
+
+/// What a killed program failed with
+#[derive(Debug)]
+struct TimedOut(Duration);
+
+impl std::fmt::Display for TimedOut {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "no exit within {}s, killed", self.0.as_secs_f64())
+    }
+}
+
+impl std::error::Error for TimedOut {}
+
+fn timed_out(limit: Duration) -> io::Error {
+    io::Error::new(io::ErrorKind::TimedOut, TimedOut(limit))
+}
+
+/// The time limit a program was killed at, if that's what `e` says
+pub fn killed_after(e: &io::Error) -> Option<Duration> {
+    e.get_ref().and_then(|inner| inner.downcast_ref::<TimedOut>()).map(|timed_out| timed_out.0)
+}
+
+/// The error for a program that couldn't be run, or a timeout error if it was killed
+pub fn error(program: &str, e: io::Error) -> McpError {
+    match killed_after(&e) {
+        Some(limit) => timeouts::timeout_error(program, limit),
+        None if program == "xdotool" && e.kind() == io::ErrorKind::NotFound => environment::not_found_error(&e),
+        None => McpError::internal_error(format!("Failed to run {}: {}", program, e), None),
+    }
+}
+
+/// A runtime for callers outside one, or on a runtime whose worker can't be blocked
+fn fallback_runtime() -> &'static Runtime {
+    static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+    RUNTIME.get_or_init(|| Builder::new_multi_thread()
+        .worker_threads(1)
+        .thread_name("process")
+        .enable_all()
+        .build()
+        .expect("failed to start the process runtime"))
+}
+
+/// Finish `work` from synchronous code without stalling the other tasks on this runtime worker
+fn block_on<F: Future + Send>(work: F) -> F::Output
+where
+    F::Output: Send,
+{
+    match Handle::try_current() {
+        Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => {
+            tokio::task::block_in_place(|| handle.block_on(work))
+        }
+        // A current-thread runtime can't block inside itself, so wait on another thread
+        Ok(_) => std::thread::scope(|scope| scope.spawn(|| fallback_runtime().block_on(work)).join().unwrap()),
+        Err(_) => fallback_runtime().block_on(work),
+    }
+}
+
+/// The program, arguments, environment and working directory of `command`, as a tokio command
+fn tokio_command(command: &Command) -> tokio::process::Command {
+    let mut copy = tokio::process::Command::new(command.get_program());
+    copy.args(command.get_args());
+    for (key, value) in command.get_envs() {
+        match value {
+            Some(value) => { copy.env(key, value); }
+            None => { copy.env_remove(key); }
+        }
+    }
+    if let Some(dir) = command.get_current_dir() {
+        copy.current_dir(dir);
+    }
+    copy
+}
+
+/// Run `command` to completion, feeding it `stdin`. Dropping the future kills the program.
+/// Without `capture` its output goes nowhere, for programs that fork a child which keeps
+/// running (and would hold the pipes open).
+async fn run_async(mut command: tokio::process::Command, stdin: Option<&[u8]>, capture: bool) -> io::Result<Output> {
+    let pipe = || if capture { Stdio::piped() } else { Stdio::null() };
+    let mut child = command
+        .stdin(if stdin.is_some() { Stdio::piped() } else { Stdio::null() })
+        .stdout(pipe())
+        .stderr(pipe())
+        .kill_on_drop(true)
+        .spawn()?;
+    let pipe = child.stdin.take();
+    // The pipe closes when this finishes, so the program sees the end of its input
+    let write = async move {
+        match (pipe, stdin) {
+            (Some(mut pipe), Some(bytes)) => pipe.write_all(bytes).await,
+            _ => Ok(()),
+        }
+    };
+    let (written, output) = tokio::join!(write, child.wait_with_output());
+    let output = output?;
+    written?;
+    Ok(output)
+}
+
+fn run(command: &mut Command, stdin: Option<&[u8]>, capture: bool, limit: Duration) -> io::Result<Output> {
+    let command = tokio_command(command);
+    block_on(async move {
+        match tokio::time::timeout(limit, run_async(command, stdin, capture)).await {
+            Ok(output) => output,
+            Err(_) => Err(timed_out(limit)),
+        }
+    })
+}
+
+/// `Command::output`, within the time limit
+pub fn output(command: &mut Command) -> io::Result<Output> {
+    run(command, None, true, timeout())
+}
+
+/// `output` with a limit of its own
+pub fn output_within(command: &mut Command, limit: Duration) -> io::Result<Output> {
+    run(command, None, true, limit)
+}
+
+/// `output`, writing `stdin` to the program
+#[cfg(feature = "ocr")]
+pub fn output_with_stdin(command: &mut Command, stdin: &[u8]) -> io::Result<Output> {
+    run(command, Some(stdin), true, timeout())
+}
+
+/// Run `command` within the time limit, writing `stdin` to it and discarding its output.
+/// For programs like xclip that leave a child behind to serve what they were given.
+pub fn status_with_stdin(command: &mut Command, stdin: &[u8]) -> io::Result<std::process::ExitStatus> {
+    run(command, Some(stdin), false, timeout()).map(|output| output.status)
+}
+
+/// `output` for tokio commands, which also stops the program when the future is dropped
+pub async fn output_async(command: &mut tokio::process::Command, limit: Duration) -> io::Result<Output> {
+    let child = command.kill_on_drop(true).output();
+    match tokio::time::timeout(limit, child).await {
+        Ok(output) => output,
+        Err(_) => Err(timed_out(limit)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn programs_past_the_limit_are_killed() {
+        let started = std::time::Instant::now();
+        let e = output_within(Command::new("sleep").arg("5"), Duration::from_millis(100)).unwrap_err();
+        assert_eq!(killed_after(&e), Some(Duration::from_millis(100)));
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn stdin_environment_and_output() {
+        let output = run(Command::new("sh").args(["-c", "cat; echo $GREETING"]).env("GREETING", "hi"), Some(b"text\n"), true, timeout()).unwrap();
+        assert!(output.status.success());
+        assert_eq!(output.stdout, b"text\nhi\n");
+    }
+
+    #[tokio::test]
+    async fn runs_on_a_current_thread_runtime() {
+        let output = output(Command::new("sh").args(["-c", "echo out; echo err >&2; exit 3"])).unwrap();
+        assert_eq!(output.status.code(), Some(3));
+        assert_eq!((output.stdout, output.stderr), (b"out\n".to_vec(), b"err\n".to_vec()));
+    }
+
+    #[test]
+    fn programs_leaving_a_child_behind_return_when_they_exit() {
+        // Like xclip: reads its input, then forks a child that outlives it
+        let started = std::time::Instant::now();
+        let status = status_with_stdin(Command::new("sh").args(["-c", "cat >/dev/null; sleep 5 &"]), b"text").unwrap();
+        assert!(status.success());
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn runs_on_a_blocking_thread() {
+        // As window commands do, off the runtime
+        let output = tokio::task::spawn_blocking(|| output(Command::new("echo").arg("hi"))).await.unwrap().unwrap();
+        assert_eq!(output.stdout, b"hi\n");
+    }
+}
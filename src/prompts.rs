@@ -0,0 +1,104 @@
+//! Ready-made prompts for common workflows.
+//!
+//! Each prompt expands to a user message walking the model through one task
+//! with this server's tools, in the order that keeps it on the right window
+//! and away from irreversible mistakes: look first, act in small verified
+//! steps, and stop to ask when something unexpected shows up.
+
+use crate::XdotoolServer;
+use rmcp::{handler::server::wrapper::Parameters, model::*, ErrorData as McpError};
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct FillFormArgs {
+    #[schemars(description = "Title (or part of it) of the window holding the form")]
+    pub window: String,
+    #[schemars(description = "What to enter, e.g. one 'Field: value' per line")]
+    pub fields: String,
+    #[schemars(description = "'yes' to submit the form when done. Default: no")]
+    pub submit: Option<String>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct DescribeScreenArgs {
+    #[schemars(description = "What to pay attention to, e.g. 'error dialogs' or 'the toolbar'. Default: everything")]
+    pub focus: Option<String>,
+    #[schemars(description = "Title (or part of it) of a window to capture instead of the whole screen")]
+    pub window: Option<String>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct CloseAppWindowsArgs {
+    #[schemars(description = "WM_CLASS of the application, e.g. 'firefox' or 'gnome-terminal'")]
+    pub app: String,
+}
+
+fn user_message(text: String) -> Vec<PromptMessage> {
+    vec![PromptMessage::new_text(PromptMessageRole::User, text)]
+}
+
+#[rmcp::prompt_router(router = "prompt_router", vis = "pub(crate)")]
+impl XdotoolServer {
+    #[rmcp::prompt(name = "fill_form", description = "Fill in a form in a given window, field by field, checking each entry")]
+    pub async fn fill_form(&self, Parameters(args): Parameters<FillFormArgs>) -> Result<Vec<PromptMessage>, McpError> {
+        let submit = matches!(args.submit.as_deref().map(str::to_lowercase).as_deref(), Some("yes" | "true"));
+        let last_step = if submit {
+            "6. Take one more screenshot to confirm every field, then submit the form (its submit button or Return) and report what the window shows afterwards."
+        } else {
+            "6. Take one more screenshot to confirm every field and report the result. Do not submit the form."
+        };
+        Ok(user_message(format!(
+            "Fill in the form in the window titled \"{}\" with:\n\n{}\n\n\
+             1. Find the window with search_window (name search) and bring it to the front with \
+             xdotool_script [[\"windowactivate\", \"--sync\", \"<id>\"]]. If there are several matches or none, stop and ask me.\n\
+             2. take_screenshot with that window_id and locate each field. Window coordinates from \
+             get_window_geometry turn positions in the screenshot into screen coordinates.\n\
+             3. For each field: click_at the field, select any existing text with key_press ctrl+a, then type_text the value. \
+             Use secret: true for passwords.\n\
+             4. Use Tab only when you've checked it moves to the field you expect.\n\
+             5. If a field is missing, a value doesn't fit (e.g. a dropdown without that option) or a dialog appears, stop and ask me.\n\
+             {}",
+            args.window, args.fields, last_step
+        )))
+    }
+
+    #[rmcp::prompt(name = "describe_screen", description = "Take a screenshot marking recent clicks and describe what is on screen")]
+    pub async fn describe_screen_prompt(&self, Parameters(args): Parameters<DescribeScreenArgs>) -> Result<Vec<PromptMessage>, McpError> {
+        let capture = match &args.window {
+            Some(window) => format!(
+                "Find the window titled \"{}\" with search_window and take_screenshot with its window_id and markers: 5.",
+                window
+            ),
+            None => "take_screenshot of the whole screen with markers: 5.".to_string(),
+        };
+        let focus = match &args.focus {
+            Some(focus) => format!("Pay particular attention to: {}.", focus),
+            None => "Cover the whole image.".to_string(),
+        };
+        Ok(user_message(format!(
+            "{} The markers show where this server last clicked or typed (newest in red).\n\n\
+             Then describe the screenshot: which application windows are visible and which one has focus \
+             (get_active_window), the main content, any dialogs, errors or notifications, and what the \
+             recent clicks landed on. {} Only look; don't click or type anything.",
+            capture, focus
+        )))
+    }
+
+    #[rmcp::prompt(name = "close_app_windows", description = "Close every window of an application, checking for unsaved work first")]
+    pub async fn close_app_windows(&self, Parameters(args): Parameters<CloseAppWindowsArgs>) -> Result<Vec<PromptMessage>, McpError> {
+        Ok(user_message(format!(
+            "Close all windows of the application \"{}\" without losing any work.\n\n\
+             1. List its windows with search_window (search_type: class) and get_window_name for each. \
+             If there are none, say so and stop.\n\
+             2. Show me the list (window_thumbnails helps tell them apart) and wait for my go-ahead before closing anything.\n\
+             3. Then, one window at a time: activate it with xdotool_script [[\"windowactivate\", \"--sync\", \"<id>\"]], \
+             confirm with get_active_window that it is the one you meant, and key_press ctrl+w (or alt+F4 if the \
+             app has no tabs).\n\
+             4. After each close, take_screenshot. If a save/discard dialog or anything else unexpected appears, \
+             stop and ask me; never choose discard yourself.\n\
+             5. Finish by running search_window again and report which windows, if any, are still open.",
+            args.app
+        )))
+    }
+}
@@ -0,0 +1,126 @@
+//! One input action at a time, shared fairly between clients.
+//!
+//! Concurrent xdotool calls interleave their events (a `type` from one
+//! client lands in the middle of another's key chord), so every input tool
+//! call and scheduled batch takes a turn on this queue before it touches the
+//! desktop and holds it until it finishes. When the turn is released, it goes
+//! to the waiting client that was served least recently, oldest call first,
+//! so a client with a long backlog can't starve the others.
+
+use crate::control::cancelled_error;
+use crate::XdotoolServer;
+use rmcp::ErrorData as McpError;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::oneshot;
+use tokio_util::sync::CancellationToken;
+
+#[derive(Debug)]
+struct Waiter {
+    client: String,
+    ticket: u64,
+    wake: oneshot::Sender<()>,
+}
+
+#[derive(Debug, Default)]
+pub struct InputQueue {
+    /// Client holding the turn
+    holder: Option<String>,
+    waiting: Vec<Waiter>,
+    next_ticket: u64,
+    /// Turn number each client was last served at
+    last_served: HashMap<String, u64>,
+    turns: u64,
+}
+
+impl InputQueue {
+    fn grant(&mut self, client: String) {
+        self.turns += 1;
+        self.last_served.insert(client.clone(), self.turns);
+        self.holder = Some(client);
+    }
+
+    /// Hand the turn to the next waiter, or leave the queue idle
+    fn release(&mut self) {
+        self.holder = None;
+        while !self.waiting.is_empty() {
+            let next = (0..self.waiting.len())
+                .min_by_key(|&i| {
+                    let waiter = &self.waiting[i];
+                    (self.last_served.get(&waiter.client).copied().unwrap_or(0), waiter.ticket)
+                })
+                .unwrap_or(0);
+            let waiter = self.waiting.remove(next);
+            if waiter.wake.send(()).is_ok() {
+                self.grant(waiter.client);
+                return;
+            }
+        }
+    }
+
+    /// Who holds the turn and who is waiting, oldest first
+    pub fn status(&self) -> (Option<&str>, Vec<&str>) {
+        let mut waiting: Vec<&Waiter> = self.waiting.iter().collect();
+        waiting.sort_by_key(|waiter| waiter.ticket);
+        (self.holder.as_deref(), waiting.iter().map(|waiter| waiter.client.as_str()).collect())
+    }
+}
+
+/// The right to send input; the next client gets it when this is dropped
+pub struct Turn {
+    queue: Arc<Mutex<InputQueue>>,
+}
+
+impl Drop for Turn {
+    fn drop(&mut self) {
+        self.queue.lock().unwrap().release();
+    }
+}
+
+impl XdotoolServer {
+    /// Who this handle's calls are attributed to: its client, else its session
+    pub(crate) fn client_name(&self) -> String {
+        match (&self.client, &self.session) {
+            (Some(scope), _) => scope.name.clone(),
+            (None, Some(session)) => session.to_string(),
+            (None, None) => "stdio".to_string(),
+        }
+    }
+
+    /// A handle on the same server whose calls are attributed to `session`
+    pub fn in_session(&self, session: impl Into<Arc<str>>) -> Self {
+        let mut server = self.clone();
+        server.session = Some(session.into());
+        server
+    }
+
+    /// Wait for this client's turn to send input, giving up if `cancel` fires first
+    pub(crate) async fn input_turn(&self, cancel: &CancellationToken) -> Result<Turn, McpError> {
+        let client = self.client_name();
+        let (wake, woken) = oneshot::channel();
+        let ticket = {
+            let mut queue = self.input_queue.lock().unwrap();
+            if queue.holder.is_none() && queue.waiting.is_empty() {
+                queue.grant(client);
+                return Ok(Turn { queue: self.input_queue.clone() });
+            }
+            queue.next_ticket += 1;
+            let ticket = queue.next_ticket;
+            queue.waiting.push(Waiter { client, ticket, wake });
+            ticket
+        };
+
+        tokio::select! {
+            _ = woken => Ok(Turn { queue: self.input_queue.clone() }),
+            _ = cancel.cancelled() => {
+                let mut queue = self.input_queue.lock().unwrap();
+                match queue.waiting.iter().position(|waiter| waiter.ticket == ticket) {
+                    Some(i) => { queue.waiting.remove(i); }
+                    // Granted just as we gave up: pass it on
+                    None => queue.release(),
+                }
+                Err(cancelled_error())
+            }
+        }
+    }
+}
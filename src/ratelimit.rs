@@ -0,0 +1,237 @@
+//! Limits on how fast input can be sent, so a runaway agent loop can't
+//! hammer the desktop faster than a human could step in.
+//!
+//! Each limit is a sliding window over recent events. Direct tool calls are
+//! charged in the handler before they run; batch and macro steps are charged
+//! one at a time as they execute, so a long batch stops at the step that
+//! crosses a limit. Calls over a limit are refused rather than delayed.
+
+use crate::backend;
+use crate::batch::Step;
+use rmcp::{model::JsonObject, ErrorData as McpError};
+use serde::Deserialize;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Limits from the `[rate_limits]` config table. Unset means unlimited.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimits {
+    /// Tool calls of any kind
+    pub calls_per_second: Option<u32>,
+    /// Mouse clicks from click, click_at, double_click, batches and scripts
+    pub clicks_per_second: Option<u32>,
+    /// Key presses from key_press, batches and scripts
+    pub keys_per_second: Option<u32>,
+    /// Characters typed by type_text, batches and scripts
+    pub chars_per_minute: Option<u32>,
+}
+
+/// How much of each limit an action uses
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Cost {
+    pub calls: u32,
+    pub clicks: u32,
+    pub keys: u32,
+    pub chars: u32,
+}
+
+impl Cost {
+    /// Cost of a tool call, judged from its name and arguments. Dry runs only count as a call.
+    pub fn for_tool(name: &str, args: Option<&JsonObject>, dry_run: bool) -> Self {
+        let mut cost = Cost { calls: 1, ..Default::default() };
+        if dry_run {
+            return cost;
+        }
+        let arg = |key: &str| args.and_then(|args| args.get(key));
+        match name {
+            "click" => cost.clicks = 1,
+            "click_at" if arg("preview").and_then(|v| v.as_bool()) != Some(true) => cost.clicks = 1,
+            "click_element" | "smart_click" | "activate_menu" | "invoke_element_action" => cost.clicks = 1,
+            "double_click" => cost.clicks = 2,
+            "key_press" => cost.keys = 1,
+            "type_text" | "set_element_text" => cost.chars = arg("text").and_then(|v| v.as_str()).map_or(0, |text| count_chars(&[text])),
+            "xdotool_script" => {
+                let commands = arg("commands").and_then(|v| v.as_array()).into_iter().flatten();
+                for argv in commands.filter_map(|v| v.as_array()) {
+                    let words: Vec<&str> = argv.iter().filter_map(|v| v.as_str()).collect();
+                    match words.first() {
+                        Some(&"click") => cost.clicks = cost.clicks.saturating_add(click_repeat(&words)),
+                        Some(&"key") => cost.keys = cost.keys.saturating_add(words.len().saturating_sub(1).max(1) as u32),
+                        Some(&"type") => cost.chars = cost.chars.saturating_add(count_chars(&words[1..])),
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+        cost
+    }
+
+    /// Cost of one batch step
+    pub fn for_step(step: &Step) -> Self {
+        match step {
+            Step::Click { repeat, .. } => Cost { clicks: *repeat, ..Default::default() },
+            Step::Key { .. } => Cost { keys: 1, ..Default::default() },
+            Step::Type { text, .. } => Cost { chars: count_chars(&[text]), ..Default::default() },
+            _ => Cost::default(),
+        }
+    }
+}
+
+/// How many clicks an xdotool `click` command makes: its `--repeat`, or one
+fn click_repeat(words: &[&str]) -> u32 {
+    let (options, _) = backend::parse(words, &["--repeat", "--delay", "--window"]);
+    options.iter()
+        .find(|(option, _)| *option == "--repeat")
+        .and_then(|(_, repeat)| repeat.parse::<u64>().ok())
+        .map_or(1, |repeat| u32::try_from(repeat).unwrap_or(u32::MAX).max(1))
+}
+
+/// Characters in some text, saturating rather than wrapping
+fn count_chars(texts: &[&str]) -> u32 {
+    texts.iter().fold(0u32, |total, text| total.saturating_add(u32::try_from(text.chars().count()).unwrap_or(u32::MAX)))
+}
+
+/// A sliding-window limit of `max` units per `period`
+#[derive(Debug)]
+struct Window {
+    what: &'static str,
+    max: u32,
+    period: Duration,
+    events: VecDeque<(Instant, u32)>,
+}
+
+impl Window {
+    fn new(what: &'static str, max: Option<u32>, period: Duration) -> Option<Self> {
+        max.map(|max| Window { what, max, period, events: VecDeque::new() })
+    }
+
+    /// Drop expired events and check whether `amount` more fits
+    fn check(&mut self, amount: u32, now: Instant) -> Result<(), McpError> {
+        while self.events.front().is_some_and(|(at, _)| now.duration_since(*at) >= self.period) {
+            self.events.pop_front();
+        }
+        let used = self.events.iter().fold(0u32, |used, (_, n)| used.saturating_add(*n));
+        if amount == 0 || used.saturating_add(amount) <= self.max {
+            return Ok(());
+        }
+        let unit = if self.period == Duration::from_secs(60) { "minute" } else { "second" };
+        if amount > self.max {
+            return Err(McpError::internal_error(
+                format!("Rate limit: {} {} is more than the {} allowed per {}", amount, self.what, self.max, unit),
+                None
+            ));
+        }
+        let retry = self.events.front()
+            .map(|(at, _)| self.period.saturating_sub(now.duration_since(*at)))
+            .unwrap_or(self.period);
+        Err(McpError::internal_error(
+            format!(
+                "Rate limit: at most {} {} per {} (try again in {:.1}s)",
+                self.max, self.what, unit, retry.as_secs_f64()
+            ),
+            None
+        ))
+    }
+
+    fn record(&mut self, amount: u32, now: Instant) {
+        if amount > 0 {
+            self.events.push_back((now, amount));
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    calls: Option<Window>,
+    clicks: Option<Window>,
+    keys: Option<Window>,
+    chars: Option<Window>,
+}
+
+impl RateLimiter {
+    pub fn new(limits: &RateLimits) -> Self {
+        let second = Duration::from_secs(1);
+        RateLimiter {
+            calls: Window::new("tool calls", limits.calls_per_second, second),
+            clicks: Window::new("clicks", limits.clicks_per_second, second),
+            keys: Window::new("key presses", limits.keys_per_second, second),
+            chars: Window::new("typed characters", limits.chars_per_minute, Duration::from_secs(60)),
+        }
+    }
+
+    fn windows(&mut self, cost: Cost) -> [(&mut Option<Window>, u32); 4] {
+        [
+            (&mut self.calls, cost.calls),
+            (&mut self.clicks, cost.clicks),
+            (&mut self.keys, cost.keys),
+            (&mut self.chars, cost.chars),
+        ]
+    }
+
+    /// Whether `cost` fits in every limit, without recording it
+    pub fn check(&mut self, cost: Cost, now: Instant) -> Result<(), McpError> {
+        for (window, amount) in self.windows(cost) {
+            if let Some(window) = window {
+                window.check(amount, now)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Record `cost` against every limit; call after `check` has passed
+    pub fn record(&mut self, cost: Cost, now: Instant) {
+        for (window, amount) in self.windows(cost) {
+            if let Some(window) = window {
+                window.record(amount, now);
+            }
+        }
+    }
+
+    /// Record `cost` if it fits in every limit, otherwise refuse it without recording anything
+    pub fn charge(&mut self, cost: Cost) -> Result<(), McpError> {
+        let now = Instant::now();
+        self.check(cost, now)?;
+        self.record(cost, now);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn script(commands: serde_json::Value) -> Cost {
+        let args = json!({"commands": commands});
+        Cost::for_tool("xdotool_script", args.as_object(), false)
+    }
+
+    #[test]
+    fn script_clicks_count_their_repeats() {
+        assert_eq!(script(json!([["click", "1"]])).clicks, 1);
+        assert_eq!(script(json!([["click", "--repeat", "5", "1"], ["click", "3"]])).clicks, 6);
+        assert_eq!(script(json!([["click", "--repeat", "99999999999", "1"]])).clicks, u32::MAX);
+        assert_eq!(script(json!([["key", "a", "b"], ["type", "abc", "de"]])).keys, 2);
+        assert_eq!(script(json!([["type", "abc", "de"]])).chars, 5);
+    }
+
+    #[test]
+    fn huge_costs_are_refused_not_wrapped() {
+        let mut limiter = RateLimiter::new(&RateLimits { clicks_per_second: Some(10), ..Default::default() });
+        limiter.charge(Cost { clicks: 5, ..Default::default() }).unwrap();
+        assert!(limiter.charge(Cost { clicks: u32::MAX, ..Default::default() }).is_err());
+        assert!(limiter.charge(Cost { clicks: u32::MAX - 2, ..Default::default() }).is_err());
+        limiter.charge(Cost { clicks: 5, ..Default::default() }).unwrap();
+        assert!(limiter.charge(Cost { clicks: 1, ..Default::default() }).is_err());
+    }
+
+    #[test]
+    fn batch_click_repeats_are_charged_in_full() {
+        let step: Step = serde_json::from_value(json!({"action": "click", "repeat": u32::MAX})).unwrap();
+        let mut limiter = RateLimiter::new(&RateLimits { clicks_per_second: Some(10), ..Default::default() });
+        limiter.charge(Cost { clicks: 1, ..Default::default() }).unwrap();
+        assert!(limiter.charge(Cost::for_step(&step)).is_err());
+    }
+}
@@ -0,0 +1,172 @@
+//! Screen recording via ffmpeg's x11grab input.
+//!
+//! One recording at a time. ffmpeg is stopped by sending `q` on stdin so it
+//! finalizes the container instead of leaving a truncated file behind.
+
+use crate::control::sleep_or_cancel;
+use crate::display;
+use crate::output;
+use crate::XdotoolServer;
+use rmcp::{
+    handler::server::wrapper::Parameters,
+    model::*,
+    service::{RequestContext, RoleServer},
+    ErrorData as McpError,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Child, Stdio};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// How often stop_recording checks whether ffmpeg has finished
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct StartRecordingParams {
+    #[schemars(description = "Output file path. Default: a timestamped file in the temp directory")]
+    pub path: Option<String>,
+    #[schemars(description = "Container format: 'mp4' or 'webm'. Default: mp4 (ignored if path has an extension)")]
+    #[serde(default = "default_format")]
+    pub format: String,
+    #[schemars(description = "Frames per second. Default: 15")]
+    #[serde(default = "default_framerate")]
+    pub framerate: u32,
+}
+
+fn default_format() -> String { "mp4".to_string() }
+
+fn default_framerate() -> u32 { 15 }
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct RecordingStarted {
+    /// The video file being written
+    pub path: String,
+    pub width: u32,
+    pub height: u32,
+    pub framerate: u32,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct RecordingSaved {
+    pub path: String,
+    /// Length of the recording in seconds
+    pub seconds: f64,
+}
+
+#[derive(Debug)]
+pub struct Recording {
+    child: Child,
+    path: PathBuf,
+    started: Instant,
+}
+
+fn codec_args(format: &str) -> Option<&'static [&'static str]> {
+    match format {
+        "mp4" => Some(&["-c:v", "libx264", "-preset", "ultrafast", "-pix_fmt", "yuv420p"]),
+        "webm" => Some(&["-c:v", "libvpx-vp9", "-deadline", "realtime", "-cpu-used", "8"]),
+        _ => None,
+    }
+}
+
+#[rmcp::tool_router(router = recording_router, vis = "pub(crate)")]
+impl XdotoolServer {
+    #[rmcp::tool(description = "Start recording the screen to an mp4/webm video file (requires ffmpeg). Only one recording can run at a time.", annotations(read_only_hint = false, destructive_hint = true), output_schema = output::schema::<RecordingStarted>())]
+    pub async fn start_recording(
+        &self,
+        Parameters(params): Parameters<StartRecordingParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let mut recording = self.recording.lock().unwrap();
+        if let Some(active) = recording.as_ref() {
+            return Err(McpError::internal_error(
+                format!("Already recording to {}", active.path.display()),
+                None
+            ));
+        }
+
+        let path = match params.path {
+            Some(path) => PathBuf::from(path),
+            None => {
+                let stamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+                std::env::temp_dir().join(format!("rmcp-xdotool-{}.{}", stamp, params.format))
+            }
+        };
+        let format = path.extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase())
+            .unwrap_or(params.format.to_lowercase());
+        let codec = codec_args(&format).ok_or_else(|| McpError::internal_error(
+            "Invalid format. Use: mp4, webm",
+            None
+        ))?;
+
+        let (width, height) = Self::display_geometry()?;
+        let display = Self::x_display();
+
+        let child = display::command("ffmpeg")
+            .args([
+                "-y", "-loglevel", "error",
+                "-f", "x11grab",
+                "-framerate", &params.framerate.to_string(),
+                "-video_size", &format!("{}x{}", width, height),
+                "-i", &display,
+            ])
+            .args(codec)
+            .arg(&path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| McpError::internal_error(format!("Failed to run ffmpeg: {}", e), None))?;
+
+        let message = format!("Recording {}x{} at {} fps to {}", width, height, params.framerate, path.display());
+        let started = RecordingStarted { path: path.display().to_string(), width, height, framerate: params.framerate };
+        *recording = Some(Recording { child, path, started: Instant::now() });
+
+        Ok(output::structured(message, &started))
+    }
+
+    #[rmcp::tool(description = "Stop the active screen recording and return the path of the video file", annotations(read_only_hint = false, destructive_hint = false), output_schema = output::schema::<RecordingSaved>())]
+    pub async fn stop_recording(&self, context: RequestContext<RoleServer>) -> Result<CallToolResult, McpError> {
+        let Some(mut active) = self.recording.lock().unwrap().take() else {
+            return Err(McpError::internal_error("No recording in progress", None));
+        };
+
+        if let Some(mut stdin) = active.child.stdin.take() {
+            let _ = stdin.write_all(b"q");
+        }
+        // Finalizing can take a moment; if the client gives up, kill and reap ffmpeg rather than leave it behind
+        let status = loop {
+            match active.child.try_wait() {
+                Ok(Some(status)) => break status,
+                Ok(None) => {}
+                Err(e) => return Err(McpError::internal_error(format!("Failed to stop ffmpeg: {}", e), None)),
+            }
+            if sleep_or_cancel(STOP_POLL_INTERVAL, &context.ct).await.is_err() {
+                let _ = active.child.kill();
+                let _ = active.child.wait();
+                return Err(McpError::internal_error(
+                    format!("Cancelled; ffmpeg was killed and {} may be incomplete", active.path.display()),
+                    None
+                ));
+            }
+        };
+
+        // ffmpeg exits non-zero when it dies before writing anything, e.g. no X access
+        if !status.success() && !active.path.exists() {
+            return Err(McpError::internal_error(
+                format!("ffmpeg exited with {} and produced no file", status),
+                None
+            ));
+        }
+
+        let saved = RecordingSaved {
+            path: active.path.display().to_string(),
+            seconds: active.started.elapsed().as_secs_f64(),
+        };
+        Ok(output::structured(
+            format!("Recording saved to {} ({:.1}s)", saved.path, saved.seconds),
+            &saved
+        ))
+    }
+}
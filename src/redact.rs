@@ -0,0 +1,259 @@
+//! Keeping typed text out of the audit log and the server log.
+//!
+//! By default text that gets typed or set (type_text, set_element_text, `type`
+//! batch steps and script commands, clipboard contents, macro params and the
+//! steps of imported macros) is replaced with its length wherever the server
+//! records it. The `heuristic` setting shows text unless it looks like a
+//! password or token or matches a configured pattern, and `visible` shows
+//! everything except calls marked `secret`. Unless text is visible, rmcp's
+//! request/response debug logging is capped at info too.
+
+use regex_automata::meta::Regex;
+use rmcp::model::JsonObject;
+use serde::Deserialize;
+use serde_json::Value;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TypedText {
+    /// Always replaced with its length
+    #[default]
+    Hidden,
+    /// Shown unless it looks secret
+    Heuristic,
+    /// Shown unless the call is marked secret
+    Visible,
+}
+
+/// The `[redaction]` config table
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Redaction {
+    pub typed_text: TypedText,
+    /// Regexes that mark text as secret in heuristic mode
+    pub secret_patterns: Vec<String>,
+}
+
+#[derive(Debug)]
+pub struct Redactor {
+    mode: TypedText,
+    patterns: Vec<Regex>,
+}
+
+/// Passwords and tokens: a single word mixing character classes, or a long run of hex/base64
+fn looks_secret(text: &str) -> bool {
+    if text.is_empty() || text.len() > 256 || text.chars().any(char::is_whitespace) {
+        return false;
+    }
+    let classes = [
+        text.chars().any(|c| c.is_ascii_lowercase()),
+        text.chars().any(|c| c.is_ascii_uppercase()),
+        text.chars().any(|c| c.is_ascii_digit()),
+        text.chars().any(|c| !c.is_ascii_alphanumeric()),
+    ];
+    let mixed = text.len() >= 8 && classes.iter().filter(|&&class| class).count() >= 3;
+    let token = text.len() >= 20
+        && text.chars().all(|c| c.is_ascii_alphanumeric() || "+/=_-".contains(c))
+        && text.chars().any(|c| c.is_ascii_digit());
+    mixed || token
+}
+
+impl Redactor {
+    pub fn new(config: &Redaction) -> anyhow::Result<Self> {
+        let patterns = config.secret_patterns.iter()
+            .map(|pattern| Regex::new(pattern).map_err(|e| anyhow::anyhow!("Invalid secret pattern '{}': {}", pattern, e)))
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Redactor { mode: config.typed_text, patterns })
+    }
+
+    /// Whether rmcp's debug logging of full requests is safe to leave on
+    pub fn shows_text(&self) -> bool {
+        self.mode == TypedText::Visible
+    }
+
+    fn hides(&self, text: &str, secret: bool) -> bool {
+        secret || match self.mode {
+            TypedText::Hidden => true,
+            TypedText::Heuristic => looks_secret(text) || self.patterns.iter().any(|pattern| pattern.is_match(text)),
+            TypedText::Visible => false,
+        }
+    }
+
+    /// Text as it may appear in logs
+    pub fn show(&self, text: &str, secret: bool) -> String {
+        if self.hides(text, secret) {
+            format!("[{} characters redacted]", text.chars().count())
+        } else {
+            text.to_string()
+        }
+    }
+
+    /// Tool arguments with typed text redacted, plus the original strings that were hidden
+    pub fn arguments(&self, tool: &str, arguments: &Option<JsonObject>) -> (Option<JsonObject>, Vec<String>) {
+        let Some(arguments) = arguments else {
+            return (None, vec![]);
+        };
+        let mut arguments = arguments.clone();
+        let mut hidden = vec![];
+        let secret = arguments.get("secret").and_then(Value::as_bool).unwrap_or(false);
+        let mut hide = |value: &mut Value| {
+            if let Value::String(text) = value {
+                if self.hides(text, secret) {
+                    hidden.push(text.clone());
+                    *value = Value::String(self.show(text, true));
+                }
+            }
+        };
+
+        match tool {
+            "type_text" | "set_element_text" | "set_clipboard" | "set_primary_selection" => {
+                if let Some(text) = arguments.get_mut("text") {
+                    hide(text);
+                }
+            }
+            "execute_batch" | "schedule_batch" | "save_macro" => {
+                if let Some(Value::Array(steps)) = arguments.get_mut("steps") {
+                    redact_steps(steps, &mut hide);
+                }
+            }
+            "import_macro" => {
+                if let Some(json) = arguments.get_mut("json") {
+                    let document = json.as_str().and_then(|json| serde_json::from_str::<Value>(json).ok());
+                    match document {
+                        Some(mut document) => {
+                            // An export envelope keeps the macro under "macro"
+                            let pointer = if document.get("macro").is_some() { "/macro/steps" } else { "/steps" };
+                            if let Some(Value::Array(steps)) = document.pointer_mut(pointer) {
+                                redact_steps(steps, &mut hide);
+                            }
+                            *json = Value::String(document.to_string());
+                        }
+                        // Don't guess at what's in a document that doesn't parse
+                        None => hide(json),
+                    }
+                }
+            }
+            "replay_macro" => {
+                if let Some(Value::Object(params)) = arguments.get_mut("params") {
+                    params.values_mut().for_each(&mut hide);
+                }
+            }
+            "xdotool_script" => {
+                if let Some(Value::Array(commands)) = arguments.get_mut("commands") {
+                    for command in commands.iter_mut().filter_map(Value::as_array_mut) {
+                        if command.first().and_then(Value::as_str) == Some("type") {
+                            command.iter_mut().skip(1).for_each(&mut hide);
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+        (Some(arguments), hidden)
+    }
+}
+
+/// Redact the text of `type` steps, including inside repeat blocks
+fn redact_steps(steps: &mut [Value], hide: &mut impl FnMut(&mut Value)) {
+    for step in steps.iter_mut().filter_map(Value::as_object_mut) {
+        match step.get("action").and_then(Value::as_str) {
+            Some("type") => {
+                if let Some(text) = step.get_mut("text") {
+                    hide(text);
+                }
+            }
+            Some("repeat") => {
+                if let Some(Value::Array(steps)) = step.get_mut("steps") {
+                    redact_steps(steps, hide);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn redactor(typed_text: TypedText) -> Redactor {
+        Redactor::new(&Redaction { typed_text, secret_patterns: vec!["^sk-".to_string()] }).unwrap()
+    }
+
+    fn arguments(value: Value) -> Option<JsonObject> {
+        value.as_object().cloned()
+    }
+
+    #[test]
+    fn secrets_are_recognised() {
+        assert!(looks_secret("hunter2!Pass"));
+        assert!(looks_secret("ghp_0123456789abcdefABCDEF"));
+        assert!(!looks_secret("hello world"));
+        assert!(!looks_secret("password"));
+        assert!(!looks_secret(""));
+    }
+
+    #[test]
+    fn modes_decide_what_is_shown() {
+        assert_eq!(redactor(TypedText::Hidden).show("hello", false), "[5 characters redacted]");
+        assert_eq!(redactor(TypedText::Heuristic).show("hello", false), "hello");
+        assert_eq!(redactor(TypedText::Heuristic).show("sk-live", false), "[7 characters redacted]");
+        assert_eq!(redactor(TypedText::Heuristic).show("hunter2!Pass", false), "[12 characters redacted]");
+        assert_eq!(redactor(TypedText::Visible).show("hunter2!Pass", false), "hunter2!Pass");
+        assert_eq!(redactor(TypedText::Visible).show("hello", true), "[5 characters redacted]");
+    }
+
+    #[test]
+    fn typed_text_and_nested_steps_are_hidden() {
+        let redactor = redactor(TypedText::Hidden);
+        let (redacted, hidden) = redactor.arguments("type_text", &arguments(json!({"text": "hello", "delay_ms": 12})));
+        assert_eq!(Value::Object(redacted.unwrap()), json!({"text": "[5 characters redacted]", "delay_ms": 12}));
+        assert_eq!(hidden, ["hello"]);
+
+        let steps = json!({"steps": [
+            {"action": "click", "button": 1},
+            {"action": "repeat", "times": 2, "steps": [{"action": "type", "text": "abc"}]},
+        ]});
+        let (redacted, hidden) = redactor.arguments("execute_batch", &arguments(steps));
+        assert_eq!(redacted.unwrap()["steps"][1]["steps"][0]["text"], "[3 characters redacted]");
+        assert_eq!(hidden, ["abc"]);
+
+        let script = json!({"commands": [["type", "--delay", "secret"], ["key", "Return"]]});
+        let (redacted, _) = redactor.arguments("xdotool_script", &arguments(script));
+        assert_eq!(redacted.unwrap()["commands"], json!([["type", "[7 characters redacted]", "[6 characters redacted]"], ["key", "Return"]]));
+    }
+
+    #[test]
+    fn imported_macros_are_redacted() {
+        let redactor = redactor(TypedText::Hidden);
+        let export = json!({"format": "rmcp-xdotool-macro", "version": 1, "macro": {
+            "name": "login",
+            "steps": [{"action": "type", "text": "hunter2"}, {"action": "key", "keys": "Return"}],
+        }});
+        let (redacted, hidden) = redactor.arguments("import_macro", &arguments(json!({"json": export.to_string()})));
+        let json = redacted.unwrap()["json"].as_str().unwrap().to_string();
+        assert!(!json.contains("hunter2"));
+        let document: Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(document["macro"]["steps"][0]["text"], "[7 characters redacted]");
+        assert_eq!(document["macro"]["steps"][1]["keys"], "Return");
+        assert_eq!(hidden, ["hunter2"]);
+
+        let bare = json!({"name": "login", "steps": [{"action": "type", "text": "hunter2"}]});
+        let (redacted, _) = redactor.arguments("import_macro", &arguments(json!({"json": bare.to_string()})));
+        assert!(!redacted.unwrap()["json"].as_str().unwrap().contains("hunter2"));
+
+        let (redacted, hidden) = redactor.arguments("import_macro", &arguments(json!({"json": "{\"steps\": [hunter2"})));
+        assert_eq!(redacted.unwrap()["json"], "[18 characters redacted]");
+        assert_eq!(hidden.len(), 1);
+    }
+
+    #[test]
+    fn visible_text_is_left_alone() {
+        let (redacted, hidden) = redactor(TypedText::Visible).arguments("type_text", &arguments(json!({"text": "hello"})));
+        assert_eq!(redacted.unwrap()["text"], "hello");
+        assert!(hidden.is_empty());
+        let (redacted, _) = redactor(TypedText::Visible).arguments("type_text", &arguments(json!({"text": "hello", "secret": true})));
+        assert_eq!(redacted.unwrap()["text"], "[5 characters redacted]");
+    }
+}
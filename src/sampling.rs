@@ -0,0 +1,109 @@
+//! Screen descriptions from the client's own model, via MCP sampling.
+//!
+//! `describe_screen` takes a screenshot like take_screenshot and sends it to
+//! the client in a `sampling/createMessage` request, asking its model to
+//! describe the screen or locate what the caller asked about. The answer comes
+//! back next to the screenshot, so an agent without vision, or one saving its
+//! context, can work from text. The model is told where the image sits on
+//! screen so the coordinates it gives can be clicked directly. Clients
+//! decide whether to run the request at all; those without sampling get an
+//! error.
+
+use crate::control::cancelled_error;
+use crate::screenshot::{Screenshot, TakeScreenshotParams};
+use crate::{output, XdotoolServer};
+use base64::Engine;
+use rmcp::{
+    handler::server::wrapper::Parameters,
+    model::*,
+    service::{RequestContext, RoleServer},
+    ErrorData as McpError,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct DescribeScreenParams {
+    #[schemars(description = "What to find out, e.g. 'Where is the Save button?' or 'Is an error dialog showing?'. Default: a general description")]
+    pub question: Option<String>,
+    #[schemars(description = "Most tokens the client's model may use for the answer. Default: 1000")]
+    #[serde(default = "default_max_tokens")]
+    pub max_tokens: u32,
+    #[serde(flatten)]
+    pub screenshot: TakeScreenshotParams,
+}
+
+fn default_max_tokens() -> u32 { 1000 }
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ScreenDescription {
+    /// The client model's answer
+    pub description: String,
+    /// Model that answered, as the client reports it
+    pub model: String,
+    pub screenshot: Screenshot,
+}
+
+/// Instructions for the client's model, placing the image on screen
+fn system_prompt(screenshot: &Screenshot) -> String {
+    let region = screenshot.region;
+    format!(
+        "You are looking at a screenshot of part of a Linux desktop: the {}x{} pixel area whose top-left corner \
+         is at screen position ({}, {}). Describe only what is visible; say so when something is unclear or \
+         not shown. When you give the position of an element, give the screen coordinates of its center, \
+         i.e. its pixel position in the image plus ({}, {}).",
+        region.width, region.height, region.x, region.y, region.x, region.y
+    )
+}
+
+#[rmcp::tool_router(router = sampling_router, vis = "pub(crate)")]
+impl XdotoolServer {
+    #[rmcp::tool(description = "Take a screenshot and have the client's model describe it or locate elements in it (MCP sampling), returning its answer with screen coordinates next to the image. Needs a client that supports sampling.", annotations(read_only_hint = true), output_schema = output::schema::<ScreenDescription>())]
+    pub async fn describe_screen(
+        &self,
+        Parameters(params): Parameters<DescribeScreenParams>,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let sampling = context.peer.peer_info().is_some_and(|info| info.capabilities.sampling.is_some());
+        if !sampling {
+            return Err(McpError::internal_error(
+                "describe_screen needs a client that supports sampling; use take_screenshot instead",
+                None
+            ));
+        }
+
+        let (png, mut content, screenshot) = self.capture_screenshot(&params.screenshot)?;
+        let question = params.question.as_deref()
+            .unwrap_or("Describe this screen: the visible windows and which has focus, their main content, and any dialogs, errors or notifications.");
+        let image = Content::image(base64::engine::general_purpose::STANDARD.encode(&png), "image/png");
+        let request = CreateMessageRequestParam {
+            messages: vec![
+                SamplingMessage { role: Role::User, content: image },
+                SamplingMessage { role: Role::User, content: Content::text(question) },
+            ],
+            model_preferences: None,
+            system_prompt: Some(system_prompt(&screenshot)),
+            include_context: Some(ContextInclusion::None),
+            temperature: None,
+            max_tokens: params.max_tokens,
+            stop_sequences: None,
+            metadata: None,
+        };
+        let result = tokio::select! {
+            result = context.peer.create_message(request) => result.map_err(|e| McpError::internal_error(
+                format!("Sampling request failed: {}", e),
+                None
+            ))?,
+            _ = context.ct.cancelled() => return Err(cancelled_error()),
+        };
+
+        let Some(description) = result.message.content.as_text().map(|text| text.text.clone()) else {
+            return Err(McpError::internal_error(
+                format!("The client's model ({}) answered without text", result.model),
+                None
+            ));
+        };
+        content.insert(0, Content::text(format!("{} describes the screen:\n{}", result.model, description)));
+        Ok(output::with_content(content, &ScreenDescription { description, model: result.model, screenshot }))
+    }
+}
@@ -0,0 +1,168 @@
+//! Batches queued to run later, e.g. "in 30 seconds, dismiss the dialog that
+//! will appear".
+//!
+//! A scheduled batch is registered with the action registry as soon as it is
+//! queued, so `list_actions` shows it and `cancel_action` cancels it whether it
+//! is still waiting or already running. It runs in a background task, detached
+//! from the request that scheduled it, and its outcome goes to the server log.
+
+use crate::batch::{default_step_delay, dry_run_report, plan_steps, RunOptions, Step};
+use crate::control::{sleep_or_cancel, ActionRegistry};
+use crate::output;
+use crate::XdotoolServer;
+use rmcp::{handler::server::wrapper::Parameters, model::*, ErrorData as McpError};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio_util::sync::CancellationToken;
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ScheduleBatchParams {
+    #[schemars(description = "Ordered steps, in the same format as execute_batch")]
+    pub steps: Vec<Step>,
+    #[schemars(description = "Run after this many milliseconds. Give either this or 'at'")]
+    pub delay_ms: Option<u64>,
+    #[schemars(description = "Run at this Unix timestamp in seconds, e.g. 1767225600.5. Give either this or 'delay_ms'")]
+    pub at: Option<f64>,
+    #[schemars(description = "Pause between steps in milliseconds. Default: 50")]
+    #[serde(default = "default_step_delay")]
+    pub step_delay_ms: u64,
+    #[schemars(description = "ID to register the batch under for list_actions/cancel_action. Default: generated")]
+    pub id: Option<String>,
+    #[schemars(description = "Validate and list the xdotool commands that would run, without scheduling them. Default: the server's dry_run setting")]
+    pub dry_run: Option<bool>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ScheduledBatch {
+    /// ID for list_actions and cancel_action; absent in dry runs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub steps: usize,
+    /// Seconds until the batch starts
+    pub starts_in_seconds: f64,
+    /// The commands that would run, in dry runs. Steps inside repeat blocks are indented.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dry_run: Option<Vec<String>>,
+}
+
+/// How far ahead a batch may be scheduled
+const MAX_AHEAD: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// How long to wait before running, from a relative delay or an absolute timestamp
+fn schedule_delay(delay_ms: Option<u64>, at: Option<f64>) -> Result<Duration, McpError> {
+    let delay = match (delay_ms, at) {
+        (Some(ms), None) => Duration::from_millis(ms),
+        (None, Some(at)) => {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs_f64();
+            if !at.is_finite() || at < now {
+                return Err(McpError::internal_error(
+                    format!("'at' ({}) is not a future Unix timestamp (now is {:.1})", at, now),
+                    None
+                ));
+            }
+            // Past MAX_AHEAD, which the check below rejects, when too far off for a Duration
+            Duration::try_from_secs_f64(at - now).unwrap_or(Duration::MAX)
+        }
+        _ => return Err(McpError::internal_error("Give exactly one of 'delay_ms' or 'at'", None)),
+    };
+    if delay > MAX_AHEAD {
+        return Err(McpError::internal_error(
+            format!("Batches can be scheduled at most {} days ahead", MAX_AHEAD.as_secs() / 86400),
+            None
+        ));
+    }
+    Ok(delay)
+}
+
+#[rmcp::tool_router(router = schedule_router, vis = "pub(crate)")]
+impl XdotoolServer {
+    #[rmcp::tool(description = "Queue a batch of steps (execute_batch format) to run after a delay or at a Unix timestamp, and return immediately. Scheduled batches show up in list_actions and can be cancelled with cancel_action.", annotations(read_only_hint = false, destructive_hint = true), output_schema = output::schema::<ScheduledBatch>())]
+    pub async fn schedule_batch(
+        &self,
+        Parameters(params): Parameters<ScheduleBatchParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let delay = schedule_delay(params.delay_ms, params.at)?;
+        self.check_batch_limits(&params.steps)?;
+        plan_steps(&params.steps)?;
+
+        if params.dry_run.unwrap_or(self.config.dry_run) {
+            let lines = dry_run_report(&params.steps)?;
+            return Ok(output::structured(
+                format!(
+                    "Dry run, {} step(s) valid. Would run in {:.1}s:\n{}",
+                    params.steps.len(), delay.as_secs_f64(), lines.join("\n")
+                ),
+                &ScheduledBatch {
+                    id: None,
+                    steps: params.steps.len(),
+                    starts_in_seconds: delay.as_secs_f64(),
+                    dry_run: Some(lines),
+                }
+            ));
+        }
+
+        let label = format!("scheduled batch of {} step(s)", params.steps.len());
+        let start = Instant::now() + delay;
+        // Not tied to this request: it finishes as soon as the batch is queued
+        let guard = ActionRegistry::register_at(&self.actions, params.id, label, &CancellationToken::new(), start)?;
+        let id = guard.id.clone();
+        let options = RunOptions {
+            delay: Duration::from_millis(params.step_delay_ms),
+            cancel: guard.token.clone(),
+            ..Default::default()
+        };
+
+        let server = self.clone();
+        let scheduled = ScheduledBatch {
+            id: Some(id.clone()),
+            steps: params.steps.len(),
+            starts_in_seconds: delay.as_secs_f64(),
+            dry_run: None,
+        };
+        let steps = params.steps;
+        tokio::spawn(async move {
+            let result = async {
+                sleep_or_cancel(delay, &guard.token).await?;
+                let _turn = server.input_turn(&guard.token).await?;
+                server.run_cancellable(&guard, &steps, &options).await
+            }.await;
+            match result {
+                Ok(log) => tracing::info!("Scheduled action {} ran {} step(s)", guard.id, log.len()),
+                Err(e) => tracing::warn!("Scheduled action {} did not complete: {}", guard.id, e.message),
+            }
+        });
+
+        Ok(output::structured(
+            format!("Scheduled {} to run in {:.1}s. Cancel it with cancel_action.", id, delay.as_secs_f64()),
+            &scheduled
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn now() -> f64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs_f64()
+    }
+
+    #[test]
+    fn delay_from_either_argument() {
+        assert_eq!(schedule_delay(Some(1500), None).unwrap(), Duration::from_millis(1500));
+        let delay = schedule_delay(None, Some(now() + 60.0)).unwrap();
+        assert!(delay > Duration::from_secs(59) && delay <= Duration::from_secs(60));
+        assert!(schedule_delay(None, None).is_err());
+        assert!(schedule_delay(Some(1), Some(now() + 1.0)).is_err());
+    }
+
+    #[test]
+    fn rejects_past_and_unrepresentable_times() {
+        for at in [now() - 10.0, f64::NAN, f64::INFINITY, 1e300] {
+            assert!(schedule_delay(None, Some(at)).is_err(), "{}", at);
+        }
+        assert!(schedule_delay(Some(u64::MAX), None).is_err());
+        assert!(schedule_delay(None, Some(now() + 8.0 * 86400.0)).is_err());
+    }
+}
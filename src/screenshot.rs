@@ -0,0 +1,504 @@
+//! Screenshots via ffmpeg's x11grab input, returned inline and kept in a small
+//! in-memory cache that is exposed as `screenshot://<id>` MCP resources.
+//! Recent actions from the history can be drawn on top with ffmpeg's drawbox.
+
+use crate::control::sleep_or_cancel;
+use crate::display;
+use crate::history::Action;
+use crate::listing;
+use crate::output;
+use crate::process;
+use crate::XdotoolServer;
+use base64::Engine;
+use rmcp::{
+    handler::server::wrapper::Parameters,
+    model::*,
+    service::{RequestContext, RoleServer},
+    ErrorData as McpError,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Number of screenshots kept for resource reads before the oldest is evicted
+const CACHE_CAPACITY: usize = 16;
+
+/// Pause between frames compared by wait_for_stable_screen
+const FRAME_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Size of the crop taken around a point for click previews
+const PREVIEW_SIZE: (u32, u32) = (640, 480);
+
+pub const URI_PREFIX: &str = "screenshot://";
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct TakeScreenshotParams {
+    #[schemars(description = "Capture only this window (from search_window or get_active_window)")]
+    pub window_id: Option<String>,
+    #[schemars(description = "Region left edge. Requires y, width and height")]
+    pub x: Option<i32>,
+    #[schemars(description = "Region top edge")]
+    pub y: Option<i32>,
+    #[schemars(description = "Region width")]
+    pub width: Option<u32>,
+    #[schemars(description = "Region height")]
+    pub height: Option<u32>,
+    #[schemars(description = "Draw markers for the last N clicks/typing positions performed by this server (newest in red, older in yellow). Default: 0")]
+    #[serde(default)]
+    pub markers: usize,
+    #[schemars(description = "Include the image inline in the result. Set false to only get a screenshot:// resource link. Default: true")]
+    #[serde(default = "default_inline")]
+    pub inline: bool,
+}
+
+fn default_inline() -> bool { true }
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct WaitForStableScreenParams {
+    #[schemars(description = "Watch only this window (from search_window or get_active_window)")]
+    pub window_id: Option<String>,
+    #[schemars(description = "Region left edge. Requires y, width and height")]
+    pub x: Option<i32>,
+    #[schemars(description = "Region top edge")]
+    pub y: Option<i32>,
+    #[schemars(description = "Region width")]
+    pub width: Option<u32>,
+    #[schemars(description = "Region height")]
+    pub height: Option<u32>,
+    #[schemars(description = "Milliseconds the region must stay unchanged. Default: 500")]
+    #[serde(default = "default_quiet_ms")]
+    pub quiet_ms: u64,
+    #[schemars(description = "Pixels that may still differ, e.g. for a blinking text cursor. Default: 0")]
+    #[serde(default)]
+    pub tolerance: usize,
+    #[schemars(description = "Seconds to wait before giving up. Default: 10")]
+    #[serde(default = "default_stable_timeout")]
+    pub timeout_seconds: u64,
+}
+
+fn default_quiet_ms() -> u64 { 500 }
+
+fn default_stable_timeout() -> u64 { 10 }
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct WindowThumbnailsParams {
+    #[schemars(description = "Thumbnail width in pixels (height keeps the aspect ratio). Default: 320")]
+    #[serde(default = "default_thumbnail_width")]
+    pub width: u32,
+    #[serde(flatten)]
+    pub filter: listing::WindowFilterParams,
+    #[serde(flatten)]
+    pub page: listing::PageParams,
+}
+
+fn default_thumbnail_width() -> u32 { 320 }
+
+/// Rectangle of the screen to capture
+#[derive(Debug, Clone, Copy, Serialize, JsonSchema)]
+pub struct Region {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct StableScreen {
+    pub region: Region,
+    /// How long the call waited, in milliseconds, the quiet period included
+    pub waited_ms: u64,
+    /// Frames captured
+    pub frames: u32,
+}
+
+/// A captured screenshot, as tools report it
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct Screenshot {
+    /// screenshot:// resource holding the PNG
+    pub uri: String,
+    /// Part of the screen captured
+    pub region: Region,
+    /// PNG size in bytes
+    pub bytes: u32,
+    /// Recent actions drawn on the image, newest first
+    pub markers: Vec<Marker>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct Marker {
+    /// What happened there, e.g. "click left" or "type"
+    pub kind: String,
+    pub position: output::Point,
+    pub seconds_ago: f64,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct Thumbnails {
+    pub windows: Vec<Thumbnail>,
+    /// Pass as cursor to get the next page; absent on the last one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct Thumbnail {
+    pub window: output::Window,
+    /// The window's position and size; the image shows only its on-screen part
+    pub geometry: crate::WindowGeometry,
+}
+
+#[derive(Debug)]
+struct CachedScreenshot {
+    id: u64,
+    png: Vec<u8>,
+    region: Region,
+    captured_at: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct ScreenshotCache {
+    next_id: u64,
+    entries: VecDeque<CachedScreenshot>,
+}
+
+impl ScreenshotCache {
+    /// Store a screenshot and return its resource URI
+    pub fn insert(&mut self, png: Vec<u8>, region: Region) -> String {
+        self.next_id += 1;
+        if self.entries.len() == CACHE_CAPACITY {
+            self.entries.pop_front();
+        }
+        let captured_at = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        self.entries.push_back(CachedScreenshot { id: self.next_id, png, region, captured_at });
+        format!("{}{}", URI_PREFIX, self.next_id)
+    }
+
+    pub fn get(&self, uri: &str) -> Option<&[u8]> {
+        let id: u64 = uri.strip_prefix(URI_PREFIX)?.parse().ok()?;
+        self.entries.iter().find(|entry| entry.id == id).map(|entry| entry.png.as_slice())
+    }
+
+    pub fn resources(&self) -> Vec<Resource> {
+        self.entries.iter().rev().map(|entry| {
+            let mut resource = RawResource::new(
+                format!("{}{}", URI_PREFIX, entry.id),
+                format!("screenshot-{}.png", entry.id)
+            );
+            resource.description = Some(format!(
+                "{}x{} at ({}, {}), captured at unix time {}",
+                entry.region.width, entry.region.height, entry.region.x, entry.region.y, entry.captured_at
+            ));
+            resource.mime_type = Some("image/png".to_string());
+            resource.size = Some(entry.png.len() as u32);
+            resource.no_annotation()
+        }).collect()
+    }
+}
+
+/// ffmpeg filter drawing a box around each action, relative to the captured region
+fn marker_filter(region: Region, actions: &[Action]) -> Option<String> {
+    let boxes: Vec<String> = actions.iter().enumerate().filter_map(|(i, action)| {
+        let x = action.x - region.x;
+        let y = action.y - region.y;
+        if x < 0 || y < 0 || x >= region.width as i32 || y >= region.height as i32 {
+            return None;
+        }
+        let color = if i == 0 { "red" } else { "yellow@0.8" };
+        Some(format!(
+            "drawbox=x={}:y={}:w=21:h=21:color={}:t=3,drawbox=x={}:y={}:w=3:h=3:color={}:t=fill",
+            x - 10, y - 10, color, x - 1, y - 1, color
+        ))
+    }).collect();
+
+    (!boxes.is_empty()).then(|| boxes.join(","))
+}
+
+/// ffmpeg filter drawing a crosshair through (x, y), relative to the captured region
+pub fn crosshair_filter(region: Region, x: i32, y: i32) -> String {
+    let x = x - region.x;
+    let y = y - region.y;
+    format!(
+        "drawbox=x=0:y={}:w={}:h=1:color=red:t=fill,drawbox=x={}:y=0:w=1:h={}:color=red:t=fill,drawbox=x={}:y={}:w=31:h=31:color=red:t=2",
+        y, region.width, x, region.height, x - 15, y - 15
+    )
+}
+
+impl Region {
+    /// The part of this region that lies on a screen of the given size, if any
+    pub fn clip_to(self, screen: (u32, u32)) -> Option<Region> {
+        let left = self.x.max(0);
+        let top = self.y.max(0);
+        let right = (self.x + self.width as i32).min(screen.0 as i32);
+        let bottom = (self.y + self.height as i32).min(screen.1 as i32);
+        (right > left && bottom > top).then(|| Region {
+            x: left,
+            y: top,
+            width: (right - left) as u32,
+            height: (bottom - top) as u32,
+        })
+    }
+}
+
+/// Area around (x, y) used for previews, kept inside the screen
+pub fn region_around(x: i32, y: i32, screen: (u32, u32)) -> Region {
+    let width = screen.0.min(PREVIEW_SIZE.0);
+    let height = screen.1.min(PREVIEW_SIZE.1);
+    let left = (x - width as i32 / 2).clamp(0, (screen.0 - width) as i32);
+    let top = (y - height as i32 / 2).clamp(0, (screen.1 - height) as i32);
+    Region { x: left, y: top, width, height }
+}
+
+/// Grab a region of the screen as PNG bytes, optionally run through an ffmpeg filter
+pub fn capture_png(region: Region, filter: Option<&str>) -> Result<Vec<u8>, McpError> {
+    let mut command = display::command("ffmpeg");
+    command.args([
+        "-loglevel", "error",
+        "-f", "x11grab",
+        "-video_size", &format!("{}x{}", region.width, region.height),
+        "-i", &format!("{}+{},{}", XdotoolServer::x_display(), region.x, region.y),
+        "-frames:v", "1",
+    ]);
+    if let Some(filter) = filter {
+        command.args(["-vf", filter]);
+    }
+    let output = process::output(command.args(["-f", "image2pipe", "-vcodec", "png", "-"]))
+        .map_err(|e| process::error("ffmpeg", e))?;
+
+    if output.status.success() && !output.stdout.is_empty() {
+        Ok(output.stdout)
+    } else {
+        Err(McpError::internal_error(
+            format!("ffmpeg error: {}", String::from_utf8_lossy(&output.stderr)),
+            None
+        ))
+    }
+}
+
+/// Raw RGB pixels of a region, without the mouse pointer, for comparing frames
+pub fn capture_frame(region: Region) -> Result<Vec<u8>, McpError> {
+    let output = process::output(display::command("ffmpeg")
+        .args([
+            "-loglevel", "error",
+            "-f", "x11grab",
+            "-draw_mouse", "0",
+            "-video_size", &format!("{}x{}", region.width, region.height),
+            "-i", &format!("{}+{},{}", XdotoolServer::x_display(), region.x, region.y),
+            "-frames:v", "1",
+            "-f", "rawvideo", "-pix_fmt", "rgb24", "-",
+        ]))
+        .map_err(|e| process::error("ffmpeg", e))?;
+
+    if output.status.success() && !output.stdout.is_empty() {
+        Ok(output.stdout)
+    } else {
+        Err(McpError::internal_error(
+            format!("ffmpeg error: {}", String::from_utf8_lossy(&output.stderr)),
+            None
+        ))
+    }
+}
+
+/// How many pixels differ between two frames of the same region
+fn changed_pixels(a: &[u8], b: &[u8]) -> usize {
+    a.chunks(3).zip(b.chunks(3)).filter(|(a, b)| a != b).count() + a.len().abs_diff(b.len()) / 3
+}
+
+/// Color of a single screen pixel as RGB
+pub fn capture_pixel(x: i32, y: i32) -> Result<[u8; 3], McpError> {
+    let output = process::output(display::command("ffmpeg")
+        .args([
+            "-loglevel", "error",
+            "-f", "x11grab",
+            "-video_size", "1x1",
+            "-i", &format!("{}+{},{}", XdotoolServer::x_display(), x, y),
+            "-frames:v", "1",
+            "-f", "rawvideo", "-pix_fmt", "rgb24", "-",
+        ]))
+        .map_err(|e| process::error("ffmpeg", e))?;
+
+    match output.stdout.as_slice() {
+        [r, g, b, ..] if output.status.success() => Ok([*r, *g, *b]),
+        _ => Err(McpError::internal_error(
+            format!("ffmpeg error: {}", String::from_utf8_lossy(&output.stderr)),
+            None
+        )),
+    }
+}
+
+impl XdotoolServer {
+    /// Work out which part of the screen a screenshot request refers to
+    fn screenshot_region(params: &TakeScreenshotParams) -> Result<Region, McpError> {
+        Self::region_of(params.window_id.as_deref(), (params.x, params.y, params.width, params.height))
+    }
+
+    /// A window's area, the region given as (x, y, width, height), or else the whole screen
+    fn region_of(window_id: Option<&str>, region: (Option<i32>, Option<i32>, Option<u32>, Option<u32>)) -> Result<Region, McpError> {
+        if let Some(window_id) = window_id {
+            let geometry = Self::window_geometry(window_id)?;
+            return Ok(Region { x: geometry.x, y: geometry.y, width: geometry.width, height: geometry.height });
+        }
+
+        match region {
+            (Some(x), Some(y), Some(width), Some(height)) => Ok(Region { x, y, width, height }),
+            (None, None, None, None) => {
+                let (width, height) = Self::display_geometry()?;
+                Ok(Region { x: 0, y: 0, width, height })
+            }
+            _ => Err(McpError::internal_error(
+                "Region screenshots need all of x, y, width and height",
+                None
+            )),
+        }
+    }
+
+    /// Cache a captured PNG and build the tool result content for it:
+    /// a summary line (plus `details`), the inline image, and a resource link.
+    /// Also returns its structured description, without markers.
+    pub(crate) fn screenshot_contents(
+        &self,
+        png: Vec<u8>,
+        region: Region,
+        details: &str,
+        inline: bool,
+    ) -> (Vec<Content>, Screenshot) {
+        let size = png.len() as u32;
+        let encoded = inline.then(|| base64::engine::general_purpose::STANDARD.encode(&png));
+        let uri = self.screenshots.lock().unwrap().insert(png, region);
+
+        let mut link = RawResource::new(&uri, format!("screenshot-{}.png", uri.trim_start_matches(URI_PREFIX)));
+        link.mime_type = Some("image/png".to_string());
+        link.size = Some(size);
+
+        let mut summary = format!("Screenshot {}x{} at ({}, {}) saved as {}", region.width, region.height, region.x, region.y, uri);
+        if !details.is_empty() {
+            summary.push('\n');
+            summary.push_str(details);
+        }
+
+        let mut content = vec![Content::text(summary)];
+        if let Some(encoded) = encoded {
+            content.push(Content::image(encoded, "image/png"));
+        }
+        content.push(Content::resource_link(link));
+        (content, Screenshot { uri, region, bytes: size, markers: vec![] })
+    }
+
+    /// Capture what `params` asks for, markers drawn: the PNG, result content and structured description
+    pub(crate) fn capture_screenshot(&self, params: &TakeScreenshotParams) -> Result<(Vec<u8>, Vec<Content>, Screenshot), McpError> {
+        let region = Self::screenshot_region(params)?;
+        let actions = self.history.lock().unwrap().recent(params.markers);
+        let png = capture_png(region, marker_filter(region, &actions).as_deref())?;
+        let mut details = String::new();
+        if !actions.is_empty() {
+            details.push_str("Markers (newest first):");
+            for (i, action) in actions.iter().enumerate() {
+                details.push_str(&format!(
+                    "\n  {}. {} at ({}, {}), {:.1}s ago",
+                    i + 1, action.kind, action.x, action.y, action.at.elapsed().as_secs_f64()
+                ));
+            }
+        }
+
+        let (content, mut shot) = self.screenshot_contents(png.clone(), region, &details, params.inline);
+        shot.markers = actions.iter()
+            .map(|action| Marker {
+                kind: action.kind.clone(),
+                position: output::Point { x: action.x, y: action.y },
+                seconds_ago: action.at.elapsed().as_secs_f64(),
+            })
+            .collect();
+        Ok((png, content, shot))
+    }
+}
+
+#[rmcp::tool_router(router = screenshot_router, vis = "pub(crate)")]
+impl XdotoolServer {
+    #[rmcp::tool(description = "Take a screenshot of the screen, a window, or a region (requires ffmpeg). Also stored as a screenshot:// resource.", annotations(read_only_hint = true), output_schema = output::schema::<Screenshot>())]
+    pub async fn take_screenshot(
+        &self,
+        Parameters(params): Parameters<TakeScreenshotParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let (_, content, shot) = self.capture_screenshot(&params)?;
+        Ok(output::with_content(content, &shot))
+    }
+
+    #[rmcp::tool(description = "Capture a small labeled thumbnail of every visible top-level window, to pick the right one when titles are ambiguous. Thumbnails show what is on screen, so overlapped windows appear covered.", annotations(read_only_hint = true), output_schema = output::schema::<Thumbnails>())]
+    pub async fn window_thumbnails(
+        &self,
+        Parameters(params): Parameters<WindowThumbnailsParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let screen = Self::display_geometry()?;
+        let page = listing::page(Self::visible_windows()?, &params.page, &params.filter, 20)?;
+        let scale = format!("scale={}:-2", params.width.max(16));
+
+        let mut content = vec![];
+        let mut thumbnails = vec![];
+        for window_id in &page.window_ids {
+            let geometry = Self::window_geometry(window_id)?;
+            let region = Region { x: geometry.x, y: geometry.y, width: geometry.width, height: geometry.height };
+            let Some(region) = region.clip_to(screen) else {
+                continue;
+            };
+            let png = capture_png(region, Some(&scale))?;
+            let name = Self::window_name(window_id).unwrap_or_default();
+
+            content.push(Content::text(format!(
+                "Window {}: \"{}\" ({}x{} at ({}, {}))",
+                window_id, name, geometry.width, geometry.height, geometry.x, geometry.y
+            )));
+            content.push(Content::image(base64::engine::general_purpose::STANDARD.encode(&png), "image/png"));
+            thumbnails.push(Thumbnail {
+                window: output::Window { id: window_id.clone(), name: Some(name) },
+                geometry,
+            });
+        }
+
+        let result = Thumbnails { windows: thumbnails, next_cursor: page.next_cursor };
+        if let Some(cursor) = &result.next_cursor {
+            content.push(Content::text(format!("More windows; pass cursor \"{}\" for the next page", cursor)));
+        }
+        if result.windows.is_empty() && result.next_cursor.is_none() {
+            return Ok(output::structured("No visible windows on screen", &result));
+        }
+        content.insert(0, Content::text(format!("{} visible window(s):", result.windows.len())));
+        Ok(output::with_content(content, &result))
+    }
+
+    #[rmcp::tool(description = "Wait until the screen, a window or a region stops changing for quiet_ms, to wait out animations, spinners and pages still rendering (requires ffmpeg). The mouse pointer is ignored", annotations(read_only_hint = true), output_schema = output::schema::<StableScreen>())]
+    pub async fn wait_for_stable_screen(
+        &self,
+        Parameters(params): Parameters<WaitForStableScreenParams>,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let region = Self::region_of(params.window_id.as_deref(), (params.x, params.y, params.width, params.height))?;
+        let quiet = Duration::from_millis(params.quiet_ms);
+        let timeout = Duration::from_secs(params.timeout_seconds);
+        let started = Instant::now();
+        // Each frame is compared with the first one since the last change, so slow fades count too
+        let mut reference = capture_frame(region)?;
+        let mut unchanged_since = Instant::now();
+        let mut frames = 1;
+        loop {
+            sleep_or_cancel(FRAME_INTERVAL, &context.ct).await?;
+            let frame = capture_frame(region)?;
+            frames += 1;
+            if changed_pixels(&reference, &frame) > params.tolerance {
+                reference = frame;
+                unchanged_since = Instant::now();
+            } else if unchanged_since.elapsed() >= quiet {
+                let waited_ms = started.elapsed().as_millis() as u64;
+                return Ok(output::structured(
+                    format!("Region {}x{} at ({}, {}) unchanged for {}ms, after {}ms", region.width, region.height, region.x, region.y, params.quiet_ms, waited_ms),
+                    &StableScreen { region, waited_ms, frames }
+                ));
+            }
+            if started.elapsed() >= timeout {
+                return Err(McpError::internal_error(
+                    format!("Timed out after {}s waiting for the screen to stay unchanged for {}ms", params.timeout_seconds, params.quiet_ms),
+                    None
+                ));
+            }
+        }
+    }
+}
@@ -0,0 +1,181 @@
+//! Restricted xdotool passthrough for commands the server doesn't wrap yet.
+//!
+//! Each command is run as its own xdotool invocation with arguments passed as
+//! argv, never through a shell. xdotool chains commands when leftover
+//! arguments name another command, so those words are refused as arguments
+//! too (except for `type`, which treats everything after it as text, so its
+//! `--terminator`, `--args` and `--file` options are refused).
+
+use crate::output;
+use crate::XdotoolServer;
+use rmcp::{
+    handler::server::wrapper::Parameters,
+    model::*,
+    service::{RequestContext, RoleServer},
+    ErrorData as McpError,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ScriptRun {
+    /// What each command printed, in order
+    pub commands: Vec<CommandOutput>,
+    /// The invocations that would run, in dry runs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dry_run: Option<Vec<String>>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct CommandOutput {
+    pub command: String,
+    /// Trimmed stdout, e.g. window IDs from search; empty for most input commands
+    pub stdout: String,
+}
+
+/// xdotool commands the passthrough accepts
+const ALLOWED_COMMANDS: &[&str] = &[
+    "click", "mousedown", "mouseup", "mousemove", "mousemove_relative", "getmouselocation",
+    "key", "keydown", "keyup", "type",
+    "search", "getactivewindow", "getwindowfocus", "getwindowname", "getwindowpid",
+    "getwindowgeometry", "getdisplaygeometry", "selectwindow",
+    "windowactivate", "windowfocus", "windowraise", "windowminimize", "windowmap", "windowunmap",
+    "windowmove", "windowsize", "windowstate",
+    "get_desktop", "set_desktop", "get_num_desktops", "get_desktop_for_window",
+    "set_desktop_for_window", "get_desktop_viewport", "set_desktop_viewport",
+    "sleep",
+];
+
+/// Every xdotool command name, allowed or not. None of these may appear as an argument.
+const ALL_COMMANDS: &[&str] = &[
+    "behave", "behave_screen_edge", "exec", "script", "set_window", "windowkill", "windowclose",
+    "windowquit", "windowreparent", "set_num_desktops", "version", "help",
+];
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct XdotoolScriptParams {
+    #[schemars(description = "Commands to run in order, each as an argv array without the leading 'xdotool', e.g. [[\"windowactivate\", \"--sync\", \"12345\"], [\"key\", \"ctrl+l\"]]")]
+    pub commands: Vec<Vec<String>>,
+    #[schemars(description = "Validate and echo the commands without running them. Default: the server's dry_run setting")]
+    pub dry_run: Option<bool>,
+}
+
+pub(crate) fn is_command_name(word: &str) -> bool {
+    ALLOWED_COMMANDS.contains(&word) || ALL_COMMANDS.contains(&word)
+}
+
+/// Check one command against the allowlist and the no-chaining rule
+pub fn validate_command(argv: &[String]) -> Result<(), String> {
+    let Some(command) = argv.first() else {
+        return Err("empty command".to_string());
+    };
+    if !ALLOWED_COMMANDS.contains(&command.as_str()) {
+        return Err(format!("'{}' is not an allowed xdotool command", command));
+    }
+    if command != "type" {
+        if let Some(word) = argv[1..].iter().find(|arg| is_command_name(arg)) {
+            return Err(format!("argument '{}' would chain another xdotool command", word));
+        }
+    } else if let Some(option) = argv[1..].iter().find(|arg| ends_text(arg)) {
+        return Err(format!("type option '{}' isn't allowed here; use type_text to type from a file or with a terminator", option));
+    }
+    Ok(())
+}
+
+/// `type` options that end its text early (so the words after it chain commands) or read
+/// it from a file. xdotool also takes them with one dash, abbreviated or with `=value`.
+fn ends_text(arg: &str) -> bool {
+    let name = arg.trim_start_matches('-');
+    let name = name.split('=').next().unwrap_or_default();
+    arg.starts_with('-') && !name.is_empty() && ["terminator", "args", "file"].iter().any(|option| option.starts_with(name))
+}
+
+#[rmcp::tool_router(router = script_router, vis = "pub(crate)")]
+impl XdotoolServer {
+    #[rmcp::tool(description = "Run a restricted sequence of raw xdotool commands for features not wrapped by other tools. Commands are checked against an allowlist (no exec/behave/windowkill) and run without a shell.", annotations(read_only_hint = false, destructive_hint = true), output_schema = output::schema::<ScriptRun>())]
+    pub async fn xdotool_script(
+        &self,
+        Parameters(params): Parameters<XdotoolScriptParams>,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        for (i, argv) in params.commands.iter().enumerate() {
+            validate_command(argv).map_err(|reason| McpError::internal_error(
+                format!("Command {} rejected: {}", i + 1, reason),
+                None
+            ))?;
+            if argv[0] == "type" {
+                self.check_text_length(&argv[1..].concat()).map_err(|e| McpError::internal_error(
+                    format!("Command {} rejected: {}", i + 1, e.message),
+                    None
+                ))?;
+            }
+        }
+
+        if params.dry_run.unwrap_or(self.config.dry_run) {
+            let invocations: Vec<String> = params.commands.iter().map(|argv| Self::format_invocation(argv)).collect();
+            let lines: Vec<String> = invocations.iter().enumerate()
+                .map(|(i, invocation)| format!("  {}. {}", i + 1, invocation))
+                .collect();
+            return Ok(output::structured(
+                format!("Dry run, would run:\n{}", lines.join("\n")),
+                &ScriptRun { commands: vec![], dry_run: Some(invocations) }
+            ));
+        }
+
+        let mut report = vec![];
+        let mut outputs = vec![];
+        for (i, argv) in params.commands.iter().enumerate() {
+            let args: Vec<&str> = argv.iter().map(String::as_str).collect();
+            // `search --sync` and `windowactivate --sync` can block indefinitely, so these stop on cancel
+            let stdout = self.run_checked_until(&args, &context.ct).await.map_err(|e| {
+                if context.ct.is_cancelled() {
+                    Self::release_input();
+                }
+                McpError::internal_error(
+                    format!("Command {} ({}) failed: {}", i + 1, argv[0], e.message),
+                    None
+                )
+            })?;
+            let stdout = stdout.trim();
+            if stdout.is_empty() {
+                report.push(format!("  {}. {}: ok", i + 1, argv[0]));
+            } else {
+                report.push(format!("  {}. {}:\n{}", i + 1, argv[0], stdout));
+            }
+            outputs.push(CommandOutput { command: argv[0].clone(), stdout: stdout.to_string() });
+        }
+
+        Ok(output::structured(
+            format!("Ran {} command(s):\n{}", report.len(), report.join("\n")),
+            &ScriptRun { commands: outputs, dry_run: None }
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn argv(words: &[&str]) -> Vec<String> {
+        words.iter().map(|word| word.to_string()).collect()
+    }
+
+    #[test]
+    fn commands_are_allowlisted_and_not_chained() {
+        assert!(validate_command(&argv(&["windowactivate", "--sync", "123"])).is_ok());
+        assert!(validate_command(&argv(&["exec", "sh"])).is_err());
+        assert!(validate_command(&argv(&["key", "a", "exec", "sh"])).is_err());
+        assert!(validate_command(&[]).is_err());
+        // type's words are text, command names included
+        assert!(validate_command(&argv(&["type", "--delay", "5", "run exec", "exec"])).is_ok());
+    }
+
+    #[test]
+    fn type_options_that_end_the_text_are_refused() {
+        for option in ["--terminator", "--terminator=END", "-terminator", "--term", "--args", "-a", "--file", "--file=/etc/passwd"] {
+            let command = argv(&["type", option, "END", "x", "END", "exec", "sh", "-c", "id"]);
+            assert!(validate_command(&command).is_err(), "{}", option);
+        }
+        assert!(validate_command(&argv(&["type", "--delay", "12", "-", "--"])).is_ok());
+    }
+}
@@ -0,0 +1,173 @@
+//! One tool for clicking a thing however it can be found.
+//!
+//! `smart_click` looks for the text as an element's accessible name over
+//! AT-SPI first (exact, and independent of theme and scaling), then as words
+//! tesseract reads in the window, then falls back to the coordinates the call
+//! gave, if any. The result says which of those found it and why the earlier
+//! ones didn't, so an agent learns which approach works for an app. Region
+//! rules apply to the point it clicks, as for click_element.
+
+#[cfg(feature = "accessibility")]
+use crate::accessibility::Element;
+use crate::{output, XdotoolServer};
+use rmcp::{
+    handler::server::wrapper::Parameters,
+    model::*,
+    service::{RequestContext, RoleServer},
+    ErrorData as McpError,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct SmartClickParams {
+    #[schemars(description = "What to click: a widget's accessible name or the text shown on it, e.g. \"Save\"")]
+    pub text: String,
+    #[schemars(description = "AT-SPI role the element must have, e.g. \"push button\"; only used for the accessibility lookup")]
+    pub role: Option<String>,
+    #[schemars(description = "Window to look in. Default: the active window")]
+    pub window_id: Option<String>,
+    #[schemars(description = "X coordinate to click if neither lookup finds the text")]
+    pub x: Option<i32>,
+    #[schemars(description = "Y coordinate to click if neither lookup finds the text")]
+    pub y: Option<i32>,
+    #[schemars(description = "Button to click: 1 (left), 2 (middle), 3 (right). Default: 1")]
+    #[serde(default = "crate::default_button")]
+    pub button: u8,
+    #[schemars(description = "Only report what would be clicked. Default: the server's dry_run setting")]
+    pub dry_run: Option<bool>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Strategy {
+    /// Found by accessible name over AT-SPI
+    Accessibility,
+    /// Found by OCR in the window
+    Ocr,
+    /// The coordinates the call gave
+    Coordinates,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct SmartClicked {
+    /// How the target was found
+    pub strategy: Strategy,
+    pub position: output::Point,
+    /// The element clicked, when found over AT-SPI
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub element: Option<Element>,
+    /// Why each strategy before the one used didn't find the text
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tried: Vec<String>,
+    /// What would have been done, in dry runs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dry_run: Option<String>,
+}
+
+/// Without the accessibility feature nothing is found over AT-SPI, so there's never an element
+#[cfg(not(feature = "accessibility"))]
+#[derive(Debug, Serialize, JsonSchema)]
+pub enum Element {}
+
+/// The element with the accessible name `text` in the window, and the middle of it
+#[cfg(feature = "accessibility")]
+fn accessible_center(window_id: &str, text: &str, role: Option<&str>) -> Result<(Element, (i32, i32)), McpError> {
+    XdotoolServer::element_center(window_id, text, role)
+}
+
+#[cfg(not(feature = "accessibility"))]
+fn accessible_center(_window_id: &str, _text: &str, _role: Option<&str>) -> Result<(Element, (i32, i32)), McpError> {
+    Err(crate::feature_disabled("accessibility"))
+}
+
+/// The middle of where OCR reads `text` in the window, in screen coordinates
+#[cfg(feature = "ocr")]
+fn ocr_center(window_id: &str, text: &str) -> Result<(i32, i32), McpError> {
+    let geometry = XdotoolServer::window_geometry(window_id)?;
+    let window = crate::screenshot::Region { x: geometry.x, y: geometry.y, width: geometry.width, height: geometry.height };
+    let Some(region) = window.clip_to(XdotoolServer::display_geometry()?) else {
+        return Err(McpError::internal_error(format!("Window {} is off the screen", window_id), None));
+    };
+    let png = crate::screenshot::capture_png(region, None)?;
+    match crate::ocr::find_text(&png, text)? {
+        Some((x, y, width, height)) => Ok((region.x + x + width as i32 / 2, region.y + y + height as i32 / 2)),
+        None => Err(McpError::internal_error(format!("OCR doesn't read \"{}\" in the window", text), None)),
+    }
+}
+
+#[cfg(not(feature = "ocr"))]
+fn ocr_center(_window_id: &str, _text: &str) -> Result<(i32, i32), McpError> {
+    Err(crate::feature_disabled("ocr"))
+}
+
+#[rmcp::tool_router(router = smartclick_router, vis = "pub(crate)")]
+impl XdotoolServer {
+    #[rmcp::tool(description = "Click something by its label, trying the AT-SPI accessible name first, then OCR of the window, then the x/y given as a fallback, and report which worked. One resilient entry point when you don't know what an app supports", annotations(read_only_hint = false, destructive_hint = true), output_schema = output::schema::<SmartClicked>())]
+    pub async fn smart_click(
+        &self,
+        Parameters(params): Parameters<SmartClickParams>,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        if params.text.trim().is_empty() {
+            return Err(McpError::invalid_params("text can't be empty", None));
+        }
+        let fallback = match (params.x, params.y) {
+            (Some(x), Some(y)) => Some((x, y)),
+            (None, None) => None,
+            _ => return Err(McpError::invalid_params("Give both x and y, or neither", None)),
+        };
+        let window_id = match params.window_id {
+            Some(id) => id,
+            None => Self::run_xdotool(&["getactivewindow"])?.trim().to_string(),
+        };
+
+        let mut tried = vec![];
+        let mut element = None;
+        let found = match accessible_center(&window_id, &params.text, params.role.as_deref()) {
+            Ok((found, center)) => {
+                element = Some(found);
+                Some((Strategy::Accessibility, center))
+            }
+            Err(e) => {
+                tried.push(format!("accessibility: {}", e.message));
+                None
+            }
+        };
+        let found = found.or_else(|| match ocr_center(&window_id, &params.text) {
+            Ok(center) => Some((Strategy::Ocr, center)),
+            Err(e) => {
+                tried.push(format!("ocr: {}", e.message));
+                None
+            }
+        });
+        let Some((strategy, (x, y))) = found.or(fallback.map(|point| (Strategy::Coordinates, point))) else {
+            return Err(McpError::internal_error(
+                format!("Couldn't find \"{}\" in window {} ({}); pass x and y to fall back on", params.text, window_id, tried.join("; ")),
+                None
+            ));
+        };
+
+        // Coordinates from the call already went through the rules with its arguments
+        if strategy != Strategy::Coordinates && !params.dry_run.unwrap_or(self.config.dry_run) {
+            self.check_policy_at("smart_click", (x, y), &context).await?;
+        }
+        let (px, py) = self.bound_point(x, y, params.dry_run)?;
+        let (x_arg, y_arg, button) = (px.to_string(), py.to_string(), params.button.to_string());
+        let mut clicked = SmartClicked { strategy, position: (px, py).into(), element, tried, dry_run: None };
+        clicked.dry_run = self.run_input(&["mousemove", &x_arg, &y_arg, "click", &button], params.dry_run)?;
+        if let Some(command) = &clicked.dry_run {
+            return Ok(output::structured(Self::dry_run_report(command), &clicked));
+        }
+        self.history.lock().unwrap().record(format!("click {}", Self::button_name(params.button)), px, py);
+        let how = match strategy {
+            Strategy::Accessibility => "found by accessible name",
+            Strategy::Ocr => "found by OCR",
+            Strategy::Coordinates => "at the given coordinates",
+        };
+        Ok(output::structured(
+            format!("Clicked {} \"{}\" at ({}, {}), {}", Self::button_name(params.button), params.text, px, py, how),
+            &clicked
+        ))
+    }
+}
@@ -0,0 +1,149 @@
+//! The MCP server over HTTP with Server-Sent Events (the 2024-11-05 transport).
+//!
+//! `GET /sse` opens an event stream and gets its own session, whose first
+//! event, `endpoint`, names the URL to POST JSON-RPC messages to
+//! (`/message?sessionId=...`). Posted messages are answered with 202 and the
+//! replies arrive as `message` events on the stream. Each session is served by
+//! its own clone of the server, so state like running actions and rate limits
+//! is shared between them. Closing the stream ends the session.
+//!
+//! Every request needs a bearer token (see the auth module), and only the
+//! token that opened a session may post to it. Browser requests carrying an
+//! `Origin` other than localhost are refused.
+
+use crate::auth;
+use crate::http::{self, Request};
+use crate::XdotoolServer;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::BufReader;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+
+/// Comment lines sent this often keep proxies from closing an idle stream
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// An open session: the channel into its MCP service and the token that opened it
+struct Session {
+    input: mpsc::UnboundedSender<String>,
+    token: String,
+}
+
+type Sessions = Arc<Mutex<HashMap<String, Session>>>;
+
+/// Accept connections until the listener fails
+pub async fn serve(server: XdotoolServer, addr: SocketAddr) -> anyhow::Result<()> {
+    let listener = TcpListener::bind(addr).await
+        .map_err(|e| anyhow::anyhow!("Failed to listen on {}: {}", addr, e))?;
+    tracing::info!("Serving MCP over SSE at http://{}/sse", listener.local_addr()?);
+    let sessions = Sessions::default();
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        if !server.config.allows_peer(peer.ip()) {
+            tracing::warn!("Refused SSE connection from {}, not in allowed_peers", peer);
+            continue;
+        }
+        let (server, sessions) = (server.clone(), sessions.clone());
+        tokio::spawn(async move {
+            if let Err(e) = handle(stream, server, sessions).await {
+                tracing::debug!("SSE connection from {} failed: {}", peer, e);
+            }
+        });
+    }
+}
+
+async fn handle(stream: TcpStream, server: XdotoolServer, sessions: Sessions) -> anyhow::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+    let Some(mut request) = http::read_head(&mut reader).await? else {
+        return Ok(());
+    };
+    if request.header("origin").is_some_and(|origin| !http::local_origin(origin)) {
+        http::respond(&mut writer, 403, "text/plain", b"Origin not allowed").await?;
+        return Ok(());
+    }
+    let token = auth::bearer(request.header("authorization")).unwrap_or_default().to_string();
+    let Some(server) = server.authenticate(&token) else {
+        http::respond_with(&mut writer, 401, &[("WWW-Authenticate", "Bearer")], "text/plain", b"Unauthorized").await?;
+        return Ok(());
+    };
+    http::read_body(&mut reader, &mut request).await?;
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/sse") => open_session(writer, server, token, sessions).await,
+        ("POST", "/message") => post_message(writer, &request, &token, &sessions).await,
+        (_, "/sse" | "/message") => Ok(http::respond(&mut writer, 405, "text/plain", b"Method not allowed").await?),
+        _ => Ok(http::respond(&mut writer, 404, "text/plain", b"Not found").await?),
+    }
+}
+
+/// Start an MCP service for a new event stream and forward its output as events
+async fn open_session(
+    mut writer: tokio::net::tcp::OwnedWriteHalf,
+    server: XdotoolServer,
+    token: String,
+    sessions: Sessions,
+) -> anyhow::Result<()> {
+    let id = http::random_id()?;
+    let pipe = http::spawn_service(server.in_session(format!("sse:{}", &id[..8])));
+    sessions.lock().unwrap().insert(id.clone(), Session { input: pipe.input, token });
+    tracing::info!("SSE session {} opened", id);
+
+    let result = stream_events(&mut writer, &id, pipe.output).await;
+    sessions.lock().unwrap().remove(&id);
+    pipe.service.abort();
+    tracing::info!("SSE session {} closed", id);
+    result
+}
+
+async fn stream_events(
+    writer: &mut tokio::net::tcp::OwnedWriteHalf,
+    id: &str,
+    mut lines: http::Output,
+) -> anyhow::Result<()> {
+    http::start_event_stream(writer, &[]).await?;
+    http::send_event(writer, "endpoint", &format!("/message?sessionId={}", id)).await?;
+    let mut keepalive = tokio::time::interval_at(tokio::time::Instant::now() + KEEPALIVE_INTERVAL, KEEPALIVE_INTERVAL);
+    loop {
+        tokio::select! {
+            line = lines.next_line() => match line? {
+                Some(line) => http::send_event(writer, "message", &line).await?,
+                None => return Ok(()),
+            },
+            _ = keepalive.tick() => http::send_keepalive(writer).await?,
+        }
+    }
+}
+
+/// Hand a posted JSON-RPC message to its session
+async fn post_message(
+    mut writer: tokio::net::tcp::OwnedWriteHalf,
+    request: &Request,
+    token: &str,
+    sessions: &Sessions,
+) -> anyhow::Result<()> {
+    // Only the token that opened a session may post to it
+    let sender = request.query.get("sessionId").and_then(|id| {
+        sessions.lock().unwrap().get(id)
+            .filter(|session| auth::same_token(&session.token, token))
+            .map(|session| session.input.clone())
+    });
+    let Some(sender) = sender else {
+        http::respond(&mut writer, 404, "text/plain", b"Unknown session").await?;
+        return Ok(());
+    };
+    // Re-serialize so the message is valid JSON on a single line
+    let message = match serde_json::from_slice::<serde_json::Value>(&request.body) {
+        Ok(message) => message.to_string(),
+        Err(e) => {
+            http::respond(&mut writer, 400, "text/plain", format!("Invalid JSON: {}", e).as_bytes()).await?;
+            return Ok(());
+        }
+    };
+    if sender.send(message).is_err() {
+        http::respond(&mut writer, 404, "text/plain", b"Session closed").await?;
+        return Ok(());
+    }
+    Ok(http::respond(&mut writer, 202, "text/plain", b"Accepted").await?)
+}
@@ -0,0 +1,377 @@
+//! The MCP server over streamable HTTP (the 2025-03-26 transport).
+//!
+//! Everything goes through one endpoint, `/mcp`. A POSTed `initialize`
+//! starts a session whose ID comes back in the `Mcp-Session-Id` header and
+//! must be sent with every later request. POSTs holding only notifications
+//! or responses get 202; POSTs with requests are answered with an event
+//! stream carrying the replies and anything the server sends meanwhile (like
+//! approval prompts), or with plain JSON if the client doesn't accept
+//! `text/event-stream`. `GET /mcp` opens a stream for server messages sent
+//! while no POST is open, and `DELETE /mcp` ends the session. A session with
+//! no open stream or unanswered request that goes unused for a while ends
+//! too, so clients that vanish without a DELETE don't leave services behind.
+//!
+//! As with SSE, every request needs a bearer token, a session only answers
+//! to the token that started it, every session is served by its own clone
+//! of the server, and browser requests from other origins are refused.
+
+use crate::auth;
+use crate::http::{self, Request};
+use crate::XdotoolServer;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::BufReader;
+use tokio::net::tcp::OwnedWriteHalf;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+const SESSION_HEADER: &str = "mcp-session-id";
+/// Comment lines sent this often keep proxies from closing an idle stream
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(15);
+/// How long a session may sit unused before it's ended
+const SESSION_IDLE_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+/// How often idle sessions are looked for
+const SESSION_SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+struct Session {
+    /// The bearer token that started the session; later requests must match it
+    token: String,
+    input: mpsc::UnboundedSender<String>,
+    /// Where each outstanding request's reply goes, keyed by its serialized ID
+    pending: Mutex<HashMap<String, mpsc::UnboundedSender<String>>>,
+    /// Open streams; server-initiated messages go to the newest one still open
+    streams: Mutex<Vec<mpsc::UnboundedSender<String>>>,
+    service: JoinHandle<()>,
+    router: JoinHandle<()>,
+    /// When a request last named the session
+    last_used: Mutex<Instant>,
+}
+
+impl Session {
+    fn touch(&self) {
+        *self.last_used.lock().unwrap() = Instant::now();
+    }
+
+    /// Unused for `timeout`, with no stream open and no request waiting for its reply
+    fn idle(&self, timeout: Duration) -> bool {
+        let busy = self.streams.lock().unwrap().iter().any(|stream| !stream.is_closed())
+            || self.pending.lock().unwrap().values().any(|reply| !reply.is_closed());
+        !busy && self.last_used.lock().unwrap().elapsed() >= timeout
+    }
+
+    /// Stop the session's MCP service
+    fn end(&self) {
+        self.service.abort();
+        self.router.abort();
+    }
+
+    /// Route one message from the service: replies to their request, the rest to a stream
+    fn route(&self, line: String) {
+        self.touch();
+        let Ok(message) = serde_json::from_str::<Value>(&line) else {
+            return;
+        };
+        if message.get("method").is_none() {
+            if let Some(id) = message.get("id") {
+                match self.pending.lock().unwrap().remove(&id.to_string()) {
+                    Some(sender) => { let _ = sender.send(line); }
+                    None => tracing::debug!("Dropping reply to unknown request {}", id),
+                }
+                return;
+            }
+        }
+        let mut streams = self.streams.lock().unwrap();
+        while let Some(stream) = streams.last() {
+            if stream.send(line.clone()).is_ok() {
+                return;
+            }
+            streams.pop();
+        }
+        tracing::debug!("Dropping server message, no stream is open");
+    }
+}
+
+type Sessions = Arc<Mutex<HashMap<String, Arc<Session>>>>;
+
+/// Accept connections until the listener fails
+pub async fn serve(server: XdotoolServer, addr: SocketAddr) -> anyhow::Result<()> {
+    let listener = TcpListener::bind(addr).await
+        .map_err(|e| anyhow::anyhow!("Failed to listen on {}: {}", addr, e))?;
+    tracing::info!("Serving MCP over streamable HTTP at http://{}/mcp", listener.local_addr()?);
+    let sessions = Sessions::default();
+    tokio::spawn(expire_sessions(sessions.clone()));
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        if !server.config.allows_peer(peer.ip()) {
+            tracing::warn!("Refused HTTP connection from {}, not in allowed_peers", peer);
+            continue;
+        }
+        let (server, sessions) = (server.clone(), sessions.clone());
+        tokio::spawn(async move {
+            if let Err(e) = handle(stream, server, sessions).await {
+                tracing::debug!("HTTP connection from {} failed: {}", peer, e);
+            }
+        });
+    }
+}
+
+/// End sessions that have been idle for SESSION_IDLE_TIMEOUT
+async fn expire_sessions(sessions: Sessions) {
+    let mut sweep = tokio::time::interval(SESSION_SWEEP_INTERVAL);
+    loop {
+        sweep.tick().await;
+        let expired: Vec<(String, Arc<Session>)> = {
+            let mut sessions = sessions.lock().unwrap();
+            let ids: Vec<String> = sessions.iter()
+                .filter(|(_, session)| session.idle(SESSION_IDLE_TIMEOUT))
+                .map(|(id, _)| id.clone())
+                .collect();
+            ids.into_iter().filter_map(|id| sessions.remove(&id).map(|session| (id, session))).collect()
+        };
+        for (id, session) in expired {
+            session.end();
+            tracing::info!("HTTP session {} expired after {} idle seconds", id, SESSION_IDLE_TIMEOUT.as_secs());
+        }
+    }
+}
+
+async fn handle(stream: TcpStream, server: XdotoolServer, sessions: Sessions) -> anyhow::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+    let Some(mut request) = http::read_head(&mut reader).await? else {
+        return Ok(());
+    };
+    if request.header("origin").is_some_and(|origin| !http::local_origin(origin)) {
+        http::respond(&mut writer, 403, "text/plain", b"Origin not allowed").await?;
+        return Ok(());
+    }
+    if request.path != "/mcp" {
+        http::respond(&mut writer, 404, "text/plain", b"Not found").await?;
+        return Ok(());
+    }
+    let token = auth::bearer(request.header("authorization")).unwrap_or_default().to_string();
+    let Some(server) = server.authenticate(&token) else {
+        http::respond_with(&mut writer, 401, &[("WWW-Authenticate", "Bearer")], "text/plain", b"Unauthorized").await?;
+        return Ok(());
+    };
+    http::read_body(&mut reader, &mut request).await?;
+    match request.method.as_str() {
+        "POST" => post(writer, &request, server, &sessions).await,
+        "GET" => {
+            let Some(session) = find_session(&mut writer, &request, &sessions).await? else {
+                return Ok(());
+            };
+            let (sender, receiver) = mpsc::unbounded_channel();
+            session.streams.lock().unwrap().push(sender);
+            stream_messages(&mut writer, &[], receiver, usize::MAX).await
+        }
+        "DELETE" => {
+            let Some(header) = request.header(SESSION_HEADER) else {
+                http::respond(&mut writer, 400, "text/plain", b"Missing Mcp-Session-Id").await?;
+                return Ok(());
+            };
+            let session = {
+                let mut sessions = sessions.lock().unwrap();
+                let owned = sessions.get(header).is_some_and(|session| auth::same_token(&session.token, &token));
+                if owned { sessions.remove(header) } else { None }
+            };
+            if let Some(session) = session {
+                session.end();
+                tracing::info!("HTTP session {} ended", header);
+            }
+            Ok(http::respond(&mut writer, 200, "text/plain", b"").await?)
+        }
+        _ => Ok(http::respond(&mut writer, 405, "text/plain", b"Method not allowed").await?),
+    }
+}
+
+/// The session named by the request's header, answering 400/404 if there isn't one
+async fn find_session(
+    writer: &mut OwnedWriteHalf,
+    request: &Request,
+    sessions: &Sessions,
+) -> anyhow::Result<Option<Arc<Session>>> {
+    let Some(id) = request.header(SESSION_HEADER) else {
+        http::respond(writer, 400, "text/plain", b"Missing Mcp-Session-Id").await?;
+        return Ok(None);
+    };
+    // A session is only visible to the token that started it
+    let token = auth::bearer(request.header("authorization")).unwrap_or_default();
+    let session = sessions.lock().unwrap().get(id)
+        .filter(|session| auth::same_token(&session.token, token))
+        .cloned();
+    match &session {
+        Some(session) => session.touch(),
+        None => http::respond(writer, 404, "text/plain", b"Unknown session").await?,
+    }
+    Ok(session)
+}
+
+fn start_session(server: XdotoolServer, token: &str, sessions: &Sessions) -> anyhow::Result<(String, Arc<Session>)> {
+    let id = http::random_id()?;
+    let http::Pipe { input, mut output, service } = http::spawn_service(server.in_session(format!("http:{}", &id[..8])));
+    let session = Arc::new_cyclic(|weak: &std::sync::Weak<Session>| {
+        let weak = weak.clone();
+        let router = tokio::spawn(async move {
+            while let Ok(Some(line)) = output.next_line().await {
+                let Some(session) = weak.upgrade() else {
+                    break;
+                };
+                session.route(line);
+            }
+        });
+        Session {
+            token: token.to_string(),
+            input,
+            pending: Mutex::default(),
+            streams: Mutex::default(),
+            service,
+            router,
+            last_used: Mutex::new(Instant::now()),
+        }
+    });
+    sessions.lock().unwrap().insert(id.clone(), session.clone());
+    tracing::info!("HTTP session {} started", id);
+    Ok((id, session))
+}
+
+async fn post(
+    mut writer: OwnedWriteHalf,
+    request: &Request,
+    server: XdotoolServer,
+    sessions: &Sessions,
+) -> anyhow::Result<()> {
+    let messages = match serde_json::from_slice::<Value>(&request.body) {
+        Ok(Value::Array(messages)) => messages,
+        Ok(message) => vec![message],
+        Err(e) => {
+            http::respond(&mut writer, 400, "text/plain", format!("Invalid JSON: {}", e).as_bytes()).await?;
+            return Ok(());
+        }
+    };
+    let batch = request.body.trim_ascii_start().starts_with(b"[");
+    let initialize = messages.iter().any(|message| message.get("method").and_then(Value::as_str) == Some("initialize"));
+
+    let (id, session) = if initialize && request.header(SESSION_HEADER).is_none() {
+        start_session(server, auth::bearer(request.header("authorization")).unwrap_or_default(), sessions)?
+    } else {
+        let Some(session) = find_session(&mut writer, request, sessions).await? else {
+            return Ok(());
+        };
+        (request.header(SESSION_HEADER).unwrap_or_default().to_string(), session)
+    };
+
+    let request_ids: Vec<String> = messages.iter()
+        .filter(|message| message.get("method").is_some())
+        .filter_map(|message| message.get("id").map(Value::to_string))
+        .collect();
+    let (sender, receiver) = mpsc::unbounded_channel();
+    for request_id in &request_ids {
+        session.pending.lock().unwrap().insert(request_id.clone(), sender.clone());
+    }
+    let streaming = request.header("accept").is_some_and(|accept| accept.contains("text/event-stream"));
+    if streaming && !request_ids.is_empty() {
+        session.streams.lock().unwrap().push(sender);
+    } else {
+        drop(sender);
+    }
+    for message in &messages {
+        if session.input.send(message.to_string()).is_err() {
+            http::respond(&mut writer, 404, "text/plain", b"Session closed").await?;
+            return Ok(());
+        }
+    }
+
+    let headers = [("Mcp-Session-Id", id.as_str())];
+    if request_ids.is_empty() {
+        http::respond_with(&mut writer, 202, &headers, "text/plain", b"").await?;
+    } else if streaming {
+        stream_messages(&mut writer, &headers, receiver, request_ids.len()).await?;
+    } else {
+        let replies = collect_replies(receiver, request_ids.len()).await;
+        let body = if batch { Value::Array(replies).to_string() } else { replies.into_iter().next().unwrap_or_default().to_string() };
+        http::respond_with(&mut writer, 200, &headers, "application/json", body.as_bytes()).await?;
+    }
+    Ok(())
+}
+
+/// Wait for `count` replies, ignoring anything else
+async fn collect_replies(mut receiver: mpsc::UnboundedReceiver<String>, count: usize) -> Vec<Value> {
+    let mut replies = vec![];
+    while replies.len() < count {
+        let Some(line) = receiver.recv().await else {
+            break;
+        };
+        if let Ok(message) = serde_json::from_str::<Value>(&line) {
+            if message.get("method").is_none() {
+                replies.push(message);
+            }
+        }
+    }
+    replies
+}
+
+/// Send messages as events until `replies` replies have gone out or the channel closes
+async fn stream_messages(
+    writer: &mut OwnedWriteHalf,
+    headers: &[(&str, &str)],
+    mut receiver: mpsc::UnboundedReceiver<String>,
+    mut replies: usize,
+) -> anyhow::Result<()> {
+    http::start_event_stream(writer, headers).await?;
+    let mut keepalive = tokio::time::interval_at(tokio::time::Instant::now() + KEEPALIVE_INTERVAL, KEEPALIVE_INTERVAL);
+    while replies > 0 {
+        tokio::select! {
+            line = receiver.recv() => {
+                let Some(line) = line else {
+                    break;
+                };
+                let reply = serde_json::from_str::<Value>(&line).is_ok_and(|message| message.get("method").is_none());
+                http::send_event(writer, "message", &line).await?;
+                if reply {
+                    replies -= 1;
+                }
+            }
+            _ = keepalive.tick() => http::send_keepalive(writer).await?,
+        }
+    }
+    Ok(tokio::io::AsyncWriteExt::shutdown(writer).await?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session() -> Session {
+        Session {
+            token: "t".to_string(),
+            input: mpsc::unbounded_channel().0,
+            pending: Mutex::default(),
+            streams: Mutex::default(),
+            service: tokio::spawn(async {}),
+            router: tokio::spawn(async {}),
+            last_used: Mutex::new(Instant::now()),
+        }
+    }
+
+    #[tokio::test]
+    async fn open_streams_and_waiting_requests_keep_a_session() {
+        let session = session();
+        assert!(!session.idle(Duration::from_secs(60)));
+        assert!(session.idle(Duration::ZERO));
+
+        let (stream, receiver) = mpsc::unbounded_channel();
+        session.streams.lock().unwrap().push(stream);
+        assert!(!session.idle(Duration::ZERO));
+        drop(receiver);
+        assert!(session.idle(Duration::ZERO));
+
+        let (reply, _receiver) = mpsc::unbounded_channel();
+        session.pending.lock().unwrap().insert("1".to_string(), reply);
+        assert!(!session.idle(Duration::ZERO));
+    }
+}
@@ -0,0 +1,81 @@
+//! The MCP server as newline-delimited JSON-RPC over raw TCP.
+//!
+//! Meant for a LAN, e.g. driving a test machine's X session from another
+//! host: each connection is its own MCP session. Its first line must be
+//! `Bearer <token>` (see the auth module); after that it speaks exactly what
+//! stdio would. There is no encryption, so keep it on networks you trust.
+
+use crate::XdotoolServer;
+use rmcp::ServiceExt;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+
+/// How long a new connection has to send its `Bearer <token>` line
+const AUTH_TIMEOUT: Duration = Duration::from_secs(10);
+/// Longest `Bearer <token>` line a connection may send before it's dropped
+const MAX_AUTH_LINE: u64 = 4096;
+
+/// The token from a connection's `Bearer <token>` line, or None if the line is longer
+/// than MAX_AUTH_LINE or the connection ends first
+async fn read_token<R: AsyncBufRead + Unpin>(reader: &mut R) -> Option<String> {
+    let mut line = String::new();
+    reader.take(MAX_AUTH_LINE).read_line(&mut line).await.ok()?;
+    // A line cut off by the limit isn't a token line
+    if !line.ends_with('\n') {
+        return None;
+    }
+    Some(line.trim().strip_prefix("Bearer ").unwrap_or_default().to_string())
+}
+
+/// Accept connections until the listener fails
+pub async fn serve(server: XdotoolServer, addr: SocketAddr) -> anyhow::Result<()> {
+    let listener = TcpListener::bind(addr).await
+        .map_err(|e| anyhow::anyhow!("Failed to listen on {}: {}", addr, e))?;
+    tracing::info!("Serving MCP over TCP at {}", listener.local_addr()?);
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        if !server.config.allows_peer(peer.ip()) {
+            tracing::warn!("Refused TCP connection from {}, not in allowed_peers", peer);
+            continue;
+        }
+        let server = server.in_session(format!("tcp:{}", peer));
+        tokio::spawn(async move {
+            let (reader, mut writer) = stream.into_split();
+            let mut reader = BufReader::new(reader);
+            let authenticated = match tokio::time::timeout(AUTH_TIMEOUT, read_token(&mut reader)).await {
+                Ok(Some(token)) => server.authenticate(&token),
+                _ => None,
+            };
+            let Some(server) = authenticated else {
+                tracing::warn!("Refused TCP connection from {}, no valid token", peer);
+                let _ = writer.write_all(b"Unauthorized\n").await;
+                return;
+            };
+            tracing::info!("TCP session from {} opened", peer);
+            match server.serve((reader, writer)).await {
+                Ok(service) => { let _ = service.waiting().await; }
+                Err(e) => tracing::debug!("TCP session from {} failed to start: {}", peer, e),
+            }
+            tracing::info!("TCP session from {} closed", peer);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn token_lines_are_size_limited() {
+        let mut reader: &[u8] = b"Bearer abc\n{\"jsonrpc\": \"2.0\"}\n";
+        assert_eq!(read_token(&mut reader).await.as_deref(), Some("abc"));
+        // The rest is left for the session
+        assert_eq!(reader, b"{\"jsonrpc\": \"2.0\"}\n");
+
+        let long = format!("Bearer {}\n", "a".repeat(MAX_AUTH_LINE as usize));
+        assert_eq!(read_token(&mut long.as_bytes()).await, None);
+        assert_eq!(read_token(&mut &b"Bearer abc"[..]).await, None);
+    }
+}
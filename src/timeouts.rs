@@ -0,0 +1,71 @@
+//! How long a tool call may run.
+//!
+//! `[timeouts]` caps whole calls, globally and per tool: a click that takes
+//! more than a couple of seconds is stuck, while a wait may legitimately run
+//! for a minute. A call over its limit is stopped, which kills any program it
+//! was running, and fails with `TIMEOUT_CODE` instead of the usual internal
+//! error. Programs killed at `command_timeout_secs` fail with the same code.
+//! Clients can so tell a call that timed out, and may be worth retrying, from
+//! one that failed.
+//!
+//! Only running the tool counts: time spent waiting for approval or for
+//! another client's input to finish doesn't.
+
+use rmcp::model::ErrorCode;
+use rmcp::ErrorData as McpError;
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::HashMap;
+use std::future::Future;
+use std::time::Duration;
+
+/// JSON-RPC error code for calls and programs that ran out of time
+pub const TIMEOUT_CODE: ErrorCode = ErrorCode(-32001);
+
+/// The `[timeouts]` config table. Unset means calls run as long as they take.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Timeouts {
+    /// Seconds any tool call may run
+    pub default_secs: Option<u64>,
+    /// Seconds for particular tools, by name, instead of default_secs
+    pub tools: HashMap<String, u64>,
+}
+
+impl Timeouts {
+    /// The limit for a tool, if it has one
+    pub fn limit(&self, tool: &str) -> Option<Duration> {
+        self.tools.get(tool).copied().or(self.default_secs).map(Duration::from_secs)
+    }
+}
+
+/// Check the `[timeouts]` table
+pub fn validate(timeouts: &Timeouts) -> Result<(), String> {
+    if timeouts.default_secs == Some(0) {
+        return Err("timeouts.default_secs must be at least 1".to_string());
+    }
+    if let Some((tool, _)) = timeouts.tools.iter().find(|(_, secs)| **secs == 0) {
+        return Err(format!("timeouts.tools.{} must be at least 1", tool));
+    }
+    Ok(())
+}
+
+/// The error for something that didn't finish within `limit`
+pub fn timeout_error(what: &str, limit: Duration) -> McpError {
+    McpError::new(
+        TIMEOUT_CODE,
+        format!("{} didn't finish within {}s", what, limit.as_secs_f64()),
+        Some(json!({ "timeout_secs": limit.as_secs_f64() }))
+    )
+}
+
+/// Run a tool call's future, stopping it once `limit` has passed
+pub async fn run<T>(tool: &str, limit: Option<Duration>, call: impl Future<Output = Result<T, McpError>>) -> Result<T, McpError> {
+    let Some(limit) = limit else {
+        return call.await;
+    };
+    match tokio::time::timeout(limit, call).await {
+        Ok(result) => result,
+        Err(_) => Err(timeout_error(tool, limit)),
+    }
+}
@@ -0,0 +1,432 @@
+//! Input through a virtual keyboard and mouse made with Linux uinput.
+//!
+//! With `input_backend = "uinput"` the server creates its own input devices
+//! through `/dev/uinput` and writes evdev events to them. The kernel hands
+//! those on like a real keyboard and mouse, so input works under X11, any
+//! Wayland compositor and on text consoles alike, with no X connection and
+//! no ydotoold. Invocations are translated command by command:
+//!
+//! - mousemove, mousemove_relative, click (scroll buttons included),
+//!   mousedown, mouseup
+//! - key, keydown, keyup, type
+//!
+//! Window commands go to the window backend: the compositor on a Wayland
+//! session when `compositor` knows it, else xdotool. Everything else (queries
+//! like getmouselocation, `--window` targeted input, calls for another
+//! display) runs through xdotool, which only works where there's an X server.
+//!
+//! `/dev/uinput` is usually writable only by root. Access is checked at
+//! startup, and without it the server logs what to change and falls back to
+//! xdotool. The devices are created on the first input and destroyed when
+//! the server stops; the kernel also removes them if it dies.
+//!
+//! Keys are evdev codes (see `evdev`), so typed text is limited to printable
+//! ASCII and comes out right on a US layout. Absolute moves need the screen
+//! size, taken from the window backend's `getdisplaygeometry` or, on a
+//! console, the framebuffer.
+
+use crate::allowlist::split_commands;
+use crate::backend::{self, is_window_command, parse, InputBackend, Step};
+use crate::display;
+use crate::evdev::{self, combo_codes, KEY_LEFTSHIFT};
+use rmcp::ErrorData as McpError;
+use std::ffi::{c_int, c_void};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::OpenOptionsExt;
+use std::sync::Mutex;
+use std::time::Duration;
+
+const PATH: &str = "/dev/uinput";
+
+const EV_SYN: u16 = 0x00;
+const EV_KEY: u16 = 0x01;
+const EV_REL: u16 = 0x02;
+const EV_ABS: u16 = 0x03;
+const SYN_REPORT: u16 = 0;
+const REL_X: u16 = 0x00;
+const REL_Y: u16 = 0x01;
+const REL_HWHEEL: u16 = 0x06;
+const REL_WHEEL: u16 = 0x08;
+const ABS_X: u16 = 0x00;
+const ABS_Y: u16 = 0x01;
+const BTN_LEFT: u16 = 0x110;
+const BTN_RIGHT: u16 = 0x111;
+const BTN_MIDDLE: u16 = 0x112;
+const BTN_SIDE: u16 = 0x113;
+const BTN_EXTRA: u16 = 0x114;
+const BUS_VIRTUAL: u16 = 0x06;
+/// Keyboard keys the virtual keyboard has, every code `evdev` knows included
+const KEYS: std::ops::RangeInclusive<u16> = 1..=248;
+
+const UI_DEV_CREATE: libc::Ioctl = libc::_IO(b'U' as u32, 1);
+const UI_DEV_DESTROY: libc::Ioctl = libc::_IO(b'U' as u32, 2);
+const UI_DEV_SETUP: libc::Ioctl = libc::_IOW::<libc::uinput_setup>(b'U' as u32, 3);
+const UI_ABS_SETUP: libc::Ioctl = libc::_IOW::<libc::uinput_abs_setup>(b'U' as u32, 4);
+const UI_SET_EVBIT: libc::Ioctl = libc::_IOW::<c_int>(b'U' as u32, 100);
+const UI_SET_KEYBIT: libc::Ioctl = libc::_IOW::<c_int>(b'U' as u32, 101);
+const UI_SET_RELBIT: libc::Ioctl = libc::_IOW::<c_int>(b'U' as u32, 102);
+const UI_SET_ABSBIT: libc::Ioctl = libc::_IOW::<c_int>(b'U' as u32, 103);
+
+/// How long a new device takes to be picked up; events sent sooner are lost
+const SETTLE_TIME: Duration = Duration::from_millis(300);
+
+/// One xdotool command as device events. Delays are in milliseconds, like xdotool's.
+#[derive(Debug)]
+pub enum Action {
+    Move { x: i32, y: i32 },
+    MoveRelative { x: i32, y: i32 },
+    Click { button: u16, repeat: u32, delay: u64 },
+    Scroll { axis: u16, amount: i32, repeat: u32 },
+    Button { button: u16, down: bool },
+    /// Each combo pressed in order and released in reverse
+    Combos { combos: Vec<Vec<u16>>, delay: u64 },
+    Keys { codes: Vec<u16>, down: bool },
+    /// Key codes, each with whether it needs shift
+    Type { keys: Vec<(u16, bool)>, delay: u64 },
+}
+
+/// Key code for a typed character on a US layout, and whether it needs shift
+fn char_key(c: char) -> Option<(u16, bool)> {
+    const SHIFTED: &str = "~!@#$%^&*()_+{}|:\"<>?";
+    const UNSHIFTED: &str = "`1234567890-=[]\\;',./";
+    if c.is_ascii_alphanumeric() {
+        return evdev::key_code(&c.to_ascii_lowercase().to_string()).map(|code| (code, c.is_ascii_uppercase()));
+    }
+    if let Some(i) = SHIFTED.find(c) {
+        return char_key(UNSHIFTED.as_bytes()[i] as char).map(|(code, _)| (code, true));
+    }
+    let name = match c {
+        '`' => "grave",
+        '-' => "minus",
+        '=' => "equal",
+        '[' => "bracketleft",
+        ']' => "bracketright",
+        '\\' => "backslash",
+        ';' => "semicolon",
+        '\'' => "apostrophe",
+        ',' => "comma",
+        '.' => "period",
+        '/' => "slash",
+        ' ' => "space",
+        '\n' => "Return",
+        '\t' => "Tab",
+        _ => return None,
+    };
+    evdev::key_code(name).map(|code| (code, false))
+}
+
+/// What an xdotool button number is on the pointer device
+enum Button {
+    Key(u16),
+    /// A scroll button: the wheel axis and direction
+    Wheel(u16, i32),
+}
+
+fn button(button: &str) -> Result<Button, McpError> {
+    match button {
+        "1" => Ok(Button::Key(BTN_LEFT)),
+        "2" => Ok(Button::Key(BTN_MIDDLE)),
+        "3" => Ok(Button::Key(BTN_RIGHT)),
+        "8" => Ok(Button::Key(BTN_SIDE)),
+        "9" => Ok(Button::Key(BTN_EXTRA)),
+        "4" => Ok(Button::Wheel(REL_WHEEL, 1)),
+        "5" => Ok(Button::Wheel(REL_WHEEL, -1)),
+        "6" => Ok(Button::Wheel(REL_HWHEEL, -1)),
+        "7" => Ok(Button::Wheel(REL_HWHEEL, 1)),
+        _ => Err(McpError::internal_error(
+            format!("Button {} has no uinput equivalent", button),
+            None
+        )),
+    }
+}
+
+fn number<T: std::str::FromStr>(value: &str) -> Result<T, McpError> {
+    value.parse().map_err(|_| McpError::internal_error(format!("Not a number: {}", value), None))
+}
+
+/// The events for one command, or None if it should run through xdotool
+fn translate(command: &[&str]) -> Result<Option<Action>, McpError> {
+    let (options, args) = parse(command, &["--window", "--delay", "--repeat", "--repeat-delay", "--screen", "--file"]);
+    let option = |name: &str| options.iter().find(|(option, _)| *option == name).map(|(_, value)| *value);
+    // Input aimed at a particular window is sent as X events, which only xdotool can do
+    if option("--window").is_some() {
+        return Ok(None);
+    }
+    let delay = |default: u64| option("--delay").map(number).unwrap_or(Ok(default));
+    let action = match (command[0], args.as_slice()) {
+        ("mousemove", [x, y]) => Action::Move { x: number(x)?, y: number(y)? },
+        ("mousemove_relative", [x, y]) => Action::MoveRelative { x: number(x)?, y: number(y)? },
+        ("click", [name]) => {
+            let repeat = option("--repeat").map(number).unwrap_or(Ok(1))?;
+            match button(name)? {
+                Button::Key(button) => Action::Click { button, repeat, delay: delay(100)? },
+                Button::Wheel(axis, amount) => Action::Scroll { axis, amount, repeat },
+            }
+        }
+        ("mousedown" | "mouseup", [name]) => match button(name)? {
+            Button::Key(button) => Action::Button { button, down: command[0] == "mousedown" },
+            Button::Wheel(..) => return Ok(None),
+        },
+        ("key", combos) if !combos.is_empty() => Action::Combos {
+            combos: combos.iter().map(|combo| combo_codes(combo)).collect::<Result<_, _>>()?,
+            delay: delay(12)?,
+        },
+        ("keydown" | "keyup", keys) if !keys.is_empty() => Action::Keys {
+            codes: keys.iter().map(|key| combo_codes(key)).collect::<Result<Vec<_>, _>>()?.concat(),
+            down: command[0] == "keydown",
+        },
+        ("type", texts) => {
+            let mut keys = vec![];
+            for c in texts.concat().chars() {
+                keys.push(char_key(c).ok_or_else(|| McpError::internal_error(
+                    format!("uinput can't type '{}': only printable ASCII, newlines and tabs can be typed", c.escape_default()),
+                    None
+                ))?);
+            }
+            Action::Type { keys, delay: delay(12)? }
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(action))
+}
+
+/// Virtual devices as the input backend
+pub struct Uinput;
+
+impl InputBackend for Uinput {
+    fn name(&self) -> &'static str {
+        "uinput"
+    }
+
+    fn plan<'a>(&self, args: &'a [&'a str]) -> Result<Vec<Step<'a>>, McpError> {
+        // The devices belong to this machine's seat; other displays get their own xdotool
+        if display::current().is_some() {
+            return Ok(vec![Step::Xdotool(args)]);
+        }
+        let mut steps = vec![];
+        for command in split_commands(args) {
+            if is_window_command(command[0]) {
+                steps.push(Step::Window(command));
+                continue;
+            }
+            match translate(command)? {
+                Some(action) => steps.push(Step::Uinput(action, command)),
+                None => return Ok(vec![Step::Xdotool(args)]),
+            }
+        }
+        Ok(steps)
+    }
+}
+
+/// Check that devices can be created, saying how to allow it if not
+pub fn check() -> Result<(), String> {
+    match OpenOptions::new().write(true).open(PATH) {
+        Ok(_) => Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            Err(format!("{} doesn't exist; load the module with `modprobe uinput`", PATH))
+        }
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied => Err(format!(
+            "no write access to {}; allow it with a udev rule such as \
+             KERNEL==\"uinput\", GROUP=\"input\", MODE=\"0660\" and join the input group",
+            PATH
+        )),
+        Err(e) => Err(format!("{}: {}", PATH, e)),
+    }
+}
+
+fn ioctl(file: &File, request: libc::Ioctl, arg: *const c_void) -> io::Result<()> {
+    if unsafe { libc::ioctl(file.as_raw_fd(), request, arg) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+fn set_bit(file: &File, request: libc::Ioctl, bit: u16) -> io::Result<()> {
+    if unsafe { libc::ioctl(file.as_raw_fd(), request, bit as c_int) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// A device as created by `create`, destroyed when dropped
+struct Device(File);
+
+impl Drop for Device {
+    fn drop(&mut self) {
+        let _ = ioctl(&self.0, UI_DEV_DESTROY, std::ptr::null());
+    }
+}
+
+impl Device {
+    /// Make a device with the capabilities `setup` enables
+    fn create(name: &str, setup: impl FnOnce(&File) -> io::Result<()>) -> io::Result<Self> {
+        let file = OpenOptions::new().write(true).custom_flags(libc::O_NONBLOCK).open(PATH)?;
+        setup(&file)?;
+        let mut device: libc::uinput_setup = unsafe { std::mem::zeroed() };
+        device.id = libc::input_id { bustype: BUS_VIRTUAL, vendor: 0, product: 0, version: 1 };
+        for (slot, byte) in device.name.iter_mut().zip(name.bytes().take(libc::UINPUT_MAX_NAME_SIZE - 1)) {
+            *slot = byte as _;
+        }
+        ioctl(&file, UI_DEV_SETUP, &device as *const _ as *const c_void)?;
+        ioctl(&file, UI_DEV_CREATE, std::ptr::null())?;
+        Ok(Device(file))
+    }
+
+    /// Write events followed by a report, so they take effect together
+    fn emit(&self, events: &[(u16, u16, i32)]) -> Result<(), McpError> {
+        let mut bytes = vec![];
+        for &(type_, code, value) in events.iter().chain([&(EV_SYN, SYN_REPORT, 0)]) {
+            let mut event: libc::input_event = unsafe { std::mem::zeroed() };
+            event.type_ = type_;
+            event.code = code;
+            event.value = value;
+            let raw = unsafe {
+                std::slice::from_raw_parts(&event as *const _ as *const u8, size_of::<libc::input_event>())
+            };
+            bytes.extend_from_slice(raw);
+        }
+        (&self.0).write_all(&bytes)
+            .map_err(|e| McpError::internal_error(format!("Failed to write to {}: {}", PATH, e), None))
+    }
+}
+
+struct Devices {
+    keyboard: Device,
+    pointer: Device,
+    /// Whether the pointer has absolute axes, which needs the screen size
+    absolute: bool,
+}
+
+static DEVICES: Mutex<Option<Devices>> = Mutex::new(None);
+
+/// The screen size absolute positions are scaled to
+fn screen_size() -> Option<(i32, i32)> {
+    let parse = |text: &str, separator: char| {
+        let (width, height) = text.trim().split_once(separator)?;
+        Some((width.trim().parse().ok()?, height.trim().parse().ok()?))
+    };
+    backend::windows().run(&["getdisplaygeometry"]).ok()
+        .and_then(|geometry| parse(&geometry, ' '))
+        .or_else(|| std::fs::read_to_string("/sys/class/graphics/fb0/virtual_size").ok().and_then(|size| parse(&size, ',')))
+}
+
+impl Devices {
+    fn create() -> io::Result<Self> {
+        let keyboard = Device::create("rmcp-xdotool keyboard", |file| {
+            set_bit(file, UI_SET_EVBIT, EV_KEY)?;
+            KEYS.into_iter().try_for_each(|key| set_bit(file, UI_SET_KEYBIT, key))
+        })?;
+        let size = screen_size();
+        if size.is_none() {
+            tracing::warn!("Screen size unknown, so uinput can only move the pointer relatively");
+        }
+        let pointer = Device::create("rmcp-xdotool pointer", |file| {
+            set_bit(file, UI_SET_EVBIT, EV_KEY)?;
+            for button in [BTN_LEFT, BTN_RIGHT, BTN_MIDDLE, BTN_SIDE, BTN_EXTRA] {
+                set_bit(file, UI_SET_KEYBIT, button)?;
+            }
+            set_bit(file, UI_SET_EVBIT, EV_REL)?;
+            for axis in [REL_X, REL_Y, REL_WHEEL, REL_HWHEEL] {
+                set_bit(file, UI_SET_RELBIT, axis)?;
+            }
+            if let Some((width, height)) = size {
+                set_bit(file, UI_SET_EVBIT, EV_ABS)?;
+                for (axis, length) in [(ABS_X, width), (ABS_Y, height)] {
+                    set_bit(file, UI_SET_ABSBIT, axis)?;
+                    let setup = libc::uinput_abs_setup {
+                        code: axis,
+                        absinfo: libc::input_absinfo { value: 0, minimum: 0, maximum: length - 1, fuzz: 0, flat: 0, resolution: 0 },
+                    };
+                    ioctl(file, UI_ABS_SETUP, &setup as *const _ as *const c_void)?;
+                }
+            }
+            Ok(())
+        })?;
+        tracing::info!("Created uinput keyboard and pointer devices");
+        std::thread::sleep(SETTLE_TIME);
+        Ok(Devices { keyboard, pointer, absolute: size.is_some() })
+    }
+
+    fn run(&self, action: &Action) -> Result<(), McpError> {
+        let pause = |ms: u64| std::thread::sleep(Duration::from_millis(ms));
+        match action {
+            Action::Move { x, y } => {
+                if !self.absolute {
+                    return Err(McpError::internal_error(
+                        "uinput can't move to absolute positions: the screen size couldn't be read",
+                        None
+                    ));
+                }
+                self.pointer.emit(&[(EV_ABS, ABS_X, *x), (EV_ABS, ABS_Y, *y)])?;
+            }
+            Action::MoveRelative { x, y } => self.pointer.emit(&[(EV_REL, REL_X, *x), (EV_REL, REL_Y, *y)])?,
+            Action::Click { button, repeat, delay } => {
+                for i in 0..*repeat {
+                    if i > 0 {
+                        pause(*delay);
+                    }
+                    self.pointer.emit(&[(EV_KEY, *button, 1)])?;
+                    self.pointer.emit(&[(EV_KEY, *button, 0)])?;
+                }
+            }
+            Action::Scroll { axis, amount, repeat } => {
+                for _ in 0..*repeat {
+                    self.pointer.emit(&[(EV_REL, *axis, *amount)])?;
+                }
+            }
+            Action::Button { button, down } => self.pointer.emit(&[(EV_KEY, *button, *down as i32)])?,
+            Action::Combos { combos, delay } => {
+                for (i, codes) in combos.iter().enumerate() {
+                    if i > 0 {
+                        pause(*delay);
+                    }
+                    let press: Vec<_> = codes.iter().map(|code| (EV_KEY, *code, 1)).collect();
+                    let release: Vec<_> = codes.iter().rev().map(|code| (EV_KEY, *code, 0)).collect();
+                    self.keyboard.emit(&press)?;
+                    self.keyboard.emit(&release)?;
+                }
+            }
+            Action::Keys { codes, down } => {
+                let events: Vec<_> = codes.iter().map(|code| (EV_KEY, *code, *down as i32)).collect();
+                self.keyboard.emit(&events)?;
+            }
+            Action::Type { keys, delay } => {
+                for (i, (code, shift)) in keys.iter().enumerate() {
+                    if i > 0 {
+                        pause(*delay);
+                    }
+                    if *shift {
+                        self.keyboard.emit(&[(EV_KEY, KEY_LEFTSHIFT, 1)])?;
+                    }
+                    self.keyboard.emit(&[(EV_KEY, *code, 1)])?;
+                    self.keyboard.emit(&[(EV_KEY, *code, 0)])?;
+                    if *shift {
+                        self.keyboard.emit(&[(EV_KEY, KEY_LEFTSHIFT, 0)])?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Send one command's events, creating the devices first if need be
+pub fn run(action: &Action) -> Result<String, McpError> {
+    let mut devices = DEVICES.lock().unwrap();
+    if devices.is_none() {
+        let created = Devices::create()
+            .map_err(|e| McpError::internal_error(format!("Failed to create uinput devices: {}", e), None))?;
+        *devices = Some(created);
+    }
+    devices.as_ref().unwrap().run(action)?;
+    Ok(String::new())
+}
+
+/// Destroy the devices, if they were created
+pub fn teardown() {
+    if DEVICES.lock().unwrap().take().is_some() {
+        tracing::info!("Removed uinput devices");
+    }
+}
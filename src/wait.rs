@@ -0,0 +1,216 @@
+//! Conditions the server can poll for, shared by batch wait steps, `repeat`
+//! blocks and the `poll_until` tool.
+
+use crate::control::sleep_or_cancel;
+#[cfg(feature = "screenshots")]
+use crate::screenshot;
+use crate::{clipboard, output, XdotoolServer};
+use rmcp::{
+    handler::server::wrapper::Parameters,
+    model::*,
+    service::{RequestContext, RoleServer},
+    ErrorData as McpError,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
+
+/// How often conditions are re-checked
+pub const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// The shortest interval poll_until accepts, so a loop can't hammer xdotool or tesseract
+const MIN_INTERVAL: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(tag = "condition", rename_all = "snake_case")]
+pub enum Condition {
+    /// A window matching the query exists
+    WindowExists {
+        query: String,
+        #[serde(default = "crate::default_search_type")]
+        search_type: String,
+    },
+    /// OCR finds the text (case-insensitive) on screen or in a region. Requires tesseract
+    TextVisible {
+        text: String,
+        x: Option<i32>,
+        y: Option<i32>,
+        width: Option<u32>,
+        height: Option<u32>,
+    },
+    /// The pixel at x,y has a color within tolerance of "#rrggbb"
+    PixelColor {
+        x: i32,
+        y: i32,
+        color: String,
+        #[serde(default)]
+        tolerance: u8,
+    },
+    /// The clipboard's text contains the text (case-sensitive). Requires xclip
+    ClipboardContains {
+        text: String,
+    },
+}
+
+/// Parse "#rrggbb" or "rrggbb"
+pub fn parse_color(color: &str) -> Result<[u8; 3], McpError> {
+    let hex = color.trim_start_matches('#');
+    let channel = |i: usize| hex.get(i..i + 2).and_then(|c| u8::from_str_radix(c, 16).ok());
+    match (hex.len(), channel(0), channel(2), channel(4)) {
+        (6, Some(r), Some(g), Some(b)) => Ok([r, g, b]),
+        _ => Err(McpError::internal_error(
+            format!("Invalid color '{}'. Use #rrggbb", color),
+            None
+        )),
+    }
+}
+
+impl Condition {
+    /// Check the condition's parameters without touching the desktop
+    pub fn validate(&self) -> Result<(), McpError> {
+        match self {
+            Condition::PixelColor { color, .. } => parse_color(color).map(|_| ()),
+            Condition::TextVisible { x, y, width, height, .. } => {
+                match (x, y, width, height) {
+                    (Some(_), Some(_), Some(_), Some(_)) | (None, None, None, None) => Ok(()),
+                    _ => Err(McpError::internal_error("Region needs all of x, y, width and height", None)),
+                }
+            }
+            Condition::WindowExists { .. } | Condition::ClipboardContains { .. } => Ok(()),
+        }
+    }
+
+    /// Evaluate the condition once
+    pub fn check(&self) -> Result<bool, McpError> {
+        match self {
+            Condition::WindowExists { query, search_type } => {
+                Ok(!XdotoolServer::search_windows(query, search_type, false)?.is_empty())
+            }
+            Condition::TextVisible { text, x, y, width, height } => {
+                let region = match (x, y, width, height) {
+                    (Some(x), Some(y), Some(width), Some(height)) => Some((*x, *y, *width, *height)),
+                    _ => None,
+                };
+                text_visible(text, region)
+            }
+            Condition::PixelColor { x, y, color, tolerance } => {
+                let expected = parse_color(color)?;
+                let actual = pixel(*x, *y)?;
+                Ok(expected.iter().zip(actual).all(|(e, a)| e.abs_diff(a) <= *tolerance))
+            }
+            Condition::ClipboardContains { text } => {
+                let clipboard = clipboard::read_selection("clipboard", None)?.unwrap_or_default();
+                Ok(String::from_utf8_lossy(&clipboard).contains(text.as_str()))
+            }
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Condition::WindowExists { query, .. } => format!("window '{}' exists", query),
+            Condition::TextVisible { text, .. } => format!("text '{}' visible", text),
+            Condition::PixelColor { x, y, color, .. } => format!("pixel ({}, {}) is {}", x, y, color),
+            Condition::ClipboardContains { text } => format!("clipboard contains '{}'", text),
+        }
+    }
+}
+
+/// Whether OCR finds `text` in a region (x, y, width, height), or on the whole screen
+#[cfg(feature = "ocr")]
+fn text_visible(text: &str, region: Option<(i32, i32, u32, u32)>) -> Result<bool, McpError> {
+    let region = match region {
+        Some((x, y, width, height)) => screenshot::Region { x, y, width, height },
+        None => {
+            let (width, height) = XdotoolServer::display_geometry()?;
+            screenshot::Region { x: 0, y: 0, width, height }
+        }
+    };
+    let png = screenshot::capture_png(region, None)?;
+    let found = crate::ocr::recognize_text(&png)?;
+    Ok(found.to_lowercase().contains(&text.to_lowercase()))
+}
+
+#[cfg(not(feature = "ocr"))]
+fn text_visible(_text: &str, _region: Option<(i32, i32, u32, u32)>) -> Result<bool, McpError> {
+    Err(crate::feature_disabled("ocr"))
+}
+
+#[cfg(feature = "screenshots")]
+fn pixel(x: i32, y: i32) -> Result<[u8; 3], McpError> {
+    screenshot::capture_pixel(x, y)
+}
+
+#[cfg(not(feature = "screenshots"))]
+fn pixel(_x: i32, _y: i32) -> Result<[u8; 3], McpError> {
+    Err(crate::feature_disabled("screenshots"))
+}
+
+/// Poll every `interval` until the condition holds, returning how long it took
+pub async fn wait_for(condition: &Condition, interval: Duration, timeout: Duration, cancel: &CancellationToken) -> Result<Duration, McpError> {
+    let started = Instant::now();
+    loop {
+        if condition.check()? {
+            return Ok(started.elapsed());
+        }
+        if started.elapsed() >= timeout {
+            return Err(McpError::internal_error(
+                format!("Timed out after {}ms waiting for {}", timeout.as_millis(), condition.describe()),
+                None
+            ));
+        }
+        sleep_or_cancel(interval, cancel).await?;
+    }
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct PollUntilParams {
+    /// What to wait for: {"condition": "window_exists", "query": ...}, "text_visible", "pixel_color" or "clipboard_contains" with its fields
+    #[serde(flatten)]
+    pub condition: Condition,
+    #[schemars(description = "Milliseconds between checks, at least 50. Default: 200")]
+    #[serde(default = "default_interval")]
+    pub interval_ms: u64,
+    #[schemars(description = "Seconds to wait before giving up. Default: 30")]
+    #[serde(default = "default_poll_timeout")]
+    pub timeout_seconds: u64,
+}
+
+fn default_interval() -> u64 { 200 }
+
+fn default_poll_timeout() -> u64 { 30 }
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct PollResult {
+    /// The condition that came true, described
+    pub condition: String,
+    /// How long it took to come true, in milliseconds
+    pub waited_ms: u64,
+}
+
+#[rmcp::tool_router(router = wait_router, vis = "pub(crate)")]
+impl XdotoolServer {
+    #[rmcp::tool(description = "Wait until a condition holds, checking it every interval_ms: a window matching a search exists (window_exists), OCR finds text (text_visible), a pixel has a color (pixel_color), or the clipboard contains text (clipboard_contains). The same conditions batch repeat blocks take as `until`", annotations(read_only_hint = true), output_schema = output::schema::<PollResult>())]
+    pub async fn poll_until(
+        &self,
+        Parameters(params): Parameters<PollUntilParams>,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        params.condition.validate()?;
+        let interval = Duration::from_millis(params.interval_ms);
+        if interval < MIN_INTERVAL {
+            return Err(McpError::invalid_params(
+                format!("interval_ms must be at least {}", MIN_INTERVAL.as_millis()),
+                None
+            ));
+        }
+        let timeout = Duration::from_secs(params.timeout_seconds);
+        let waited = wait_for(&params.condition, interval, timeout, &context.ct).await?;
+        let condition = params.condition.describe();
+        let waited_ms = waited.as_millis() as u64;
+        Ok(output::structured(
+            format!("{} after {}ms", condition, waited_ms),
+            &PollResult { condition, waited_ms }
+        ))
+    }
+}
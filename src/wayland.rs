@@ -0,0 +1,123 @@
+//! Input on Wayland sessions through ydotool and wtype.
+//!
+//! Under Wayland, xdotool only reaches XWayland windows. With
+//! `input_backend = "wayland"` (chosen automatically when XDG_SESSION_TYPE is
+//! wayland) the xdotool input commands the tools build are translated before
+//! anything runs: pointer moves, clicks, scrolling and key presses go to
+//! ydotool, which injects through uinput and works under any compositor,
+//! and typed text to wtype, which handles any Unicode text. Window commands
+//! go to the compositor when it's one `compositor` knows; everything else in
+//! an invocation (other queries, `--window` targeted input) still runs
+//! through xdotool, so it keeps working for XWayland windows.
+//!
+//! ydotool needs its daemon (`ydotoold`) running with access to
+//! `/dev/uinput`. It sends evdev key codes, looked up in `evdev`.
+
+use crate::allowlist::split_commands;
+use crate::backend::{argv, is_window_command, parse, InputBackend, Step};
+use crate::evdev::combo_codes;
+use rmcp::ErrorData as McpError;
+
+/// ydotool button code for an xdotool button number (1 left, 2 middle, 3 right, 8 back, 9 forward)
+fn button_code(button: &str) -> Result<u8, McpError> {
+    match button {
+        "1" => Ok(0x00),
+        "2" => Ok(0x02),
+        "3" => Ok(0x01),
+        "8" => Ok(0x06),
+        "9" => Ok(0x05),
+        _ => Err(McpError::internal_error(
+            format!("Button {} has no Wayland equivalent", button),
+            None
+        )),
+    }
+}
+
+/// Wheel movement for a scroll button, as ydotool mousemove --wheel -x/-y arguments
+fn wheel(button: &str) -> Option<(&'static str, &'static str)> {
+    match button {
+        "4" => Some(("0", "1")),
+        "5" => Some(("0", "-1")),
+        "6" => Some(("-1", "0")),
+        "7" => Some(("1", "0")),
+        _ => None,
+    }
+}
+
+/// The programs to run, in order, for one xdotool command
+fn translate(command: &[&str]) -> Result<Vec<Vec<String>>, McpError> {
+    let (options, args) = parse(command, &["--window", "--delay", "--repeat", "--repeat-delay", "--screen", "--file"]);
+    let option = |name: &str| options.iter().find(|(option, _)| *option == name).map(|(_, value)| *value);
+    let xdotool = || Ok(vec![argv("xdotool", command)]);
+    // Input aimed at a particular window is sent as X events, which only xdotool can do
+    if option("--window").is_some() {
+        return xdotool();
+    }
+    match (command[0], args.as_slice()) {
+        ("mousemove", [x, y]) => Ok(vec![argv("ydotool", &["mousemove", "--absolute", "-x", x, "-y", y])]),
+        ("mousemove_relative", [x, y]) => Ok(vec![argv("ydotool", &["mousemove", "-x", x, "-y", y])]),
+        ("click", [button]) => {
+            let repeat = option("--repeat").unwrap_or("1");
+            if let Some((x, y)) = wheel(button) {
+                let clicks = repeat.parse::<usize>().unwrap_or(1);
+                return Ok(vec![argv("ydotool", &["mousemove", "--wheel", "-x", x, "-y", y]); clicks]);
+            }
+            let code = format!("0x{:02X}", 0xC0 | button_code(button)?);
+            let delay = option("--delay").unwrap_or("100");
+            Ok(vec![argv("ydotool", &["click", "--repeat", repeat, "--next-delay", delay, &code])])
+        }
+        ("mousedown" | "mouseup", [button]) => {
+            let state = if command[0] == "mousedown" { 0x40 } else { 0x80 };
+            let code = format!("0x{:02X}", state | button_code(button)?);
+            Ok(vec![argv("ydotool", &["click", &code])])
+        }
+        ("key", combos) if !combos.is_empty() => {
+            let mut events = vec![];
+            for combo in combos {
+                let codes = combo_codes(combo)?;
+                events.extend(codes.iter().map(|code| format!("{}:1", code)));
+                events.extend(codes.iter().rev().map(|code| format!("{}:0", code)));
+            }
+            let mut key = vec!["key".to_string()];
+            if let Some(delay) = option("--delay") {
+                key.extend(["--key-delay".to_string(), delay.to_string()]);
+            }
+            key.extend(events);
+            Ok(vec![std::iter::once("ydotool".to_string()).chain(key).collect()])
+        }
+        ("keydown" | "keyup", keys) if !keys.is_empty() => {
+            let state = if command[0] == "keydown" { 1 } else { 0 };
+            let mut key = vec!["ydotool".to_string(), "key".to_string()];
+            for name in keys {
+                key.extend(combo_codes(name)?.iter().map(|code| format!("{}:{}", code, state)));
+            }
+            Ok(vec![key])
+        }
+        ("type", texts) => {
+            let delay = option("--delay").unwrap_or("12");
+            Ok(texts.iter().map(|text| argv("wtype", &["-d", delay, "--", text])).collect())
+        }
+        _ => xdotool(),
+    }
+}
+
+/// ydotool and wtype as the input backend
+pub struct Wayland;
+
+impl InputBackend for Wayland {
+    fn name(&self) -> &'static str {
+        "wayland"
+    }
+
+    fn plan<'a>(&self, args: &'a [&'a str]) -> Result<Vec<Step<'a>>, McpError> {
+        let mut steps = vec![];
+        for command in split_commands(args) {
+            if is_window_command(command[0]) {
+                steps.push(Step::Window(command));
+            } else {
+                steps.extend(translate(command)?.into_iter().map(Step::Program));
+            }
+        }
+        Ok(steps)
+    }
+}
@@ -0,0 +1,135 @@
+//! Cached window titles, WM_CLASS names and geometry.
+//!
+//! One interaction looks the same windows up many times: the allowlist and
+//! policy check a window's WM_CLASS and title before each click, the audit
+//! log and screenshot captions read the title again, window listings read
+//! all three for every window. Each lookup is an xprop or xdotool process,
+//! so answers are kept per display and window.
+//!
+//! An answer is dropped:
+//! - when the server runs anything but a query (input, activating, moving,
+//!   renaming), since that may have changed any window;
+//! - after `MAX_AGE`, which catches what the user does by hand;
+//! - when a poller reads the title itself, the desktop watcher and
+//!   `wait_for_window_event` noticing title changes, whose answer replaces it.
+//!
+//! Window stack references like `%1` and `%@` name different windows at
+//! different times and are never cached.
+
+use crate::{display, WindowGeometry};
+use rmcp::ErrorData as McpError;
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/// How long an answer is trusted without anything invalidating it
+const MAX_AGE: Duration = Duration::from_secs(2);
+
+/// How many windows are kept before expired ones are pruned
+const PRUNE_AT: usize = 256;
+
+struct Entry {
+    cached_at: Instant,
+    name: Option<String>,
+    classes: Option<Vec<String>>,
+    geometry: Option<WindowGeometry>,
+}
+
+impl Entry {
+    fn new() -> Self {
+        Self { cached_at: Instant::now(), name: None, classes: None, geometry: None }
+    }
+
+    fn expired(&self) -> bool {
+        self.cached_at.elapsed() >= MAX_AGE
+    }
+}
+
+#[derive(Default)]
+struct Cache {
+    /// By display (None for $DISPLAY) and window ID
+    entries: HashMap<(Option<String>, String), Entry>,
+    /// Bumped by every invalidation, so a lookup that raced one doesn't store a stale answer
+    generation: u64,
+}
+
+fn cache() -> &'static Mutex<Cache> {
+    static CACHE: OnceLock<Mutex<Cache>> = OnceLock::new();
+    CACHE.get_or_init(|| Mutex::new(Cache::default()))
+}
+
+fn key(window_id: &str) -> Option<(Option<String>, String)> {
+    let window_id = window_id.trim();
+    if window_id.is_empty() || window_id.starts_with('%') {
+        return None;
+    }
+    Some((display::current(), window_id.to_string()))
+}
+
+fn store<T>(key: (Option<String>, String), generation: u64, field: fn(&mut Entry) -> &mut Option<T>, value: T) {
+    let mut cache = cache().lock().unwrap();
+    if cache.generation != generation {
+        return;
+    }
+    if cache.entries.len() >= PRUNE_AT {
+        cache.entries.retain(|_, entry| !entry.expired());
+    }
+    let entry = cache.entries.entry(key).or_insert_with(Entry::new);
+    if entry.expired() {
+        *entry = Entry::new();
+    }
+    *field(entry) = Some(value);
+}
+
+/// A cached answer, or `lookup`'s, which is then cached
+fn cached<T: Clone>(
+    window_id: &str,
+    field: fn(&mut Entry) -> &mut Option<T>,
+    lookup: impl FnOnce() -> Result<T, McpError>,
+) -> Result<T, McpError> {
+    let Some(key) = key(window_id) else {
+        return lookup();
+    };
+    let generation = {
+        let mut cache = cache().lock().unwrap();
+        if let Some(value) = cache.entries.get_mut(&key).filter(|entry| !entry.expired()).and_then(|entry| field(entry).clone()) {
+            return Ok(value);
+        }
+        cache.generation
+    };
+    let value = lookup()?;
+    store(key, generation, field, value.clone());
+    Ok(value)
+}
+
+/// A window's title
+pub(crate) fn name(window_id: &str, lookup: impl FnOnce() -> Result<String, McpError>) -> Result<String, McpError> {
+    cached(window_id, |entry| &mut entry.name, lookup)
+}
+
+/// A window's title, always looked up, replacing the cached one
+pub(crate) fn fresh_name(window_id: &str, lookup: impl FnOnce() -> Result<String, McpError>) -> Result<String, McpError> {
+    let generation = cache().lock().unwrap().generation;
+    let name = lookup()?;
+    if let Some(key) = key(window_id) {
+        store(key, generation, |entry| &mut entry.name, name.clone());
+    }
+    Ok(name)
+}
+
+/// A window's instance and class names
+pub(crate) fn classes(window_id: &str, lookup: impl FnOnce() -> Result<Vec<String>, McpError>) -> Result<Vec<String>, McpError> {
+    cached(window_id, |entry| &mut entry.classes, lookup)
+}
+
+/// A window's position and size
+pub(crate) fn geometry(window_id: &str, lookup: impl FnOnce() -> Result<WindowGeometry, McpError>) -> Result<WindowGeometry, McpError> {
+    cached(window_id, |entry| &mut entry.geometry, lookup)
+}
+
+/// Forget everything, after something may have changed windows
+pub(crate) fn invalidate() {
+    let mut cache = cache().lock().unwrap();
+    cache.entries.clear();
+    cache.generation += 1;
+}